@@ -1,6 +1,9 @@
 use config::{Config, ConfigError, Environment, File};
+use rusmpp::CommandStatus;
 use serde::Deserialize;
+use std::collections::HashMap;
 use std::env;
+use std::path::{Path, PathBuf};
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct AppConfig {
@@ -11,34 +14,402 @@ pub struct AppConfig {
     pub lifecycle: LifecycleConfig,
     #[serde(default)]
     pub mo_service: MoServiceConfig,
+    /// ESMEs to dial out to when `smpp.outbind_enabled` is set
+    #[serde(default)]
+    pub outbind: Vec<OutbindTarget>,
+    #[serde(default)]
+    pub shutdown: ShutdownConfig,
+    #[serde(default)]
+    pub audit: AuditConfig,
+}
+
+/// Audit trail of submits and deliveries, one JSON object per line
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct AuditConfig {
+    #[serde(default)]
+    pub file_path: Option<String>, // unset = no audit file
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct ShutdownConfig {
+    pub drain_timeout_ms: u64, // wait for DR draining and unbind_resp before force-closing connections
+}
+
+impl Default for ShutdownConfig {
+    fn default() -> Self {
+        Self {
+            drain_timeout_ms: 5000,
+        }
+    }
 }
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct ServerConfig {
     pub host: String,
     pub port: u16,
+    #[serde(default)]
+    pub auth: Option<AuthConfig>, // Web UI/API protection; open access when absent
+}
+
+/// Credentials for the web UI and REST API. Browser routes take Basic auth, `/api/*`
+/// also takes `Authorization: Bearer <api_token>`; `/health` is always open. Without a
+/// username and password the browser routes stay open and only the API is protected.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct AuthConfig {
+    #[serde(default)]
+    pub username: Option<String>,
+    #[serde(default)]
+    pub password: Option<String>,
+    #[serde(default)]
+    pub api_token: Option<String>,
+}
+
+impl AuthConfig {
+    /// Whether a username and password are set, so browser routes can log in
+    pub fn has_basic_credentials(&self) -> bool {
+        self.username.is_some() && self.password.is_some()
+    }
 }
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct SmppConfig {
     pub system_id: String, // Fallback/Default system_id
     pub password: String,  // Fallback/Default password
+    #[serde(default = "default_smpp_host")]
+    pub host: String, // interface the SMPP listeners bind to
     pub port: u16,
+    #[serde(default)]
+    pub ports: Vec<u16>, // listen on all of these instead of `port` when non-empty
     pub max_sessions: usize,
     #[serde(default)]
     pub accounts: Vec<SmppAccount>,
     #[serde(default = "default_smpp_version")]
     pub version: String,  // "3.4" or "5.0"
+    #[serde(default = "default_message_id_format")]
+    pub message_id_format: String, // "hex", "decimal" or a template like "MSG{counter:5}"
+    #[serde(default)]
+    pub min_reconnect_interval_ms: u64, // 0 = no anti-flap check
+    #[serde(default)]
+    pub max_bind_failures: u32, // failed binds within bind_failure_window_ms that lock a system_id out; 0 = no lockout
+    #[serde(default = "default_bind_failure_window_ms")]
+    pub bind_failure_window_ms: u64,
+    #[serde(default = "default_lockout_duration_ms")]
+    pub lockout_duration_ms: u64, // binds are refused, even with the right password, for this long
+    #[serde(default = "default_true")]
+    pub capture_tlvs: bool, // keep submit_sm TLVs on the queued message
+    #[serde(default)]
+    pub capture_pdus: bool, // record raw PDU bytes per connection for /api/sessions/{id}/capture
+    #[serde(default)]
+    pub async_message_id: bool, // submit_sm_resp gets a provisional ID, the DR a final one
+    #[serde(default)]
+    pub gsm7_packing: bool, // GSM 7-bit packed septets for the default coding: packed in DeliverSm, unpacked for display
+    #[serde(default, alias = "bind_resp_system_id")]
+    pub smsc_system_id: Option<String>, // SMSC identity returned in bind responses; unset = system_id
+    #[serde(default)]
+    pub max_tlvs: usize, // max TLVs accepted on submit_sm, 0 = unlimited
+    #[serde(default)]
+    pub max_tlv_bytes: usize, // max total encoded TLV bytes on submit_sm, 0 = unlimited
+    #[serde(default = "default_max_sm_length")]
+    pub max_sm_length: usize, // longest short_message accepted without message_payload before ESME_RINVMSGLEN
+    #[serde(default)]
+    pub max_submit_per_second: u32, // per-session submit_sm rate before ESME_RTHROTTLED, 0 = unlimited
+    #[serde(default)]
+    pub max_window_size: usize, // max accepted submit_sm awaiting their resp before ESME_RMSGQFULL, 0 = unlimited
+    #[serde(default)]
+    pub congestion_throttle_per_second: u32, // per-session submit rate reported as congestion_state 100 and answered ESME_RTHROTTLED, 0 = no congestion model
+    #[serde(default = "default_congestion_onset_percent")]
+    pub congestion_onset_percent: u8, // load (% of congestion_throttle_per_second) from which submit_sm_resp carries congestion_state
+    #[serde(default = "default_congestion_window_ms")]
+    pub congestion_window_ms: u64, // sliding window the congestion model measures the submit rate over
+    #[serde(default)]
+    pub dedup_window_ms: u64, // identical submit_sm within this window return the original message_id, 0 = off
+    #[serde(default)]
+    pub corrupt_response_percent: u8, // CHAOS: % of responses sent with a damaged encoding, 0 = off
+    #[serde(default)]
+    pub fire_and_forget: bool, // drop submit_sm_resp to simulate a one-way link
+    #[serde(default)]
+    pub drop_response_percent: u8, // CHAOS: % of accepted submit_sm answered with no response at all, 0 = off
+    #[serde(default = "default_true")]
+    pub drop_response_queue: bool, // still queue (and later receipt) a submit whose response was dropped
+    #[serde(default)]
+    pub response_delay_ms: u64, // wait before writing each response, 0 = immediate
+    #[serde(default)]
+    pub response_delays: HashMap<String, u64>, // per-response overrides of response_delay_ms, e.g. submit_sm_resp = 2000
+    #[serde(default)]
+    pub force_bind_failure: bool, // answer every bind with ESME_RBINDFAIL, even with valid credentials
+    #[serde(default)]
+    pub enquire_link_drop_percent: u8, // CHAOS: % of enquire_link left unanswered, 0 = off
+    #[serde(default)]
+    pub enquire_link_delay_ms: u64, // wait before answering enquire_link, 0 = immediate
+    #[serde(default)]
+    pub bind_delay_ms: u64, // wait before answering a bind, on top of response_delay_ms, 0 = immediate
+    #[serde(default = "default_tcp_keepalive_secs")]
+    pub tcp_keepalive_secs: u64, // idle time before TCP keepalive probes, and the gap between them, 0 = off
+    #[serde(default = "default_true")]
+    pub tcp_nodelay: bool, // send each PDU at once instead of batching small writes (Nagle)
+    #[serde(default)]
+    pub strict_replace_if_present: bool, // replace_if_present drops every pending message to the same source/dest and queues the new one under its own ID
+    #[serde(default)]
+    pub submit_errors: Vec<SubmitErrorRule>, // fixed error statuses for matching destinations, first match wins
+    #[serde(default)]
+    pub outbind_enabled: bool, // dial the configured [[outbind]] targets instead of only listening
+    #[serde(default)]
+    pub tls: TlsConfig,
+}
+
+impl SmppConfig {
+    /// Plain SMPP ports to listen on: `ports` when set, otherwise the single `port`
+    pub fn listen_ports(&self) -> Vec<u16> {
+        if self.ports.is_empty() {
+            return vec![self.port];
+        }
+        let mut ports = self.ports.clone();
+        ports.sort_unstable();
+        ports.dedup();
+        ports
+    }
+
+    /// Check every `submit_errors` rule has a valid pattern and a known status, so a typo is
+    /// reported when the config is loaded rather than on each matching submit
+    pub fn validate(&self) -> Result<(), String> {
+        for rule in &self.submit_errors {
+            if let Err(e) = regex::Regex::new(&rule.dest_pattern) {
+                return Err(format!("submit error pattern {:?} is not a valid regex: {}", rule.dest_pattern, e));
+            }
+            if rule.command_status().is_none() {
+                return Err(format!("submit error rule for {:?} has unknown status {:?}", rule.dest_pattern, rule.status));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Answer submit_sm to destinations matching `dest_pattern` with `status` instead of accepting them
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct SubmitErrorRule {
+    pub dest_pattern: String, // regex, e.g. "^999"
+    pub status: String,       // "ESME_RINVDSTADR", "EsmeRsubmitfail" or a number such as "0x45"
+    /// `dest_pattern` compiled on first use; `None` if it is not a valid regex
+    #[serde(skip)]
+    compiled: std::sync::OnceLock<Option<regex::Regex>>,
+    /// `status` parsed on first use, which `SmppConfig::validate` forces at load
+    #[serde(skip)]
+    command_status: std::sync::OnceLock<Option<CommandStatus>>,
+}
+
+impl SubmitErrorRule {
+    pub fn new(dest_pattern: &str, status: &str) -> Self {
+        Self {
+            dest_pattern: dest_pattern.to_string(),
+            status: status.to_string(),
+            compiled: std::sync::OnceLock::new(),
+            command_status: std::sync::OnceLock::new(),
+        }
+    }
+
+    /// The status to answer with; `None` if `status` is not a known name or a number
+    pub fn command_status(&self) -> Option<CommandStatus> {
+        *self.command_status.get_or_init(|| parse_command_status(&self.status))
+    }
+
+    /// Whether the rule applies to `dest_addr`
+    pub fn matches(&self, dest_addr: &str) -> bool {
+        let regex = self.compiled.get_or_init(|| match regex::Regex::new(&self.dest_pattern) {
+            Ok(regex) => Some(regex),
+            Err(e) => {
+                tracing::warn!("Invalid submit error pattern {:?}: {}", self.dest_pattern, e);
+                None
+            }
+        });
+        regex.as_ref().is_some_and(|regex| regex.is_match(dest_addr))
+    }
+}
+
+/// Parse a command_status by name ("ESME_RINVDSTADR", "EsmeRinvdstadr", case-insensitive)
+/// or number ("0x0B", "11")
+pub fn parse_command_status(s: &str) -> Option<CommandStatus> {
+    let s = s.trim();
+    let number = match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+        None => s.parse::<u32>().ok(),
+    };
+    if let Some(number) = number {
+        return Some(CommandStatus::from(number));
+    }
+    // Named statuses all sit below 0x200; match their variant names without underscores
+    let wanted = s.replace('_', "").to_ascii_lowercase();
+    (0..0x200u32)
+        .map(CommandStatus::from)
+        .filter(|status| !matches!(status, CommandStatus::Other(_)))
+        .find(|status| format!("{:?}", status).to_ascii_lowercase() == wanted)
+}
+
+/// An ESME the simulator connects to and sends `outbind`, prompting it to bind_receiver
+#[derive(Debug, Deserialize, Clone)]
+pub struct OutbindTarget {
+    pub host: String,
+    pub port: u16,
+    pub system_id: String, // sent in the outbind PDU to identify the SMSC
+    #[serde(default)]
+    pub password: String,
+    #[serde(default = "default_outbind_retry_ms")]
+    pub retry_interval_ms: u64, // wait before redialling after the connection ends
+}
+
+fn default_outbind_retry_ms() -> u64 {
+    5000
+}
+
+/// SMPP over TLS listener, run alongside the plaintext one
+#[derive(Debug, Deserialize, Clone)]
+pub struct TlsConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default)]
+    pub cert_path: String, // PEM certificate chain
+    #[serde(default)]
+    pub key_path: String,  // PEM private key
+    #[serde(default = "default_tls_port")]
+    pub port: u16,
+    #[serde(default)]
+    pub require_client_cert: bool, // mTLS: client cert CN must match the bind system_id
+    #[serde(default)]
+    pub ca_path: Option<String>,   // PEM CA bundle used to verify client certs
+}
+
+fn default_tls_port() -> u16 {
+    3550
+}
+
+impl Default for TlsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            cert_path: String::new(),
+            key_path: String::new(),
+            port: default_tls_port(),
+            require_client_cert: false,
+            ca_path: None,
+        }
+    }
+}
+
+fn default_smpp_host() -> String {
+    "0.0.0.0".to_string()
 }
 
 fn default_smpp_version() -> String {
     "5.0".to_string()
 }
 
+fn default_message_id_format() -> String {
+    "hex".to_string()
+}
+
+fn default_max_sm_length() -> usize {
+    254
+}
+
+fn default_bind_failure_window_ms() -> u64 {
+    60_000
+}
+
+fn default_lockout_duration_ms() -> u64 {
+    60_000
+}
+
+fn default_congestion_onset_percent() -> u8 {
+    50
+}
+
+fn default_congestion_window_ms() -> u64 {
+    1000
+}
+
+fn default_tcp_keepalive_secs() -> u64 {
+    60
+}
+
+fn default_true() -> bool {
+    true
+}
+
+impl Default for SmppConfig {
+    fn default() -> Self {
+        Self {
+            system_id: "smppclient1".to_string(),
+            password: "password".to_string(),
+            host: default_smpp_host(),
+            port: 2775,
+            ports: vec![],
+            max_sessions: 50,
+            accounts: vec![],
+            version: default_smpp_version(),
+            message_id_format: default_message_id_format(),
+            min_reconnect_interval_ms: 0,
+            max_bind_failures: 0,
+            bind_failure_window_ms: default_bind_failure_window_ms(),
+            lockout_duration_ms: default_lockout_duration_ms(),
+            capture_tlvs: true,
+            capture_pdus: false,
+            async_message_id: false,
+            gsm7_packing: false,
+            smsc_system_id: None,
+            max_tlvs: 0,
+            max_tlv_bytes: 0,
+            max_sm_length: default_max_sm_length(),
+            max_submit_per_second: 0,
+            max_window_size: 0,
+            congestion_throttle_per_second: 0,
+            congestion_onset_percent: default_congestion_onset_percent(),
+            congestion_window_ms: default_congestion_window_ms(),
+            dedup_window_ms: 0,
+            corrupt_response_percent: 0,
+            fire_and_forget: false,
+            drop_response_percent: 0,
+            drop_response_queue: true,
+            response_delay_ms: 0,
+            response_delays: HashMap::new(),
+            force_bind_failure: false,
+            enquire_link_drop_percent: 0,
+            enquire_link_delay_ms: 0,
+            bind_delay_ms: 0,
+            tcp_keepalive_secs: default_tcp_keepalive_secs(),
+            tcp_nodelay: true,
+            strict_replace_if_present: false,
+            submit_errors: vec![],
+            outbind_enabled: false,
+            tls: TlsConfig::default(),
+        }
+    }
+}
+
 #[derive(Debug, Deserialize, Clone)]
 pub struct SmppAccount {
     pub system_id: String,
     pub password: String,
+    /// Bind types this account may use ("transmitter", "receiver", "transceiver"); empty allows all
+    #[serde(default)]
+    pub allowed_bind_types: Vec<String>,
+    /// Per-account submit_sm rate limit; `None` is unlimited
+    #[serde(default)]
+    pub max_submit_per_second: Option<u32>,
+    /// Receipt text template for this account's messages; falls back to `lifecycle.dr_template`
+    #[serde(default)]
+    pub dr_template: Option<String>,
+}
+
+impl SmppAccount {
+    /// An unrestricted account with the given credentials
+    pub fn new(system_id: &str, password: &str) -> Self {
+        Self {
+            system_id: system_id.to_string(),
+            password: password.to_string(),
+            allowed_bind_types: vec![],
+            max_submit_per_second: None,
+            dr_template: None,
+        }
+    }
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -50,7 +421,93 @@ pub struct LifecycleConfig {
     pub percent_undeliverable: u8,
     pub percent_accepted: u8,
     pub percent_rejected: u8,
-    pub delivery_receipt_tlv: Option<String>,
+    #[serde(default)]
+    pub percent_deleted: u8,
+    #[serde(default)]
+    pub percent_unknown: u8,
+    pub delivery_receipt_tlv: Option<String>, // "tag:hexvalue" TLV added to every receipt, e.g. "0x1401:4F4B"
+    #[serde(default)]
+    pub echo_tlvs: Vec<u16>,           // submit_sm TLV tags copied onto the message's delivery receipts
+    pub error_code_undeliverable: u16, // err:NNN reported for UNDELIV
+    pub error_code_rejected: u16,      // err:NNN reported for REJECTD
+    pub batch_dr_sends: bool,          // group DRs per session and flush the socket once per batch
+    #[serde(default)]
+    pub intermediate_receipts: bool,   // send a stat:ENROUTE receipt halfway through the time en route
+    #[serde(default)]
+    pub sme_acks: bool,                // send the SME delivery/user acks a submit's registered_delivery asks for
+    #[serde(default)]
+    pub dr_retry_count: u32,           // re-sends of a DR the client has not answered with deliver_sm_resp (0 = never)
+    #[serde(default = "default_dr_retry_interval_ms")]
+    pub dr_retry_interval_ms: u64,     // how long to wait for deliver_sm_resp before each re-send
+    pub clock_skew_ms: i64,            // offset applied to receipt submit/done dates (may be negative)
+    pub dr_template: Option<String>,   // receipt text template, see lifecycle::DEFAULT_DR_TEMPLATE
+    #[serde(default)]
+    pub delay_distribution: DelayDistribution, // per-message time en route before the final state
+    #[serde(default)]
+    pub rules: Vec<LifecycleRule>,     // per-destination overrides, first matching prefix wins
+}
+
+fn default_dr_retry_interval_ms() -> u64 {
+    10000
+}
+
+/// Final-state behaviour for destinations starting with `dest_prefix` (and submitted with
+/// `service_type`, when set): either a forced `state`, or percentages used instead of the global ones
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct LifecycleRule {
+    #[serde(default)]
+    pub dest_prefix: String,
+    #[serde(default)]
+    pub service_type: Option<String>, // e.g. "VMN"; matches the submit_sm service_type exactly
+    #[serde(default)]
+    pub state: Option<String>, // e.g. "undeliverable" or "UNDELIV"
+    #[serde(default)]
+    pub percent_delivered: u8,
+    #[serde(default)]
+    pub percent_undeliverable: u8,
+    #[serde(default)]
+    pub percent_accepted: u8,
+    #[serde(default)]
+    pub percent_rejected: u8,
+    #[serde(default)]
+    pub percent_deleted: u8,
+    #[serde(default)]
+    pub percent_unknown: u8,
+}
+
+/// How long each message stays en route, drawn once at submit time
+#[derive(Debug, Deserialize, Clone, PartialEq, Default)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum DelayDistribution {
+    /// Every message takes `max_time_enroute_ms`
+    #[default]
+    Fixed,
+    /// Uniformly between `min_ms` and `max_ms`
+    Uniform { min_ms: u64, max_ms: u64 },
+    /// Exponentially distributed with mean `mean_ms`
+    Exponential { mean_ms: u64 },
+}
+
+impl LifecycleConfig {
+    /// Check the state odds: the global percentages, and those of each rule without a fixed
+    /// `state`, must add up to 100
+    pub fn validate(&self) -> Result<(), String> {
+        let total = [self.percent_delivered, self.percent_undeliverable, self.percent_accepted,
+            self.percent_rejected, self.percent_deleted, self.percent_unknown]
+            .iter().map(|&p| u32::from(p)).sum::<u32>();
+        if total != 100 {
+            return Err(format!("lifecycle percentages add up to {}, not 100", total));
+        }
+        for rule in self.rules.iter().filter(|rule| rule.state.is_none()) {
+            let total = [rule.percent_delivered, rule.percent_undeliverable, rule.percent_accepted,
+                rule.percent_rejected, rule.percent_deleted, rule.percent_unknown]
+                .iter().map(|&p| u32::from(p)).sum::<u32>();
+            if total != 100 {
+                return Err(format!("lifecycle rule for prefix {:?} has percentages adding up to {}, not 100", rule.dest_prefix, total));
+            }
+        }
+        Ok(())
+    }
 }
 
 impl Default for LifecycleConfig {
@@ -63,7 +520,21 @@ impl Default for LifecycleConfig {
             percent_undeliverable: 6,
             percent_accepted: 2,
             percent_rejected: 2,
+            percent_deleted: 0,
+            percent_unknown: 0,
             delivery_receipt_tlv: None,
+            echo_tlvs: vec![],
+            error_code_undeliverable: 1,
+            error_code_rejected: 8,
+            batch_dr_sends: false,
+            intermediate_receipts: false,
+            sme_acks: false,
+            dr_retry_count: 0,
+            dr_retry_interval_ms: default_dr_retry_interval_ms(),
+            clock_skew_ms: 0,
+            dr_template: None,
+            delay_distribution: DelayDistribution::Fixed,
+            rules: vec![],
         }
     }
 }
@@ -73,6 +544,22 @@ pub struct MoServiceConfig {
     pub enabled: bool,
     pub delivery_messages_per_minute: u32,
     pub file_path: String,
+    #[serde(default = "default_true", rename = "loop")]
+    pub loop_file: bool,        // replay file_path forever; false = dispatch it once and stop
+    #[serde(default)]
+    pub prefer: Option<String>, // "receiver" or "transceiver" when both match; unset = first match
+    #[serde(default = "default_max_parked")]
+    pub max_parked: usize,      // MO messages kept while no receiver matches (0 = drop them)
+    #[serde(default = "default_parked_max_age_ms")]
+    pub parked_max_age_ms: u64, // parked MO messages older than this are discarded
+}
+
+fn default_max_parked() -> usize {
+    1000
+}
+
+fn default_parked_max_age_ms() -> u64 {
+    300_000
 }
 
 impl Default for MoServiceConfig {
@@ -81,6 +568,10 @@ impl Default for MoServiceConfig {
             enabled: false,
             delivery_messages_per_minute: 0,
             file_path: "deliver_messages.csv".to_string(),
+            loop_file: true,
+            prefer: None,
+            max_parked: default_max_parked(),
+            parked_max_age_ms: default_parked_max_age_ms(),
         }
     }
 }
@@ -88,24 +579,101 @@ impl Default for MoServiceConfig {
 #[derive(Debug, Deserialize, Clone)]
 pub struct LogConfig {
     pub level: String,
+    #[serde(default)]
+    pub format: LogFormat, // console and web log stream: "text" or "json" (one object per line)
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self {
+            level: "info".to_string(),
+            format: LogFormat::Text,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// `[LEVEL] target: message`
+    #[default]
+    Text,
+    /// JSON objects with level, target, message, timestamp and fields
+    Json,
+}
+
+/// The `--config <path>` (or `--config=<path>`, `-c <path>`) command line argument, if given.
+/// `args` excludes the program name.
+pub fn config_path_from_args(mut args: impl Iterator<Item = String>) -> Result<Option<PathBuf>, String> {
+    let mut path = None;
+    while let Some(arg) = args.next() {
+        if let Some(value) = arg.strip_prefix("--config=") {
+            path = Some(PathBuf::from(value));
+        } else if arg == "--config" || arg == "-c" {
+            let value = args.next().ok_or_else(|| format!("{} needs a file path", arg))?;
+            path = Some(PathBuf::from(value));
+        } else {
+            return Err(format!("unknown argument: {}", arg));
+        }
+    }
+    Ok(path)
 }
 
 impl AppConfig {
-    pub fn new() -> Result<Self, ConfigError> {
+    /// Load defaults, then `config_path` if given (it must exist) or else the optional
+    /// `config` / `config.{RUN_MODE}` files in the working directory, then the environment
+    pub fn new(config_path: Option<&Path>) -> Result<Self, ConfigError> {
         let run_mode = env::var("RUN_MODE").unwrap_or_else(|_| "development".into());
 
-        let s = Config::builder()
+        let builder = Config::builder()
             // Start with default values
             .set_default("server.host", "0.0.0.0")?
             .set_default("server.port", 8080)?
+            .set_default("smpp.host", "0.0.0.0")?
             .set_default("smpp.port", 2775)?
             .set_default("smpp.system_id", "smppclient1")?
             .set_default("smpp.password", "password")?
             .set_default("smpp.max_sessions", 50)?
             .set_default("smpp.version", "5.0")?
+            .set_default("smpp.message_id_format", "hex")?
+            .set_default("smpp.min_reconnect_interval_ms", 0)?
+            .set_default("smpp.max_bind_failures", 0)?
+            .set_default("smpp.bind_failure_window_ms", 60_000)?
+            .set_default("smpp.lockout_duration_ms", 60_000)?
+            .set_default("smpp.capture_tlvs", true)?
+            .set_default("smpp.capture_pdus", false)?
+            .set_default("smpp.async_message_id", false)?
+            .set_default("smpp.gsm7_packing", false)?
+            .set_default("smpp.max_tlvs", 0)?
+            .set_default("smpp.max_tlv_bytes", 0)?
+            .set_default("smpp.max_sm_length", 254)?
+            .set_default("smpp.max_submit_per_second", 0)?
+            .set_default("smpp.max_window_size", 0)?
+            .set_default("smpp.congestion_throttle_per_second", 0)?
+            .set_default("smpp.congestion_onset_percent", 50)?
+            .set_default("smpp.congestion_window_ms", 1000)?
+            .set_default("smpp.dedup_window_ms", 0)?
+            .set_default("smpp.corrupt_response_percent", 0)?
+            .set_default("smpp.fire_and_forget", false)?
+            .set_default("smpp.drop_response_percent", 0)?
+            .set_default("smpp.drop_response_queue", true)?
+            .set_default("smpp.response_delay_ms", 0)?
+            .set_default("smpp.force_bind_failure", false)?
+            .set_default("smpp.bind_delay_ms", 0)?
+            .set_default("smpp.tcp_keepalive_secs", 60)?
+            .set_default("smpp.tcp_nodelay", true)?
+            .set_default("smpp.strict_replace_if_present", false)?
+            .set_default("smpp.enquire_link_drop_percent", 0)?
+            .set_default("smpp.enquire_link_delay_ms", 0)?
+            .set_default("smpp.outbind_enabled", false)?
+            .set_default("smpp.tls.enabled", false)?
+            .set_default("smpp.tls.port", 3550)?
+            .set_default("smpp.tls.require_client_cert", false)?
             .set_default("log.level", "info")?
+            .set_default("log.format", "text")?
             
             // Lifecycle defaults
+            .set_default("shutdown.drain_timeout_ms", 5000)?
             .set_default("lifecycle.message_state_check_frequency_ms", 5000)?
             .set_default("lifecycle.max_time_enroute_ms", 10000)?
             .set_default("lifecycle.discard_from_queue_after_ms", 60000)?
@@ -113,16 +681,34 @@ impl AppConfig {
             .set_default("lifecycle.percent_undeliverable", 6)?
             .set_default("lifecycle.percent_accepted", 2)?
             .set_default("lifecycle.percent_rejected", 2)?
+            .set_default("lifecycle.percent_deleted", 0)?
+            .set_default("lifecycle.percent_unknown", 0)?
+            .set_default("lifecycle.error_code_undeliverable", 1)?
+            .set_default("lifecycle.error_code_rejected", 8)?
+            .set_default("lifecycle.batch_dr_sends", false)?
+            .set_default("lifecycle.intermediate_receipts", false)?
+            .set_default("lifecycle.sme_acks", false)?
+            .set_default("lifecycle.dr_retry_count", 0)?
+            .set_default("lifecycle.dr_retry_interval_ms", 10000)?
+            .set_default("lifecycle.clock_skew_ms", 0)?
 
              // MO Service defaults
             .set_default("mo_service.enabled", false)?
             .set_default("mo_service.delivery_messages_per_minute", 0)?
             .set_default("mo_service.file_path", "deliver_messages.csv")?
+            .set_default("mo_service.loop", true)?
+            .set_default("mo_service.max_parked", 1000)?
+            .set_default("mo_service.parked_max_age_ms", 300_000)?;
 
-            // Add configuration file
-            .add_source(File::with_name("config").required(false))
-            .add_source(File::with_name(&format!("config.{}", run_mode)).required(false))
-            
+        // Add configuration file
+        let builder = match config_path {
+            Some(path) => builder.add_source(File::from(path)),
+            None => builder
+                .add_source(File::with_name("config").required(false))
+                .add_source(File::with_name(&format!("config.{}", run_mode)).required(false)),
+        };
+
+        let s = builder
             // Add environment variables (e.g., SMPP_SERVER_PORT=8080)
             // Add environment variables (prefix with SMPP__)
             .add_source(Environment::with_prefix("SMPP").separator("__"))
@@ -130,16 +716,105 @@ impl AppConfig {
             // Allow explicit overrides for documented env vars
             .set_override_option("server.host", env::var("SERVER_HOST").ok())?
             .set_override_option("server.port", env::var("SERVER_PORT").ok().map(|v| v.parse::<u16>().unwrap_or(8080)))?
+            .set_override_option("server.auth.username", env::var("SERVER_AUTH_USERNAME").ok())?
+            .set_override_option("server.auth.password", env::var("SERVER_AUTH_PASSWORD").ok())?
+            .set_override_option("server.auth.api_token", env::var("SERVER_AUTH_API_TOKEN").ok())?
+            .set_override_option("smpp.host", env::var("SMPP_HOST").ok())?
             .set_override_option("smpp.port", env::var("SMPP_PORT").ok().map(|v| v.parse::<u16>().unwrap_or(2775)))?
+            .set_override_option("smpp.ports", env::var("SMPP_PORTS").ok().map(|v| v.split(',').filter_map(|p| p.trim().parse::<u16>().ok()).map(i64::from).collect::<Vec<_>>()))?
             .set_override_option("smpp.system_id", env::var("SMPP_SYSTEM_ID").ok())?
             .set_override_option("smpp.password", env::var("SMPP_PASSWORD").ok())?
+            .set_override_option("smpp.smsc_system_id", env::var("SMPP_SMSC_SYSTEM_ID").ok())?
+            .set_override_option("smpp.message_id_format", env::var("SMPP_MESSAGE_ID_FORMAT").ok())?
             .set_override_option("smpp.version", env::var("SMPP_VERSION").ok())?
+            .set_override_option("smpp.max_window_size", env::var("SMPP_MAX_WINDOW_SIZE").ok().map(|v| v.parse::<u64>().unwrap_or(0)))?
+            .set_override_option("smpp.congestion_throttle_per_second", env::var("SMPP_CONGESTION_THROTTLE_PER_SECOND").ok().map(|v| v.parse::<u32>().unwrap_or(0)))?
+            .set_override_option("smpp.fire_and_forget", env::var("SMPP_FIRE_AND_FORGET").ok().map(|v| v.eq_ignore_ascii_case("true") || v == "1"))?
+            .set_override_option("smpp.max_bind_failures", env::var("SMPP_MAX_BIND_FAILURES").ok().map(|v| v.parse::<u32>().unwrap_or(0)))?
+            .set_override_option("smpp.force_bind_failure", env::var("SMPP_FORCE_BIND_FAILURE").ok().map(|v| v.eq_ignore_ascii_case("true") || v == "1"))?
+            .set_override_option("smpp.bind_delay_ms", env::var("SMPP_BIND_DELAY_MS").ok().map(|v| v.parse::<u64>().unwrap_or(0)))?
+            .set_override_option("smpp.tcp_keepalive_secs", env::var("SMPP_TCP_KEEPALIVE_SECS").ok().map(|v| v.parse::<u64>().unwrap_or(60)))?
+            .set_override_option("smpp.tcp_nodelay", env::var("SMPP_TCP_NODELAY").ok().map(|v| v.eq_ignore_ascii_case("true") || v == "1"))?
+            .set_override_option("smpp.strict_replace_if_present", env::var("SMPP_STRICT_REPLACE_IF_PRESENT").ok().map(|v| v.eq_ignore_ascii_case("true") || v == "1"))?
+            .set_override_option("smpp.enquire_link_drop_percent", env::var("SMPP_ENQUIRE_LINK_DROP_PERCENT").ok().map(|v| v.parse::<u8>().unwrap_or(0)))?
+            .set_override_option("smpp.enquire_link_delay_ms", env::var("SMPP_ENQUIRE_LINK_DELAY_MS").ok().map(|v| v.parse::<u64>().unwrap_or(0)))?
+            .set_override_option("smpp.drop_response_percent", env::var("SMPP_DROP_RESPONSE_PERCENT").ok().map(|v| v.parse::<u8>().unwrap_or(0)))?
+            .set_override_option("smpp.response_delay_ms", env::var("SMPP_RESPONSE_DELAY_MS").ok().map(|v| v.parse::<u64>().unwrap_or(0)))?
+            .set_override_option("smpp.corrupt_response_percent", env::var("SMPP_CORRUPT_RESPONSE_PERCENT").ok().map(|v| v.parse::<u8>().unwrap_or(0)))?
             .set_override_option("log.level", env::var("LOG_LEVEL").ok())?
+            .set_override_option("log.format", env::var("LOG_FORMAT").ok())?
             .set_override_option("lifecycle.max_time_enroute_ms", env::var("LIFECYCLE_MAX_TIME_ENROUTE_MS").ok().map(|v| v.parse::<u64>().unwrap_or(10000)))?
             .set_override_option("lifecycle.percent_delivered", env::var("LIFECYCLE_PERCENT_DELIVERED").ok().map(|v| v.parse::<u8>().unwrap_or(90)))?
+            .set_override_option("lifecycle.batch_dr_sends", env::var("LIFECYCLE_BATCH_DR_SENDS").ok().map(|v| v.eq_ignore_ascii_case("true") || v == "1"))?
+            .set_override_option("lifecycle.sme_acks", env::var("LIFECYCLE_SME_ACKS").ok().map(|v| v.eq_ignore_ascii_case("true") || v == "1"))?
+            .set_override_option("lifecycle.clock_skew_ms", env::var("LIFECYCLE_CLOCK_SKEW_MS").ok().map(|v| v.parse::<i64>().unwrap_or(0)))?
+            .set_override_option("audit.file_path", env::var("AUDIT_FILE_PATH").ok())?
+            .set_override_option("shutdown.drain_timeout_ms", env::var("SHUTDOWN_DRAIN_TIMEOUT_MS").ok().map(|v| v.parse::<u64>().unwrap_or(5000)))?
             
             .build()?;
 
-        s.try_deserialize()
+        let config: AppConfig = s.try_deserialize()?;
+        config.smpp.validate().map_err(ConfigError::Message)?;
+        config.lifecycle.validate().map_err(ConfigError::Message)?;
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(list: &[&str]) -> impl Iterator<Item = String> {
+        list.iter().map(|s| s.to_string()).collect::<Vec<_>>().into_iter()
+    }
+
+    #[test]
+    fn test_config_path_from_args() {
+        assert_eq!(config_path_from_args(args(&[])), Ok(None));
+        assert_eq!(config_path_from_args(args(&["--config", "/etc/smpp-sim/config.toml"])), Ok(Some(PathBuf::from("/etc/smpp-sim/config.toml"))));
+        assert_eq!(config_path_from_args(args(&["--config=a.toml"])), Ok(Some(PathBuf::from("a.toml"))));
+        assert_eq!(config_path_from_args(args(&["-c", "b.toml"])), Ok(Some(PathBuf::from("b.toml"))));
+        assert!(config_path_from_args(args(&["--config"])).is_err());
+        assert!(config_path_from_args(args(&["--verbose"])).is_err());
+    }
+
+    #[test]
+    fn test_load_from_explicit_path() {
+        let path = env::temp_dir().join(format!("smpp-sim-config-{}.toml", std::process::id()));
+        std::fs::write(&path, "[smpp]\nsystem_id = \"from_file\"\nmax_sessions = 7\n").unwrap();
+        let config = AppConfig::new(Some(&path));
+        std::fs::remove_file(&path).unwrap();
+        let config = config.unwrap();
+        assert_eq!(config.smpp.max_sessions, 7);
+        assert_eq!(config.lifecycle.percent_delivered, 90, "Defaults still fill in the rest");
+
+        // Unlike the default search, an explicit file has to exist
+        assert!(AppConfig::new(Some(Path::new("/nonexistent/smpp-sim.toml"))).is_err());
+    }
+
+    #[test]
+    fn test_submit_errors_validated_at_load() {
+        let path = env::temp_dir().join(format!("smpp-sim-submit-errors-{}.toml", std::process::id()));
+        std::fs::write(&path, "[[smpp.submit_errors]]\ndest_pattern = \"^999\"\nstatus = \"ESME_RNOSUCHTHING\"\n").unwrap();
+        let config = AppConfig::new(Some(&path));
+        std::fs::remove_file(&path).unwrap();
+        let err = config.unwrap_err().to_string();
+        assert!(err.contains("ESME_RNOSUCHTHING"), "{}", err);
+
+        let smpp = SmppConfig { submit_errors: vec![SubmitErrorRule::new("^999", "0x0B")], ..Default::default() };
+        assert!(smpp.validate().is_ok());
+        assert_eq!(smpp.submit_errors[0].command_status(), Some(CommandStatus::EsmeRinvdstadr));
+        let smpp = SmppConfig { submit_errors: vec![SubmitErrorRule::new("^(999", "0x0B")], ..Default::default() };
+        assert!(smpp.validate().is_err());
+    }
+
+    #[test]
+    fn test_lifecycle_validated_at_load() {
+        let path = env::temp_dir().join(format!("smpp-sim-lifecycle-{}.toml", std::process::id()));
+        std::fs::write(&path, "[lifecycle]\npercent_delivered = 50\n").unwrap();
+        let config = AppConfig::new(Some(&path));
+        std::fs::remove_file(&path).unwrap();
+        let err = config.unwrap_err().to_string();
+        assert!(err.contains("lifecycle percentages add up to"), "{}", err);
     }
 }
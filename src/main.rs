@@ -1,6 +1,7 @@
-use rust_smpp_sim::config::AppConfig;
+use rust_smpp_sim::config::{config_path_from_args, AppConfig, LogFormat};
+use rust_smpp_sim::smpp::audit::AuditLog;
 use rust_smpp_sim::smpp::session::SessionManager;
-use rust_smpp_sim::smpp::queue::MessageQueue;
+use rust_smpp_sim::smpp::queue::{MessageQueue, MessageIdFormat};
 use rust_smpp_sim::web::{LogBuffer, LogBufferLayer};
 use rust_smpp_sim::{smpp, web};
 use dotenvy::dotenv;
@@ -13,8 +14,15 @@ use std::sync::Arc;
 async fn main() -> std::io::Result<()> {
     dotenv().ok();
 
-    // Initialize configuration
-    let config = match AppConfig::new() {
+    // Initialize configuration, from `--config <path>` when given
+    let config_path = match config_path_from_args(std::env::args().skip(1)) {
+        Ok(path) => path,
+        Err(e) => {
+            eprintln!("{}\nUsage: rust-smpp-sim [--config <path>]", e);
+            std::process::exit(2);
+        }
+    };
+    let config = match AppConfig::new(config_path.as_deref()) {
         Ok(cfg) => cfg,
         Err(e) => {
             eprintln!("Failed to load configuration: {}", e);
@@ -26,10 +34,12 @@ async fn main() -> std::io::Result<()> {
     let log_buffer = LogBuffer::new();
 
     // Initialize logging with custom layer for web UI
+    let json_logs = config.log.format == LogFormat::Json;
     tracing_subscriber::registry()
-        .with(tracing_subscriber::fmt::layer())
+        .with((!json_logs).then(tracing_subscriber::fmt::layer))
+        .with(json_logs.then(|| tracing_subscriber::fmt::layer().json()))
         .with(tracing_subscriber::EnvFilter::new(format!("{},{}", config.log.level, "actix_web=info")))
-        .with(LogBufferLayer::new(log_buffer.clone()))
+        .with(LogBufferLayer::new(log_buffer.clone()).with_format(config.log.format))
         .init();
 
     info!("Starting Rust SMPP Simulator...");
@@ -38,8 +48,30 @@ async fn main() -> std::io::Result<()> {
     // Create shared state
     let config = Arc::new(config);
     let session_manager = Arc::new(SessionManager::new());
-    let message_queue = Arc::new(MessageQueue::new());
+    session_manager.set_force_bind_failure(config.smpp.force_bind_failure);
+    if let Some(path) = &config.audit.file_path {
+        match AuditLog::open(path).await {
+            Ok(log) => {
+                info!("Writing audit records to {}", path);
+                session_manager.set_audit_log(log);
+            }
+            Err(e) => {
+                tracing::error!("Failed to open audit file {}: {}", path, e);
+                std::process::exit(1);
+            }
+        }
+    }
+    let message_id_format = match config.smpp.message_id_format.parse::<MessageIdFormat>() {
+        Ok(format) => format,
+        Err(e) => {
+            tracing::error!("Invalid configuration: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let message_queue = Arc::new(MessageQueue::with_message_id_format(message_id_format));
+    message_queue.set_dedup_window_ms(config.smpp.dedup_window_ms);
     let mo_message_queue = Arc::new(rust_smpp_sim::smpp::queue::MoMessageQueue::new());
+    mo_message_queue.set_park_capacity(config.mo_service.max_parked);
 
     // Start Web Server
     let web_config = config.clone();
@@ -49,7 +81,7 @@ async fn main() -> std::io::Result<()> {
     let web_log_buffer = log_buffer.clone();
     std::thread::spawn(move || {
         let sys = actix_rt::System::new();
-        if let Err(e) = sys.block_on(web::start_web_server(web_config, web_session_manager, web_message_queue, web_mo_queue, web_log_buffer)) {
+        if let Err(e) = sys.block_on(web::start_web_server(web_config, config_path, web_session_manager, web_message_queue, web_mo_queue, web_log_buffer)) {
              tracing::error!("Web server error: {}", e);
         }
     });
@@ -59,11 +91,23 @@ async fn main() -> std::io::Result<()> {
     let smpp_session_manager = session_manager.clone();
     let smpp_message_queue = message_queue.clone();
     let smpp_server = tokio::spawn(async move {
-        if let Err(e) = smpp::server::start_smpp_server(smpp_config, smpp_session_manager, smpp_message_queue).await {
+        let shutdown = async {
+            let _ = tokio::signal::ctrl_c().await;
+            info!("Shutdown requested");
+        };
+        if let Err(e) = smpp::server::run_smpp_server(smpp_config, smpp_session_manager, smpp_message_queue, shutdown).await {
             tracing::error!("SMPP server error: {}", e);
         }
     });
 
+    // Start Outbind Connector (no-op unless smpp.outbind_enabled)
+    let ob_config = config.clone();
+    let ob_session_manager = session_manager.clone();
+    let ob_message_queue = message_queue.clone();
+    let outbind_task = tokio::spawn(async move {
+        smpp::outbind::start_outbind_connector(ob_config, ob_session_manager, ob_message_queue).await;
+    });
+
     // Start Lifecycle Manager
     let lc_config = config.clone();
     let lc_session_manager = session_manager.clone();
@@ -81,8 +125,12 @@ async fn main() -> std::io::Result<()> {
         smpp::mo_service::start_mo_service_task(mo_config, mo_session_manager, mo_queue_service).await;
     });
 
-    // Wait for the servers
-    let _ = tokio::join!(smpp_server, lifecycle_task, mo_task);
+    // Run until the SMPP server has shut down, then stop the background tasks
+    let _ = smpp_server.await;
+    outbind_task.abort();
+    lifecycle_task.abort();
+    mo_task.abort();
+    info!("Shutdown complete");
     
     Ok(())
 }
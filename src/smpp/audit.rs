@@ -0,0 +1,95 @@
+//! Audit trail of submits and deliveries
+//!
+//! With `audit.file_path` set, every accepted submit_sm, every final delivery receipt sent,
+//! every message dropped by a strict replace and every MO deliver_sm is appended to the file as one JSON object per line, for
+//! reconciliation after a test run. Records go through a channel to a background task
+//! that owns a buffered writer, so the SMPP handlers never wait on the disk.
+
+use serde::Serialize;
+use tokio::io::{AsyncWriteExt, BufWriter};
+use tokio::sync::mpsc;
+
+/// What an audit line records
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditEvent {
+    /// A submit_sm was accepted and given a message ID
+    SubmitSm,
+    /// The lifecycle resolved a message and sent its delivery receipt
+    DeliveryReceipt,
+    /// An MO deliver_sm was sent to a session
+    MoDeliverSm,
+    /// A pending message was dropped, without a receipt, by a strict replace_if_present submit
+    Replaced,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AuditRecord {
+    pub timestamp: chrono::DateTime<chrono::Local>,
+    pub event: AuditEvent,
+    pub system_id: String,
+    /// Empty for MO messages
+    pub message_id: String,
+    pub source_addr: String,
+    pub dest_addr: String,
+    /// Final DR stat (DELIVRD, UNDELIV, ...) for delivery receipts
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub state: Option<String>,
+}
+
+impl AuditRecord {
+    pub fn new(event: AuditEvent, system_id: &str, message_id: &str, source_addr: &str, dest_addr: &str) -> Self {
+        Self {
+            timestamp: chrono::Local::now(),
+            event,
+            system_id: system_id.to_string(),
+            message_id: message_id.to_string(),
+            source_addr: source_addr.to_string(),
+            dest_addr: dest_addr.to_string(),
+            state: None,
+        }
+    }
+
+    pub fn with_state(mut self, state: &str) -> Self {
+        self.state = Some(state.to_string());
+        self
+    }
+}
+
+/// Handle to the audit file writer
+pub struct AuditLog {
+    tx: mpsc::UnboundedSender<AuditRecord>,
+}
+
+impl AuditLog {
+    /// Open `path` for appending and start the writer task. Must be called within a Tokio runtime.
+    pub async fn open(path: &str) -> std::io::Result<Self> {
+        let file = tokio::fs::OpenOptions::new().create(true).append(true).open(path).await?;
+        let (tx, mut rx) = mpsc::unbounded_channel::<AuditRecord>();
+        let path = path.to_string();
+        tokio::spawn(async move {
+            let mut writer = BufWriter::new(file);
+            while let Some(record) = rx.recv().await {
+                // Write everything already queued, then flush once
+                let mut next = Some(record);
+                while let Some(record) = next {
+                    let mut line = serde_json::to_string(&record).unwrap_or_default();
+                    line.push('\n');
+                    if let Err(e) = writer.write_all(line.as_bytes()).await {
+                        tracing::error!("Failed to write audit record to {}: {}", path, e);
+                    }
+                    next = rx.try_recv().ok();
+                }
+                if let Err(e) = writer.flush().await {
+                    tracing::error!("Failed to flush audit file {}: {}", path, e);
+                }
+            }
+        });
+        Ok(Self { tx })
+    }
+
+    /// Queue a record; never blocks
+    pub fn record(&self, record: AuditRecord) {
+        let _ = self.tx.send(record);
+    }
+}
@@ -0,0 +1,218 @@
+//! Raw PDU capture for protocol debugging
+//!
+//! With `smpp.capture_pdus` each connection's codec records the bytes of every PDU it
+//! reads or writes into a bounded ring buffer. The capture can be exported as a hex
+//! dump or as a pcap file with synthetic IPv4/TCP framing, which Wireshark's SMPP
+//! dissector decodes like a real packet capture.
+
+use std::collections::VecDeque;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+
+use parking_lot::Mutex;
+
+/// PDUs kept per connection; the oldest are dropped first
+pub const MAX_CAPTURED_PDUS: usize = 1000;
+
+/// Which way a captured PDU travelled
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Direction {
+    /// Read from the client
+    Inbound,
+    /// Written to the client
+    Outbound,
+}
+
+#[derive(Debug, Clone)]
+pub struct CapturedPdu {
+    pub at: chrono::DateTime<chrono::Local>,
+    pub direction: Direction,
+    pub bytes: Vec<u8>,
+}
+
+/// Ring buffer of the PDUs exchanged on one connection
+pub struct PduCapture {
+    /// The client end of the connection
+    peer: SocketAddr,
+    /// The simulator end, as shown in the pcap
+    local: SocketAddr,
+    pdus: Mutex<VecDeque<CapturedPdu>>,
+}
+
+impl PduCapture {
+    pub fn new(peer: SocketAddr, local: SocketAddr) -> Self {
+        Self { peer, local, pdus: Mutex::new(VecDeque::new()) }
+    }
+
+    /// Record the encoded bytes of one PDU
+    pub fn record(&self, direction: Direction, bytes: &[u8]) {
+        let mut pdus = self.pdus.lock();
+        if pdus.len() >= MAX_CAPTURED_PDUS {
+            pdus.pop_front();
+        }
+        pdus.push_back(CapturedPdu { at: chrono::Local::now(), direction, bytes: bytes.to_vec() });
+    }
+
+    /// Captured PDUs, oldest first
+    pub fn pdus(&self) -> Vec<CapturedPdu> {
+        self.pdus.lock().iter().cloned().collect()
+    }
+
+    /// One block per PDU: a summary line then offset, hex and ASCII columns
+    pub fn hex_dump(&self) -> String {
+        let mut out = String::new();
+        for pdu in self.pdus.lock().iter() {
+            let arrow = match pdu.direction {
+                Direction::Inbound => "<<",
+                Direction::Outbound => ">>",
+            };
+            let command_id = header_u32(&pdu.bytes, 4).map_or("?".to_string(), |id| format!("0x{:08X}", id));
+            let sequence = header_u32(&pdu.bytes, 12).map_or("?".to_string(), |seq| seq.to_string());
+            out.push_str(&format!("{} {} command_id={} seq={} ({} bytes)\n",
+                pdu.at.format("%Y-%m-%d %H:%M:%S%.3f"), arrow, command_id, sequence, pdu.bytes.len()));
+            for (line, chunk) in pdu.bytes.chunks(16).enumerate() {
+                let hex: Vec<String> = chunk.iter().map(|b| format!("{:02x}", b)).collect();
+                let ascii: String = chunk.iter()
+                    .map(|&b| if b.is_ascii_graphic() || b == b' ' { b as char } else { '.' })
+                    .collect();
+                out.push_str(&format!("  {:04x}  {:<47}  {}\n", line * 16, hex.join(" "), ascii));
+            }
+            out.push('\n');
+        }
+        out
+    }
+
+    /// A pcap file (LINKTYPE_RAW) with each PDU in its own IPv4/TCP segment.
+    /// TCP sequence numbers advance per direction so Wireshark can reassemble the stream;
+    /// checksums are left at zero. IPv6 endpoints are shown as 127.0.0.1 / 127.0.0.2.
+    pub fn to_pcap(&self) -> Vec<u8> {
+        const LINKTYPE_RAW: u32 = 101;
+        let mut out = Vec::new();
+        // Global header: magic, version 2.4, thiszone, sigfigs, snaplen, linktype
+        out.extend_from_slice(&0xA1B2_C3D4u32.to_le_bytes());
+        out.extend_from_slice(&2u16.to_le_bytes());
+        out.extend_from_slice(&4u16.to_le_bytes());
+        out.extend_from_slice(&0i32.to_le_bytes());
+        out.extend_from_slice(&0u32.to_le_bytes());
+        out.extend_from_slice(&65535u32.to_le_bytes());
+        out.extend_from_slice(&LINKTYPE_RAW.to_le_bytes());
+
+        let client = (ipv4_or(self.peer.ip(), Ipv4Addr::new(127, 0, 0, 2)), self.peer.port());
+        let server = (ipv4_or(self.local.ip(), Ipv4Addr::new(127, 0, 0, 1)), self.local.port());
+        let (mut client_seq, mut server_seq) = (1u32, 1u32);
+
+        for pdu in self.pdus.lock().iter() {
+            // Keep each segment within the IPv4 total length
+            for segment in pdu.bytes.chunks(65535 - 40) {
+                let (src, dst, seq, ack) = match pdu.direction {
+                    Direction::Inbound => (client, server, &mut client_seq, server_seq),
+                    Direction::Outbound => (server, client, &mut server_seq, client_seq),
+                };
+                let packet = tcp_packet(src, dst, *seq, ack, segment);
+                *seq = seq.wrapping_add(segment.len() as u32);
+
+                let micros = pdu.at.timestamp_subsec_micros();
+                out.extend_from_slice(&(pdu.at.timestamp() as u32).to_le_bytes());
+                out.extend_from_slice(&micros.to_le_bytes());
+                out.extend_from_slice(&(packet.len() as u32).to_le_bytes());
+                out.extend_from_slice(&(packet.len() as u32).to_le_bytes());
+                out.extend_from_slice(&packet);
+            }
+        }
+        out
+    }
+}
+
+/// Big-endian u32 at `offset` of a PDU header, if present
+fn header_u32(bytes: &[u8], offset: usize) -> Option<u32> {
+    bytes.get(offset..offset + 4).map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+fn ipv4_or(ip: IpAddr, fallback: Ipv4Addr) -> Ipv4Addr {
+    match ip {
+        IpAddr::V4(v4) if !v4.is_unspecified() => v4,
+        IpAddr::V6(v6) => v6.to_ipv4_mapped().unwrap_or(fallback),
+        _ => fallback,
+    }
+}
+
+/// IPv4 header + TCP header (PSH|ACK) + payload
+fn tcp_packet(src: (Ipv4Addr, u16), dst: (Ipv4Addr, u16), seq: u32, ack: u32, payload: &[u8]) -> Vec<u8> {
+    let total_len = (20 + 20 + payload.len()) as u16;
+    let mut packet = Vec::with_capacity(total_len as usize);
+    // IPv4: version/IHL, DSCP, total length, id, flags (DF), TTL, protocol TCP, checksum
+    packet.extend_from_slice(&[0x45, 0x00]);
+    packet.extend_from_slice(&total_len.to_be_bytes());
+    packet.extend_from_slice(&[0x00, 0x00, 0x40, 0x00, 64, 6, 0x00, 0x00]);
+    packet.extend_from_slice(&src.0.octets());
+    packet.extend_from_slice(&dst.0.octets());
+    let checksum = ipv4_checksum(&packet[..20]);
+    packet[10..12].copy_from_slice(&checksum.to_be_bytes());
+    // TCP: ports, seq, ack, data offset 5, flags PSH|ACK, window, checksum, urgent
+    packet.extend_from_slice(&src.1.to_be_bytes());
+    packet.extend_from_slice(&dst.1.to_be_bytes());
+    packet.extend_from_slice(&seq.to_be_bytes());
+    packet.extend_from_slice(&ack.to_be_bytes());
+    packet.extend_from_slice(&[0x50, 0x18, 0xFF, 0xFF, 0x00, 0x00, 0x00, 0x00]);
+    packet.extend_from_slice(payload);
+    packet
+}
+
+fn ipv4_checksum(header: &[u8]) -> u16 {
+    let mut sum: u32 = header.chunks(2).map(|w| u32::from(u16::from_be_bytes([w[0], w[1]]))).sum();
+    while sum > 0xFFFF {
+        sum = (sum & 0xFFFF) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn capture() -> PduCapture {
+        let capture = PduCapture::new("10.0.0.2:40000".parse().unwrap(), "10.0.0.1:2775".parse().unwrap());
+        // enquire_link seq 7 and its response
+        capture.record(Direction::Inbound, &[0, 0, 0, 16, 0, 0, 0, 0x15, 0, 0, 0, 0, 0, 0, 0, 7]);
+        capture.record(Direction::Outbound, &[0, 0, 0, 16, 0x80, 0, 0, 0x15, 0, 0, 0, 0, 0, 0, 0, 7]);
+        capture
+    }
+
+    #[test]
+    fn test_hex_dump() {
+        let dump = capture().hex_dump();
+        assert!(dump.contains("<< command_id=0x00000015 seq=7 (16 bytes)"), "{}", dump);
+        assert!(dump.contains(">> command_id=0x80000015 seq=7"), "{}", dump);
+        assert!(dump.contains("  0000  00 00 00 10 00 00 00 15 00 00 00 00 00 00 00 07  "), "{}", dump);
+    }
+
+    #[test]
+    fn test_pcap_framing() {
+        let pcap = capture().to_pcap();
+        assert_eq!(&pcap[..4], &0xA1B2_C3D4u32.to_le_bytes());
+        assert_eq!(u32::from_le_bytes(pcap[20..24].try_into().unwrap()), 101);
+        // Two records of 16-byte record header + 20 IP + 20 TCP + 16 payload
+        assert_eq!(pcap.len(), 24 + 2 * (16 + 56));
+
+        let first = &pcap[24 + 16..24 + 16 + 56];
+        assert_eq!(ipv4_checksum(&first[..20]), 0, "IP header checksum must verify");
+        assert_eq!(&first[12..16], &[10, 0, 0, 2]);
+        assert_eq!(u16::from_be_bytes([first[22], first[23]]), 2775);
+        assert_eq!(&first[40..], &[0, 0, 0, 16, 0, 0, 0, 0x15, 0, 0, 0, 0, 0, 0, 0, 7]);
+
+        // The response flows the other way and acknowledges the request's bytes
+        let second = &pcap[24 + 2 * 16 + 56..];
+        assert_eq!(&second[12..16], &[10, 0, 0, 1]);
+        assert_eq!(u32::from_be_bytes(second[28..32].try_into().unwrap()), 17);
+    }
+
+    #[test]
+    fn test_capture_is_bounded() {
+        let capture = PduCapture::new("10.0.0.2:40000".parse().unwrap(), "10.0.0.1:2775".parse().unwrap());
+        for seq in 0..(MAX_CAPTURED_PDUS as u32 + 5) {
+            capture.record(Direction::Inbound, &seq.to_be_bytes());
+        }
+        let pdus = capture.pdus();
+        assert_eq!(pdus.len(), MAX_CAPTURED_PDUS);
+        assert_eq!(pdus[0].bytes, 5u32.to_be_bytes());
+    }
+}
@@ -4,10 +4,15 @@
 //! compatibility with SMPP 3.4 clients. The Node.js `smpp` package (v0.3.x)
 //! uses SMPP 3.4 which has subtle differences in PDU encoding.
 
-use bytes::{BytesMut, BufMut};
-use rusmpp::{tokio_codec::CommandCodec, Command};
+use bytes::{Buf, BytesMut, BufMut};
+use rusmpp::{tokio_codec::CommandCodec, Command, CommandStatus};
+use rusmpp::values::InterfaceVersion;
 use tokio_util::codec::{Decoder, Encoder};
 use std::io;
+use std::sync::Arc;
+use rand::Rng;
+
+use crate::smpp::capture::{Direction, PduCapture};
 
 /// SMPP protocol version for compatibility mode
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -19,6 +24,7 @@ pub enum SmppVersion {
 }
 
 impl SmppVersion {
+    #[allow(clippy::should_implement_trait)]
     pub fn from_str(s: &str) -> Self {
         match s {
             "3.4" | "34" | "3" => SmppVersion::V34,
@@ -32,12 +38,48 @@ impl SmppVersion {
             SmppVersion::V50 => "5.0",
         }
     }
+
+    /// The interface_version this SMSC advertises in bind responses
+    pub fn interface_version(&self) -> InterfaceVersion {
+        match self {
+            SmppVersion::V34 => InterfaceVersion::Smpp3_4,
+            SmppVersion::V50 => InterfaceVersion::Smpp5_0,
+        }
+    }
+}
+
+/// A complete PDU that could not be decoded. Its bytes have been consumed, so the
+/// stream is still in sync and the next PDU can be read.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InvalidFrame {
+    pub command_id: u32,
+    pub sequence_number: u32,
+    /// Status for the generic_nack answering it
+    pub status: CommandStatus,
+    pub reason: String,
+}
+
+impl InvalidFrame {
+    /// Whether the PDU claimed to be a response, which is never nacked
+    pub fn is_response(&self) -> bool {
+        self.command_id & 0x8000_0000 != 0
+    }
 }
 
 /// Custom SMPP codec that wraps CommandCodec with version compatibility
+///
+/// Decoding yields `Err(InvalidFrame)` items for malformed or oversized PDUs, which the
+/// connection can answer and move past. Only a command_length too short to hold a header
+/// is fatal, as the next PDU boundary can no longer be found.
 pub struct SmppCodec {
     inner: CommandCodec,
     version: SmppVersion,
+    /// Bytes of an oversized PDU still to be skipped
+    discard: usize,
+    /// CHAOS: percentage of encoded responses to corrupt (0 = never)
+    corrupt_response_percent: u8,
+    /// Records the raw bytes of every PDU read or written, when set
+    capture: Option<Arc<PduCapture>>,
 }
 
 impl SmppCodec {
@@ -46,9 +88,25 @@ impl SmppCodec {
         Self {
             inner: CommandCodec::new(),
             version,
+            discard: 0,
+            corrupt_response_percent: 0,
+            capture: None,
         }
     }
 
+    /// Record every PDU's bytes, as sent on the wire, into `capture`
+    pub fn with_capture(mut self, capture: Option<Arc<PduCapture>>) -> Self {
+        self.capture = capture;
+        self
+    }
+
+    /// CHAOS FEATURE: corrupt this percentage of outgoing response PDUs so that
+    /// client decoders can be fuzzed. Never enable against a real client.
+    pub fn with_corrupt_response_percent(mut self, percent: u8) -> Self {
+        self.corrupt_response_percent = percent.min(100);
+        self
+    }
+
     /// Get the configured SMPP version
     pub fn version(&self) -> SmppVersion {
         self.version
@@ -56,10 +114,78 @@ impl SmppCodec {
 }
 
 impl Decoder for SmppCodec {
-    type Item = Command;
+    type Item = Result<Command, InvalidFrame>;
     type Error = io::Error;
 
     fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        const HEADER_LENGTH: usize = 16;
+
+        if self.discard > 0 {
+            let skipped = self.discard.min(src.len());
+            src.advance(skipped);
+            self.discard -= skipped;
+            if self.discard > 0 {
+                return Ok(None);
+            }
+        }
+        if src.len() < HEADER_LENGTH {
+            return Ok(None);
+        }
+
+        let header = |at: usize| u32::from_be_bytes([src[at], src[at + 1], src[at + 2], src[at + 3]]);
+        let command_length = header(0) as usize;
+        let command_id = header(4);
+        let sequence_number = header(12);
+        if command_length < HEADER_LENGTH {
+            return Err(io::Error::new(io::ErrorKind::InvalidData,
+                format!("command_length {} is shorter than the PDU header", command_length)));
+        }
+        let max_length = self.inner.max_length().unwrap_or(usize::MAX);
+        if command_length > max_length {
+            // Skip the PDU as its bytes arrive rather than buffering it
+            let skipped = command_length.min(src.len());
+            src.advance(skipped);
+            self.discard = command_length - skipped;
+            return Ok(Some(Err(InvalidFrame {
+                command_id,
+                sequence_number,
+                status: CommandStatus::EsmeRinvcmdlen,
+                reason: format!("command_length {} exceeds the maximum of {}", command_length, max_length),
+            })));
+        }
+        if src.len() < command_length {
+            src.reserve(command_length - src.len());
+            return Ok(None);
+        }
+
+        // Decode the PDU on its own so a failure part way through its body
+        // cannot leave the rest of the stream misaligned
+        let mut frame = src.split_to(command_length);
+        // Capture the frame as received, before any 3.4 fix-ups rewrite it
+        if let Some(capture) = &self.capture {
+            capture.record(Direction::Inbound, &frame);
+        }
+        match self.decode_frame(&mut frame) {
+            Ok(Some(command)) => Ok(Some(Ok(command))),
+            Ok(None) => Ok(Some(Err(InvalidFrame {
+                command_id,
+                sequence_number,
+                status: CommandStatus::EsmeRinvcmdlen,
+                reason: "PDU body is shorter than its command_length".to_string(),
+            }))),
+            Err(e) => Ok(Some(Err(InvalidFrame {
+                command_id,
+                sequence_number,
+                status: CommandStatus::EsmeRinvcmdlen,
+                reason: e.to_string(),
+            }))),
+        }
+    }
+}
+
+impl SmppCodec {
+    /// Decode one complete PDU
+    fn decode_frame(&mut self, src: &mut BytesMut) -> Result<Option<Command>, io::Error> {
         // For SMPP 5.0, use standard decoding
         if self.version == SmppVersion::V50 {
             return self.inner.decode(src).map_err(|e| {
@@ -68,13 +194,16 @@ impl Decoder for SmppCodec {
         }
 
         // For SMPP 3.4, try standard decoding first
-        // Keep a copy of the buffer position in case we need to retry
-        let original_len = src.len();
+        // Keep a copy of the buffer in case we need to retry: a failed decode has
+        // already consumed the PDU from `src`
+        let mut original = src.clone();
+        let original_len = original.len();
         
         match self.inner.decode(src) {
             Ok(result) => Ok(result),
             Err(e) => {
                 let err_msg = e.to_string();
+                tracing::debug!("SMPP 3.4 decode error: {}", err_msg);
                 
                 // Check if this is a null terminator issue
                 if err_msg.contains("Not null terminated") || 
@@ -88,7 +217,7 @@ impl Decoder for SmppCodec {
                     // null-terminate certain strings, or use different PDU layouts.
                     //
                     // We'll try to fix the buffer by ensuring proper null terminators.
-                    if let Some(fixed_buf) = try_fix_pdu_nulls(src, original_len) {
+                    if let Some(fixed_buf) = try_fix_pdu_nulls(&mut original, original_len) {
                         *src = fixed_buf;
                         return self.inner.decode(src).map_err(|e| {
                             io::Error::new(io::ErrorKind::InvalidData, 
@@ -107,9 +236,21 @@ impl Encoder<Command> for SmppCodec {
     type Error = io::Error;
 
     fn encode(&mut self, item: Command, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        let start = dst.len();
+        let is_response = item.id().is_response();
         self.inner.encode(item, dst).map_err(|e| {
             io::Error::new(io::ErrorKind::InvalidData, e.to_string())
-        })
+        })?;
+
+        if is_response
+            && self.corrupt_response_percent > 0
+            && rand::rng().random_range(0..100) < self.corrupt_response_percent {
+            corrupt_pdu(dst, start);
+        }
+        if let Some(capture) = &self.capture {
+            capture.record(Direction::Outbound, &dst[start..]);
+        }
+        Ok(())
     }
 }
 
@@ -117,9 +258,32 @@ impl Encoder<&Command> for SmppCodec {
     type Error = io::Error;
 
     fn encode(&mut self, item: &Command, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        let start = dst.len();
         self.inner.encode(item, dst).map_err(|e| {
             io::Error::new(io::ErrorKind::InvalidData, e.to_string())
-        })
+        })?;
+        if let Some(capture) = &self.capture {
+            capture.record(Direction::Outbound, &dst[start..]);
+        }
+        Ok(())
+    }
+}
+
+/// CHAOS: damage the PDU encoded at `dst[start..]` so that decoding it fails, either by
+/// truncating its final byte (which cuts a COctetString terminator or TLV value short) with
+/// command_length rewritten to match or, for header-only PDUs and otherwise at random, by
+/// flipping the high byte of command_length so it exceeds any sane maximum.
+fn corrupt_pdu(dst: &mut BytesMut, start: usize) {
+    const HEADER_LENGTH: usize = 16;
+    let mut rng = rand::rng();
+    let length = dst.len() - start;
+    if length > HEADER_LENGTH && rng.random_bool(0.5) {
+        dst.truncate(start + length - 1);
+        dst[start..start + 4].copy_from_slice(&((length - 1) as u32).to_be_bytes());
+        tracing::warn!("CHAOS: truncated outgoing response to {} bytes", length - 1);
+    } else {
+        dst[start] ^= 0xFF;
+        tracing::warn!("CHAOS: flipped command_length byte of outgoing response");
     }
 }
 
@@ -188,15 +352,14 @@ fn try_fix_pdu_nulls(src: &mut BytesMut, original_len: usize) -> Option<BytesMut
                 fixed.put_slice(&src[pos..pos + remaining_fixed]);
                 pos += remaining_fixed;
                 
-                // Copy address_range (COctetString)
-                if pos < cmd_len {
-                    let (end_pos, added_null) = copy_coctet_string(&src[pos..cmd_len], &mut fixed);
-                    if added_null {
-                        fixed_count += 1;
-                        tracing::debug!("Added null terminator for address_range");
-                    }
-                    pos += end_pos;
+                // Copy address_range (COctetString); clients that leave an empty
+                // address_range unterminated end the PDU right after addr_npi
+                let (end_pos, added_null) = copy_coctet_string(&src[pos..cmd_len], &mut fixed);
+                if added_null {
+                    fixed_count += 1;
+                    tracing::debug!("Added null terminator for address_range");
                 }
+                pos += end_pos;
                 
                 // Copy any remaining bytes (TLVs, etc.)
                 if pos < cmd_len {
@@ -218,6 +381,94 @@ fn try_fix_pdu_nulls(src: &mut BytesMut, original_len: usize) -> Option<BytesMut
                 return Some(fixed);
             }
         }
+        0x00000004 => {
+            // submit_sm PDU structure after 16-byte header:
+            // - service_type (COctetString, max 6)
+            // - source_addr_ton, source_addr_npi (1 byte each)
+            // - source_addr (COctetString, max 21)
+            // - dest_addr_ton, dest_addr_npi (1 byte each)
+            // - destination_addr (COctetString, max 21)
+            // - esm_class, protocol_id, priority_flag (1 byte each)
+            // - schedule_delivery_time, validity_period (COctetString, max 17)
+            // - registered_delivery, replace_if_present_flag, data_coding,
+            //   sm_default_msg_id (1 byte each)
+            // - sm_length (1 byte) + short_message (sm_length bytes, no terminator)
+            //
+            // A string missing its terminator runs to the end of the PDU, so the
+            // repair applies to PDUs cut short: missing strings become empty and
+            // missing fixed fields become zero.
+
+            tracing::debug!("Attempting to fix submit_sm PDU (cmd_id: 0x{:08x})", cmd_id);
+
+            let mut fixed = BytesMut::with_capacity(cmd_len + 16);
+            fixed.put_slice(&src[0..16]);
+
+            let body = &src[..cmd_len];
+            let mut pos = 16;
+            let mut fixed_count = 0;
+
+            // Each COctetString with the number of fixed bytes that precede it
+            let strings = [
+                ("service_type", 0),
+                ("source_addr", 2),
+                ("destination_addr", 2),
+                ("schedule_delivery_time", 3),
+                ("validity_period", 0),
+            ];
+            for (field_name, fixed_before) in strings {
+                let (consumed, padded) = copy_fixed_bytes(&body[pos.min(cmd_len)..], fixed_before, &mut fixed);
+                pos += consumed;
+                if padded {
+                    fixed_count += 1;
+                    tracing::debug!("Zero-filled fixed fields before {}", field_name);
+                }
+                let (consumed, added_null) = copy_coctet_string(&body[pos.min(cmd_len)..], &mut fixed);
+                pos += consumed;
+                if added_null {
+                    fixed_count += 1;
+                    tracing::debug!("Added null terminator for {}", field_name);
+                }
+            }
+
+            // registered_delivery, replace_if_present_flag, data_coding, sm_default_msg_id, sm_length
+            let (consumed, padded) = copy_fixed_bytes(&body[pos.min(cmd_len)..], 5, &mut fixed);
+            pos += consumed;
+            if padded {
+                fixed_count += 1;
+                tracing::debug!("Zero-filled fixed fields before short_message");
+            }
+
+            // short_message is length-prefixed: copy it as-is, shrinking sm_length
+            // if the PDU ends before the message does
+            let sm_length = fixed[fixed.len() - 1] as usize;
+            let available = cmd_len.saturating_sub(pos);
+            let take = sm_length.min(available);
+            if take < sm_length {
+                let last = fixed.len() - 1;
+                fixed[last] = take as u8;
+                fixed_count += 1;
+                tracing::debug!("Truncated sm_length from {} to {}", sm_length, take);
+            }
+            fixed.put_slice(&body[pos.min(cmd_len)..pos.min(cmd_len) + take]);
+            pos += take;
+
+            // Copy any remaining bytes (TLVs)
+            if pos < cmd_len {
+                fixed.put_slice(&body[pos..]);
+            }
+
+            if fixed_count > 0 {
+                let new_len = fixed.len() as u32;
+                fixed[0..4].copy_from_slice(&new_len.to_be_bytes());
+
+                if original_len > cmd_len {
+                    fixed.put_slice(&src[cmd_len..original_len]);
+                }
+
+                tracing::info!("SMPP 3.4 compatibility: fixed {} fields in submit_sm", fixed_count);
+                return Some(fixed);
+            }
+        }
         _ => {
             // For other PDU types, we don't have specific fixes yet
             tracing::debug!("No specific fix for PDU type 0x{:08x}", cmd_id);
@@ -248,6 +499,15 @@ fn copy_coctet_string(src: &[u8], dst: &mut BytesMut) -> (usize, bool) {
     }
 }
 
+/// Copy `count` fixed-size bytes, zero-filling any the source is missing
+/// Returns (bytes_consumed_from_source, was_padded)
+fn copy_fixed_bytes(src: &[u8], count: usize, dst: &mut BytesMut) -> (usize, bool) {
+    let available = count.min(src.len());
+    dst.put_slice(&src[..available]);
+    dst.put_bytes(0, count - available);
+    (available, available < count)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -267,4 +527,143 @@ mod tests {
         assert_eq!(SmppVersion::V34.as_str(), "3.4");
         assert_eq!(SmppVersion::V50.as_str(), "5.0");
     }
+
+    #[test]
+    fn test_corrupt_responses() {
+        use rusmpp::{CommandStatus, Pdu};
+        use std::str::FromStr;
+
+        let enquire_link_resp = || Command::builder()
+            .status(CommandStatus::EsmeRok)
+            .sequence_number(7)
+            .pdu(Pdu::EnquireLinkResp);
+        let submit_sm_resp = || Command::builder()
+            .status(CommandStatus::EsmeRok)
+            .sequence_number(7)
+            .pdu(Pdu::SubmitSmResp(rusmpp::pdus::SubmitSmResp::new(
+                rusmpp::types::COctetString::from_str("0000002A").unwrap(),
+                vec![],
+            )));
+
+        let mut codec = SmppCodec::new(SmppVersion::V50).with_corrupt_response_percent(100);
+        for command in [enquire_link_resp, submit_sm_resp] {
+            let mut clean = BytesMut::new();
+            SmppCodec::new(SmppVersion::V50).encode(command(), &mut clean).unwrap();
+            for _ in 0..20 {
+                let mut corrupted = BytesMut::new();
+                codec.encode(command(), &mut corrupted).unwrap();
+                assert_ne!(corrupted, clean, "Response should have been modified");
+                // The client's decoder rejects it
+                assert!(CommandCodec::new().decode(&mut corrupted).is_err());
+            }
+        }
+
+        // Server-initiated requests are left alone
+        let request = Command::builder()
+            .status(CommandStatus::EsmeRok)
+            .sequence_number(8)
+            .pdu(Pdu::EnquireLink);
+        let mut encoded = BytesMut::new();
+        codec.encode(request, &mut encoded).unwrap();
+        assert!(CommandCodec::new().decode(&mut encoded).unwrap().is_some());
+    }
+
+    #[test]
+    fn test_smpp34_submit_sm_null_fix() {
+        // submit_sm that ends right after an unterminated destination_addr
+        let mut body = Vec::new();
+        body.push(0); // service_type ""
+        body.extend_from_slice(&[1, 1]);
+        body.extend_from_slice(b"12345\0");
+        body.extend_from_slice(&[1, 1]);
+        body.extend_from_slice(b"67890");
+        let mut pdu = BytesMut::new();
+        pdu.put_u32(16 + body.len() as u32);
+        pdu.put_u32(0x00000004);
+        pdu.put_u32(0);
+        pdu.put_u32(3);
+        pdu.put_slice(&body);
+
+        assert!(CommandCodec::new().decode(&mut pdu.clone()).is_err());
+
+        let mut codec = SmppCodec::new(SmppVersion::V34);
+        let command = codec.decode(&mut pdu).unwrap().expect("Fixed PDU should decode").unwrap();
+        assert_eq!(command.sequence_number(), 3);
+        let Some(rusmpp::Pdu::SubmitSm(submit)) = command.pdu() else { panic!("Expected SubmitSm") };
+        assert_eq!(submit.source_addr.to_string(), "12345");
+        assert_eq!(submit.destination_addr.to_string(), "67890");
+        assert!(submit.short_message().is_empty());
+        assert!(pdu.is_empty());
+    }
+
+    #[test]
+    fn test_smpp34_submit_sm_short_message_kept() {
+        // A complete submit_sm passes through untouched, including a short_message
+        // containing NUL bytes
+        let mut body = Vec::new();
+        body.extend_from_slice(b"\0\x01\x01src\0\x01\x01dst\0\0\0\0\0\0\0\0\0\0");
+        body.push(3);
+        body.extend_from_slice(b"a\0b");
+        let mut pdu = BytesMut::new();
+        pdu.put_u32(16 + body.len() as u32);
+        pdu.put_u32(0x00000004);
+        pdu.put_u32(0);
+        pdu.put_u32(4);
+        pdu.put_slice(&body);
+
+        let mut codec = SmppCodec::new(SmppVersion::V34);
+        let command = codec.decode(&mut pdu).unwrap().expect("PDU should decode").unwrap();
+        let Some(rusmpp::Pdu::SubmitSm(submit)) = command.pdu() else { panic!("Expected SubmitSm") };
+        assert_eq!(submit.short_message().as_ref(), b"a\0b");
+    }
+
+    #[test]
+    fn test_invalid_frames_are_skipped() {
+        let enquire_link = |sequence_number: u32| {
+            let mut pdu = BytesMut::new();
+            pdu.put_u32(16);
+            pdu.put_u32(0x00000015);
+            pdu.put_u32(0);
+            pdu.put_u32(sequence_number);
+            pdu
+        };
+
+        // submit_sm whose body stops inside service_type, then a valid enquire_link
+        let mut src = BytesMut::new();
+        src.put_u32(19);
+        src.put_u32(0x00000004);
+        src.put_u32(0);
+        src.put_u32(5);
+        src.put_slice(b"abc");
+        src.extend_from_slice(&enquire_link(6));
+
+        let mut codec = SmppCodec::new(SmppVersion::V50);
+        let invalid = codec.decode(&mut src).unwrap().unwrap().expect_err("Malformed PDU should be reported");
+        assert_eq!(invalid.sequence_number, 5);
+        assert_eq!(invalid.status, CommandStatus::EsmeRinvcmdlen);
+        assert!(!invalid.is_response());
+        let command = codec.decode(&mut src).unwrap().unwrap().expect("Next PDU should decode");
+        assert_eq!(command.sequence_number(), 6);
+        assert!(src.is_empty());
+
+        // An oversized PDU is skipped across reads without being buffered
+        let mut src = BytesMut::new();
+        src.put_u32(10_000);
+        src.put_u32(0x00000004);
+        src.put_u32(0);
+        src.put_u32(7);
+        src.put_bytes(b'x', 100);
+        let invalid = codec.decode(&mut src).unwrap().unwrap().expect_err("Oversized PDU should be reported");
+        assert_eq!(invalid.sequence_number, 7);
+        assert!(src.is_empty());
+        src.put_bytes(b'x', 10_000 - 116);
+        src.extend_from_slice(&enquire_link(8));
+        let command = codec.decode(&mut src).unwrap().unwrap().expect("Next PDU should decode");
+        assert_eq!(command.sequence_number(), 8);
+
+        // A command_length shorter than the header loses the PDU boundaries
+        let mut src = enquire_link(9);
+        src[0..4].copy_from_slice(&8u32.to_be_bytes());
+        assert!(codec.decode(&mut src).is_err());
+    }
 }
@@ -0,0 +1,172 @@
+//! GSM 03.38 7-bit default alphabet helpers
+//!
+//! When a DeliverSm is sent with the GSM 7-bit default data coding, the
+//! short_message should carry septets packed into octets rather than raw
+//! ASCII bytes. This module maps text onto the default alphabet (plus the
+//! escape-prefixed extension table) and packs it, and does the reverse for
+//! packed content received from a client.
+
+/// Escape to the extension table; the next septet selects the character
+const ESCAPE: u8 = 0x1B;
+
+/// Default alphabet, indexed by septet. 0x1B is the escape and never decoded directly.
+const BASIC: [char; 128] = [
+    '@', '£', '$', '¥', 'è', 'é', 'ù', 'ì', 'ò', 'Ç', '\n', 'Ø', 'ø', '\r', 'Å', 'å',
+    'Δ', '_', 'Φ', 'Γ', 'Λ', 'Ω', 'Π', 'Ψ', 'Σ', 'Θ', 'Ξ', '\u{A0}', 'Æ', 'æ', 'ß', 'É',
+    ' ', '!', '"', '#', '¤', '%', '&', '\'', '(', ')', '*', '+', ',', '-', '.', '/',
+    '0', '1', '2', '3', '4', '5', '6', '7', '8', '9', ':', ';', '<', '=', '>', '?',
+    '¡', 'A', 'B', 'C', 'D', 'E', 'F', 'G', 'H', 'I', 'J', 'K', 'L', 'M', 'N', 'O',
+    'P', 'Q', 'R', 'S', 'T', 'U', 'V', 'W', 'X', 'Y', 'Z', 'Ä', 'Ö', 'Ñ', 'Ü', '§',
+    '¿', 'a', 'b', 'c', 'd', 'e', 'f', 'g', 'h', 'i', 'j', 'k', 'l', 'm', 'n', 'o',
+    'p', 'q', 'r', 's', 't', 'u', 'v', 'w', 'x', 'y', 'z', 'ä', 'ö', 'ñ', 'ü', 'à',
+];
+
+/// Extension table: septet following the escape -> character
+const EXTENSION: [(u8, char); 10] = [
+    (0x0A, '\u{0C}'),
+    (0x14, '^'),
+    (0x28, '{'),
+    (0x29, '}'),
+    (0x2F, '\\'),
+    (0x3C, '['),
+    (0x3D, '~'),
+    (0x3E, ']'),
+    (0x40, '|'),
+    (0x65, '€'),
+];
+
+/// Map a character to its GSM 7-bit default alphabet code.
+///
+/// Characters outside the basic table are replaced with `?`.
+pub fn char_to_septet(c: char) -> u8 {
+    BASIC.iter()
+        .position(|&b| b == c)
+        .filter(|&pos| pos != usize::from(ESCAPE))
+        .map_or(0x3F, |pos| pos as u8)
+}
+
+/// Encode text as septets, using escape sequences for extension-table characters.
+///
+/// Characters in neither table are replaced with `?`.
+pub fn encode_septets(text: &str) -> Vec<u8> {
+    let mut septets = Vec::with_capacity(text.len());
+    for c in text.chars() {
+        if let Some(&(code, _)) = EXTENSION.iter().find(|&&(_, ext)| ext == c) {
+            septets.extend_from_slice(&[ESCAPE, code]);
+        } else {
+            septets.push(char_to_septet(c));
+        }
+    }
+    septets
+}
+
+/// Decode septets back into text, resolving escape sequences.
+///
+/// An escape followed by an unknown code falls back to the basic character, as the spec asks.
+pub fn decode_septets(septets: &[u8]) -> String {
+    let mut text = String::with_capacity(septets.len());
+    let mut iter = septets.iter().map(|&s| s & 0x7F);
+    while let Some(septet) = iter.next() {
+        if septet == ESCAPE {
+            let Some(code) = iter.next() else { break };
+            match EXTENSION.iter().find(|&&(ext, _)| ext == code) {
+                Some(&(_, c)) => text.push(c),
+                None => text.push(BASIC[usize::from(code)]),
+            }
+        } else {
+            text.push(BASIC[usize::from(septet)]);
+        }
+    }
+    text
+}
+
+/// Pack 7-bit septets into octets, least significant bit first, per GSM 03.38.
+pub fn pack_septets(septets: &[u8]) -> Vec<u8> {
+    let mut packed = Vec::with_capacity((septets.len() * 7).div_ceil(8));
+    let mut acc: u16 = 0;
+    let mut bits = 0;
+
+    for &septet in septets {
+        acc |= ((septet & 0x7F) as u16) << bits;
+        bits += 7;
+        while bits >= 8 {
+            packed.push((acc & 0xFF) as u8);
+            acc >>= 8;
+            bits -= 8;
+        }
+    }
+    if bits > 0 {
+        packed.push((acc & 0xFF) as u8);
+    }
+    packed
+}
+
+/// Unpack octets into septets, the inverse of `pack_septets`.
+///
+/// When the septets exactly fill a multiple of seven octets, the last 7 bits are padding;
+/// a trailing zero septet there is dropped rather than read as `@`.
+pub fn unpack_septets(packed: &[u8]) -> Vec<u8> {
+    let mut septets = Vec::with_capacity(packed.len() * 8 / 7);
+    let mut acc: u16 = 0;
+    let mut bits = 0;
+
+    for &octet in packed {
+        acc |= (octet as u16) << bits;
+        bits += 8;
+        while bits >= 7 {
+            septets.push((acc & 0x7F) as u8);
+            acc >>= 7;
+            bits -= 7;
+        }
+    }
+    if packed.len().is_multiple_of(7) && septets.last() == Some(&0) {
+        septets.pop();
+    }
+    septets
+}
+
+/// Encode text to the GSM default alphabet and pack it.
+pub fn pack(text: &str) -> Vec<u8> {
+    pack_septets(&encode_septets(text))
+}
+
+/// Unpack GSM 7-bit packed content into text.
+pub fn unpack(packed: &[u8]) -> String {
+    decode_septets(&unpack_septets(packed))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pack_known_string() {
+        // Classic GSM 03.38 example: "hellohello" -> E8 32 9B FD 46 97 D9 EC 37
+        assert_eq!(pack("hellohello"), vec![0xE8, 0x32, 0x9B, 0xFD, 0x46, 0x97, 0xD9, 0xEC, 0x37]);
+    }
+
+    #[test]
+    fn test_pack_eight_septets_fits_seven_octets() {
+        assert_eq!(pack("12345678").len(), 7);
+        assert_eq!(pack("12345678"), vec![0x31, 0xD9, 0x8C, 0x56, 0xB3, 0xDD, 0x70]);
+    }
+
+    #[test]
+    fn test_char_mapping() {
+        assert_eq!(char_to_septet('@'), 0x00);
+        assert_eq!(char_to_septet('A'), 0x41);
+        assert_eq!(char_to_septet('_'), 0x11);
+        assert_eq!(char_to_septet('€'), 0x3F);
+    }
+
+    #[test]
+    fn test_round_trip_with_extension_table() {
+        let text = "Only 5€ {today} [x] ~^|\\ £ é Ω";
+        assert_eq!(encode_septets("€{"), vec![ESCAPE, 0x65, ESCAPE, 0x28]);
+        assert_eq!(unpack(&pack(text)), text);
+        assert_eq!(unpack(&pack("hellohello")), "hellohello");
+        // Seven septets leave 7 padding bits that must not come back as '@'
+        assert_eq!(unpack(&pack("1234567")), "1234567");
+        assert_eq!(pack("x€?"), pack_septets(&[0x78, ESCAPE, 0x65, 0x3F]));
+    }
+}
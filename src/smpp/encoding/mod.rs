@@ -0,0 +1,98 @@
+//! Text encoding and decoding for short_message bodies
+//!
+//! The bytes of a submit_sm only make sense together with its data_coding; this is
+//! the one place that turns them into display text (dashboard, API, DR previews),
+//! and outbound text into bytes for a given coding.
+//! It also decides whether an outbound body fits short_message or needs message_payload.
+
+pub mod gsm7;
+
+use rusmpp::tlvs::MessageDeliveryRequestTlvValue;
+use rusmpp::types::{AnyOctetString, OctetString};
+use rusmpp::values::MessagePayload;
+
+/// Largest body short_message may carry; anything longer goes in message_payload
+pub const MAX_SHORT_MESSAGE_LEN: usize = 254;
+
+/// Place an outbound body: in short_message when it fits, otherwise an empty
+/// short_message plus a message_payload TLV carrying the whole body
+pub fn short_message_or_payload(body: Vec<u8>) -> (OctetString<0, 255>, Option<MessageDeliveryRequestTlvValue>) {
+    if body.len() <= MAX_SHORT_MESSAGE_LEN {
+        (OctetString::from_bytes(body.into()).unwrap_or_default(), None)
+    } else {
+        let payload = MessagePayload::new(AnyOctetString::from_vec(body));
+        (OctetString::default(), Some(MessageDeliveryRequestTlvValue::MessagePayload(payload)))
+    }
+}
+
+/// Render short_message according to its data_coding: UCS2 and Latin-1 are decoded,
+/// binary codings are shown as hex and anything else is read as UTF-8. With
+/// `gsm7_packed` (the `smpp.gsm7_packing` link setting) the default coding is
+/// unpacked as GSM 7-bit.
+pub fn decode_short_message(bytes: &[u8], data_coding: u8, gsm7_packed: bool) -> String {
+    match data_coding {
+        0x00 if gsm7_packed => gsm7::unpack(bytes),
+        0x08 => {
+            // UCS2 is UTF-16BE on the wire; a dangling odd byte is dropped
+            let units: Vec<u16> = bytes.chunks_exact(2).map(|pair| u16::from_be_bytes([pair[0], pair[1]])).collect();
+            String::from_utf16_lossy(&units)
+        }
+        0x03 => bytes.iter().map(|&b| b as char).collect(),
+        0x02 | 0x04 => hex::encode(bytes),
+        _ => String::from_utf8_lossy(bytes).to_string(),
+    }
+}
+
+/// Encode outbound text for a data_coding, the inverse of `decode_short_message`.
+/// Characters Latin-1 cannot hold become `?`; binary codings get the UTF-8 bytes.
+pub fn encode_text(text: &str, data_coding: u8, gsm7_packed: bool) -> Vec<u8> {
+    match data_coding {
+        0x00 if gsm7_packed => gsm7::pack(text),
+        0x08 => text.encode_utf16().flat_map(u16::to_be_bytes).collect(),
+        0x03 => text.chars().map(|c| u8::try_from(c).unwrap_or(b'?')).collect(),
+        _ => text.as_bytes().to_vec(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_by_data_coding() {
+        assert_eq!(decode_short_message(b"hello", 0x00, false), "hello");
+        assert_eq!(decode_short_message(&[0x04, 0x1F, 0x04, 0x40], 0x08, false), "\u{41F}\u{440}");
+        assert_eq!(decode_short_message(&[0x63, 0x61, 0x66, 0xE9], 0x03, false), "caf\u{e9}");
+        assert_eq!(decode_short_message(&[0x00, 0xFF], 0x04, false), "00ff");
+        assert_eq!(decode_short_message(&gsm7::pack("hello"), 0x00, true), "hello");
+    }
+
+    #[test]
+    fn test_encode_text_round_trip() {
+        for (text, data_coding) in [("hello", 0x00), ("\u{41F}\u{440} 😀", 0x08), ("caf\u{e9}", 0x03)] {
+            assert_eq!(decode_short_message(&encode_text(text, data_coding, false), data_coding, false), text);
+        }
+        assert_eq!(encode_text("hellohello", 0x00, true), gsm7::pack("hellohello"));
+        assert_eq!(encode_text("a\u{41F}", 0x03, false), b"a?");
+    }
+
+    #[test]
+    fn test_short_message_or_payload() {
+        let (short_message, payload) = short_message_or_payload(vec![b'a'; MAX_SHORT_MESSAGE_LEN]);
+        assert_eq!(short_message.as_ref().len(), MAX_SHORT_MESSAGE_LEN);
+        assert!(payload.is_none());
+
+        let (short_message, payload) = short_message_or_payload(vec![b'a'; 300]);
+        assert!(short_message.as_ref().is_empty());
+        let Some(MessageDeliveryRequestTlvValue::MessagePayload(payload)) = payload else { panic!("Expected message_payload") };
+        assert_eq!(payload.value.as_ref().len(), 300);
+    }
+
+    #[test]
+    fn test_decode_ucs2() {
+        // "Привет" followed by an emoji that needs a surrogate pair
+        let bytes = [0x04, 0x1F, 0x04, 0x40, 0x04, 0x38, 0x04, 0x32, 0x04, 0x35, 0x04, 0x42, 0xD8, 0x3D, 0xDE, 0x00];
+        assert_eq!(decode_short_message(&bytes, 0x08, false), "Привет\u{1F600}");
+        assert_eq!(decode_short_message(&[0x00, 0x41, 0x00], 0x08, false), "A");
+    }
+}
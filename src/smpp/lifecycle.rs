@@ -1,16 +1,29 @@
 use std::sync::Arc;
 use tokio::time::{sleep, Duration};
 use rand::Rng;
-use crate::config::AppConfig;
-use crate::smpp::session::{SessionManager, BindType};
-use crate::smpp::queue::{MessageQueue, QueuedMessage};
+use crate::config::{AppConfig, DelayDistribution, LifecycleConfig};
+use crate::smpp::audit::{AuditEvent, AuditRecord};
+use crate::smpp::session::{Session, SessionManager, BindType};
+use std::collections::HashMap;
+use crate::smpp::queue::{MessageQueue, MessageState, QueuedMessage};
+use crate::smpp::encoding::{decode_short_message, gsm7, short_message_or_payload};
 use rusmpp::{Command, Pdu, CommandStatus};
-use rusmpp::types::{COctetString, OctetString, EmptyOrFullCOctetString};
+use rusmpp::tlvs::{MessageDeliveryRequestTlvValue, TlvTag};
+use rusmpp::types::{AnyOctetString, COctetString, EmptyOrFullCOctetString};
 use rusmpp::values::{
     Ton, Npi, EsmClass, PriorityFlag, RegisteredDelivery, ReplaceIfPresentFlag, DataCoding,
-    ServiceType, MessagingMode, MessageType, Ansi41Specific, GsmFeatures
+    ServiceType, MessagingMode, MessageType, Ansi41Specific, GsmFeatures,
+    MessageState as SmppMessageState, NetworkErrorCode, ErrorCodeNetworkType,
 };
 use std::str::FromStr;
+use std::time::Instant;
+
+/// How long the parts of a concatenated message are buffered waiting for the rest
+const CONCAT_PARTS_MAX_AGE: Duration = Duration::from_secs(600);
+
+/// A DR held for a batched send: message ID, PDU and, for a final receipt, the audit
+/// record written once it has actually been sent
+type BatchedDr = (String, Command, Option<AuditRecord>);
 
 pub async fn start_lifecycle_task(
     config: Arc<AppConfig>,
@@ -18,16 +31,23 @@ pub async fn start_lifecycle_task(
     message_queue: Arc<MessageQueue>,
 ) {
     tracing::info!("Lifecycle Manager started");
+    if let Some(spec) = &config.lifecycle.delivery_receipt_tlv
+        && parse_receipt_tlv(spec).is_none() {
+        tracing::warn!("Ignoring lifecycle.delivery_receipt_tlv {:?}: expected tag:hexvalue, e.g. 0x1401:4F4B", spec);
+    }
     
     loop {
-        sleep(Duration::from_millis(config.lifecycle.message_state_check_frequency_ms)).await;
+        // Re-read each tick so a config reload changes odds and delays without a restart
+        let live = session_manager.live_config(&config);
+        sleep(Duration::from_millis(live.lifecycle.message_state_check_frequency_ms)).await;
         
-        process_pending_messages(&config, &session_manager, &message_queue).await;
+        let live = session_manager.live_config(&config);
+        process_pending_messages(&live, &session_manager, &message_queue).await;
         // cleanup_old_messages(&config, &message_queue).await; // TODO: Implement cleanup
     }
 }
 
-async fn process_pending_messages(
+pub(crate) async fn process_pending_messages(
     config: &AppConfig,
     session_manager: &SessionManager,
     message_queue: &MessageQueue,
@@ -35,126 +55,459 @@ async fn process_pending_messages(
     // We need to iterate over pending_dr messages
     // pending_dr is DashMap<String, QueuedMessage>
     
+    retry_unacked_receipts(config, session_manager).await;
+    let expired = message_queue.expire_concat_parts(CONCAT_PARTS_MAX_AGE);
+    if expired > 0 {
+        tracing::warn!("Gave up waiting for the rest of {} concatenated messages", expired);
+    }
+
     let pending_msgs: Vec<QueuedMessage> = message_queue.get_pending_messages();
     
+    // With batching, DRs are grouped per session and queued back to back so the
+    // connection can write them all before a single flush
+    let mut batches: HashMap<String, (Session, Vec<BatchedDr>)> = HashMap::new();
+    
     for msg in pending_msgs {
+        // The interim ENROUTE receipt goes out first; the message stays pending for its final DR
+        if !msg.cancelled && interim_receipt_due(&msg, config) {
+            if let Some(session) = session_manager.get_session(&msg.session_id) {
+                let receipt = match account_dr_template(&session.system_id, config) {
+                    Some(template) => create_delivery_receipt_with_template(&msg, MessageState::Enroute, config, template),
+                    None => create_delivery_receipt(&msg, MessageState::Enroute, config),
+                };
+                if let Some(pdu) = receipt {
+                    tracing::info!("Sending intermediate receipt for message {}", msg.message_id);
+                    session_manager.metrics().delivery_receipts_total.with_label_values(&[receipt_stat(MessageState::Enroute)]).inc();
+                    session_manager.metrics().receipt_rate.record();
+                    if config.lifecycle.batch_dr_sends {
+                        batches.entry(session.id.clone())
+                            .or_insert_with(|| (session, Vec::new()))
+                            .1.push((msg.message_id.clone(), pdu, None));
+                    } else {
+                        send_dr(session_manager, &session, &msg.message_id, pdu).await;
+                    }
+                }
+            }
+            message_queue.mark_interim_receipt_sent(&msg.message_id);
+        }
+
         if let Some(final_state) = check_transition(&msg, config) {
             // Transition occurred!
             tracing::info!("Message {} transitioning to {:?}", msg.message_id, final_state);
             
-            // 1. Generate Delivery Receipt
-            if let Some(pdu) = create_delivery_receipt(&msg, final_state, config) {
-                 // 2. Find Session
-                 if let Some(session) = session_manager.get_session(&msg.session_id) {
+            // 1. Find Session
+            if let Some(session) = session_manager.get_session(&msg.session_id) {
+                 // 2. Generate Delivery Receipt in the submitting account's format
+                 let receipt = match account_dr_template(&session.system_id, config) {
+                     Some(template) => create_delivery_receipt_with_template(&msg, final_state, config, template),
+                     None => create_delivery_receipt(&msg, final_state, config),
+                 };
+                 if let Some(pdu) = receipt {
                      let can_receive = match session.bind_type {
                          BindType::Receiver | BindType::Transceiver => true,
                          BindType::Transmitter => true,
                      };
                      
                      if can_receive {
-                         if let Err(e) = session.send_command(pdu).await {
-                             tracing::error!("Failed to send DR to session {}: {}", session.id, e);
+                         session_manager.metrics().delivery_receipts_total.with_label_values(&[receipt_stat(final_state)]).inc();
+                         session_manager.metrics().receipt_rate.record();
+                         // SME acks follow the receipt of a delivered message, through the same path
+                         let acks = if final_state == MessageState::Delivered { create_sme_acks(&msg, config) } else { vec![] };
+                         if !acks.is_empty() {
+                             tracing::info!("Sending {} SME ack(s) for message {}", acks.len(), msg.message_id);
+                         }
+                         let record = AuditRecord::new(AuditEvent::DeliveryReceipt, &session.system_id, &msg.message_id, &msg.source_addr, &msg.dest_addr)
+                             .with_state(receipt_stat(final_state));
+                         if config.lifecycle.batch_dr_sends {
+                             let batch = &mut batches.entry(session.id.clone())
+                                 .or_insert_with(|| (session, Vec::new()))
+                                 .1;
+                             batch.push((msg.message_id.clone(), pdu, Some(record)));
+                             batch.extend(acks.into_iter().map(|pdu| (msg.message_id.clone(), pdu, None)));
                          } else {
-                             tracing::info!("Sent DR for {} to session {}", msg.message_id, session.id);
+                             if send_dr(session_manager, &session, &msg.message_id, pdu).await {
+                                 session_manager.audit(record);
+                             }
+                             for pdu in acks {
+                                 send_dr(session_manager, &session, &msg.message_id, pdu).await;
+                             }
                          }
                      }
-                 } else {
-                     tracing::warn!("Session {} not found for DR of message {}", msg.session_id, msg.message_id);
                  }
+            } else {
+                 tracing::warn!("Session {} not found for DR of message {}", msg.session_id, msg.message_id);
             }
             
             // 3. Remove from pending queue (it's handled)
             message_queue.remove_pending_dr(&msg.message_id);
+            message_queue.set_final_state(&msg.message_id, final_state);
+            message_queue.record_final_state(final_state);
+        }
+    }
+    
+    for (session, drs) in batches.into_values() {
+        tracing::info!("Sending batch of {} DRs to session {}", drs.len(), session.id);
+        for (message_id, pdu, record) in drs {
+            if send_dr(session_manager, &session, &message_id, pdu).await
+                && let Some(record) = record {
+                session_manager.audit(record);
+            }
         }
     }
 }
 
-#[derive(Debug, Clone, Copy)]
-enum MessageState {
-    Delivered,
-    // Expired, // Reserved for future implementation
-    // Deleted, // Reserved for future implementation
-    Undeliverable,
-    Accepted,
-    // Unknown, // Reserved for future implementation
-    Rejected,
+/// Send a DR and track it until its deliver_sm_resp; returns whether it went out
+async fn send_dr(session_manager: &SessionManager, session: &Session, message_id: &str, pdu: Command) -> bool {
+    let pdu = session.track_receipt(message_id, pdu);
+    let sequence_number = pdu.sequence_number();
+    if let Err(e) = session.send_command(pdu).await {
+        session.ack_receipt(sequence_number);
+        tracing::error!("Failed to send DR to session {}: {}", session.id, e);
+        false
+    } else {
+        session.record_deliver();
+        session_manager.metrics().deliver_total.inc();
+        tracing::info!("Sent DR for {} to session {}", message_id, session.id);
+        true
+    }
+}
+
+/// Re-send delivery receipts whose deliver_sm_resp has not arrived within
+/// `lifecycle.dr_retry_interval_ms`, giving up after `lifecycle.dr_retry_count` re-sends.
+/// Re-sends keep the original sequence number, so a late response still matches.
+/// With retries off, receipts unanswered after the interval are simply forgotten.
+async fn retry_unacked_receipts(config: &AppConfig, session_manager: &SessionManager) {
+    let lifecycle = &config.lifecycle;
+    let interval = Duration::from_millis(lifecycle.dr_retry_interval_ms);
+    for session in session_manager.get_all_sessions() {
+        let mut due = Vec::new();
+        session.unacked_receipts.retain(|sequence_number, receipt| {
+            if receipt.sent_at.elapsed() < interval {
+                return true;
+            }
+            if lifecycle.dr_retry_count == 0 {
+                return false;
+            }
+            if receipt.retries >= lifecycle.dr_retry_count {
+                tracing::warn!("Giving up on DR for {} to session {} after {} retries (seq {})",
+                    receipt.message_id, session.id, receipt.retries, sequence_number);
+                return false;
+            }
+            receipt.retries += 1;
+            receipt.sent_at = Instant::now();
+            due.push((receipt.message_id.clone(), receipt.retries, receipt.pdu.clone()));
+            true
+        });
+        for (message_id, attempt, pdu) in due {
+            tracing::info!("Re-sending unacknowledged DR for {} to session {} (retry {}/{})",
+                message_id, session.id, attempt, lifecycle.dr_retry_count);
+            match session.send_command(pdu).await {
+                Ok(()) => {
+                    session.record_deliver();
+                    session_manager.metrics().deliver_total.inc();
+                }
+                Err(e) => tracing::error!("Failed to re-send DR to session {}: {}", session.id, e),
+            }
+        }
+    }
+}
+
+/// Map a simulated final state to the SMPP `message_state` value
+pub(crate) fn smpp_message_state(state: MessageState) -> SmppMessageState {
+    match state {
+        MessageState::Delivered => SmppMessageState::Delivered,
+        MessageState::Expired => SmppMessageState::Expired,
+        MessageState::Undeliverable => SmppMessageState::Undeliverable,
+        MessageState::Accepted => SmppMessageState::Accepted,
+        MessageState::Rejected => SmppMessageState::Rejected,
+        MessageState::Deleted => SmppMessageState::Deleted,
+        MessageState::Unknown => SmppMessageState::Unknown,
+        MessageState::Enroute => SmppMessageState::Enroute,
+    }
+}
+
+/// Draw a message's time en route from the configured delay distribution
+pub(crate) fn delivery_delay_ms(lifecycle: &LifecycleConfig) -> u64 {
+    let mut rng = rand::rng();
+    match lifecycle.delay_distribution {
+        DelayDistribution::Fixed => lifecycle.max_time_enroute_ms,
+        DelayDistribution::Uniform { min_ms, max_ms } => rng.random_range(min_ms.min(max_ms)..=max_ms.max(min_ms)),
+        DelayDistribution::Exponential { mean_ms } => {
+            // Inverse transform sampling; 1 - u keeps ln() away from zero
+            let u: f64 = rng.random();
+            (-(mean_ms as f64) * (1.0 - u).ln()).round() as u64
+        }
+    }
+}
+
+/// When the time en route starts: at submit, or at the schedule_delivery_time if that is later
+fn enroute_since(msg: &QueuedMessage) -> std::time::Instant {
+    msg.deliver_not_before.map_or(msg.submitted_at, |at| at.max(msg.submitted_at))
+}
+
+/// Whether an intermediate receipt should be sent: halfway through the time en route, once
+fn interim_receipt_due(msg: &QueuedMessage, config: &AppConfig) -> bool {
+    if !config.lifecycle.intermediate_receipts || msg.interim_receipt_sent {
+        return false;
+    }
+    // Not yet en route while held for its schedule_delivery_time
+    let Some(elapsed) = std::time::Instant::now().checked_duration_since(enroute_since(msg)) else {
+        return false;
+    };
+    let delay_ms = msg.delivery_delay_ms.unwrap_or(config.lifecycle.max_time_enroute_ms);
+    elapsed.as_millis() as u64 >= delay_ms / 2
 }
 
 fn check_transition(msg: &QueuedMessage, config: &AppConfig) -> Option<MessageState> {
     let now = std::time::Instant::now();
-    let elapsed = now.duration_since(msg.submitted_at).as_millis() as u64;
+    let delay_ms = msg.delivery_delay_ms.unwrap_or(config.lifecycle.max_time_enroute_ms);
+    // A scheduled message is held until its schedule_delivery_time, then spends the usual time en route
+    let delivery_due = enroute_since(msg) + Duration::from_millis(delay_ms);
     
-    if elapsed >= config.lifecycle.max_time_enroute_ms {
-        // Time to transition!
-        let mut rng = rand::rng();
-        let roll = rng.random_range(0..100);
-        
-        let mut cumulative = 0;
-        
-        cumulative += config.lifecycle.percent_delivered;
-        if roll < cumulative { return Some(MessageState::Delivered); }
-        
-        cumulative += config.lifecycle.percent_undeliverable;
-        if roll < cumulative { return Some(MessageState::Undeliverable); }
-        
-        cumulative += config.lifecycle.percent_accepted;
-        if roll < cumulative { return Some(MessageState::Accepted); }
-        
-        cumulative += config.lifecycle.percent_rejected;
-        if roll < cumulative { return Some(MessageState::Rejected); }
-        
-        // Default fallthrough calculation
-        Some(MessageState::Delivered) 
+    if msg.cancelled {
+        return Some(MessageState::Deleted);
+    }
+    
+    // A validity period that runs out before the simulated delivery time expires the message
+    if let Some(expires_at) = msg.expires_at
+        && expires_at <= delivery_due && now >= expires_at {
+        return Some(MessageState::Expired);
+    }
+    
+    if now >= delivery_due {
+        // Time to transition! The first rule matching the destination and service_type overrides the global odds
+        let lifecycle = &config.lifecycle;
+        let percentages = match lifecycle.rules.iter().find(|rule| {
+            msg.dest_addr.starts_with(&rule.dest_prefix)
+                && rule.service_type.as_ref().is_none_or(|service_type| *service_type == msg.service_type)
+        }) {
+            Some(rule) => {
+                if let Some(name) = &rule.state {
+                    match MessageState::parse(name) {
+                        Some(state) => return Some(state),
+                        None => tracing::warn!("Lifecycle rule for prefix {} has unknown state {:?}", rule.dest_prefix, name),
+                    }
+                }
+                [rule.percent_delivered, rule.percent_undeliverable, rule.percent_accepted, rule.percent_rejected, rule.percent_deleted, rule.percent_unknown]
+            }
+            None => [
+                lifecycle.percent_delivered, lifecycle.percent_undeliverable, lifecycle.percent_accepted,
+                lifecycle.percent_rejected, lifecycle.percent_deleted, lifecycle.percent_unknown,
+            ],
+        };
+        Some(roll_final_state(percentages))
     } else {
         None
     }
 }
 
-fn create_delivery_receipt(msg: &QueuedMessage, state: MessageState, _config: &AppConfig) -> Option<Command> {
-    // Format: id:IIIIIIII sub:001 dlvrd:001 submit date:YYMMDDhhmm done date:YYMMDDhhmm stat:DELIVRD err:000 text:..........
-    let now = chrono::Local::now();
-    let submit_date = now.format("%y%m%d%H%M").to_string(); // Approximate
-    let done_date = now.format("%y%m%d%H%M").to_string();
+/// Pick a final state from [delivered, undeliverable, accepted, rejected, deleted, unknown] percentages
+fn roll_final_state([delivered, undeliverable, accepted, rejected, deleted, unknown]: [u8; 6]) -> MessageState {
+    let mut rng = rand::rng();
+    let roll: u16 = rng.random_range(0..100);
+    
+    let mut cumulative: u16 = 0;
+    
+    cumulative += u16::from(delivered);
+    if roll < cumulative { return MessageState::Delivered; }
+    
+    cumulative += u16::from(undeliverable);
+    if roll < cumulative { return MessageState::Undeliverable; }
+    
+    cumulative += u16::from(accepted);
+    if roll < cumulative { return MessageState::Accepted; }
     
-    let stat_str = match state {
+    cumulative += u16::from(rejected);
+    if roll < cumulative { return MessageState::Rejected; }
+    
+    cumulative += u16::from(deleted);
+    if roll < cumulative { return MessageState::Deleted; }
+    
+    cumulative += u16::from(unknown);
+    if roll < cumulative { return MessageState::Unknown; }
+    
+    // Default fallthrough calculation
+    MessageState::Delivered
+}
+
+/// Error code reported in the receipt for a final state (0 = no error)
+fn receipt_error_code(state: MessageState, config: &AppConfig) -> u16 {
+    match state {
+        MessageState::Undeliverable => config.lifecycle.error_code_undeliverable,
+        MessageState::Rejected => config.lifecycle.error_code_rejected,
+        MessageState::Delivered | MessageState::Expired | MessageState::Accepted
+        | MessageState::Deleted | MessageState::Unknown | MessageState::Enroute => 0,
+    }
+}
+
+/// Receipt text format used when neither the account nor `lifecycle.dr_template` sets one
+pub const DEFAULT_DR_TEMPLATE: &str = "id:{id} sub:001 dlvrd:001 submit date:{submit_date} done date:{done_date} stat:{stat} err:{err} text:{text}";
+
+/// Receipt template configured on the account `system_id`, if it has its own
+fn account_dr_template<'a>(system_id: &str, config: &'a AppConfig) -> Option<&'a str> {
+    config.smpp.accounts.iter()
+        .find(|account| account.system_id == system_id)
+        .and_then(|account| account.dr_template.as_deref())
+}
+
+/// Build a receipt using the global `lifecycle.dr_template` (or the default format)
+pub(crate) fn create_delivery_receipt(msg: &QueuedMessage, state: MessageState, config: &AppConfig) -> Option<Command> {
+    let template = config.lifecycle.dr_template.as_deref().unwrap_or(DEFAULT_DR_TEMPLATE);
+    create_delivery_receipt_with_template(msg, state, config, template)
+}
+
+/// The SME acknowledgements a delivered message's registered_delivery asked for (bits 2-3:
+/// 0x04 delivery ack, 0x08 user ack), when `lifecycle.sme_acks` is on. Each is a deliver_sm
+/// from the recipient with esm_class message type 0x08 (delivery ack) or 0x10 (manual/user
+/// ack), carrying the acknowledged message's ID in receipted_message_id.
+pub(crate) fn create_sme_acks(msg: &QueuedMessage, config: &AppConfig) -> Vec<Command> {
+    if !config.lifecycle.sme_acks {
+        return vec![];
+    }
+    let receipt_id = msg.final_message_id.as_deref().unwrap_or(&msg.message_id);
+    [(0x04, 0x08), (0x08, 0x10)].into_iter()
+        .filter(|(requested, _)| msg.registered_delivery & requested != 0)
+        .map(|(_, message_type)| Command::builder()
+            .status(CommandStatus::EsmeRok)
+            .sequence_number(0) // assigned when tracked for this session
+            .pdu(Pdu::DeliverSm(rusmpp::pdus::DeliverSm::builder()
+                .service_type(ServiceType::new(COctetString::from_str(&msg.service_type).unwrap_or_default()))
+                .source_addr_ton(Ton::from(msg.dest_addr_ton))
+                .source_addr_npi(Npi::from(msg.dest_addr_npi))
+                .source_addr(COctetString::from_str(&msg.dest_addr).unwrap_or_default())
+                .dest_addr_ton(Ton::from(msg.source_addr_ton))
+                .dest_addr_npi(Npi::from(msg.source_addr_npi))
+                .destination_addr(COctetString::from_str(&msg.source_addr).unwrap_or_default())
+                .esm_class(EsmClass::from(message_type))
+                .push_tlv(MessageDeliveryRequestTlvValue::ReceiptedMessageId(
+                    COctetString::from_str(receipt_id).unwrap_or_default(),
+                ))
+                .build())))
+        .collect()
+}
+
+/// TLVs the receipt itself sets; never echoed from the submit
+const RECEIPT_TLV_TAGS: [TlvTag; 4] = [
+    TlvTag::ReceiptedMessageId,
+    TlvTag::MessageState,
+    TlvTag::MessagePayload,
+    TlvTag::NetworkErrorCode,
+];
+
+/// The submit_sm TLVs whose tag is in `allowlist`, re-encoded for the receipt as raw values
+fn echoed_tlvs(msg: &QueuedMessage, allowlist: &[u16]) -> Vec<MessageDeliveryRequestTlvValue> {
+    msg.tlvs.iter()
+        .filter(|tlv| allowlist.contains(&tlv.tag) && !RECEIPT_TLV_TAGS.contains(&TlvTag::from(tlv.tag)))
+        .filter_map(|tlv| hex::decode(&tlv.value).ok().map(|value| MessageDeliveryRequestTlvValue::Other {
+            tag: TlvTag::from(tlv.tag),
+            value: AnyOctetString::from_vec(value),
+        }))
+        .collect()
+}
+
+/// Parse `lifecycle.delivery_receipt_tlv`: a tag (decimal or `0x` hex), a colon, then the value in hex
+pub(crate) fn parse_receipt_tlv(spec: &str) -> Option<(u16, Vec<u8>)> {
+    let (tag, value) = spec.trim().split_once(':')?;
+    let tag = tag.trim();
+    let tag = match tag.strip_prefix("0x").or_else(|| tag.strip_prefix("0X")) {
+        Some(hex) => u16::from_str_radix(hex, 16).ok()?,
+        None => tag.parse::<u16>().ok()?,
+    };
+    Some((tag, hex::decode(value.trim()).ok()?))
+}
+
+/// The DR `stat:` value for a final state
+pub(crate) fn receipt_stat(state: MessageState) -> &'static str {
+    match state {
         MessageState::Delivered => "DELIVRD",
-        // MessageState::Expired => "EXPIRED",
-        // MessageState::Deleted => "DELETED",
+        MessageState::Expired => "EXPIRED",
+        MessageState::Deleted => "DELETED",
         MessageState::Undeliverable => "UNDELIV",
         MessageState::Accepted => "ACCEPTD",
-        // MessageState::Unknown => "UNKNOWN",
+        MessageState::Unknown => "UNKNOWN",
         MessageState::Rejected => "REJECTD",
-    };
+        MessageState::Enroute => "ENROUTE",
+    }
+}
 
-    let short_message = format!(
-        "id:{} sub:001 dlvrd:001 submit date:{} done date:{} stat:{} err:000 text:{}",
-        msg.message_id, submit_date, done_date, stat_str, String::from_utf8_lossy(&msg.short_message).chars().take(20).collect::<String>()
-    );
+/// Build a receipt whose text follows `template`. Placeholders: `{id}`, `{submit_date}`,
+/// `{done_date}`, `{stat}`, `{err}` (3 digits) and `{text}` (first 20 chars of the message).
+pub(crate) fn create_delivery_receipt_with_template(msg: &QueuedMessage, state: MessageState, config: &AppConfig, template: &str) -> Option<Command> {
+    // Default format: id:IIIIIIII sub:001 dlvrd:001 submit date:YYMMDDhhmm done date:YYMMDDhhmm stat:DELIVRD err:000 text:..........
+    // Simulated SMSC clock skew shifts both dates
+    let skew = chrono::Duration::milliseconds(config.lifecycle.clock_skew_ms);
+    let submit_date = (msg.submitted_wall + skew).format("%y%m%d%H%M").to_string();
+    let done_date = (chrono::Local::now() + skew).format("%y%m%d%H%M").to_string();
+    
+    let stat_str = receipt_stat(state);
+
+    // With async message IDs the receipt refers to the final ID, not the provisional one
+    let receipt_id = msg.final_message_id.as_deref().unwrap_or(&msg.message_id);
+
+    let error_code = receipt_error_code(state, config);
 
-    // EsmClass: Message Type = SMSC Delivery Receipt (0x04)
+    let short_message = template
+        .replace("{id}", receipt_id)
+        .replace("{submit_date}", &submit_date)
+        .replace("{done_date}", &done_date)
+        .replace("{stat}", stat_str)
+        .replace("{err}", &format!("{:03}", error_code))
+        .replace("{text}", &decode_short_message(&msg.short_message, msg.data_coding, config.smpp.gsm7_packing).chars().take(20).collect::<String>());
+
+    let receipt_body = if config.smpp.gsm7_packing {
+        gsm7::pack(&short_message)
+    } else {
+        short_message.into_bytes()
+    };
+    let (receipt_text, receipt_payload) = short_message_or_payload(receipt_body);
+
+    // EsmClass: Message Type = SMSC Delivery Receipt (0x04), or Intermediate Delivery
+    // Notification (0x20) for interim receipts
     // Mode = Default (Store and Forward)
+    let message_type = match state {
+        MessageState::Enroute => MessageType::ShortMessageContainsIntermediateDeliveryNotification,
+        _ => MessageType::default(),
+    };
     let esm_class = EsmClass::new(
         MessagingMode::default(),
-        MessageType::default(),
+        message_type,
         Ansi41Specific::default(),
         GsmFeatures::default()
     );
 
+    // SMPP 5.0 clients read these instead of parsing the receipt text
+    let mut tlvs = vec![
+        MessageDeliveryRequestTlvValue::ReceiptedMessageId(
+            COctetString::from_str(receipt_id).unwrap_or_default(),
+        ),
+        MessageDeliveryRequestTlvValue::MessageState(smpp_message_state(state)),
+    ];
+    tlvs.extend(receipt_payload);
+    if error_code != 0 {
+        tlvs.push(MessageDeliveryRequestTlvValue::NetworkErrorCode(
+            NetworkErrorCode::new(ErrorCodeNetworkType::Gsm, error_code),
+        ));
+    }
+    tlvs.extend(echoed_tlvs(msg, &config.lifecycle.echo_tlvs));
+    if let Some((tag, value)) = config.lifecycle.delivery_receipt_tlv.as_deref().and_then(parse_receipt_tlv) {
+        tlvs.push(MessageDeliveryRequestTlvValue::Other { tag: TlvTag::from(tag), value: AnyOctetString::from_vec(value) });
+    }
+
     Some(Command::builder()
         .status(CommandStatus::EsmeRok)
-        .sequence_number(0) // Server initiated, usually 0 or monotonic
+        .sequence_number(0) // assigned when tracked for this session
         .pdu(Pdu::DeliverSm(rusmpp::pdus::DeliverSm::new(
-             // Service Type
-             ServiceType::default(), 
+             // Service Type: echoed from the submit_sm
+             ServiceType::new(COctetString::from_str(&msg.service_type).unwrap_or_default()),
              
-             // Source Addr
-             Ton::Unknown,
-             Npi::Unknown,
+             // Source Addr: the original destination, with its TON/NPI
+             Ton::from(msg.dest_addr_ton),
+             Npi::from(msg.dest_addr_npi),
              COctetString::from_str(&msg.dest_addr).unwrap_or_default(), 
              
-             // Dest Addr
-             Ton::Unknown,
-             Npi::Unknown,
+             // Dest Addr: the original sender
+             Ton::from(msg.source_addr_ton),
+             Npi::from(msg.source_addr_npi),
              COctetString::from_str(&msg.source_addr).unwrap_or_default(), 
              
              // esm_class
@@ -185,10 +538,10 @@ fn create_delivery_receipt(msg: &QueuedMessage, state: MessageState, _config: &A
              0,
              
              // short_message
-             OctetString::from_str(&short_message).unwrap_or_default(),
+             receipt_text,
              
              // tlvs
-             vec![]
+             tlvs
         )))
     )
 }
@@ -198,27 +551,28 @@ mod tests {
     use super::*;
     use crate::config::*; 
     use crate::smpp::queue::QueuedMessage;
+    use rusmpp::tlvs::TlvValue;
     use std::time::Instant;
 
+    /// Default settings; tests replace the sections they exercise
+    fn test_config() -> AppConfig {
+        AppConfig {
+            server: ServerConfig { host: "".into(), port: 0, auth: None },
+            smpp: SmppConfig::default(),
+            log: LogConfig { level: "info".into(), ..Default::default() },
+            lifecycle: LifecycleConfig::default(),
+            mo_service: MoServiceConfig::default(),
+            outbind: vec![],
+            shutdown: ShutdownConfig::default(),
+            audit: AuditConfig::default(),
+        }
+    }
+
     #[test]
     fn test_create_delivery_receipt_structure() {
-        let msg = QueuedMessage {
-            message_id: "test1".to_string(),
-            source_addr: "src".to_string(),
-            dest_addr: "dst".to_string(),
-            short_message: b"hello".to_vec(),
-            data_coding: 0,
-            session_id: "sess".to_string(),
-            submitted_at: Instant::now(),
-        };
+        let msg = QueuedMessage::for_test("test1", "dst");
         
-        let config = AppConfig {
-            server: ServerConfig { host: "".into(), port: 0 },
-            smpp: SmppConfig { system_id: "".into(), password: "".into(), port: 0, max_sessions: 0, accounts: vec![], version: "5.0".into() },
-            log: LogConfig { level: "info".into() },
-            lifecycle: LifecycleConfig::default(),
-            mo_service: MoServiceConfig::default(),
-        };
+        let config = test_config();
         
         let pdu = create_delivery_receipt(&msg, MessageState::Delivered, &config);
         
@@ -227,8 +581,294 @@ mod tests {
         if let Some(Pdu::DeliverSm(req)) = command.pdu() {
             assert_eq!(req.source_addr.to_string(), "dst");
             assert_eq!(req.destination_addr.to_string(), "src");
+
+            let values: Vec<_> = req.tlvs().iter().filter_map(|t| t.value()).collect();
+            assert!(values.iter().any(|v| matches!(v, TlvValue::ReceiptedMessageId(id) if id.to_string() == "test1")));
+            assert!(values.iter().any(|v| matches!(v, TlvValue::MessageState(SmppMessageState::Delivered))));
         } else {
             panic!("Expected DeliverSm PDU");
         }
     }
+
+    /// The `<label>:YYMMDDhhmm` date in a receipt text
+    fn receipt_date(text: &str, label: &str) -> chrono::NaiveDateTime {
+        let start = text.find(&format!("{}:", label)).unwrap_or_else(|| panic!("no {} in {}", label, text)) + label.len() + 1;
+        chrono::NaiveDateTime::parse_from_str(&format!("{}00", &text[start..start + 10]), "%y%m%d%H%M%S").unwrap()
+    }
+
+    /// Whether a minute-precision receipt date falls in the minutes spanning `from..=to`
+    fn within_minutes(date: chrono::NaiveDateTime, from: chrono::DateTime<chrono::Local>, to: chrono::DateTime<chrono::Local>) -> bool {
+        date > from.naive_local() - chrono::Duration::minutes(1) && date <= to.naive_local()
+    }
+
+    #[test]
+    fn test_delivery_receipt_uses_submit_time() {
+        let submitted_wall = chrono::Local::now() - chrono::Duration::hours(2);
+        let msg = QueuedMessage {
+            submitted_wall,
+            ..QueuedMessage::for_test("test2", "dst")
+        };
+        let config = test_config();
+        
+        let before = chrono::Local::now();
+        let command = create_delivery_receipt(&msg, MessageState::Delivered, &config).unwrap();
+        let after = chrono::Local::now();
+        let Some(Pdu::DeliverSm(req)) = command.pdu() else { panic!("Expected DeliverSm PDU") };
+        let text = String::from_utf8_lossy(req.short_message().as_ref()).to_string();
+        
+        let submit_date = submitted_wall.format("%y%m%d%H%M").to_string();
+        assert!(text.contains(&format!("submit date:{}", submit_date)), "DR text {} should carry the submit time", text);
+        // The done date is read from the clock, so it may have ticked over to the next minute
+        assert!(within_minutes(receipt_date(&text, "done date"), before, after), "DR text {} should carry the done time", text);
+    }
+
+    #[test]
+    fn test_delivery_receipt_clock_skew() {
+        let submitted_wall = chrono::Local::now();
+        let msg = QueuedMessage {
+            submitted_wall,
+            ..QueuedMessage::for_test("test5", "dst")
+        };
+        let config = AppConfig {
+            lifecycle: LifecycleConfig { clock_skew_ms: 3_600_000, ..LifecycleConfig::default() },
+            ..test_config()
+        };
+        
+        let command = create_delivery_receipt(&msg, MessageState::Delivered, &config).unwrap();
+        let after = chrono::Local::now();
+        let Some(Pdu::DeliverSm(req)) = command.pdu() else { panic!("Expected DeliverSm PDU") };
+        let text = String::from_utf8_lossy(req.short_message().as_ref()).to_string();
+        
+        // Both dates run one hour ahead of real time
+        let hour = chrono::Duration::hours(1);
+        let skewed = (submitted_wall + hour).format("%y%m%d%H%M").to_string();
+        assert!(text.contains(&format!("submit date:{}", skewed)), "DR text {} should carry the skewed submit time", text);
+        assert!(within_minutes(receipt_date(&text, "done date"), submitted_wall + hour, after + hour),
+            "DR text {} should carry the skewed done time", text);
+    }
+
+    #[test]
+    fn test_delivery_receipt_error_codes() {
+        let msg = QueuedMessage::for_test("test3", "dst");
+        let config = AppConfig {
+            lifecycle: LifecycleConfig { error_code_undeliverable: 13, error_code_rejected: 42, ..LifecycleConfig::default() },
+            ..test_config()
+        };
+        
+        for (state, expected) in [
+            (MessageState::Delivered, 0),
+            (MessageState::Accepted, 0),
+            (MessageState::Undeliverable, 13),
+            (MessageState::Rejected, 42),
+            (MessageState::Deleted, 0),
+            (MessageState::Unknown, 0),
+        ] {
+            let command = create_delivery_receipt(&msg, state, &config).unwrap();
+            let Some(Pdu::DeliverSm(req)) = command.pdu() else { panic!("Expected DeliverSm PDU") };
+            let text = String::from_utf8_lossy(req.short_message().as_ref()).to_string();
+            assert!(text.contains(&format!("err:{:03}", expected)), "{:?}: unexpected DR text {}", state, text);
+            
+            let network_error = req.tlvs().iter().find_map(|t| match t.value() {
+                Some(TlvValue::NetworkErrorCode(code)) => Some(code.error_code),
+                _ => None,
+            });
+            if expected == 0 {
+                assert!(network_error.is_none(), "{:?}: no network_error_code expected", state);
+            } else {
+                assert_eq!(network_error, Some(expected), "{:?}: network_error_code should match err", state);
+            }
+        }
+    }
+
+    #[test]
+    fn test_delivery_receipt_configured_tlv() {
+        assert_eq!(parse_receipt_tlv("0x1401:4F4B"), Some((0x1401, vec![0x4F, 0x4B])));
+        assert_eq!(parse_receipt_tlv("5121 : 00ff"), Some((5121, vec![0x00, 0xFF])));
+        assert_eq!(parse_receipt_tlv("0x1401:"), Some((0x1401, vec![])));
+        assert_eq!(parse_receipt_tlv("0x1401"), None);
+        assert_eq!(parse_receipt_tlv("0x1401:XYZ"), None);
+        assert_eq!(parse_receipt_tlv("0x10000:00"), None);
+
+        let msg = QueuedMessage::for_test("test4", "dst");
+        let config = AppConfig {
+            lifecycle: LifecycleConfig { delivery_receipt_tlv: Some("0x1401:4F4B".into()), ..LifecycleConfig::default() },
+            ..test_config()
+        };
+
+        let command = create_delivery_receipt(&msg, MessageState::Delivered, &config).unwrap();
+        let Some(Pdu::DeliverSm(req)) = command.pdu() else { panic!("Expected DeliverSm PDU") };
+        let custom: Vec<_> = req.tlvs().iter()
+            .filter_map(|t| match t.value() {
+                Some(TlvValue::Other { tag, value }) => Some((u16::from(*tag), value.as_ref().to_vec())),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(custom, vec![(0x1401, b"OK".to_vec())]);
+        // The standard receipt TLVs are still there
+        assert!(req.tlvs().iter().any(|t| matches!(t.value(), Some(TlvValue::ReceiptedMessageId(_)))));
+    }
+
+    #[test]
+    fn test_validity_period_expiry() {
+        let mut msg = QueuedMessage {
+            expires_at: Some(Instant::now()),
+            ..QueuedMessage::for_test("test4", "dst")
+        };
+        let config = test_config();
+        
+        // Validity ran out before max_time_enroute_ms
+        let state = check_transition(&msg, &config);
+        assert!(matches!(state, Some(MessageState::Expired)), "Expected EXPIRED, got {:?}", state);
+        
+        let command = create_delivery_receipt(&msg, MessageState::Expired, &config).unwrap();
+        let Some(Pdu::DeliverSm(req)) = command.pdu() else { panic!("Expected DeliverSm PDU") };
+        let text = String::from_utf8_lossy(req.short_message().as_ref()).to_string();
+        assert!(text.contains("stat:EXPIRED"), "Unexpected DR text {}", text);
+        
+        // Without a validity period the normal enroute timer applies
+        msg.expires_at = None;
+        assert!(check_transition(&msg, &config).is_none());
+    }
+
+    #[test]
+    fn test_schedule_delivery_time_defers_transition() {
+        let mut msg = QueuedMessage {
+            deliver_not_before: Some(Instant::now() + Duration::from_secs(60)),
+            delivery_delay_ms: Some(0),
+            ..QueuedMessage::for_test("test5", "dst")
+        };
+        let config = AppConfig {
+            lifecycle: LifecycleConfig { intermediate_receipts: true, ..LifecycleConfig::default() },
+            ..test_config()
+        };
+
+        // Held until the scheduled time, even with no time en route
+        assert!(check_transition(&msg, &config).is_none());
+        assert!(!interim_receipt_due(&msg, &config));
+
+        // A validity period ending before the scheduled time expires it instead
+        msg.expires_at = Some(Instant::now());
+        assert!(matches!(check_transition(&msg, &config), Some(MessageState::Expired)));
+        msg.expires_at = None;
+
+        // Once the scheduled time is reached the usual roll applies
+        msg.deliver_not_before = Some(Instant::now());
+        assert!(check_transition(&msg, &config).is_some());
+    }
+
+    #[test]
+    fn test_delivery_delay_distribution() {
+        let mut lifecycle = LifecycleConfig { max_time_enroute_ms: 1234, ..LifecycleConfig::default() };
+        assert_eq!(delivery_delay_ms(&lifecycle), 1234);
+
+        lifecycle.delay_distribution = DelayDistribution::Uniform { min_ms: 100, max_ms: 200 };
+        let samples: Vec<u64> = (0..1000).map(|_| delivery_delay_ms(&lifecycle)).collect();
+        assert!(samples.iter().all(|d| (100..=200).contains(d)));
+        assert!(samples.iter().any(|&d| d < 150) && samples.iter().any(|&d| d > 150), "Delays should be spread out");
+
+        lifecycle.delay_distribution = DelayDistribution::Exponential { mean_ms: 1000 };
+        let mean = (0..10_000).map(|_| delivery_delay_ms(&lifecycle)).sum::<u64>() as f64 / 10_000.0;
+        assert!((900.0..1100.0).contains(&mean), "Sample mean {} should be near 1000", mean);
+    }
+
+    #[test]
+    fn test_check_transition_uses_per_message_delay() {
+        let mut msg = QueuedMessage {
+            delivery_delay_ms: Some(0),
+            ..QueuedMessage::for_test("test5", "dst")
+        };
+        let mut config = AppConfig {
+            lifecycle: LifecycleConfig { max_time_enroute_ms: 60_000, ..LifecycleConfig::default() },
+            ..test_config()
+        };
+        assert!(check_transition(&msg, &config).is_some(), "Per-message delay overrides max_time_enroute_ms");
+
+        msg.delivery_delay_ms = Some(60_000);
+        config.lifecycle.max_time_enroute_ms = 0;
+        assert!(check_transition(&msg, &config).is_none());
+    }
+
+    #[test]
+    fn test_delay_distribution_from_toml() {
+        let parse = |toml: &str| -> DelayDistribution {
+            config::Config::builder()
+                .add_source(config::File::from_str(toml, config::FileFormat::Toml))
+                .build().unwrap()
+                .get("delay_distribution").unwrap()
+        };
+        assert_eq!(parse(r#"delay_distribution = { type = "fixed" }"#), DelayDistribution::Fixed);
+        assert_eq!(parse(r#"delay_distribution = { type = "uniform", min_ms = 500, max_ms = 3000 }"#),
+            DelayDistribution::Uniform { min_ms: 500, max_ms: 3000 });
+        assert_eq!(parse(r#"delay_distribution = { type = "exponential", mean_ms = 2000 }"#),
+            DelayDistribution::Exponential { mean_ms: 2000 });
+    }
+
+    #[test]
+    fn test_lifecycle_rules_by_dest_prefix() {
+        let msg_to = |dest: &str| QueuedMessage {
+            delivery_delay_ms: Some(0),
+            ..QueuedMessage::for_test("test6", dest)
+        };
+        let config = AppConfig {
+            lifecycle: LifecycleConfig {
+                percent_delivered: 100,
+                percent_undeliverable: 0,
+                percent_accepted: 0,
+                percent_rejected: 0,
+                rules: vec![
+                    LifecycleRule { dest_prefix: "666".into(), state: Some("UNDELIV".into()), ..Default::default() },
+                    LifecycleRule { dest_prefix: "66".into(), state: Some("expired".into()), ..Default::default() },
+                    LifecycleRule { dest_prefix: "777".into(), percent_rejected: 100, ..Default::default() },
+                    LifecycleRule { dest_prefix: "888".into(), percent_deleted: 100, ..Default::default() },
+                    LifecycleRule { dest_prefix: "999".into(), state: Some("unknown".into()), ..Default::default() },
+                ],
+                ..LifecycleConfig::default()
+            },
+            ..test_config()
+        };
+
+        for _ in 0..50 {
+            assert_eq!(check_transition(&msg_to("666123"), &config), Some(MessageState::Undeliverable), "First matching rule wins");
+            assert_eq!(check_transition(&msg_to("667123"), &config), Some(MessageState::Expired));
+            assert_eq!(check_transition(&msg_to("777123"), &config), Some(MessageState::Rejected));
+            assert_eq!(check_transition(&msg_to("888123"), &config), Some(MessageState::Deleted));
+            assert_eq!(check_transition(&msg_to("999123"), &config), Some(MessageState::Unknown));
+            assert_eq!(check_transition(&msg_to("123666"), &config), Some(MessageState::Delivered), "Falls back to global percentages");
+        }
+    }
+
+    #[test]
+    fn test_lifecycle_rules_by_service_type() {
+        let msg_with = |service_type: &str| QueuedMessage {
+            delivery_delay_ms: Some(0),
+            service_type: service_type.to_string(),
+            ..QueuedMessage::for_test("test7", "123456")
+        };
+        let config = AppConfig {
+            lifecycle: LifecycleConfig {
+                percent_delivered: 100,
+                percent_undeliverable: 0,
+                percent_accepted: 0,
+                percent_rejected: 0,
+                rules: vec![
+                    LifecycleRule { service_type: Some("VMN".into()), state: Some("REJECTD".into()), ..Default::default() },
+                    LifecycleRule { dest_prefix: "123".into(), service_type: Some("WAP".into()), percent_deleted: 100, ..Default::default() },
+                ],
+                ..LifecycleConfig::default()
+            },
+            ..test_config()
+        };
+
+        for _ in 0..20 {
+            assert_eq!(check_transition(&msg_with("VMN"), &config), Some(MessageState::Rejected));
+            assert_eq!(check_transition(&msg_with("WAP"), &config), Some(MessageState::Deleted));
+            assert_eq!(check_transition(&msg_with("CMT"), &config), Some(MessageState::Delivered));
+            assert_eq!(check_transition(&msg_with(""), &config), Some(MessageState::Delivered));
+        }
+
+        // The receipt carries the submit's service_type
+        let command = create_delivery_receipt(&msg_with("VMN"), MessageState::Rejected, &config).unwrap();
+        let Some(Pdu::DeliverSm(req)) = command.pdu() else { panic!("Expected DeliverSm PDU") };
+        assert_eq!(req.service_type.value().to_string(), "VMN");
+    }
 }
@@ -0,0 +1,171 @@
+//! Prometheus metrics
+//!
+//! Counters are bumped where the traffic happens (submit handling, DR and MO
+//! dispatch); gauges are sampled from the session manager and message queue when
+//! `/metrics` is scraped. Submit and receipt rates for the dashboard are kept
+//! alongside in per-second rings, so they need no Prometheus query.
+
+use prometheus::{Encoder, IntCounter, IntCounterVec, IntGauge, Opts, Registry, TextEncoder};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Instant;
+
+/// Seconds of history kept by a `RateCounter`
+pub const RATE_WINDOW_SECS: u64 = 60;
+
+pub struct Metrics {
+    registry: Registry,
+    pub sessions_active: IntGauge,
+    pub submit_total: IntCounter,
+    pub deliver_total: IntCounter,
+    pub pending_dr: IntGauge,
+    /// Delivery receipts generated, labelled by DR `stat` (DELIVRD, UNDELIV, ...)
+    pub delivery_receipts_total: IntCounterVec,
+    /// Delivery receipts answered by deliver_sm_resp, labelled by response status (ok, error)
+    pub delivery_receipts_acked_total: IntCounterVec,
+    /// submit_sm accepted per second
+    pub submit_rate: RateCounter,
+    /// Delivery receipts generated per second
+    pub receipt_rate: RateCounter,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        let registry = Registry::new();
+        let sessions_active = IntGauge::new("smpp_sessions_active", "Currently bound SMPP sessions").unwrap();
+        let submit_total = IntCounter::new("smpp_submit_total", "submit_sm accepted").unwrap();
+        let deliver_total = IntCounter::new("smpp_deliver_total", "deliver_sm sent (DRs and MO)").unwrap();
+        let pending_dr = IntGauge::new("smpp_pending_dr", "Messages waiting for their delivery receipt").unwrap();
+        let delivery_receipts_total = IntCounterVec::new(
+            Opts::new("smpp_delivery_receipts_total", "Delivery receipts generated by final state"),
+            &["state"],
+        ).unwrap();
+        let delivery_receipts_acked_total = IntCounterVec::new(
+            Opts::new("smpp_delivery_receipts_acked_total", "Delivery receipts answered by deliver_sm_resp"),
+            &["status"],
+        ).unwrap();
+
+        registry.register(Box::new(sessions_active.clone())).unwrap();
+        registry.register(Box::new(submit_total.clone())).unwrap();
+        registry.register(Box::new(deliver_total.clone())).unwrap();
+        registry.register(Box::new(pending_dr.clone())).unwrap();
+        registry.register(Box::new(delivery_receipts_total.clone())).unwrap();
+        registry.register(Box::new(delivery_receipts_acked_total.clone())).unwrap();
+
+        Self {
+            registry,
+            sessions_active,
+            submit_total,
+            deliver_total,
+            pending_dr,
+            delivery_receipts_total,
+            delivery_receipts_acked_total,
+            submit_rate: RateCounter::new(),
+            receipt_rate: RateCounter::new(),
+        }
+    }
+
+    /// Text exposition of every registered metric
+    pub fn render(&self) -> String {
+        let mut buffer = Vec::new();
+        if let Err(e) = TextEncoder::new().encode(&self.registry.gather(), &mut buffer) {
+            tracing::error!("Failed to encode metrics: {}", e);
+        }
+        String::from_utf8(buffer).unwrap_or_default()
+    }
+}
+
+/// Events per second over the last `RATE_WINDOW_SECS`, as a ring of per-second buckets.
+/// Each slot remembers which second it counts and is reset when that second comes round again.
+pub struct RateCounter {
+    started: Instant,
+    counts: [AtomicU64; RATE_WINDOW_SECS as usize + 1],
+    seconds: [AtomicU64; RATE_WINDOW_SECS as usize + 1],
+}
+
+impl Default for RateCounter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RateCounter {
+    pub fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            counts: std::array::from_fn(|_| AtomicU64::new(0)),
+            seconds: std::array::from_fn(|_| AtomicU64::new(0)),
+        }
+    }
+
+    fn now(&self) -> u64 {
+        self.started.elapsed().as_secs()
+    }
+
+    /// Count one event in the current second
+    pub fn record(&self) {
+        self.record_at(self.now());
+    }
+
+    fn record_at(&self, second: u64) {
+        let slot = (second % self.counts.len() as u64) as usize;
+        let stamp = self.seconds[slot].load(Ordering::Acquire);
+        if stamp != second && self.seconds[slot].compare_exchange(stamp, second, Ordering::AcqRel, Ordering::Acquire).is_ok() {
+            self.counts[slot].store(0, Ordering::Release);
+        }
+        self.counts[slot].fetch_add(1, Ordering::AcqRel);
+    }
+
+    /// Events in the last complete second
+    pub fn last_second(&self) -> u64 {
+        self.sum_before(self.now(), 1)
+    }
+
+    /// Mean events per second over the last `RATE_WINDOW_SECS` complete seconds, or since start if sooner
+    pub fn average(&self) -> f64 {
+        let now = self.now();
+        let secs = now.clamp(1, RATE_WINDOW_SECS);
+        self.sum_before(now, secs) as f64 / secs as f64
+    }
+
+    /// Events in the `secs` complete seconds before `now`
+    fn sum_before(&self, now: u64, secs: u64) -> u64 {
+        (now.saturating_sub(secs)..now)
+            .map(|second| {
+                let slot = (second % self.counts.len() as u64) as usize;
+                if self.seconds[slot].load(Ordering::Acquire) == second { self.counts[slot].load(Ordering::Acquire) } else { 0 }
+            })
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rate_counter_buckets() {
+        let rate = RateCounter::new();
+        for _ in 0..3 {
+            rate.record_at(10);
+        }
+        rate.record_at(11);
+        rate.record_at(12);
+
+        // The current second is still filling, so it is left out
+        assert_eq!(rate.sum_before(12, 1), 1);
+        assert_eq!(rate.sum_before(12, 5), 4);
+        assert_eq!(rate.sum_before(13, 3), 5);
+
+        // Second 10's slot is reused once it has left the window; 12 is still inside it
+        let reused = 10 + RATE_WINDOW_SECS + 1;
+        rate.record_at(reused);
+        assert_eq!(rate.sum_before(reused + 1, 1), 1);
+        assert_eq!(rate.sum_before(reused + 1, RATE_WINDOW_SECS), 2);
+    }
+}
@@ -1,12 +1,14 @@
 use std::sync::Arc;
 use tokio::time::{sleep, Duration};
 use crate::config::AppConfig;
-use crate::smpp::session::SessionManager;
+use crate::smpp::audit::{AuditEvent, AuditRecord};
+use crate::smpp::session::{BindType, SessionManager};
+use crate::smpp::encoding::{encode_text, short_message_or_payload};
 use crate::smpp::queue::{MoMessageQueue, MoMessage};
 use std::fs::File;
 use std::io::{BufRead, BufReader};
 use rusmpp::{Command, Pdu, CommandStatus};
-use rusmpp::types::{COctetString, OctetString, EmptyOrFullCOctetString};
+use rusmpp::types::{COctetString, EmptyOrFullCOctetString};
 use rusmpp::values::{
     Ton, Npi, EsmClass, PriorityFlag, RegisteredDelivery, ReplaceIfPresentFlag, DataCoding,
     ServiceType
@@ -25,9 +27,9 @@ pub async fn start_mo_service_task(
     }
     
     tracing::info!("MO Service started");
+    mo_queue.set_running(true);
     
     let rate = config.mo_service.delivery_messages_per_minute;
-    let period_ms = if rate > 0 { 60000 / rate as u64 } else { 1000 }; // Default wait if 0/error
     
     // We need a loop that:
     // 1. Reads user injection from queue (Web UI)
@@ -37,102 +39,210 @@ pub async fn start_mo_service_task(
     // Task 1: Web Injection Dispatcher
     let queue_manager = session_manager.clone();
     let queue_recv = mo_queue.clone();
+    let queue_config = config.clone();
     tokio::spawn(async move {
-        process_injected_messages(queue_recv, queue_manager).await;
+        process_injected_messages(queue_recv, queue_manager, queue_config).await;
     });
 
-    if rate > 0 {
-        // Task 2: CSV Injection
-         loop {
-             match File::open(&config.mo_service.file_path) {
-                 Ok(file) => {
-                     let reader = BufReader::new(file);
-                     for line in reader.lines() {
-                         if let Ok(content) = line {
-                             if content.trim().is_empty() || content.starts_with('#') { continue; }
-                             
-                             let parts: Vec<&str> = content.split(',').collect();
-                             if parts.len() >= 3 {
-                                 let source = parts[0].trim();
-                                 let dest = parts[1].trim();
-                                 let msg_content = parts[2..].join(","); // Join remaining in case msg has comma
-                                 
-                                 // Inject
-                                 let mo = MoMessage {
-                                     source_addr: source.to_string(),
-                                     dest_addr: dest.to_string(),
-                                     short_message: msg_content,
-                                 };
-                                 
-                                 dispatch_mo(&mo, &session_manager).await;
-                                 
-                                 // Wait for rate limit
-                                 sleep(Duration::from_millis(period_ms)).await;
-                             }
-                         }
-                     }
-                 }
-                 Err(e) => {
-                     tracing::error!("Failed to open MO messages file {}: {}", config.mo_service.file_path, e);
-                     sleep(Duration::from_secs(10)).await; // Wait before retry
-                 }
-             }
-             // Loop file again? SMPPSim behavior dictates looping usually or restart?
-             // Assuming loop for traffic generation
-             tracing::info!("MO CSV file finished, restarting...");
-         }
+    // Task 2: Retry MO messages parked while no receiver matched
+    let park_manager = session_manager.clone();
+    let park_queue = mo_queue.clone();
+    let park_config = config.clone();
+    tokio::spawn(async move {
+        loop {
+            sleep(PARKED_RETRY_PERIOD).await;
+            retry_parked(&park_queue, &park_manager, &park_manager.live_config(&park_config)).await;
+        }
+    });
+
+    // Task 3: CSV Injection. Looping replays need a rate; a single pass runs flat out without one
+    let run_once = !config.mo_service.loop_file;
+    if rate > 0 || run_once {
+        loop {
+            // A reloaded rate takes effect from the next pass; a looping replay pauses at rate 0
+            let live = session_manager.live_config(&config);
+            let rate = live.mo_service.delivery_messages_per_minute;
+            if rate == 0 && !run_once {
+                sleep(Duration::from_secs(10)).await;
+                continue;
+            }
+            let period = (rate > 0).then(|| Duration::from_millis(60000 / rate as u64));
+            let replayed = replay_csv(&live, &session_manager, &mo_queue, period).await;
+            if run_once {
+                tracing::info!("MO CSV file finished, not looping (mo_service.loop = false)");
+                break;
+            }
+            if replayed {
+                tracing::info!("MO CSV file finished, restarting...");
+            } else {
+                sleep(Duration::from_secs(10)).await; // Wait before retry
+            }
+        }
+    }
+}
+
+/// Dispatch every row of the MO CSV file once, `period` apart when set.
+/// Returns false if the file could not be opened.
+async fn replay_csv(config: &AppConfig, session_manager: &SessionManager, mo_queue: &MoMessageQueue, period: Option<Duration>) -> bool {
+    let file = match File::open(&config.mo_service.file_path) {
+        Ok(file) => file,
+        Err(e) => {
+            tracing::error!("Failed to open MO messages file {}: {}", config.mo_service.file_path, e);
+            mo_queue.record_csv_open_error(e.to_string());
+            return false;
+        }
+    };
+    let reader = BufReader::new(file);
+    let mut dispatched = 0;
+    for (index, content) in reader.lines().map_while(Result::ok).enumerate() {
+        if content.trim().is_empty() || content.starts_with('#') { continue; }
+
+        let mo = match parse_csv_line(&content) {
+            Ok(mo) => mo,
+            Err(e) => {
+                tracing::warn!("Skipping MO CSV line {}: {}", index + 1, e);
+                mo_queue.record_csv_error(index + 1, &content, e);
+                continue;
+            }
+        };
+        dispatch_mo(&mo, session_manager, mo_queue, config).await;
+        dispatched += 1;
+
+        // Wait for rate limit
+        if let Some(period) = period {
+            sleep(period).await;
+        }
     }
+    mo_queue.record_csv_pass(dispatched);
+    true
 }
 
-async fn process_injected_messages(mo_queue: Arc<MoMessageQueue>, session_manager: Arc<SessionManager>) {
+/// Parse a CSV row: `source,dest,message[,data_coding,source_ton,source_npi]`.
+/// The message may contain commas; the three trailing columns are only taken as such
+/// when all of them are numbers.
+pub(crate) fn parse_csv_line(line: &str) -> Result<MoMessage, String> {
+    let parts: Vec<&str> = line.split(',').collect();
+    if parts.len() < 3 {
+        return Err(format!("expected at least 3 columns (source,dest,message), found {}", parts.len()));
+    }
+    if parts[0].trim().is_empty() {
+        return Err("empty source address".to_string());
+    }
+    if parts[1].trim().is_empty() {
+        return Err("empty destination address".to_string());
+    }
+    let (message_parts, extra) = match parts.len() {
+        n if n >= 6 => {
+            let numbers: Option<Vec<u8>> = parts[n - 3..].iter().map(|p| p.trim().parse::<u8>().ok()).collect();
+            match numbers {
+                Some(numbers) => (&parts[2..n - 3], Some(numbers)),
+                None => (&parts[2..], None),
+            }
+        }
+        _ => (&parts[2..], None),
+    };
+    Ok(MoMessage {
+        source_addr: parts[0].trim().to_string(),
+        dest_addr: parts[1].trim().to_string(),
+        short_message: message_parts.join(","), // Join remaining in case msg has comma
+        data_coding: extra.as_ref().map(|n| n[0]),
+        source_ton: extra.as_ref().map(|n| n[1]),
+        source_npi: extra.as_ref().map(|n| n[2]),
+    })
+}
+
+async fn process_injected_messages(mo_queue: Arc<MoMessageQueue>, session_manager: Arc<SessionManager>, config: Arc<AppConfig>) {
     // We need to take the receiver from mutex
     if let Some(mut rx) = mo_queue.take_receiver() {
         while let Some(msg) = rx.recv().await {
-            dispatch_mo(&msg, &session_manager).await;
+            dispatch_mo(&msg, &session_manager, &mo_queue, &session_manager.live_config(&config)).await;
         }
     } else {
         tracing::error!("Failed to take MO queue receiver - already taken?");
     }
 }
 
-async fn dispatch_mo(msg: &MoMessage, session_manager: &SessionManager) {
-    // Find subscriber
-    if let Some(session) = session_manager.find_subscriber(&msg.dest_addr) {
+/// How often parked MO messages are offered to the bound sessions again
+const PARKED_RETRY_PERIOD: Duration = Duration::from_secs(1);
+
+async fn dispatch_mo(msg: &MoMessage, session_manager: &SessionManager, mo_queue: &MoMessageQueue, config: &AppConfig) {
+    if deliver_mo(msg, session_manager, config).await {
+        return;
+    }
+    // Delayed inbound queue: hold it until a matching receiver binds
+    if mo_queue.park(msg.clone()) {
+        tracing::info!("No suitable session found for MO to {}, parked ({} waiting)", msg.dest_addr, mo_queue.parked_count());
+    } else {
+        tracing::warn!("No suitable session found for MO to {} and the parked queue is full, dropping", msg.dest_addr);
+    }
+}
+
+/// Offer each parked MO message to the bound sessions again, dropping expired ones
+pub(crate) async fn retry_parked(mo_queue: &MoMessageQueue, session_manager: &SessionManager, config: &AppConfig) {
+    let max_age = Duration::from_millis(config.mo_service.parked_max_age_ms);
+    for parked in mo_queue.take_parked(max_age) {
+        if deliver_mo(&parked.msg, session_manager, config).await {
+            continue;
+        }
+        let dest_addr = parked.msg.dest_addr.clone();
+        if !mo_queue.repark(parked) {
+            tracing::warn!("Parked queue is full, dropping parked MO to {}", dest_addr);
+        }
+    }
+}
+
+/// Send an MO message to a matching session; false if none is bound
+async fn deliver_mo(msg: &MoMessage, session_manager: &SessionManager, config: &AppConfig) -> bool {
+    // Find subscriber, honouring the configured receiver/transceiver preference
+    let preferred = config.mo_service.prefer.as_deref().and_then(BindType::parse);
+    if let Some(session) = session_manager.find_subscriber_preferring(&msg.dest_addr, preferred.as_ref()) {
         tracing::info!("Delivering MO from {} to {} via session {}", msg.source_addr, msg.dest_addr, session.id);
         
-        if let Some(pdu) = create_deliver_sm(msg) {
-             if let Err(e) = session.send_command(pdu).await {
-                 tracing::error!("Failed to send MO to session {}: {}", session.id, e);
-             }
+        if let Some(pdu) = create_deliver_sm(msg, config) {
+            match session.send_command(pdu).await {
+                Ok(()) => {
+                    session.record_deliver();
+                    session_manager.metrics().deliver_total.inc();
+                    session_manager.audit(AuditRecord::new(AuditEvent::MoDeliverSm, &session.system_id, "", &msg.source_addr, &msg.dest_addr));
+                }
+                Err(e) => tracing::error!("Failed to send MO to session {}: {}", session.id, e),
+            }
         }
+        true
     } else {
-        tracing::warn!("No suitable session found for MO to {}", msg.dest_addr);
-        // Add to "Delayed Inbound Queue"? (TODO)
-        // For now just drop/log
+        false
     }
 }
 
-fn create_deliver_sm(msg: &MoMessage) -> Option<Command> {
+pub(crate) fn create_deliver_sm(msg: &MoMessage, config: &AppConfig) -> Option<Command> {
     // Determine if binary
-    let (short_message, data_coding) = if msg.short_message.starts_with("0x") {
+    let (body, data_coding) = if let Some(coding) = msg.data_coding {
+        // Explicit coding: hex content is sent as bytes, text is encoded for the coding
+        let bytes = msg.short_message.strip_prefix("0x")
+            .and_then(|hex_str| hex::decode(hex_str).ok())
+            .unwrap_or_else(|| encode_text(&msg.short_message, coding, config.smpp.gsm7_packing));
+        (bytes, DataCoding::from(coding))
+    } else if msg.short_message.starts_with("0x") {
         if let Ok(bytes) = hex::decode(&msg.short_message[2..]) {
-            (OctetString::from_bytes(bytes.into()).unwrap_or_default(), DataCoding::default()) // 8-bit binary
+            (bytes, DataCoding::default()) // 8-bit binary
         } else {
-             (OctetString::from_str(&msg.short_message).unwrap_or_default(), DataCoding::default())
+             (encode_text(&msg.short_message, 0x00, config.smpp.gsm7_packing), DataCoding::default())
         }
     } else {
-        (OctetString::from_str(&msg.short_message).unwrap_or_default(), DataCoding::default())
+        (encode_text(&msg.short_message, 0x00, config.smpp.gsm7_packing), DataCoding::default())
     };
+    // Bodies too long for short_message are sent in message_payload
+    let (short_message, payload) = short_message_or_payload(body);
 
     Some(Command::builder()
         .status(CommandStatus::EsmeRok)
-        .sequence_number(0)
+        .sequence_number(0) // assigned by the session when sent
         .pdu(Pdu::DeliverSm(rusmpp::pdus::DeliverSm::new(
              ServiceType::default(),
              
              // Source Addr (The sender of the MO)
-             Ton::Unknown,
-             Npi::Unknown,
+             msg.source_ton.map_or(Ton::Unknown, Ton::from),
+             msg.source_npi.map_or(Npi::Unknown, Npi::from),
              COctetString::from_str(&msg.source_addr).unwrap_or_default(),
              
              // Dest Addr (The ESME receiving it)
@@ -152,7 +262,7 @@ fn create_deliver_sm(msg: &MoMessage) -> Option<Command> {
              data_coding,
              0,
              short_message,
-             vec![]
+             payload.into_iter().collect()
         )))
     )
 }
@@ -160,6 +270,141 @@ fn create_deliver_sm(msg: &MoMessage) -> Option<Command> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::config::*;
+    use crate::smpp::session::Session;
+    use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+    use tokio::sync::mpsc;
+
+    fn test_config(prefer: Option<&str>) -> AppConfig {
+        AppConfig {
+            server: ServerConfig { host: "".into(), port: 0, auth: None },
+            smpp: SmppConfig::default(),
+            log: LogConfig { level: "info".into(), ..Default::default() },
+            lifecycle: LifecycleConfig::default(),
+            mo_service: MoServiceConfig { prefer: prefer.map(String::from), ..MoServiceConfig::default() },
+            outbind: vec![],
+            shutdown: ShutdownConfig::default(),
+            audit: AuditConfig::default(),
+        }
+    }
+
+    async fn dispatch_with_preference(prefer: &str) -> (bool, bool) {
+        let config = test_config(Some(prefer));
+        let session_manager = SessionManager::new();
+        let addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 12345);
+        let (rx_tx, mut rx_rx) = mpsc::channel(1);
+        let (trx_tx, mut trx_rx) = mpsc::channel(1);
+        session_manager.add_session(Session::new("rx".into(), BindType::Receiver, addr, rx_tx, Some("^123".into())));
+        session_manager.add_session(Session::new("trx".into(), BindType::Transceiver, addr, trx_tx, Some("^123".into())));
+
+        let msg = MoMessage {
+            source_addr: "src".to_string(),
+            dest_addr: "12345".to_string(),
+            short_message: "hello".to_string(),
+            data_coding: None,
+            source_ton: None,
+            source_npi: None,
+        };
+        dispatch_mo(&msg, &session_manager, &MoMessageQueue::new(), &config).await;
+
+        (rx_rx.try_recv().is_ok(), trx_rx.try_recv().is_ok())
+    }
+
+    #[tokio::test]
+    async fn test_dispatch_mo_prefers_configured_bind_type() {
+        assert_eq!(dispatch_with_preference("receiver").await, (true, false));
+        assert_eq!(dispatch_with_preference("transceiver").await, (false, true));
+    }
+
+    #[tokio::test]
+    async fn test_undeliverable_mo_is_parked_until_receiver_binds() {
+        let mut config = test_config(None);
+        let session_manager = SessionManager::new();
+        let mo_queue = MoMessageQueue::new();
+        mo_queue.set_park_capacity(1);
+        let msg = |dest: &str| MoMessage {
+            source_addr: "src".to_string(),
+            dest_addr: dest.to_string(),
+            short_message: "hello".to_string(),
+            data_coding: None,
+            source_ton: None,
+            source_npi: None,
+        };
+
+        dispatch_mo(&msg("12345"), &session_manager, &mo_queue, &config).await;
+        // Over capacity: dropped rather than parked
+        dispatch_mo(&msg("12346"), &session_manager, &mo_queue, &config).await;
+        assert_eq!(mo_queue.parked_count(), 1);
+
+        retry_parked(&mo_queue, &session_manager, &config).await;
+        assert_eq!(mo_queue.parked_count(), 1);
+
+        let addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 12345);
+        let (tx, mut rx) = mpsc::channel(1);
+        session_manager.add_session(Session::new("rx".into(), BindType::Receiver, addr, tx, Some("^123".into())));
+        retry_parked(&mo_queue, &session_manager, &config).await;
+        assert_eq!(mo_queue.parked_count(), 0);
+        let cmd = rx.try_recv().expect("parked MO delivered");
+        let Some(Pdu::DeliverSm(req)) = cmd.pdu() else { panic!("Expected DeliverSm") };
+        assert_eq!(req.destination_addr.to_string(), "12345");
+
+        // Expired entries are discarded instead of retried
+        config.mo_service.parked_max_age_ms = 0;
+        dispatch_mo(&msg("999"), &session_manager, &mo_queue, &config).await;
+        assert_eq!(mo_queue.parked_count(), 1);
+        retry_parked(&mo_queue, &session_manager, &config).await;
+        assert_eq!(mo_queue.parked_count(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_csv_run_once_without_rate() {
+        let path = std::env::temp_dir().join(format!("mo_run_once_{}.csv", std::process::id()));
+        std::fs::write(&path, "# source,dest,message\n111,12345,first\n\n222,12345,second, with comma\nbroken row\n").unwrap();
+        let mut config = test_config(None);
+        config.mo_service.enabled = true;
+        config.mo_service.loop_file = false;
+        config.mo_service.file_path = path.to_string_lossy().into_owned();
+        let session_manager = Arc::new(SessionManager::new());
+        let mo_queue = Arc::new(MoMessageQueue::new());
+
+        // Returns after one pass instead of replaying the file forever
+        tokio::time::timeout(Duration::from_secs(2), start_mo_service_task(Arc::new(config), session_manager, mo_queue.clone()))
+            .await
+            .expect("run-once CSV task should finish");
+        std::fs::remove_file(&path).unwrap();
+
+        // No receiver is bound, so both rows end up parked
+        let parked = mo_queue.take_parked(Duration::from_secs(60));
+        let bodies: Vec<&str> = parked.iter().map(|p| p.msg.short_message.as_str()).collect();
+        assert_eq!(bodies, vec!["first", "second, with comma"]);
+
+        let status = mo_queue.csv_status();
+        assert_eq!((status.file_found, status.passes, status.last_pass_dispatched), (Some(true), 1, 2));
+        assert_eq!(status.errors.len(), 1);
+        assert_eq!((status.errors[0].line, status.errors[0].content.as_str()), (5, "broken row"));
+    }
+
+    #[test]
+    fn test_parse_csv_line_extended_columns() {
+        let mo = parse_csv_line("61400000001,1234,\u{41F}\u{440}, hi,8,1,1").unwrap();
+        assert_eq!((mo.source_addr.as_str(), mo.dest_addr.as_str()), ("61400000001", "1234"));
+        assert_eq!(mo.short_message, "\u{41F}\u{440}, hi");
+        assert_eq!((mo.data_coding, mo.source_ton, mo.source_npi), (Some(8), Some(1), Some(1)));
+
+        let cmd = create_deliver_sm(&mo, &test_config(None)).unwrap();
+        let Some(Pdu::DeliverSm(req)) = cmd.pdu() else { panic!("Expected DeliverSm") };
+        assert_eq!((req.source_addr_ton, req.source_addr_npi), (Ton::International, Npi::Isdn));
+        assert_eq!(req.data_coding, DataCoding::Ucs2);
+        assert_eq!(req.short_message().as_ref(), &[0x04, 0x1F, 0x04, 0x40, 0x00, 0x2C, 0x00, 0x20, 0x00, 0x68, 0x00, 0x69]);
+
+        // Three columns are unchanged, and non-numeric trailing fields stay part of the message
+        let mo = parse_csv_line("111,222,hello").unwrap();
+        assert_eq!((mo.short_message.as_str(), mo.data_coding, mo.source_ton), ("hello", None, None));
+        let mo = parse_csv_line("111,222,a,b,c,d").unwrap();
+        assert_eq!((mo.short_message.as_str(), mo.data_coding), ("a,b,c,d", None));
+        assert!(parse_csv_line("111,222").is_err());
+        assert!(parse_csv_line(",222,hello").is_err());
+    }
 
     #[test]
     fn test_create_deliver_sm_binary() {
@@ -167,10 +412,12 @@ mod tests {
             source_addr: "src".to_string(),
             dest_addr: "dst".to_string(),
             short_message: "0x000102".to_string(),
+            data_coding: None,
+            source_ton: None,
+            source_npi: None,
         };
         
-        // This function doesn't use Config or other complex types
-        let cmd_opt = create_deliver_sm(&msg);
+        let cmd_opt = create_deliver_sm(&msg, &test_config(None));
         assert!(cmd_opt.is_some());
         
         let cmd = cmd_opt.unwrap();
@@ -180,4 +427,62 @@ mod tests {
             panic!("Expected DeliverSm");
         }
     }
+
+    #[test]
+    fn test_create_deliver_sm_gsm7_packing() {
+        let msg = MoMessage {
+            source_addr: "src".to_string(),
+            dest_addr: "dst".to_string(),
+            short_message: "hellohello".to_string(),
+            data_coding: None,
+            source_ton: None,
+            source_npi: None,
+        };
+        let mut config = test_config(None);
+        config.smpp.gsm7_packing = true;
+
+        let cmd = create_deliver_sm(&msg, &config).unwrap();
+        let Some(Pdu::DeliverSm(req)) = cmd.pdu() else { panic!("Expected DeliverSm") };
+        assert_eq!(req.data_coding, DataCoding::McSpecific);
+        assert_eq!(req.short_message().as_ref(), &[0xE8, 0x32, 0x9B, 0xFD, 0x46, 0x97, 0xD9, 0xEC, 0x37]);
+    }
+
+    #[test]
+    fn test_create_deliver_sm_explicit_data_coding() {
+        let msg = MoMessage {
+            source_addr: "src".to_string(),
+            dest_addr: "dst".to_string(),
+            short_message: "0x00480069".to_string(),
+            data_coding: Some(8),
+            source_ton: None,
+            source_npi: None,
+        };
+        let mut config = test_config(None);
+        config.smpp.gsm7_packing = true;
+
+        let cmd = create_deliver_sm(&msg, &config).unwrap();
+        let Some(Pdu::DeliverSm(req)) = cmd.pdu() else { panic!("Expected DeliverSm") };
+        assert_eq!(req.data_coding, DataCoding::Ucs2);
+        assert_eq!(req.short_message().as_ref(), &[0x00, 0x48, 0x00, 0x69]);
+    }
+
+    #[test]
+    fn test_create_deliver_sm_long_body_uses_message_payload() {
+        let msg = MoMessage {
+            source_addr: "src".to_string(),
+            dest_addr: "dst".to_string(),
+            short_message: "x".repeat(300),
+            data_coding: None,
+            source_ton: None,
+            source_npi: None,
+        };
+        let cmd = create_deliver_sm(&msg, &test_config(None)).unwrap();
+        let Some(Pdu::DeliverSm(req)) = cmd.pdu() else { panic!("Expected DeliverSm") };
+        assert!(req.short_message().as_ref().is_empty());
+        let payload = req.tlvs().iter().find_map(|t| match t.value() {
+            Some(rusmpp::tlvs::TlvValue::MessagePayload(payload)) => Some(payload.value.as_ref().to_vec()),
+            _ => None,
+        });
+        assert_eq!(payload, Some(vec![b'x'; 300]));
+    }
 }
@@ -2,8 +2,16 @@ pub mod server;
 pub mod session;
 pub mod queue;
 pub mod lifecycle;
+pub mod metrics;
+pub mod audit;
 pub mod mo_service;
+pub mod capture;
 pub mod codec;
+pub mod encoding;
+pub mod outbind;
+pub mod time;
+pub mod udh;
+pub mod tls;
 
 #[cfg(test)]
 mod session_tests;
@@ -0,0 +1,80 @@
+//! Outbind connector
+//!
+//! For ESMEs that expect the SMSC to initiate the connection: dial each configured
+//! `[[outbind]]` target, send `outbind`, then serve the connection like an accepted one
+//! so the ESME's `bind_receiver` goes through the normal session handling.
+
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::SinkExt;
+use rusmpp::{Command, CommandStatus, Pdu};
+use rusmpp::pdus::Outbind;
+use rusmpp::types::COctetString;
+use tokio::net::TcpStream;
+use tokio_util::codec::Framed;
+
+use crate::config::{AppConfig, OutbindTarget};
+use crate::smpp::codec::{SmppCodec, SmppVersion};
+use crate::smpp::queue::MessageQueue;
+use crate::smpp::server::handle_connection;
+use crate::smpp::session::SessionManager;
+
+/// Start one connector per configured target. Does nothing unless `smpp.outbind_enabled` is set.
+pub async fn start_outbind_connector(
+    config: Arc<AppConfig>,
+    session_manager: Arc<SessionManager>,
+    message_queue: Arc<MessageQueue>,
+) {
+    if !config.smpp.outbind_enabled {
+        return;
+    }
+
+    let tasks: Vec<_> = config.outbind.iter().cloned().map(|target| {
+        tokio::spawn(run_target(target, config.clone(), session_manager.clone(), message_queue.clone()))
+    }).collect();
+    futures::future::join_all(tasks).await;
+}
+
+/// Keep an outbind connection to `target` up, redialling after it ends
+async fn run_target(
+    target: OutbindTarget,
+    config: Arc<AppConfig>,
+    session_manager: Arc<SessionManager>,
+    message_queue: Arc<MessageQueue>,
+) {
+    let addr = format!("{}:{}", target.host, target.port);
+    loop {
+        match connect(&target, &addr, &config).await {
+            Ok((stream, remote_addr, local_addr)) => {
+                if let Err(e) = handle_connection(stream, remote_addr, local_addr, config.clone(), session_manager.clone(), message_queue.clone()).await {
+                    tracing::error!("Outbind connection error for {}: {}", addr, e);
+                }
+            }
+            Err(e) => tracing::warn!("Outbind to {} failed: {}", addr, e),
+        }
+        tokio::time::sleep(Duration::from_millis(target.retry_interval_ms)).await;
+    }
+}
+
+/// Dial the ESME and send the outbind PDU; returns the stream with its peer and local addresses
+async fn connect(target: &OutbindTarget, addr: &str, config: &AppConfig) -> std::io::Result<(TcpStream, std::net::SocketAddr, std::net::SocketAddr)> {
+    let mut stream = TcpStream::connect(addr).await?;
+    let remote_addr = stream.peer_addr()?;
+    let local_addr = stream.local_addr()?;
+
+    let system_id = COctetString::from_str(&target.system_id).map_err(std::io::Error::other)?;
+    let password = COctetString::from_str(&target.password).map_err(std::io::Error::other)?;
+    let outbind = Command::builder()
+        .status(CommandStatus::EsmeRok)
+        .sequence_number(1)
+        .pdu(Pdu::Outbind(Outbind::new(system_id, password)));
+
+    // The ESME answers with a bind, so nothing is buffered for reading yet
+    let mut framed = Framed::new(&mut stream, SmppCodec::new(SmppVersion::from_str(&config.smpp.version)));
+    framed.send(outbind).await.map_err(std::io::Error::other)?;
+    tracing::info!("Sent outbind to {} as {}", addr, target.system_id);
+
+    Ok((stream, remote_addr, local_addr))
+}
@@ -1,9 +1,17 @@
-use std::sync::atomic::{AtomicU32, Ordering};
+use std::collections::{BTreeMap, VecDeque};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
 use dashmap::DashMap;
+use parking_lot::Mutex;
 use serde::Serialize;
+use rusmpp::encode::{Encode, Length};
+use rusmpp::tlvs::Tlv;
 
 use tokio::sync::mpsc;
 
+use crate::smpp::udh::Concat;
+
 /// Represents a submitted message in the queue
 #[derive(Debug, Clone, Serialize)]
 pub struct QueuedMessage {
@@ -17,46 +25,486 @@ pub struct QueuedMessage {
     pub session_id: String,
     #[serde(skip)]
         pub submitted_at: std::time::Instant,
+    /// Wall-clock submit time, used for the receipt `submit date`
+    pub submitted_wall: chrono::DateTime<chrono::Local>,
+    /// Optional TLVs supplied with the submit_sm
+    pub tlvs: Vec<CapturedTlv>,
+    /// Final ID assigned after the provisional submit_sm_resp ID (async message ID mode)
+    pub final_message_id: Option<String>,
+    /// Final state once the lifecycle has issued a receipt
+    pub final_state: Option<MessageState>,
+    /// Wall-clock time the final state was reached
+    pub done_wall: Option<chrono::DateTime<chrono::Local>>,
+    /// Deadline derived from the submit_sm validity_period, if one was given
+    #[serde(skip)]
+    pub expires_at: Option<std::time::Instant>,
+    /// Earliest delivery attempt derived from the submit_sm schedule_delivery_time, if one was given
+    #[serde(skip)]
+    pub deliver_not_before: Option<std::time::Instant>,
+    /// Time en route drawn from `lifecycle.delay_distribution`; `max_time_enroute_ms` when unset
+    #[serde(skip)]
+    pub delivery_delay_ms: Option<u64>,
+    /// Whether the intermediate ENROUTE receipt has already gone out
+    #[serde(skip)]
+    pub interim_receipt_sent: bool,
+    /// Cancelled by cancel_sm; the lifecycle reports it as DELETED on its next pass
+    #[serde(skip)]
+    pub cancelled: bool,
+    /// The client answered the final delivery receipt with a successful deliver_sm_resp
+    #[serde(skip)]
+    pub receipt_acked: bool,
+    /// TON/NPI of the submit_sm addresses, echoed (swapped) in the receipt
+    #[serde(skip)]
+    pub source_addr_ton: u8,
+    #[serde(skip)]
+    pub source_addr_npi: u8,
+    #[serde(skip)]
+    pub dest_addr_ton: u8,
+    #[serde(skip)]
+    pub dest_addr_npi: u8,
+    /// Raw esm_class of the submit_sm
+    #[serde(skip)]
+    pub esm_class: u8,
+    /// Raw registered_delivery of the submit_sm
+    #[serde(skip)]
+    pub registered_delivery: u8,
+    /// service_type of the submit_sm, echoed in the receipt; empty when not given
+    pub service_type: String,
 }
 
+#[cfg(test)]
+impl QueuedMessage {
+    /// A just-submitted "hello" from "src" to `dest_addr` on session "sess", with every
+    /// optional field unset; tests override what they exercise
+    pub(crate) fn for_test(message_id: &str, dest_addr: &str) -> Self {
+        Self {
+            message_id: message_id.to_string(),
+            source_addr: "src".to_string(),
+            dest_addr: dest_addr.to_string(),
+            short_message: b"hello".to_vec(),
+            data_coding: 0,
+            session_id: "sess".to_string(),
+            submitted_at: std::time::Instant::now(),
+            submitted_wall: chrono::Local::now(),
+            tlvs: vec![],
+            final_message_id: None,
+            final_state: None,
+            done_wall: None,
+            expires_at: None,
+            deliver_not_before: None,
+            delivery_delay_ms: None,
+            interim_receipt_sent: false,
+            cancelled: false,
+            receipt_acked: false,
+            source_addr_ton: 0,
+            source_addr_npi: 0,
+            dest_addr_ton: 0,
+            dest_addr_npi: 0,
+            esm_class: 0,
+            registered_delivery: 0,
+            service_type: String::new(),
+        }
+    }
+}
+
+/// Simulated final message states
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+pub enum MessageState {
+    Delivered,
+    Expired,
+    Deleted,
+    Undeliverable,
+    Accepted,
+    Unknown,
+    Rejected,
+    /// Interim state carried by intermediate receipts; never recorded as final
+    Enroute,
+}
+
+impl MessageState {
+    /// States a message can finish in, in display order
+    pub const FINAL_STATES: [MessageState; 7] = [
+        MessageState::Delivered,
+        MessageState::Undeliverable,
+        MessageState::Accepted,
+        MessageState::Rejected,
+        MessageState::Deleted,
+        MessageState::Expired,
+        MessageState::Unknown,
+    ];
+
+    /// Parse a state by name ("undeliverable") or DR stat ("UNDELIV"), ignoring case
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "delivered" | "delivrd" => Some(MessageState::Delivered),
+            "expired" => Some(MessageState::Expired),
+            "undeliverable" | "undeliv" => Some(MessageState::Undeliverable),
+            "accepted" | "acceptd" => Some(MessageState::Accepted),
+            "rejected" | "rejectd" => Some(MessageState::Rejected),
+            "deleted" => Some(MessageState::Deleted),
+            "unknown" => Some(MessageState::Unknown),
+            _ => None,
+        }
+    }
+}
+
+/// A TLV captured from an inbound PDU, kept in a display-friendly form
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CapturedTlv {
+    pub tag: u16,
+    pub name: String,
+    /// Raw value bytes, hex encoded
+    pub value: String,
+}
+
+impl CapturedTlv {
+    pub fn from_tlv(tlv: &Tlv) -> Self {
+        let tag = tlv.tag();
+        let value = match tlv.value() {
+            Some(value) => {
+                let mut buf = vec![0u8; value.length()];
+                let size = value.encode(&mut buf);
+                hex::encode_upper(&buf[..size])
+            }
+            None => String::new(),
+        };
+        Self {
+            tag: u16::from(tag),
+            name: format!("{:?}", tag),
+            value,
+        }
+    }
+}
+
+/// Format used when rendering generated message IDs
+#[derive(Debug, Clone, PartialEq)]
+pub enum MessageIdFormat {
+    /// Upper-case hex, zero-padded to at least 8 digits (default)
+    Hex,
+    /// Plain decimal
+    Decimal,
+    /// Decimal counter between a fixed prefix and suffix, zero-padded to `width` digits,
+    /// from a template such as `MSG{counter:5}`
+    Template { prefix: String, width: usize, suffix: String },
+}
+
+/// Longest message_id a submit_sm_resp can carry (C-Octet String of 65 with its terminator)
+pub const MAX_MESSAGE_ID_LEN: usize = 64;
+/// Digits in the largest counter value (u64::MAX)
+const MAX_COUNTER_DIGITS: usize = 20;
+
+impl FromStr for MessageIdFormat {
+    type Err = String;
+
+    /// Parse "hex", "decimal" or a template containing `{counter}` / `{counter:<width>}`.
+    /// Anything else is an error, as is a template whose IDs could grow past
+    /// `MAX_MESSAGE_ID_LEN`, since the ID would not fit the response.
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s.to_ascii_lowercase().as_str() {
+            "hex" => return Ok(MessageIdFormat::Hex),
+            "decimal" | "dec" => return Ok(MessageIdFormat::Decimal),
+            _ => {}
+        }
+        let start = s.find("{counter")
+            .ok_or_else(|| format!("message_id_format {:?} is not hex, decimal or a {{counter}} template", s))?;
+        let len = s[start..].find('}')
+            .ok_or_else(|| format!("message_id_format {:?} has an unterminated {{counter}}", s))?;
+        let spec = &s[start + "{counter".len()..start + len];
+        let width = match spec.strip_prefix(':') {
+            Some(width) => width.parse().ok(),
+            None => spec.is_empty().then_some(0),
+        }
+        .ok_or_else(|| format!("message_id_format {:?} has a malformed {{counter}} width", s))?;
+        let (prefix, suffix) = (&s[..start], &s[start + len + 1..]);
+        let longest = prefix.len() + width.max(MAX_COUNTER_DIGITS) + suffix.len();
+        if longest > MAX_MESSAGE_ID_LEN {
+            return Err(format!("message_id_format {:?} can produce {}-character IDs, more than the {} a message_id allows",
+                s, longest, MAX_MESSAGE_ID_LEN));
+        }
+        Ok(MessageIdFormat::Template { prefix: prefix.to_string(), width, suffix: suffix.to_string() })
+    }
+}
+
+/// A submit_sm recognised as a repeat of one already accepted within the dedup window
+#[derive(Debug, Clone, Serialize)]
+pub struct DedupHit {
+    /// ID of the original message, returned again to the client
+    pub original_message_id: String,
+    pub source_addr: String,
+    pub dest_addr: String,
+    pub at: chrono::DateTime<chrono::Local>,
+}
+
+/// Number of dedup hits kept for display
+const DEDUP_HITS_LIMIT: usize = 100;
+/// Dedup index size above which expired entries are pruned
+const DEDUP_INDEX_PRUNE_AT: usize = 10_000;
+
 /// Thread-safe message queue for outbound messages (MT -> Delivery Reports)
 pub struct MessageQueue {
     /// Messages pending delivery reports
     pending_dr: DashMap<String, QueuedMessage>,
-    /// All received messages for display
-    all_messages: DashMap<String, QueuedMessage>,
-    /// Counter for generating message IDs
-    message_id_counter: AtomicU32,
+    /// All received messages for display, keyed by message ID and tagged with insertion order
+    all_messages: DashMap<String, (u64, QueuedMessage)>,
+    /// Counter for generating message IDs (64-bit so it never wraps in practice)
+    message_id_counter: AtomicU64,
+    /// How generated message IDs are rendered
+    message_id_format: MessageIdFormat,
+    /// Monotonic insertion counter used to order `all_messages`
+    insertion_counter: AtomicU64,
+    /// Duplicate submit window in ms (0 = dedup off); tunable at runtime
+    dedup_window_ms: AtomicU64,
+    /// Submit key (source, dest, content) -> time first seen and message ID
+    dedup_index: DashMap<String, (Instant, String)>,
+    /// Most recent dedup hits, oldest first
+    dedup_hits: Mutex<VecDeque<DedupHit>>,
+    /// Parts of concatenated messages still waiting for their siblings, keyed by (source, dest, reference)
+    concat_parts: DashMap<(String, String, u16), ConcatParts>,
+    /// Message ID (provisional or final) of each part of a reassembled message -> ID of the combined record
+    part_aliases: DashMap<String, String>,
+    /// How many messages the lifecycle has finished in each state since start or the last clear
+    final_state_counts: DashMap<MessageState, u64>,
+}
+
+/// Segments received so far for one concatenated message
+struct ConcatParts {
+    total: u8,
+    /// When the first part arrived
+    started: Instant,
+    /// Part number -> segment with the UDH already stripped
+    parts: BTreeMap<u8, QueuedMessage>,
+}
+
+/// Number of messages returned by `get_recent_messages`
+const RECENT_MESSAGES_LIMIT: usize = 50;
+
+/// Criteria for `MessageQueue::query`; unset fields match everything
+#[derive(Debug, Clone, Default)]
+pub struct MessageFilter {
+    /// Substring of the source address
+    pub source: Option<String>,
+    /// Substring of the destination address
+    pub dest: Option<String>,
+    /// Only messages carrying a TLV with this tag
+    pub tlv: Option<u16>,
+}
+
+impl MessageFilter {
+    fn matches(&self, msg: &QueuedMessage) -> bool {
+        self.source.as_deref().is_none_or(|s| msg.source_addr.contains(s))
+            && self.dest.as_deref().is_none_or(|d| msg.dest_addr.contains(d))
+            && self.tlv.is_none_or(|tag| msg.tlvs.iter().any(|t| t.tag == tag))
+    }
 }
 
 impl MessageQueue {
     pub fn new() -> Self {
+        Self::with_message_id_format(MessageIdFormat::Hex)
+    }
+
+    /// Create a queue that renders message IDs in the given format
+    pub fn with_message_id_format(message_id_format: MessageIdFormat) -> Self {
         Self {
             pending_dr: DashMap::new(),
             all_messages: DashMap::new(),
-            message_id_counter: AtomicU32::new(1),
+            message_id_counter: AtomicU64::new(1),
+            message_id_format,
+            insertion_counter: AtomicU64::new(0),
+            dedup_window_ms: AtomicU64::new(0),
+            dedup_index: DashMap::new(),
+            dedup_hits: Mutex::new(VecDeque::with_capacity(DEDUP_HITS_LIMIT)),
+            concat_parts: DashMap::new(),
+            part_aliases: DashMap::new(),
+            final_state_counts: DashMap::new(),
         }
     }
 
     /// Generate a unique message ID
     pub fn next_message_id(&self) -> String {
         let id = self.message_id_counter.fetch_add(1, Ordering::SeqCst);
-        format!("{:08X}", id)
+        match &self.message_id_format {
+            MessageIdFormat::Hex => format!("{:08X}", id),
+            MessageIdFormat::Decimal => id.to_string(),
+            MessageIdFormat::Template { prefix, width, suffix } => format!("{}{:0width$}{}", prefix, id, suffix, width = *width),
+        }
+    }
+
+    /// Fast-forward the message ID counter (used by tests)
+    #[cfg(test)]
+    pub(crate) fn set_next_message_id(&self, value: u64) {
+        self.message_id_counter.store(value, Ordering::SeqCst);
     }
 
     /// Add a message to both queues
     pub fn add_pending_dr(&self, msg: QueuedMessage) {
-        self.all_messages.insert(msg.message_id.clone(), msg.clone());
+        let seq = self.insertion_counter.fetch_add(1, Ordering::SeqCst);
+        self.all_messages.insert(msg.message_id.clone(), (seq, msg.clone()));
         self.pending_dr.insert(msg.message_id.clone(), msg);
     }
 
-    /// Get recent messages for display (latest 50)
+    /// Add one part of a concatenated message (UDH stripped). Each part waits for its own
+    /// receipt and is stored on its own until every part is in; the parts are then replaced
+    /// by the reassembled text under the first part's message ID, and the other parts' IDs
+    /// resolve to it. Returns true when this part completed the message.
+    pub fn add_pending_segment(&self, msg: QueuedMessage, concat: Concat) -> bool {
+        self.add_pending_dr(msg.clone());
+
+        let key = (msg.source_addr.clone(), msg.dest_addr.clone(), concat.reference);
+        let complete = {
+            let mut entry = self.concat_parts.entry(key.clone()).or_insert_with(|| ConcatParts {
+                total: concat.total,
+                started: Instant::now(),
+                parts: BTreeMap::new(),
+            });
+            entry.parts.insert(concat.seq, msg);
+            entry.parts.len() >= usize::from(entry.total)
+        };
+        if !complete {
+            return false;
+        }
+
+        let Some((_, buffered)) = self.concat_parts.remove(&key) else {
+            return false;
+        };
+        let mut parts = buffered.parts.into_values();
+        let Some(first) = parts.next() else {
+            return false;
+        };
+        // Start from the stored record, which may already carry a final state
+        let mut combined = self.all_messages.remove(&first.message_id)
+            .map_or_else(|| first.clone(), |(_, (_, stored))| stored);
+        combined.short_message = first.short_message;
+        for part in parts {
+            combined.short_message.extend_from_slice(&part.short_message);
+            self.all_messages.remove(&part.message_id);
+            for id in std::iter::once(&part.message_id).chain(part.final_message_id.as_ref()) {
+                self.part_aliases.insert(id.clone(), combined.message_id.clone());
+            }
+        }
+        let seq = self.insertion_counter.fetch_add(1, Ordering::SeqCst);
+        self.all_messages.insert(combined.message_id.clone(), (seq, combined));
+        true
+    }
+
+    /// Drop the buffers of concatenated messages whose parts have not all arrived within
+    /// `max_age`. Their parts stay stored and pending on their own. Returns how many were dropped.
+    pub fn expire_concat_parts(&self, max_age: Duration) -> usize {
+        let before = self.concat_parts.len();
+        self.concat_parts.retain(|_, buffered| buffered.started.elapsed() < max_age);
+        before - self.concat_parts.len()
+    }
+
+    /// The ID of the record a message ID is stored under, following reassembled parts
+    fn resolve_id(&self, message_id: &str) -> String {
+        self.part_aliases.get(message_id).map_or_else(|| message_id.to_string(), |r| r.value().clone())
+    }
+
+    /// Get recent messages for display (latest 50, newest first)
     pub fn get_recent_messages(&self) -> Vec<QueuedMessage> {
-        self.all_messages.iter().map(|r| r.value().clone()).collect()
+        let mut messages: Vec<(u64, QueuedMessage)> = self.all_messages.iter().map(|r| r.value().clone()).collect();
+        messages.sort_by_key(|(seq, _)| std::cmp::Reverse(*seq));
+        messages.into_iter().take(RECENT_MESSAGES_LIMIT).map(|(_, m)| m).collect()
+    }
+
+    /// Look up a single message by ID
+    pub fn get_message(&self, message_id: &str) -> Option<QueuedMessage> {
+        self.all_messages.get(message_id).map(|r| r.value().1.clone())
+    }
+
+    /// Look up a message by either its provisional or its final ID
+    pub fn find_message(&self, id: &str) -> Option<QueuedMessage> {
+        self.get_message(&self.resolve_id(id)).or_else(|| {
+            self.all_messages.iter()
+                .find(|r| r.value().1.final_message_id.as_deref() == Some(id))
+                .map(|r| r.value().1.clone())
+        })
+    }
+
+    /// Whether a message is still waiting for its delivery receipt
+    pub fn is_pending_dr(&self, message_id: &str) -> bool {
+        self.pending_dr.contains_key(message_id)
+    }
+
+    /// Record the final state reached by a message
+    pub fn set_final_state(&self, message_id: &str, state: MessageState) {
+        if let Some(mut entry) = self.all_messages.get_mut(&self.resolve_id(message_id)) {
+            entry.1.final_state = Some(state);
+            entry.1.done_wall = Some(chrono::Local::now());
+        }
+    }
+
+    /// One page of the messages matching `filter` (newest first), plus the total match count
+    pub fn query(&self, filter: &MessageFilter, limit: usize, offset: usize) -> (Vec<QueuedMessage>, usize) {
+        let mut messages: Vec<(u64, QueuedMessage)> = self.all_messages.iter()
+            .filter(|r| filter.matches(&r.value().1))
+            .map(|r| r.value().clone())
+            .collect();
+        let total = messages.len();
+        messages.sort_by_key(|(seq, _)| std::cmp::Reverse(*seq));
+        let page = messages.into_iter().skip(offset).take(limit).map(|(_, m)| m).collect();
+        (page, total)
+    }
+
+    /// Current duplicate submit window in ms (0 = off)
+    pub fn dedup_window_ms(&self) -> u64 {
+        self.dedup_window_ms.load(Ordering::SeqCst)
+    }
+
+    /// Change the duplicate submit window; 0 turns dedup off
+    pub fn set_dedup_window_ms(&self, window_ms: u64) {
+        self.dedup_window_ms.store(window_ms, Ordering::SeqCst);
+        if window_ms == 0 {
+            self.dedup_index.clear();
+        }
+    }
+
+    fn dedup_key(source_addr: &str, dest_addr: &str, short_message: &[u8]) -> String {
+        format!("{}|{}|{}", source_addr, dest_addr, hex::encode(short_message))
+    }
+
+    /// If an identical submit was accepted within the window, record a hit and return its message ID
+    pub fn check_duplicate(&self, source_addr: &str, dest_addr: &str, short_message: &[u8]) -> Option<String> {
+        let window = Duration::from_millis(self.dedup_window_ms());
+        if window.is_zero() {
+            return None;
+        }
+        let key = Self::dedup_key(source_addr, dest_addr, short_message);
+        let original = self.dedup_index.get(&key)
+            .filter(|entry| entry.0.elapsed() < window)
+            .map(|entry| entry.1.clone())?;
+
+        let mut hits = self.dedup_hits.lock();
+        if hits.len() >= DEDUP_HITS_LIMIT {
+            hits.pop_front();
+        }
+        hits.push_back(DedupHit {
+            original_message_id: original.clone(),
+            source_addr: source_addr.to_string(),
+            dest_addr: dest_addr.to_string(),
+            at: chrono::Local::now(),
+        });
+        Some(original)
+    }
+
+    /// Remember an accepted submit so repeats within the window are detected
+    pub fn remember_submit(&self, source_addr: &str, dest_addr: &str, short_message: &[u8], message_id: &str) {
+        let window = Duration::from_millis(self.dedup_window_ms());
+        if window.is_zero() {
+            return;
+        }
+        if self.dedup_index.len() > DEDUP_INDEX_PRUNE_AT {
+            self.dedup_index.retain(|_, (seen, _)| seen.elapsed() < window);
+        }
+        self.dedup_index.insert(Self::dedup_key(source_addr, dest_addr, short_message), (Instant::now(), message_id.to_string()));
+    }
+
+    /// Recent dedup hits, newest first
+    pub fn get_dedup_hits(&self) -> Vec<DedupHit> {
+        self.dedup_hits.lock().iter().rev().cloned().collect()
     }
 
     /// Get total message count
-        pub fn message_count(&self) -> usize {
+    pub fn message_count(&self) -> usize {
         self.all_messages.len()
     }
 
@@ -74,6 +522,139 @@ impl MessageQueue {
     pub fn remove_pending_dr(&self, message_id: &str) {
         self.pending_dr.remove(message_id);
     }
+
+    /// Cancel a pending message by ID. Returns false if it is not (or no longer) pending
+    pub fn cancel_pending(&self, message_id: &str) -> bool {
+        let Some(mut entry) = self.pending_dr.get_mut(message_id) else {
+            return false;
+        };
+        if entry.cancelled {
+            return false;
+        }
+        entry.cancelled = true;
+        true
+    }
+
+    /// Cancel every pending message from `source_addr` to `dest_addr`, returning how many were cancelled
+    pub fn cancel_pending_matching(&self, source_addr: &str, dest_addr: &str) -> usize {
+        let mut cancelled = 0;
+        for mut entry in self.pending_dr.iter_mut() {
+            if !entry.cancelled && entry.dest_addr == dest_addr && (source_addr.is_empty() || entry.source_addr == source_addr) {
+                entry.cancelled = true;
+                cancelled += 1;
+            }
+        }
+        cancelled
+    }
+
+    /// Whether pending message `existing` is one `msg` may replace (SMPP 3.4 §5.2.18): not cancelled,
+    /// from the same session and with the same source_addr, destination_addr and service_type
+    fn replaceable_by(existing: &QueuedMessage, msg: &QueuedMessage) -> bool {
+        !existing.cancelled
+            && existing.session_id == msg.session_id
+            && existing.source_addr == msg.source_addr
+            && existing.dest_addr == msg.dest_addr
+            && existing.service_type == msg.service_type
+    }
+
+    /// Drop every pending message `msg` may replace without a receipt, recording them as DELETED
+    /// (strict `replace_if_present_flag`). Cancelled messages are left to the lifecycle, as for a
+    /// non-strict replace. Returns the messages that were removed.
+    pub fn remove_pending_matching(&self, msg: &QueuedMessage) -> Vec<QueuedMessage> {
+        let message_ids: Vec<String> = self.pending_dr.iter()
+            .filter(|r| Self::replaceable_by(r, msg))
+            .map(|r| r.message_id.clone())
+            .collect();
+        let removed: Vec<QueuedMessage> = message_ids.iter()
+            .filter_map(|message_id| self.pending_dr.remove(message_id).map(|(_, msg)| msg))
+            .collect();
+        for msg in &removed {
+            self.set_final_state(&msg.message_id, MessageState::Deleted);
+            self.record_final_state(MessageState::Deleted);
+        }
+        removed
+    }
+
+    /// Give the most recent pending message `msg` may replace the content of `msg` and restart its
+    /// delivery timer (submit_sm `replace_if_present_flag`). Returns the ID of the message that
+    /// was replaced, or None if nothing matched.
+    pub fn replace_pending_matching(&self, msg: &QueuedMessage) -> Option<String> {
+        let message_id = self.pending_dr.iter()
+            .filter(|r| Self::replaceable_by(r, msg))
+            .max_by_key(|r| r.submitted_at)
+            .map(|r| r.message_id.clone())?;
+        let replace = |existing: &mut QueuedMessage| {
+            existing.short_message = msg.short_message.clone();
+            existing.data_coding = msg.data_coding;
+            existing.submitted_at = msg.submitted_at;
+            existing.submitted_wall = msg.submitted_wall;
+            existing.tlvs = msg.tlvs.clone();
+            existing.expires_at = msg.expires_at;
+            existing.deliver_not_before = msg.deliver_not_before;
+            existing.delivery_delay_ms = msg.delivery_delay_ms;
+            existing.interim_receipt_sent = false;
+            existing.esm_class = msg.esm_class;
+            existing.registered_delivery = msg.registered_delivery;
+        };
+        replace(self.pending_dr.get_mut(&message_id)?.value_mut());
+        if let Some(mut entry) = self.all_messages.get_mut(&message_id) {
+            replace(&mut entry.1);
+        }
+        Some(message_id)
+    }
+
+    /// Record that the intermediate receipt was sent; the message stays pending
+    pub fn mark_interim_receipt_sent(&self, message_id: &str) {
+        if let Some(mut entry) = self.pending_dr.get_mut(message_id) {
+            entry.interim_receipt_sent = true;
+        }
+        if let Some(mut entry) = self.all_messages.get_mut(&self.resolve_id(message_id)) {
+            entry.1.interim_receipt_sent = true;
+        }
+    }
+
+    /// Record that the client acknowledged the final delivery receipt for a message.
+    /// Acks of an intermediate receipt, sent while the message is still pending, are ignored.
+    pub fn mark_receipt_acked(&self, message_id: &str) {
+        if let Some(mut entry) = self.all_messages.get_mut(&self.resolve_id(message_id))
+            && entry.1.final_state.is_some() {
+            entry.1.receipt_acked = true;
+        }
+    }
+
+    /// Forget every stored and pending message, along with the dedup history that refers to them
+    pub fn clear(&self) {
+        self.all_messages.clear();
+        self.pending_dr.clear();
+        self.dedup_index.clear();
+        self.dedup_hits.lock().clear();
+        self.concat_parts.clear();
+        self.part_aliases.clear();
+        self.final_state_counts.clear();
+    }
+
+    /// Count a message reaching its final state
+    pub fn record_final_state(&self, state: MessageState) {
+        *self.final_state_counts.entry(state).or_insert(0) += 1;
+    }
+
+    /// Messages finished per final state, every state listed (in `FINAL_STATES` order)
+    pub fn final_state_counts(&self) -> Vec<(MessageState, u64)> {
+        MessageState::FINAL_STATES.iter()
+            .map(|&state| (state, self.final_state_counts.get(&state).map_or(0, |count| *count)))
+            .collect()
+    }
+
+    /// Restart message IDs from 1
+    pub fn reset_counter(&self) {
+        self.message_id_counter.store(1, Ordering::SeqCst);
+    }
+}
+
+impl Default for MessageQueue {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 /// Queue for MO (Mobile Originated) messages to be delivered to ESMEs
@@ -82,13 +663,62 @@ pub struct MoMessageQueue {
     tx: mpsc::Sender<MoMessage>,
     /// Channel receiver (will be cloned for subscribers)
     rx: std::sync::Mutex<Option<mpsc::Receiver<MoMessage>>>,
+    /// MO messages no bound session could take yet, oldest first
+    parked: Mutex<VecDeque<ParkedMo>>,
+    /// Most messages kept parked; 0 disables parking
+    park_capacity: AtomicUsize,
+    /// Outcome of the MO CSV replays, for `/api/mo/status`
+    csv_status: Mutex<MoCsvStatus>,
+    /// Set once the MO service has started its dispatchers
+    running: AtomicBool,
+}
+
+/// Parse errors kept in `MoCsvStatus::errors`
+const MAX_CSV_ERRORS: usize = 50;
+
+/// What the MO CSV injector did with its file
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct MoCsvStatus {
+    /// Whether the file could be opened on the latest attempt; `None` before the first
+    pub file_found: Option<bool>,
+    /// Why the file could not be opened
+    pub open_error: Option<String>,
+    /// Completed passes over the file
+    pub passes: u64,
+    /// Rows dispatched in the last completed pass
+    pub last_pass_dispatched: usize,
+    /// Most recent rows that could not be parsed, oldest first
+    pub errors: VecDeque<MoCsvError>,
+}
+
+/// A CSV row the MO injector skipped
+#[derive(Debug, Clone, Serialize)]
+pub struct MoCsvError {
+    /// 1-based line number in the file
+    pub line: usize,
+    pub content: String,
+    pub error: String,
+    pub at: chrono::DateTime<chrono::Local>,
+}
+
+/// An undeliverable MO message waiting for a matching receiver to bind
+#[derive(Debug, Clone)]
+pub struct ParkedMo {
+    pub msg: MoMessage,
+    pub parked_at: Instant,
 }
 
 #[derive(Debug, Clone)]
 pub struct MoMessage {
     pub source_addr: String,
     pub dest_addr: String,
+    /// Text, or binary content as `0x`-prefixed hex
     pub short_message: String,
+    /// data_coding for the deliver_sm; the default coding when unset
+    pub data_coding: Option<u8>,
+    /// source_addr TON and NPI; Unknown when unset
+    pub source_ton: Option<u8>,
+    pub source_npi: Option<u8>,
 }
 
 #[allow(dead_code)]
@@ -98,7 +728,87 @@ impl MoMessageQueue {
         Self {
             tx,
             rx: std::sync::Mutex::new(Some(rx)),
+            parked: Mutex::new(VecDeque::new()),
+            park_capacity: AtomicUsize::new(1000),
+            csv_status: Mutex::new(MoCsvStatus::default()),
+            running: AtomicBool::new(false),
+        }
+    }
+
+    /// Whether the MO service is running (enabled and started)
+    pub fn is_running(&self) -> bool {
+        self.running.load(Ordering::SeqCst)
+    }
+
+    pub fn set_running(&self, running: bool) {
+        self.running.store(running, Ordering::SeqCst);
+    }
+
+    /// Snapshot of the MO CSV injector's progress and recent parse errors
+    pub fn csv_status(&self) -> MoCsvStatus {
+        self.csv_status.lock().clone()
+    }
+
+    /// Record that the CSV file could not be opened
+    pub fn record_csv_open_error(&self, error: String) {
+        let mut status = self.csv_status.lock();
+        status.file_found = Some(false);
+        status.open_error = Some(error);
+    }
+
+    /// Record a CSV row that could not be parsed
+    pub fn record_csv_error(&self, line: usize, content: &str, error: String) {
+        let mut status = self.csv_status.lock();
+        if status.errors.len() >= MAX_CSV_ERRORS {
+            status.errors.pop_front();
         }
+        status.errors.push_back(MoCsvError { line, content: content.to_string(), error, at: chrono::Local::now() });
+    }
+
+    /// Record a finished pass over the CSV file
+    pub fn record_csv_pass(&self, dispatched: usize) {
+        let mut status = self.csv_status.lock();
+        status.file_found = Some(true);
+        status.open_error = None;
+        status.passes += 1;
+        status.last_pass_dispatched = dispatched;
+    }
+
+    /// Set how many undeliverable MO messages may wait for a receiver (0 drops them)
+    pub fn set_park_capacity(&self, capacity: usize) {
+        self.park_capacity.store(capacity, Ordering::Relaxed);
+    }
+
+    /// Keep an MO message that no session could take, to retry once a receiver binds.
+    /// Returns false, dropping the message, when the parked queue is full.
+    pub fn park(&self, msg: MoMessage) -> bool {
+        self.repark(ParkedMo { msg, parked_at: Instant::now() })
+    }
+
+    /// Put a message taken by `take_parked` back, keeping its original park time
+    pub fn repark(&self, parked: ParkedMo) -> bool {
+        let mut queue = self.parked.lock();
+        if queue.len() >= self.park_capacity.load(Ordering::Relaxed) {
+            return false;
+        }
+        queue.push_back(parked);
+        true
+    }
+
+    /// Take every parked message, discarding those parked longer than `max_age`
+    pub fn take_parked(&self, max_age: Duration) -> Vec<ParkedMo> {
+        let mut parked: Vec<ParkedMo> = self.parked.lock().drain(..).collect();
+        let before = parked.len();
+        parked.retain(|p| p.parked_at.elapsed() < max_age);
+        if parked.len() < before {
+            tracing::warn!("Expired {} parked MO messages", before - parked.len());
+        }
+        parked
+    }
+
+    /// Number of MO messages waiting for a receiver
+    pub fn parked_count(&self) -> usize {
+        self.parked.lock().len()
     }
 
     /// Inject an MO message (from web UI or test)
@@ -116,3 +826,9 @@ impl MoMessageQueue {
         self.tx.clone()
     }
 }
+
+impl Default for MoMessageQueue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
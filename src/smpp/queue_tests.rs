@@ -6,7 +6,9 @@
 //! - Pending delivery reports are tracked correctly
 //! - Queue operations are thread-safe
 
-use crate::smpp::queue::{MessageQueue, QueuedMessage};
+use std::str::FromStr;
+
+use crate::smpp::queue::{MessageFilter, MessageQueue, MessageIdFormat, QueuedMessage};
 
 #[test]
 fn test_message_queue_creation() {
@@ -42,13 +44,9 @@ fn test_add_pending_dr() {
     let queue = MessageQueue::new();
     
     let msg = QueuedMessage {
-        message_id: "msg-001".to_string(),
         source_addr: "+1234567890".to_string(),
-        dest_addr: "+0987654321".to_string(),
         short_message: b"Hello World".to_vec(),
-        data_coding: 0,
-        session_id: "session-001".to_string(),
-        submitted_at: std::time::Instant::now(),
+        ..QueuedMessage::for_test("msg-001", "+0987654321")
     };
     
     queue.add_pending_dr(msg);
@@ -63,13 +61,9 @@ fn test_get_recent_messages() {
     // Add multiple messages
     for i in 0..10 {
         let msg = QueuedMessage {
-            message_id: format!("msg-{:03}", i),
             source_addr: format!("+123{}", i),
-            dest_addr: format!("+987{}", i),
             short_message: format!("Message {}", i).into_bytes(),
-            data_coding: 0,
-            session_id: "session".to_string(),
-            submitted_at: std::time::Instant::now(),
+            ..QueuedMessage::for_test(&format!("msg-{:03}", i), &format!("+987{}", i))
         };
         queue.add_pending_dr(msg);
     }
@@ -84,13 +78,9 @@ fn test_message_content_preserved() {
     
     let original_content = "Test OTP: 123456";
     let msg = QueuedMessage {
-        message_id: "content-test".to_string(),
         source_addr: "+1111".to_string(),
-        dest_addr: "+2222".to_string(),
         short_message: original_content.as_bytes().to_vec(),
-        data_coding: 0,
-        session_id: "s1".to_string(),
-        submitted_at: std::time::Instant::now(),
+        ..QueuedMessage::for_test("content-test", "+2222")
     };
     
     queue.add_pending_dr(msg);
@@ -107,13 +97,9 @@ fn test_source_dest_addresses() {
     let queue = MessageQueue::new();
     
     let msg = QueuedMessage {
-        message_id: "addr-test".to_string(),
         source_addr: "+66812345678".to_string(),
-        dest_addr: "+66887654321".to_string(),
         short_message: b"SMS".to_vec(),
-        data_coding: 0,
-        session_id: "s".to_string(),
-        submitted_at: std::time::Instant::now(),
+        ..QueuedMessage::for_test("addr-test", "+66887654321")
     };
     
     queue.add_pending_dr(msg);
@@ -122,3 +108,151 @@ fn test_source_dest_addresses() {
     assert_eq!(retrieved.source_addr, "+66812345678");
     assert_eq!(retrieved.dest_addr, "+66887654321");
 }
+
+#[test]
+fn test_recent_messages_limited_and_newest_first() {
+    let queue = MessageQueue::new();
+    
+    for i in 0..60 {
+        let msg = QueuedMessage {
+            source_addr: "+1111".to_string(),
+            short_message: format!("Message {}", i).into_bytes(),
+            ..QueuedMessage::for_test(&format!("msg-{:03}", i), "+2222")
+        };
+        queue.add_pending_dr(msg);
+    }
+    
+    let recent = queue.get_recent_messages();
+    assert_eq!(recent.len(), 50, "Only the latest 50 messages should be returned");
+    assert_eq!(recent[0].message_id, "msg-059", "Newest message should come first");
+    assert_eq!(recent[49].message_id, "msg-010", "Oldest returned message should be the 50th newest");
+    assert_eq!(queue.message_count(), 60, "Total count is not truncated");
+}
+
+#[test]
+fn test_message_id_no_wrap_at_u32_boundary() {
+    let queue = MessageQueue::new();
+    queue.set_next_message_id(u32::MAX as u64 - 1);
+    
+    let ids: Vec<String> = (0..4).map(|_| queue.next_message_id()).collect();
+    assert_eq!(ids[0], "FFFFFFFE");
+    assert_eq!(ids[1], "FFFFFFFF");
+    assert_eq!(ids[2], "100000000", "Counter should widen past u32 instead of wrapping");
+    
+    let unique: std::collections::HashSet<&String> = ids.iter().collect();
+    assert_eq!(unique.len(), ids.len(), "IDs must not collide across the u32 boundary");
+    assert!(!ids.contains(&"00000001".to_string()), "Counter must not wrap back to 1");
+}
+
+#[test]
+fn test_message_id_decimal_format() {
+    let queue = MessageQueue::with_message_id_format(MessageIdFormat::Decimal);
+    queue.set_next_message_id(41);
+    assert_eq!(queue.next_message_id(), "41");
+    assert_eq!(queue.next_message_id(), "42");
+}
+
+#[test]
+fn test_message_id_hex_format() {
+    let queue = MessageQueue::with_message_id_format(MessageIdFormat::from_str("hex").unwrap());
+    queue.set_next_message_id(255);
+    assert_eq!(queue.next_message_id(), "000000FF");
+    assert_eq!(MessageIdFormat::from_str("HEX"), Ok(MessageIdFormat::Hex));
+    assert_eq!(MessageIdFormat::from_str("DEC"), Ok(MessageIdFormat::Decimal));
+    assert!(MessageIdFormat::from_str("decimall").is_err(), "Misspelled keywords are rejected");
+    assert!(MessageIdFormat::from_str("").is_err());
+}
+
+#[test]
+fn test_message_id_template_format() {
+    let queue = MessageQueue::with_message_id_format(MessageIdFormat::from_str("MSG{counter:5}").unwrap());
+    assert_eq!(queue.next_message_id(), "MSG00001");
+    queue.set_next_message_id(123_456);
+    assert_eq!(queue.next_message_id(), "MSG123456", "Width is a minimum, not a limit");
+
+    let queue = MessageQueue::with_message_id_format(MessageIdFormat::from_str("id-{counter}-x").unwrap());
+    queue.set_next_message_id(42);
+    assert_eq!(queue.next_message_id(), "id-42-x");
+
+    // Malformed or unterminated placeholders are rejected rather than falling back to hex
+    assert!(MessageIdFormat::from_str("MSG{counter:abc}").is_err());
+    assert!(MessageIdFormat::from_str("MSG{counter:}").is_err());
+    assert!(MessageIdFormat::from_str("MSG{counterx}").is_err());
+    assert!(MessageIdFormat::from_str("MSG{counter").is_err());
+
+    // IDs that could outgrow message_id's 64 characters are rejected, counting a full u64 counter
+    assert!(MessageIdFormat::from_str(&format!("{}{{counter}}", "P".repeat(44))).is_ok());
+    assert!(MessageIdFormat::from_str(&format!("{}{{counter}}", "P".repeat(45))).is_err());
+    assert!(MessageIdFormat::from_str("MSG{counter:62}").is_err());
+}
+
+#[test]
+fn test_clear_and_reset_counter() {
+    let queue = MessageQueue::with_message_id_format(MessageIdFormat::Decimal);
+    queue.set_dedup_window_ms(60_000);
+
+    for _ in 0..3 {
+        let message_id = queue.next_message_id();
+        queue.remember_submit("+1111", "+2222", b"Hello", &message_id);
+        let msg = QueuedMessage {
+            source_addr: "+1111".to_string(),
+            short_message: b"Hello".to_vec(),
+            ..QueuedMessage::for_test(&message_id, "+2222")
+        };
+        queue.add_pending_dr(msg);
+    }
+    assert!(queue.check_duplicate("+1111", "+2222", b"Hello").is_some());
+
+    queue.clear();
+    assert_eq!(queue.message_count(), 0);
+    assert_eq!(queue.pending_dr_count(), 0);
+    assert!(queue.get_dedup_hits().is_empty());
+    assert!(queue.check_duplicate("+1111", "+2222", b"Hello").is_none(), "Dedup must not point at cleared messages");
+
+    // Clearing alone keeps IDs monotonic
+    assert_eq!(queue.next_message_id(), "4");
+    queue.reset_counter();
+    assert_eq!(queue.next_message_id(), "1");
+}
+
+#[test]
+fn test_query_filters_and_pages() {
+    let queue = MessageQueue::new();
+
+    for i in 0..30 {
+        let msg = QueuedMessage {
+            source_addr: if i % 2 == 0 { "+1111".to_string() } else { "+3333".to_string() },
+            short_message: format!("Message {}", i).into_bytes(),
+            ..QueuedMessage::for_test(&format!("msg-{:03}", i), &format!("+2222{:03}", i))
+        };
+        queue.add_pending_dr(msg);
+    }
+
+    let (page, total) = queue.query(&MessageFilter::default(), 10, 0);
+    assert_eq!(total, 30);
+    assert_eq!(page.len(), 10);
+    assert_eq!(page[0].message_id, "msg-029", "Newest message should come first");
+
+    let (page, total) = queue.query(&MessageFilter::default(), 10, 25);
+    assert_eq!(total, 30);
+    assert_eq!(page.iter().map(|m| m.message_id.as_str()).collect::<Vec<_>>(), ["msg-004", "msg-003", "msg-002", "msg-001", "msg-000"]);
+
+    // Substring filters combine
+    let filter = MessageFilter {
+        source: Some("111".to_string()),
+        dest: Some("2222".to_string()),
+        ..Default::default()
+    };
+    let (page, total) = queue.query(&filter, 100, 0);
+    assert_eq!(total, 15);
+    assert!(page.iter().all(|m| m.source_addr == "+1111"));
+
+    let filter = MessageFilter { dest: Some("+222201".to_string()), ..Default::default() };
+    let (page, total) = queue.query(&filter, 100, 0);
+    assert_eq!(total, 10, "Destinations +222201x");
+    assert_eq!(page.len(), 10);
+
+    let (page, total) = queue.query(&MessageFilter::default(), 10, 100);
+    assert_eq!(total, 30);
+    assert!(page.is_empty(), "Offset past the end gives an empty page");
+}
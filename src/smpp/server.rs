@@ -1,49 +1,223 @@
-use crate::config::AppConfig;
+use crate::config::{AppConfig, SmppAccount, SmppConfig};
 use std::sync::Arc;
 use std::str::FromStr;
+use tokio::io::{AsyncRead, AsyncWrite};
 use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinSet;
+use tokio_rustls::TlsAcceptor;
 use tokio_util::codec::Framed;
+use rand::Rng;
 use rusmpp::{Command, Pdu, CommandStatus};
-use rusmpp::types::COctetString;
+use rusmpp::pdus::SubmitSm;
+use rusmpp::encode::Length;
+use rusmpp::tlvs::{MessageSubmissionResponseTlvValue, TlvTag, TlvValue};
+use rusmpp::types::{AnyOctetString, COctetString, EmptyOrFullCOctetString};
+use rusmpp::values::{GsmFeatures, InterfaceVersion, MessageState as SmppMessageState, ReplaceIfPresentFlag};
 use futures::{SinkExt, StreamExt};
 use crate::smpp::session::{Session, SessionManager, BindType};
-use crate::smpp::queue::{MessageQueue, QueuedMessage};
+use crate::smpp::queue::{CapturedTlv, MessageQueue, QueuedMessage};
+use crate::smpp::audit::{AuditEvent, AuditRecord};
+use crate::smpp::capture::PduCapture;
 use crate::smpp::codec::{SmppCodec, SmppVersion};
+use crate::smpp::lifecycle::{delivery_delay_ms, smpp_message_state};
+use crate::smpp::time;
+use crate::smpp::tls;
+use crate::smpp::udh;
 
 pub async fn start_smpp_server(
     config: Arc<AppConfig>,
     session_manager: Arc<SessionManager>,
     message_queue: Arc<MessageQueue>,
 ) -> std::io::Result<()> {
-    let addr = format!("0.0.0.0:{}", config.smpp.port);
-    let listener = TcpListener::bind(&addr).await?;
-    
+    run_smpp_server(config, session_manager, message_queue, std::future::pending()).await
+}
+
+/// Run the SMPP listeners until `shutdown` resolves, then drain: every connection flushes
+/// queued DRs and is sent `unbind`, and any still open after `shutdown.drain_timeout_ms`
+/// are force-closed.
+pub async fn run_smpp_server(
+    config: Arc<AppConfig>,
+    session_manager: Arc<SessionManager>,
+    message_queue: Arc<MessageQueue>,
+    shutdown: impl std::future::Future<Output = ()>,
+) -> std::io::Result<()> {
     let smpp_version = SmppVersion::from_str(&config.smpp.version);
-    tracing::info!("SMPP Server started/listening on {} (SMPP version: {} compatibility)", addr, smpp_version.as_str());
 
-    loop {
-        let (socket, _) = listener.accept().await?;
-        let config_clone = config.clone();
-        let session_manager = session_manager.clone();
-        let message_queue = message_queue.clone();
-        
-        tokio::spawn(async move {
-            if let Err(e) = handle_connection(socket, config_clone, session_manager, message_queue).await {
-                tracing::error!("Connection error: {}", e);
+    // One accept loop per port, all feeding the same connection set
+    let (accepted_tx, mut accepted_rx) = mpsc::channel::<(TcpStream, std::net::SocketAddr, u16)>(64);
+    let mut listeners = JoinSet::new();
+    for port in config.smpp.listen_ports() {
+        // A (host, port) pair also resolves bare IPv6 hosts such as "::"
+        let listener = TcpListener::bind((config.smpp.host.as_str(), port)).await?;
+        tracing::info!("SMPP Server started/listening on {} (SMPP version: {} compatibility)", listener.local_addr()?, smpp_version.as_str());
+        let accepted_tx = accepted_tx.clone();
+        listeners.spawn(async move {
+            loop {
+                match listener.accept().await {
+                    Ok((socket, remote_addr)) => {
+                        if accepted_tx.send((socket, remote_addr, port)).await.is_err() {
+                            break;
+                        }
+                    }
+                    Err(e) => {
+                        tracing::error!("Accept error on port {}: {}", port, e);
+                        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+                    }
+                }
             }
         });
     }
+    drop(accepted_tx);
+
+    let tls_listener = if config.smpp.tls.enabled {
+        let acceptor = tls::build_acceptor(&config.smpp.tls)?;
+        let tls_listener = TcpListener::bind((config.smpp.host.as_str(), config.smpp.tls.port)).await?;
+        tracing::info!("SMPP TLS listener on {} (client certs {})", tls_listener.local_addr()?,
+            if config.smpp.tls.require_client_cert { "required" } else { "not required" });
+        Some((tls_listener, acceptor))
+    } else {
+        None
+    };
+
+    session_manager.set_listener_ready(true);
+    let mut connections = JoinSet::new();
+    tokio::pin!(shutdown);
+
+    loop {
+        tokio::select! {
+            Some((socket, remote_addr, port)) = accepted_rx.recv() => {
+                tracing::info!("Connection from {} on port {}", remote_addr, port);
+                let local_addr = match socket.local_addr() {
+                    Ok(local_addr) => local_addr,
+                    Err(e) => {
+                        tracing::error!("Dropping connection from {}: {}", remote_addr, e);
+                        continue;
+                    }
+                };
+                configure_socket(&socket, &session_manager.live_config(&config).smpp);
+                let config_clone = config.clone();
+                let session_manager = session_manager.clone();
+                let message_queue = message_queue.clone();
+                
+                connections.spawn(async move {
+                    if let Err(e) = handle_connection(socket, remote_addr, local_addr, config_clone, session_manager, message_queue).await {
+                        tracing::error!("Connection error: {}", e);
+                    }
+                });
+            }
+            accepted = async { tls_listener.as_ref().expect("guarded by is_some").0.accept().await }, if tls_listener.is_some() => {
+                let (socket, remote_addr) = match accepted {
+                    Ok(accepted) => accepted,
+                    Err(e) => {
+                        tracing::error!("TLS accept error: {}", e);
+                        continue;
+                    }
+                };
+                configure_socket(&socket, &session_manager.live_config(&config).smpp);
+                let acceptor = tls_listener.as_ref().expect("guarded by is_some").1.clone();
+                connections.spawn(handle_tls_connection(socket, remote_addr, acceptor, config.clone(), session_manager.clone(), message_queue.clone()));
+            }
+            // Reap finished connection tasks
+            Some(_) = connections.join_next(), if !connections.is_empty() => {}
+            _ = &mut shutdown => break,
+        }
+    }
+
+    listeners.shutdown().await;
+    drop(tls_listener);
+    session_manager.set_listener_ready(false);
+    tracing::info!("Shutting down SMPP server: draining {} connections", connections.len());
+    session_manager.begin_shutdown();
+
+    let drain_timeout = std::time::Duration::from_millis(config.shutdown.drain_timeout_ms);
+    let drained = tokio::time::timeout(drain_timeout, async {
+        while connections.join_next().await.is_some() {}
+    }).await;
+    if drained.is_err() {
+        tracing::warn!("Drain timeout after {:?}: force-closing {} connections", drain_timeout, connections.len());
+        connections.shutdown().await;
+        // Aborted connections never reach their own cleanup
+        for session in session_manager.get_all_sessions() {
+            session_manager.remove_session(&session.id);
+        }
+    }
+
+    tracing::info!("SMPP server stopped");
+    Ok(())
+}
+
+/// Apply `smpp.tcp_nodelay` and `smpp.tcp_keepalive_secs` to an accepted socket, so that
+/// half-open connections are noticed without waiting for the OS default of hours
+pub(crate) fn configure_socket(socket: &TcpStream, config: &SmppConfig) {
+    let sock = socket2::SockRef::from(socket);
+    if let Err(e) = sock.set_tcp_nodelay(config.tcp_nodelay) {
+        tracing::warn!("Failed to set TCP_NODELAY: {}", e);
+    }
+    if config.tcp_keepalive_secs > 0 {
+        let period = std::time::Duration::from_secs(config.tcp_keepalive_secs);
+        let keepalive = socket2::TcpKeepalive::new().with_time(period).with_interval(period);
+        if let Err(e) = sock.set_tcp_keepalive(&keepalive) {
+            tracing::warn!("Failed to enable TCP keepalive: {}", e);
+        }
+    }
+}
+
+/// Complete the TLS handshake, then serve the SMPP session over the encrypted stream
+async fn handle_tls_connection(
+    socket: TcpStream,
+    remote_addr: std::net::SocketAddr,
+    acceptor: TlsAcceptor,
+    config: Arc<AppConfig>,
+    session_manager: Arc<SessionManager>,
+    message_queue: Arc<MessageQueue>,
+) {
+    let local_addr = match socket.local_addr() {
+        Ok(local_addr) => local_addr,
+        Err(e) => {
+            tracing::error!("Dropping TLS connection from {}: {}", remote_addr, e);
+            return;
+        }
+    };
+    let stream = match acceptor.accept(socket).await {
+        Ok(stream) => stream,
+        Err(e) => {
+            tracing::warn!("TLS handshake with {} failed: {}", remote_addr, e);
+            return;
+        }
+    };
+    // With mTLS the verified certificate CN pins the system_id this connection may bind as
+    if let Some(cn) = tls::peer_common_name(stream.get_ref().1) {
+        tracing::info!("TLS client {} presented certificate CN={}", remote_addr, cn);
+        session_manager.set_client_identity(remote_addr, cn);
+    }
+    if let Err(e) = handle_connection(stream, remote_addr, local_addr, config, session_manager.clone(), message_queue).await {
+        tracing::error!("Connection error: {}", e);
+    }
+    session_manager.clear_client_identity(&remote_addr);
 }
 
 use tokio::sync::mpsc;
 
-async fn handle_connection(socket: TcpStream, config: Arc<AppConfig>, session_manager: Arc<SessionManager>, message_queue: Arc<MessageQueue>) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    let remote_addr = socket.peer_addr()?;
+/// Every log line for the connection carries its peer address and, once bound, system_id.
+/// `local_addr` is our end of the socket, used as the simulator's endpoint in pcap exports.
+#[tracing::instrument(name = "session", skip_all, fields(addr = %remote_addr, system_id = tracing::field::Empty))]
+pub(crate) async fn handle_connection<S>(socket: S, remote_addr: std::net::SocketAddr, local_addr: std::net::SocketAddr, config: Arc<AppConfig>, session_manager: Arc<SessionManager>, message_queue: Arc<MessageQueue>) -> Result<(), Box<dyn std::error::Error + Send + Sync>>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
     tracing::info!("New connection from {}", remote_addr);
 
     // Use SmppCodec for framing with version compatibility
     let smpp_version = SmppVersion::from_str(&config.smpp.version);
-    let framed = Framed::new(socket, SmppCodec::new(smpp_version));
+    let capture = config.smpp.capture_pdus.then(|| {
+        let capture = Arc::new(PduCapture::new(remote_addr, local_addr));
+        session_manager.set_capture(remote_addr, capture.clone());
+        capture
+    });
+    let codec = SmppCodec::new(smpp_version)
+        .with_corrupt_response_percent(config.smpp.corrupt_response_percent)
+        .with_capture(capture);
+    let framed = Framed::new(socket, codec);
     let (mut sink, mut stream) = framed.split();
     
     // Channel for sending PDUs from other parts of the application (e.g. LifecycleManager) to this socket
@@ -52,17 +226,85 @@ async fn handle_connection(socket: TcpStream, config: Arc<AppConfig>, session_ma
     // Track current session ID if authenticated
     let mut current_session_id: Option<String> = None;
 
+    // Set once server shutdown has asked this client to unbind
+    let mut shutdown = session_manager.shutdown_signal();
+    let mut unbinding = false;
+
+    // Fired when the session is disconnected from the web API
+    let mut disconnect: Option<tokio_util::sync::CancellationToken> = None;
+
     loop {
+        if disconnect.is_none() {
+            disconnect = current_session_id.as_deref()
+                .and_then(|id| session_manager.get_session(id))
+                .map(|session| session.disconnect);
+        }
+        let disconnected = disconnect.clone();
+
         tokio::select! {
+            _ = async { disconnected.expect("guarded by is_some").cancelled_owned().await }, if disconnected.is_some() => {
+                tracing::info!("Session for {} disconnected by request", remote_addr);
+                break;
+            }
+            // Server shutdown: flush queued DRs, then ask the client to unbind
+            _ = async { let _ = shutdown.wait_for(|stopping| *stopping).await; }, if !unbinding => {
+                unbinding = true;
+                if current_session_id.is_none() {
+                    break;
+                }
+                while let Ok(command) = rx.try_recv() {
+                    sink.feed(command).await?;
+                }
+                tracing::info!("Shutdown: sending unbind to {}", remote_addr);
+                let sequence_number = current_session_id.as_deref()
+                    .and_then(|id| session_manager.get_session(id))
+                    .map_or(1, |session| session.next_sequence_number());
+                sink.send(Command::builder()
+                    .status(CommandStatus::EsmeRok)
+                    .sequence_number(sequence_number)
+                    .pdu(Pdu::Unbind)).await?;
+            }
             // Handle incoming PDU from client
             Some(command_result) = stream.next() => {
                 match command_result {
-                    Ok(command) => {
+                    Ok(Ok(command)) => {
                         tracing::debug!("Received Command from {}: {:?}", remote_addr, command);
+                        if unbinding && matches!(command.pdu(), Some(Pdu::UnbindResp)) {
+                            tracing::info!("Shutdown: {} acknowledged unbind", remote_addr);
+                            break;
+                        }
                         
+                        // Reloaded settings apply from the next PDU
+                        let config = session_manager.live_config(&config);
                         // Pass tx.clone() so handle_command can give it to a new Session
                         if let Some(resp) = handle_command(&command, &config, &session_manager, &message_queue, &mut current_session_id, remote_addr, tx.clone()).await {
-                            sink.send(resp).await?;
+                            let is_submit_resp = matches!(resp.pdu(), Some(Pdu::SubmitSmResp(_)));
+                            let frees_window = resp.status == CommandStatus::EsmeRok && is_submit_resp;
+                            if is_submit_resp && config.smpp.fire_and_forget {
+                                // One-way link: the client never sees its submit_sm_resp
+                                tracing::debug!("fire_and_forget: dropping SubmitSmResp seq {} to {}", resp.sequence_number(), remote_addr);
+                            } else {
+                                let delay = response_delay(&resp, &config);
+                                if !delay.is_zero() {
+                                    tracing::debug!("Delaying {:?} seq {} to {} by {:?}", resp.id(), resp.sequence_number(), remote_addr, delay);
+                                    tokio::time::sleep(delay).await;
+                                }
+                                send_response(&mut sink, resp, remote_addr).await?;
+                            }
+                            if frees_window
+                                && let Some(session) = current_session_id.as_deref().and_then(|id| session_manager.get_session(id)) {
+                                session.release_window();
+                            }
+                        }
+                    }
+                    Ok(Err(invalid)) => {
+                        tracing::warn!("Malformed PDU 0x{:08x} seq {} from {}: {}", invalid.command_id, invalid.sequence_number, remote_addr, invalid.reason);
+                        if !invalid.is_response() {
+                            let nack = Command::builder()
+                                .status(invalid.status)
+                                .sequence_number(invalid.sequence_number)
+                                .pdu(Pdu::GenericNack);
+                            send_response(&mut sink, nack, remote_addr).await?;
                         }
                     }
                     Err(e) => {
@@ -74,7 +316,8 @@ async fn handle_connection(socket: TcpStream, config: Arc<AppConfig>, session_ma
             // Handle outgoing PDU from server (e.g. Delivery Receipt)
             Some(command) = rx.recv() => {
                 tracing::debug!("Sending async Command to {}: {:?}", remote_addr, command);
-                sink.send(command).await?;
+                let batch = session_manager.live_config(&config).lifecycle.batch_dr_sends;
+                send_outbound(&mut sink, &mut rx, command, batch).await?;
             }
             else => break,
         }
@@ -85,22 +328,222 @@ async fn handle_connection(socket: TcpStream, config: Arc<AppConfig>, session_ma
         tracing::info!("Session {} disconnected", session_id);
     }
 
+    session_manager.clear_capture(&remote_addr);
     tracing::info!("Connection closed for {}", remote_addr);
     Ok(())
 }
 
-fn authenticate(system_id: &str, password: &str, config: &AppConfig) -> bool {
+/// How long a response write may stall before the client is reported as not reading
+const WRITE_STALL_WARN: std::time::Duration = std::time::Duration::from_secs(1);
+
+/// Write a response, logging when backpressure shows the client has stopped reading
+async fn send_response<S>(sink: &mut S, resp: Command, remote_addr: std::net::SocketAddr) -> Result<(), S::Error>
+where
+    S: futures::Sink<Command> + Unpin,
+{
+    let send = sink.send(resp);
+    tokio::pin!(send);
+    tokio::select! {
+        result = &mut send => result,
+        _ = tokio::time::sleep(WRITE_STALL_WARN) => {
+            tracing::warn!("Client {} is not reading responses (write stalled for {:?})", remote_addr, WRITE_STALL_WARN);
+            send.await
+        }
+    }
+}
+
+/// Write a server-initiated PDU to the client. When `batch` is set, any further
+/// PDUs already waiting on the channel are written too and the sink is flushed
+/// once for the whole group. Returns the number of PDUs written.
+pub(crate) async fn send_outbound<S>(sink: &mut S, rx: &mut mpsc::Receiver<Command>, first: Command, batch: bool) -> Result<usize, S::Error>
+where
+    S: futures::Sink<Command> + Unpin,
+{
+    if !batch {
+        sink.send(first).await?;
+        return Ok(1);
+    }
+
+    sink.feed(first).await?;
+    let mut count = 1;
+    while let Ok(command) = rx.try_recv() {
+        sink.feed(command).await?;
+        count += 1;
+    }
+    sink.flush().await?;
+    Ok(count)
+}
+
+/// Check credentials and return the matching account so its policy can be applied
+fn authenticate(system_id: &str, password: &str, config: &AppConfig) -> Option<SmppAccount> {
     // Check default account
     if system_id == config.smpp.system_id && password == config.smpp.password {
-        return true;
+        return Some(SmppAccount::new(&config.smpp.system_id, &config.smpp.password));
     }
     // Check additional accounts
-    for account in &config.smpp.accounts {
-        if system_id == account.system_id && password == account.password {
-            return true;
+    config.smpp.accounts.iter()
+        .find(|account| system_id == account.system_id && password == account.password)
+        .cloned()
+}
+
+/// Whether `account` may bind as `bind_type`
+fn bind_type_allowed(account: &SmppAccount, bind_type: &BindType) -> bool {
+    account.allowed_bind_types.is_empty()
+        || account.allowed_bind_types.iter().any(|t| BindType::parse(t).as_ref() == Some(bind_type))
+}
+
+/// The submit rate limit configured for a bound system_id, if any
+fn account_submit_limit(system_id: &str, config: &AppConfig) -> Option<u32> {
+    config.smpp.accounts.iter()
+        .find(|account| account.system_id == system_id)
+        .and_then(|account| account.max_submit_per_second)
+}
+
+/// Fields shared by bind_transmitter, bind_receiver and bind_transceiver
+struct BindRequest {
+    system_id: COctetString<1, 16>,
+    password: String,
+    interface_version: InterfaceVersion,
+    address_range: Option<String>,
+}
+
+impl BindRequest {
+    fn new(system_id: COctetString<1, 16>, password: COctetString<1, 9>, interface_version: InterfaceVersion, address_range: COctetString<1, 41>) -> Self {
+        let address_range = address_range.to_string();
+        Self {
+            system_id,
+            password: password.to_string(),
+            interface_version,
+            address_range: if address_range.is_empty() { None } else { Some(address_range) },
+        }
+    }
+}
+
+/// The version both sides speak: the lower of the client's request and the configured version
+fn negotiate_interface_version(requested: InterfaceVersion, config: &AppConfig) -> InterfaceVersion {
+    let supported = SmppVersion::from_str(&config.smpp.version).interface_version();
+    InterfaceVersion::from(u8::from(requested).min(u8::from(supported)))
+}
+
+/// Build the bind response PDU matching the requested bind type. `interface_version` is sent
+/// as the sc_interface_version TLV advertising the negotiated version; None leaves it out.
+fn bind_response(bind_type: &BindType, status: CommandStatus, sequence_number: u32, system_id: COctetString<1, 16>, interface_version: Option<InterfaceVersion>) -> Command {
+    let pdu = match bind_type {
+        BindType::Transmitter => Pdu::BindTransmitterResp(rusmpp::pdus::BindTransmitterResp::new(system_id, interface_version)),
+        BindType::Receiver => Pdu::BindReceiverResp(rusmpp::pdus::BindReceiverResp::new(system_id, interface_version)),
+        BindType::Transceiver => Pdu::BindTransceiverResp(rusmpp::pdus::BindTransceiverResp::new(system_id, interface_version)),
+    };
+    Command::builder()
+        .status(status)
+        .sequence_number(sequence_number)
+        .pdu(pdu)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn handle_bind(
+    sequence_number: u32,
+    bind_type: BindType,
+    req: BindRequest,
+    config: &AppConfig,
+    session_manager: &SessionManager,
+    current_session_id: &mut Option<String>,
+    remote_addr: std::net::SocketAddr,
+    sender: mpsc::Sender<Command>,
+) -> Command {
+    let system_id = req.system_id.to_string();
+    // Bind responses carry the SMSC's identity, not the client's
+    let smsc_system_id = config.smpp.smsc_system_id.as_deref().unwrap_or(&config.smpp.system_id);
+    let resp_system_id = COctetString::from_str(smsc_system_id).unwrap_or_else(|_| req.system_id.clone());
+
+    if session_manager.force_bind_failure() {
+        tracing::warn!("Bind for system_id {} refused: forced bind failure is on", system_id);
+        return bind_response(&bind_type, CommandStatus::EsmeRbindfail, sequence_number, resp_system_id, None);
+    }
+
+    // Lockout after repeated failures: even the right password is refused until it expires
+    let max_bind_failures = config.smpp.max_bind_failures;
+    if max_bind_failures > 0
+        && let Some(remaining) = session_manager.bind_lockout_remaining(&system_id) {
+        tracing::warn!("Bind for system_id {} refused: locked out for another {}ms", system_id, remaining.as_millis());
+        return bind_response(&bind_type, CommandStatus::EsmeRbindfail, sequence_number, resp_system_id, None);
+    }
+
+    // AUTH CHECK
+    let Some(account) = authenticate(&system_id, &req.password, config) else {
+        tracing::warn!("Auth failed for system_id: {}", system_id);
+        if max_bind_failures > 0 && session_manager.record_bind_failure(
+            &system_id,
+            max_bind_failures,
+            std::time::Duration::from_millis(config.smpp.bind_failure_window_ms),
+            std::time::Duration::from_millis(config.smpp.lockout_duration_ms),
+        ) {
+            tracing::warn!("system_id {} locked out for {}ms after {} failed binds",
+                system_id, config.smpp.lockout_duration_ms, max_bind_failures);
         }
+        return bind_response(&bind_type, CommandStatus::EsmeRbindfail, sequence_number, resp_system_id, None);
+    };
+
+    if !bind_type_allowed(&account, &bind_type) {
+        tracing::warn!("Bind type {:?} not allowed for system_id: {}", bind_type, system_id);
+        return bind_response(&bind_type, CommandStatus::EsmeRbindfail, sequence_number, resp_system_id, None);
+    }
+
+    // mTLS: the client certificate decides which system_id may bind
+    if let Some(cn) = session_manager.client_identity(&remote_addr)
+        && cn != system_id {
+        tracing::warn!("Bind as {} refused: client certificate CN is {}", system_id, cn);
+        return bind_response(&bind_type, CommandStatus::EsmeRbindfail, sequence_number, resp_system_id, None);
+    }
+
+    // Anti-flap: refuse binds arriving too soon after this system_id last disconnected
+    if config.smpp.min_reconnect_interval_ms > 0
+        && let Some(elapsed) = session_manager.since_last_disconnect(&system_id)
+        && elapsed < std::time::Duration::from_millis(config.smpp.min_reconnect_interval_ms) {
+        tracing::warn!("Rejecting bind for system_id {}: reconnected {}ms after disconnect (min {}ms)",
+            system_id, elapsed.as_millis(), config.smpp.min_reconnect_interval_ms);
+        return bind_response(&bind_type, CommandStatus::EsmeRbindfail, sequence_number, resp_system_id, None);
+    }
+
+    session_manager.clear_bind_failures(&system_id);
+    tracing::Span::current().record("system_id", system_id.as_str());
+    let interface_version = negotiate_interface_version(req.interface_version, config);
+    let mut session = Session::new(system_id, bind_type.clone(), remote_addr, sender, req.address_range);
+    session.interface_version = Some(interface_version);
+    *current_session_id = Some(session.id.clone());
+    session_manager.add_session(session);
+
+    // sc_interface_version is a TLV, which an SMPP 3.3 client cannot parse
+    let sc_interface_version = (u8::from(interface_version) >= u8::from(InterfaceVersion::Smpp3_4)).then_some(interface_version);
+    bind_response(&bind_type, CommandStatus::EsmeRok, sequence_number, resp_system_id, sc_interface_version)
+}
+
+/// Hold a bind response back for `smpp.bind_delay_ms`, simulating a slow SMSC handshake
+async fn bind_delay(config: &AppConfig) {
+    if config.smpp.bind_delay_ms > 0 {
+        tracing::debug!("Delaying bind response by {}ms (bind_delay_ms)", config.smpp.bind_delay_ms);
+        tokio::time::sleep(std::time::Duration::from_millis(config.smpp.bind_delay_ms)).await;
     }
-    false
+}
+
+/// Build a failed submit_sm_resp carrying no message_id
+fn submit_sm_error(status: CommandStatus, sequence_number: u32) -> Command {
+    Command::builder()
+        .status(status)
+        .sequence_number(sequence_number)
+        .pdu(Pdu::SubmitSmResp(rusmpp::pdus::SubmitSmResp::new(
+            COctetString::from_str("").unwrap_or_default(),
+            vec![],
+        )))
+}
+
+/// congestion_state (0-100) for a session submitting at `rate` per second: its load as a
+/// percentage of `throttle_per_second`, where 100 means congested
+fn congestion_state(rate: u32, throttle_per_second: u32) -> u8 {
+    (u64::from(rate) * 100 / u64::from(throttle_per_second.max(1))).min(100) as u8
+}
+
+fn congestion_tlv(level: u8) -> MessageSubmissionResponseTlvValue {
+    MessageSubmissionResponseTlvValue::Other { tag: TlvTag::CongestionState, value: AnyOctetString::from_vec(vec![level]) }
 }
 
 pub(crate) async fn handle_command(
@@ -117,129 +560,268 @@ pub(crate) async fn handle_command(
         match pdu {
             Pdu::BindTransmitter(req) => {
                 tracing::info!("BindTransmitter: {:?}", req);
-                // AUTH CHECK
-                if authenticate(&req.system_id.to_string(), &req.password.to_string(), config) {
-                     let address_range = if req.address_range.to_string().is_empty() { None } else { Some(req.address_range.to_string()) };
-                     let session = Session::new(req.system_id.to_string(), BindType::Transmitter, remote_addr, sender, address_range);
-                     *current_session_id = Some(session.id.clone());
-                     session_manager.add_session(session);
-                     
-                     Some(Command::builder()
-                        .status(CommandStatus::EsmeRok)
-                        .sequence_number(command.sequence_number())
-                        .pdu(Pdu::BindTransmitterResp(rusmpp::pdus::BindTransmitterResp::new(
-                            req.system_id.clone(),
-                            Some(rusmpp::values::InterfaceVersion::Smpp5_0),
-                        )))
-                     )
-                } else {
-                    tracing::warn!("Auth failed for system_id: {}", req.system_id.to_string());
-                    Some(Command::builder()
-                        .status(CommandStatus::EsmeRbindfail)
-                        .sequence_number(command.sequence_number())
-                        .pdu(Pdu::BindTransmitterResp(rusmpp::pdus::BindTransmitterResp::new(
-                             req.system_id.clone(),
-                             None
-                        )))
-                    )
-                }
+                let bind = BindRequest::new(req.system_id, req.password, req.interface_version, req.address_range);
+                let resp = handle_bind(command.sequence_number(), BindType::Transmitter, bind, config, session_manager, current_session_id, remote_addr, sender);
+                bind_delay(config).await;
+                Some(resp)
             }
             Pdu::BindReceiver(req) => {
                 tracing::info!("BindReceiver: {:?}", req);
-                 // AUTH CHECK
-                if authenticate(&req.system_id.to_string(), &req.password.to_string(), config) {
-                     let address_range = if req.address_range.to_string().is_empty() { None } else { Some(req.address_range.to_string()) };
-                     let session = Session::new(req.system_id.to_string(), BindType::Receiver, remote_addr, sender, address_range);
-                     *current_session_id = Some(session.id.clone());
-                     session_manager.add_session(session);
-
-                    Some(Command::builder()
-                        .status(CommandStatus::EsmeRok)
-                        .sequence_number(command.sequence_number())
-                        .pdu(Pdu::BindReceiverResp(rusmpp::pdus::BindReceiverResp::new(
-                            req.system_id.clone(),
-                            Some(rusmpp::values::InterfaceVersion::Smpp5_0),
-                        )))
-                     )
-                } else {
-                     Some(Command::builder()
-                        .status(CommandStatus::EsmeRbindfail)
-                        .sequence_number(command.sequence_number())
-                        .pdu(Pdu::BindReceiverResp(rusmpp::pdus::BindReceiverResp::new(
-                             req.system_id.clone(),
-                             None
-                        )))
-                    )
-                }
+                let bind = BindRequest::new(req.system_id, req.password, req.interface_version, req.address_range);
+                let resp = handle_bind(command.sequence_number(), BindType::Receiver, bind, config, session_manager, current_session_id, remote_addr, sender);
+                bind_delay(config).await;
+                Some(resp)
             }
             Pdu::BindTransceiver(req) => {
                 tracing::info!("BindTransceiver: {:?}", req);
-                 // AUTH CHECK
-                if authenticate(&req.system_id.to_string(), &req.password.to_string(), config) {
-                     let address_range = if req.address_range.to_string().is_empty() { None } else { Some(req.address_range.to_string()) };
-                     let session = Session::new(req.system_id.to_string(), BindType::Transceiver, remote_addr, sender, address_range);
-                     *current_session_id = Some(session.id.clone());
-                     session_manager.add_session(session);
-                     
-                     Some(Command::builder()
-                        .status(CommandStatus::EsmeRok)
-                        .sequence_number(command.sequence_number())
-                        .pdu(Pdu::BindTransceiverResp(rusmpp::pdus::BindTransceiverResp::new(
-                            req.system_id.clone(),
-                            Some(rusmpp::values::InterfaceVersion::Smpp5_0),
-                        )))
-                     )
-                } else {
-                     Some(Command::builder()
-                        .status(CommandStatus::EsmeRbindfail)
-                        .sequence_number(command.sequence_number())
-                        .pdu(Pdu::BindTransceiverResp(rusmpp::pdus::BindTransceiverResp::new(
-                             req.system_id.clone(),
-                             None
-                        )))
-                    )
-                }
+                let bind = BindRequest::new(req.system_id, req.password, req.interface_version, req.address_range);
+                let resp = handle_bind(command.sequence_number(), BindType::Transceiver, bind, config, session_manager, current_session_id, remote_addr, sender);
+                bind_delay(config).await;
+                Some(resp)
             }
             Pdu::SubmitSm(req) => {
                 // Check if session is bound
                 if current_session_id.is_none() {
                     tracing::warn!("SubmitSM without bound session");
+                    return Some(submit_sm_error(CommandStatus::EsmeRinvbndsts, command.sequence_number()));
+                }
+
+                // A receiver bind may only take deliveries, not submit
+                let session = current_session_id.as_deref().and_then(|id| session_manager.get_session(id));
+                if let Some(session) = &session
+                    && session.bind_type == BindType::Receiver {
+                    tracing::warn!("SubmitSM rejected: session {} is bound as receiver", session.id);
+                    return Some(submit_sm_error(CommandStatus::EsmeRinvbndsts, command.sequence_number()));
+                }
+
+                // A transmitter bound with an address_range may only send from within it
+                if let Some(session) = &session
+                    && session.bind_type == BindType::Transmitter
+                    && session.address_range.is_some()
+                    && !session.in_address_range(&req.source_addr.to_string()) {
+                    tracing::warn!("SubmitSM rejected: source {} outside address_range {:?} of session {}",
+                        req.source_addr, session.address_range, session.id);
+                    return Some(submit_sm_error(CommandStatus::EsmeRinvsrcadr, command.sequence_number()));
+                }
+
+                // Refuse new submits while the session's window is full
+                if config.smpp.max_window_size > 0
+                    && let Some(session) = &session
+                    && session.window_in_use() >= config.smpp.max_window_size {
+                    tracing::warn!("SubmitSM rejected: window full for session {} ({} outstanding)", session.id, session.window_in_use());
+                    return Some(submit_sm_error(CommandStatus::EsmeRmsgqful, command.sequence_number()));
+                }
+
+                // Per-session rate limit
+                if config.smpp.max_submit_per_second > 0
+                    && let Some(session_id) = current_session_id.as_deref()
+                    && !session_manager.try_session_submit(session_id, config.smpp.max_submit_per_second) {
+                    tracing::warn!("SubmitSM throttled for session {} (limit {}/s)", session_id, config.smpp.max_submit_per_second);
+                    return Some(submit_sm_error(CommandStatus::EsmeRthrottled, command.sequence_number()));
+                }
+
+                // Per-account rate limit
+                if let Some(session) = &session
+                    && let Some(limit) = account_submit_limit(&session.system_id, config)
+                    && !session_manager.try_account_submit(&session.system_id, limit) {
+                    tracing::warn!("SubmitSM throttled for system_id {} (limit {}/s)", session.system_id, limit);
+                    return Some(submit_sm_error(CommandStatus::EsmeRthrottled, command.sequence_number()));
+                }
+
+                // Congestion model: congestion_state climbs with the session's submit rate until it is throttled
+                let congestion = match &session {
+                    Some(session) if config.smpp.congestion_throttle_per_second > 0 => {
+                        let rate = session.submit_rate(std::time::Duration::from_millis(config.smpp.congestion_window_ms));
+                        Some(congestion_state(rate, config.smpp.congestion_throttle_per_second))
+                    }
+                    _ => None,
+                };
+                if congestion == Some(100) {
+                    tracing::warn!("SubmitSM throttled: session {:?} congested (limit {}/s)", current_session_id, config.smpp.congestion_throttle_per_second);
                     return Some(Command::builder()
-                        .status(CommandStatus::EsmeRinvbndsts)
+                        .status(CommandStatus::EsmeRthrottled)
                         .sequence_number(command.sequence_number())
                         .pdu(Pdu::SubmitSmResp(rusmpp::pdus::SubmitSmResp::new(
                             COctetString::from_str("").unwrap_or_default(),
-                            vec![],
+                            vec![congestion_tlv(100)],
                         )))
                     );
                 }
+                let response_tlvs: Vec<_> = congestion
+                    .filter(|level| *level >= config.smpp.congestion_onset_percent)
+                    .map(congestion_tlv)
+                    .into_iter()
+                    .collect();
+
+                // Guard against abusive TLV streams
+                let tlv_count = req.tlvs().len();
+                let tlv_bytes: usize = req.tlvs().iter().map(|t| t.length()).sum();
+                if (config.smpp.max_tlvs > 0 && tlv_count > config.smpp.max_tlvs)
+                    || (config.smpp.max_tlv_bytes > 0 && tlv_bytes > config.smpp.max_tlv_bytes) {
+                    tracing::warn!("SubmitSM rejected: {} TLVs / {} bytes exceeds limit ({} / {})",
+                        tlv_count, tlv_bytes, config.smpp.max_tlvs, config.smpp.max_tlv_bytes);
+                    return Some(submit_sm_error(CommandStatus::EsmeRinvtlvstream, command.sequence_number()));
+                }
+
+                // Long content belongs in message_payload
+                let has_payload = req.tlvs().iter().any(|tlv| matches!(tlv.value(), Some(TlvValue::MessagePayload(_))));
+                if !has_payload && req.short_message().as_ref().len() > config.smpp.max_sm_length {
+                    tracing::warn!("SubmitSM rejected: short_message of {} octets exceeds max_sm_length {}",
+                        req.short_message().as_ref().len(), config.smpp.max_sm_length);
+                    return Some(submit_sm_error(CommandStatus::EsmeRinvmsglen, command.sequence_number()));
+                }
                 
-                let message_id = message_queue.next_message_id();
-                tracing::info!("SubmitSM: message_id={}, dest={}", message_id, req.destination_addr.to_string());
-                
-                // Queue the message for potential delivery report
-                let queued_msg = QueuedMessage {
-                    message_id: message_id.clone(),
-                    source_addr: req.source_addr.to_string(),
-                    dest_addr: req.destination_addr.to_string(),
-                    short_message: req.short_message().as_ref().to_vec(),
-                    data_coding: 0, // TODO: extract from req.data_coding
-                    session_id: current_session_id.clone().unwrap_or_default(),
-                    submitted_at: std::time::Instant::now(),
+                // Configured error statuses for particular destinations
+                if let Some(status) = submit_error_for(&req.destination_addr.to_string(), config) {
+                    tracing::info!("SubmitSM to {} answered with configured status {:?}", req.destination_addr, status);
+                    return Some(submit_sm_error(status, command.sequence_number()));
+                }
+
+                // CHAOS: silently drop some responses so the client times out and retransmits
+                let drop_response = config.smpp.drop_response_percent > 0
+                    && rand::rng().random_range(0..100) < config.smpp.drop_response_percent;
+                let reply = if drop_response { SubmitReply::DropResponse } else { SubmitReply::Respond };
+                let message_id = match queue_submit(&req, session.as_ref(), config, session_manager, message_queue, reply) {
+                    SubmitOutcome::Queued(message_id) => message_id,
+                    // A repeat of a recently accepted submit gets the original ID back and is not queued again
+                    SubmitOutcome::Duplicate(original_id) => {
+                        return Some(Command::builder()
+                            .status(CommandStatus::EsmeRok)
+                            .sequence_number(command.sequence_number())
+                            .pdu(Pdu::SubmitSmResp(rusmpp::pdus::SubmitSmResp::new(
+                                COctetString::from_str(&original_id).unwrap_or_default(),
+                                vec![],
+                            )))
+                        );
+                    }
+                    SubmitOutcome::Dropped => {
+                        tracing::info!("SubmitSM seq {} to {}: dropping response and message (drop_response_percent)",
+                            command.sequence_number(), req.destination_addr);
+                        return None;
+                    }
                 };
-                message_queue.add_pending_dr(queued_msg);
-                
+
+                if drop_response {
+                    tracing::info!("SubmitSM seq {}: message {} queued, dropping its response (drop_response_percent)",
+                        command.sequence_number(), message_id);
+                    return None;
+                }
+
                 Some(Command::builder()
                     .status(CommandStatus::EsmeRok)
                     .sequence_number(command.sequence_number())
                     .pdu(Pdu::SubmitSmResp(rusmpp::pdus::SubmitSmResp::new(
                         COctetString::from_str(&message_id).unwrap_or_default(),
-                        vec![],
+                        response_tlvs,
                     )))
                 )
             }
+            Pdu::QuerySm(req) => {
+                if current_session_id.is_none() {
+                    tracing::warn!("QuerySM without bound session");
+                    return Some(Command::builder()
+                        .status(CommandStatus::EsmeRinvbndsts)
+                        .sequence_number(command.sequence_number())
+                        .pdu(Pdu::QuerySmResp(rusmpp::pdus::QuerySmResp::default()))
+                    );
+                }
+
+                let queried_id = req.message_id.to_string();
+                tracing::info!("QuerySM: message_id={}", queried_id);
+
+                let Some(msg) = message_queue.find_message(&queried_id) else {
+                    return Some(Command::builder()
+                        .status(CommandStatus::EsmeRqueryfail)
+                        .sequence_number(command.sequence_number())
+                        .pdu(Pdu::QuerySmResp(rusmpp::pdus::QuerySmResp::new(
+                            req.message_id.clone(),
+                            EmptyOrFullCOctetString::default(),
+                            SmppMessageState::Unknown,
+                            0,
+                        )))
+                    );
+                };
+
+                let (message_state, final_date) = match (msg.final_state, msg.done_wall) {
+                    (Some(state), Some(done)) if !message_queue.is_pending_dr(&msg.message_id) => (
+                        smpp_message_state(state),
+                        EmptyOrFullCOctetString::from_str(&done.format("%y%m%d%H%M%S000+").to_string()).unwrap_or_default(),
+                    ),
+                    _ => (SmppMessageState::Enroute, EmptyOrFullCOctetString::default()),
+                };
+
+                Some(Command::builder()
+                    .status(CommandStatus::EsmeRok)
+                    .sequence_number(command.sequence_number())
+                    .pdu(Pdu::QuerySmResp(rusmpp::pdus::QuerySmResp::new(
+                        req.message_id.clone(),
+                        final_date,
+                        message_state,
+                        0,
+                    )))
+                )
+            }
+            Pdu::CancelSm(req) => {
+                if current_session_id.is_none() {
+                    tracing::warn!("CancelSM without bound session");
+                    return Some(Command::builder()
+                        .status(CommandStatus::EsmeRinvbndsts)
+                        .sequence_number(command.sequence_number())
+                        .pdu(Pdu::CancelSmResp)
+                    );
+                }
+
+                let source_addr = req.source_addr.to_string();
+                let cancelled = if req.message_id.to_string().is_empty() {
+                    // No ID: cancel everything pending from source_addr to destination_addr
+                    message_queue.cancel_pending_matching(&source_addr, &req.destination_addr.to_string()) > 0
+                } else {
+                    // The ID may be the provisional or the final one, or that of one part of a
+                    // reassembled message; the source must match if given
+                    let message_id = req.message_id.to_string();
+                    message_queue.find_message(&message_id)
+                        .filter(|msg| source_addr.is_empty() || msg.source_addr == source_addr)
+                        .is_some_and(|msg| message_queue.cancel_pending(&message_id) || message_queue.cancel_pending(&msg.message_id))
+                };
+                tracing::info!("CancelSM: message_id={} cancelled={}", req.message_id, cancelled);
+
+                Some(Command::builder()
+                    .status(if cancelled { CommandStatus::EsmeRok } else { CommandStatus::EsmeRcancelfail })
+                    .sequence_number(command.sequence_number())
+                    .pdu(Pdu::CancelSmResp)
+                )
+            }
+            Pdu::DeliverSmResp(_) => {
+                // Reconcile with the delivery receipt this answers; MO deliver_sm are not tracked
+                let session = current_session_id.as_deref().and_then(|id| session_manager.get_session(id));
+                let acked = session.and_then(|session| session.ack_receipt(command.sequence_number()));
+                match acked {
+                    Some(message_id) if command.status == CommandStatus::EsmeRok => {
+                        tracing::debug!("DeliverSmResp seq {} acknowledged DR for {}", command.sequence_number(), message_id);
+                        session_manager.metrics().delivery_receipts_acked_total.with_label_values(&["ok"]).inc();
+                        message_queue.mark_receipt_acked(&message_id);
+                    }
+                    Some(message_id) => {
+                        tracing::warn!("DeliverSmResp seq {} rejected DR for {} with {:?}", command.sequence_number(), message_id, command.status);
+                        session_manager.metrics().delivery_receipts_acked_total.with_label_values(&["error"]).inc();
+                    }
+                    None => tracing::debug!("DeliverSmResp seq {} matches no outstanding DR", command.sequence_number()),
+                }
+                None
+            }
             Pdu::EnquireLink => {
                 tracing::debug!("EnquireLink");
+                // CHAOS: leave some keepalives unanswered or answer them late, for client link monitors
+                if config.smpp.enquire_link_drop_percent > 0
+                    && rand::rng().random_range(0..100) < config.smpp.enquire_link_drop_percent {
+                    tracing::info!("EnquireLink seq {} from {}: dropping response (enquire_link_drop_percent)", command.sequence_number(), remote_addr);
+                    return None;
+                }
+                if config.smpp.enquire_link_delay_ms > 0 {
+                    tracing::info!("EnquireLink seq {} from {}: delaying response by {}ms (enquire_link_delay_ms)",
+                        command.sequence_number(), remote_addr, config.smpp.enquire_link_delay_ms);
+                    tokio::time::sleep(std::time::Duration::from_millis(config.smpp.enquire_link_delay_ms)).await;
+                }
                  Some(Command::builder()
                     .status(CommandStatus::EsmeRok)
                     .sequence_number(command.sequence_number())
@@ -260,11 +842,183 @@ pub(crate) async fn handle_command(
             }
             _ => {
                 tracing::warn!("Unhandled Command: {:?}", command);
-                None
+                generic_nack(command)
             }
         }
     } else {
         tracing::warn!("Command without PDU: {:?}", command);
-        None
+        generic_nack(command)
+    }
+}
+
+/// Latency injected before writing `resp`: its `smpp.response_delays` entry, else `smpp.response_delay_ms`.
+/// Entries are keyed by response name, matched ignoring case and underscores (`submit_sm_resp`, `SubmitSmResp`).
+pub(crate) fn response_delay(resp: &Command, config: &AppConfig) -> std::time::Duration {
+    let name = format!("{:?}", resp.id()).to_ascii_lowercase();
+    let ms = config.smpp.response_delays.iter()
+        .find(|(key, _)| key.replace('_', "").to_ascii_lowercase() == name)
+        .map_or(config.smpp.response_delay_ms, |(_, ms)| *ms);
+    std::time::Duration::from_millis(ms)
+}
+
+/// Status from the first `smpp.submit_errors` rule matching the destination, if any
+fn submit_error_for(dest_addr: &str, config: &AppConfig) -> Option<CommandStatus> {
+    config.smpp.submit_errors.iter().find(|rule| rule.matches(dest_addr))?.command_status()
+}
+
+/// The submitted body: the message_payload TLV when present (long content), otherwise short_message
+fn submit_body(req: &rusmpp::pdus::SubmitSm) -> Vec<u8> {
+    req.tlvs().iter()
+        .find_map(|tlv| match tlv.value() {
+            Some(TlvValue::MessagePayload(payload)) => Some(payload.value.as_ref().to_vec()),
+            _ => None,
+        })
+        .unwrap_or_else(|| req.short_message().as_ref().to_vec())
+}
+
+/// Whether the submitter gets a submit_sm_resp, which decides if the submit holds a window slot
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum SubmitReply {
+    /// A submit_sm_resp follows; writing it frees the slot
+    Respond,
+    /// CHAOS: the response is dropped (`drop_response_percent`), and the message too unless
+    /// `drop_response_queue` is set
+    DropResponse,
+    /// Not an SMPP submit (`POST /api/submit`), so there is no response at all
+    NoResponse,
+}
+
+/// What `queue_submit` did with a submit
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum SubmitOutcome {
+    /// Queued, or merged into a pending message by replace_if_present, under this ID
+    Queued(String),
+    /// A repeat within the dedup window: the original message's ID; nothing was queued
+    Duplicate(String),
+    /// Dropped together with its response
+    Dropped,
+}
+
+/// Queue an accepted submit_sm for its delivery receipt: dedup, message IDs, replace_if_present,
+/// concatenated parts, window and submit accounting, metrics and the audit log. Shared by the
+/// submit_sm handler, once its checks have passed, and by `POST /api/submit`.
+pub(crate) fn queue_submit(
+    req: &SubmitSm,
+    session: Option<&Session>,
+    config: &AppConfig,
+    session_manager: &SessionManager,
+    message_queue: &MessageQueue,
+    reply: SubmitReply,
+) -> SubmitOutcome {
+    if let Some(original_id) = message_queue.check_duplicate(&req.source_addr.to_string(), &req.destination_addr.to_string(), req.short_message().as_ref()) {
+        tracing::info!("SubmitSM duplicate of {}, not queued", original_id);
+        return SubmitOutcome::Duplicate(original_id);
+    }
+    if reply == SubmitReply::DropResponse && !config.smpp.drop_response_queue {
+        return SubmitOutcome::Dropped;
+    }
+
+    let mut message_id = message_queue.next_message_id();
+    // In async mode the response carries a provisional ID and the receipt a different final one
+    let final_message_id = if config.smpp.async_message_id { Some(message_queue.next_message_id()) } else { None };
+    tracing::info!("SubmitSM: message_id={}, dest={}", message_id, req.destination_addr.to_string());
+    
+    // Queue the message for potential delivery report
+    let mut queued_msg = QueuedMessage {
+        message_id: message_id.clone(),
+        source_addr: req.source_addr.to_string(),
+        dest_addr: req.destination_addr.to_string(),
+        short_message: submit_body(req),
+        data_coding: u8::from(req.data_coding),
+        session_id: session.map(|session| session.id.clone()).unwrap_or_default(),
+        submitted_at: std::time::Instant::now(),
+        submitted_wall: chrono::Local::now(),
+        tlvs: if config.smpp.capture_tlvs || !config.lifecycle.echo_tlvs.is_empty() {
+            req.tlvs().iter().map(CapturedTlv::from_tlv).collect()
+        } else {
+            vec![]
+        },
+        final_message_id,
+        final_state: None,
+        done_wall: None,
+        expires_at: time::smpp_time_from_now(&req.validity_period.to_string())
+            .map(|d| std::time::Instant::now() + d),
+        deliver_not_before: time::smpp_time_from_now(&req.schedule_delivery_time.to_string())
+            .map(|d| std::time::Instant::now() + d),
+        delivery_delay_ms: Some(delivery_delay_ms(&config.lifecycle)),
+        interim_receipt_sent: false,
+        cancelled: false,
+        receipt_acked: false,
+        source_addr_ton: u8::from(req.source_addr_ton),
+        source_addr_npi: u8::from(req.source_addr_npi),
+        dest_addr_ton: u8::from(req.dest_addr_ton),
+        dest_addr_npi: u8::from(req.dest_addr_npi),
+        esm_class: u8::from(req.esm_class),
+        registered_delivery: u8::from(req.registered_delivery),
+        service_type: req.service_type.value().to_string(),
+    };
+    let udhi = matches!(req.esm_class.gsm_features, GsmFeatures::UdhiIndicator | GsmFeatures::SetUdhiAndReplyPath);
+    // replace_if_present: overwrite an undelivered message with the same source and
+    // destination, which keeps its ID, instead of queueing a new one. In strict mode
+    // every such message is dropped and the new one is queued under its own ID.
+    let replace = req.replace_if_present_flag == ReplaceIfPresentFlag::Replace && !udhi;
+    let replaced = replace && if config.smpp.strict_replace_if_present {
+        for removed in message_queue.remove_pending_matching(&queued_msg) {
+            tracing::info!("SubmitSM {} replaces pending message {} to {}", message_id, removed.message_id, queued_msg.dest_addr);
+            let system_id = session_manager.get_session(&removed.session_id).map(|session| session.system_id).unwrap_or_default();
+            session_manager.audit(AuditRecord::new(AuditEvent::Replaced, &system_id, &removed.message_id, &removed.source_addr, &removed.dest_addr));
+        }
+        false
+    } else {
+        match message_queue.replace_pending_matching(&queued_msg) {
+            Some(replaced_id) => {
+                tracing::info!("SubmitSM replaces pending message {} to {}", replaced_id, queued_msg.dest_addr);
+                message_id = replaced_id;
+                true
+            }
+            None => false,
+        }
+    };
+    message_queue.remember_submit(&queued_msg.source_addr, &queued_msg.dest_addr, &queued_msg.short_message, &message_id);
+    // Parts of a concatenated message are buffered until the whole text can be stored
+    if !replaced {
+        match udhi.then(|| udh::split_concat(&queued_msg.short_message)).flatten() {
+            Some((concat, payload)) => {
+                queued_msg.short_message = payload.to_vec();
+                if message_queue.add_pending_segment(queued_msg, concat) {
+                    tracing::info!("Reassembled {}-part message (ref {})", concat.total, concat.reference);
+                }
+            }
+            None => message_queue.add_pending_dr(queued_msg),
+        }
+    }
+    if let Some(session) = session {
+        // A response that is never written never frees its window slot, so it takes none
+        if reply == SubmitReply::Respond {
+            session.acquire_window();
+        }
+        session.record_submit();
+    }
+    session_manager.metrics().submit_total.inc();
+    session_manager.metrics().submit_rate.record();
+    session_manager.audit(AuditRecord::new(
+        AuditEvent::SubmitSm,
+        session.map_or("", |session| session.system_id.as_str()),
+        &message_id,
+        &req.source_addr.to_string(),
+        &req.destination_addr.to_string(),
+    ));
+    SubmitOutcome::Queued(message_id)
+}
+
+/// Reject an unsupported request with generic_nack (ESME_RINVCMDID), echoing its sequence number.
+/// Responses sent by the client (deliver_sm_resp, generic_nack, ...) are never nacked.
+fn generic_nack(command: &Command) -> Option<Command> {
+    if command.id().is_response() {
+        return None;
     }
+    Some(Command::builder()
+        .status(CommandStatus::EsmeRinvcmdid)
+        .sequence_number(command.sequence_number())
+        .pdu(Pdu::GenericNack))
 }
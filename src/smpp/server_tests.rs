@@ -3,18 +3,20 @@
 //! These tests verify the server's response to various SMPP PDUs.
 //! We mock the environment by creating a local AppConfig, SessionManager, and MessageQueue.
 
-use crate::smpp::server::handle_command;
-use crate::config::AppConfig;
+use crate::smpp::server::{configure_socket, handle_command, response_delay, send_outbound};
+use crate::config::{parse_command_status, AppConfig};
 use crate::smpp::session::{Session, SessionManager, BindType};
-use crate::smpp::queue::MessageQueue;
+use crate::smpp::queue::{MessageFilter, MessageQueue, MessageState};
+use crate::smpp::lifecycle::{create_delivery_receipt, process_pending_messages};
 use tokio::sync::mpsc;
 use rusmpp::{
     Command, Pdu, CommandStatus,
     pdus::{
-        BindTransmitter,
+        BindReceiver, BindTransceiver, BindTransmitter, CancelSm, Outbind, QuerySm, SubmitSm,
     },
-    types::{COctetString},
-    values::{InterfaceVersion, Ton, Npi},
+    tlvs::{MessageSubmissionRequestTlvValue, TlvTag, TlvValue},
+    types::{COctetString, OctetString},
+    values::{InterfaceVersion, Ton, Npi, PrivacyIndicator, ReplaceIfPresentFlag, ServiceType, UserMessageReference},
 };
 use std::net::{IpAddr, Ipv4Addr, SocketAddr};
 use std::str::FromStr;
@@ -29,6 +31,7 @@ fn test_config() -> AppConfig {
         server: crate::config::ServerConfig {
             host: "0.0.0.0".to_string(),
             port: 8080,
+            auth: None,
         },
         smpp: crate::config::SmppConfig {
             system_id: "user".to_string(),
@@ -37,12 +40,17 @@ fn test_config() -> AppConfig {
             max_sessions: 50,
             accounts: vec![],
             version: "5.0".to_string(),
+            ..Default::default()
         },
         log: crate::config::LogConfig {
             level: "info".to_string(),
+            ..Default::default()
         },
         lifecycle: crate::config::LifecycleConfig::default(),
         mo_service: crate::config::MoServiceConfig::default(),
+        outbind: vec![],
+        shutdown: crate::config::ShutdownConfig::default(),
+        audit: crate::config::AuditConfig::default(),
     }
 }
 
@@ -94,6 +102,69 @@ async fn test_bind_transmitter_success() {
     assert!(matches!(session.unwrap().bind_type, BindType::Transmitter));
 }
 
+#[tokio::test]
+async fn test_bind_negotiates_interface_version() {
+    // (server version, client interface_version, expected negotiated version)
+    let cases = [
+        ("5.0", InterfaceVersion::Smpp3_4, InterfaceVersion::Smpp3_4),
+        ("5.0", InterfaceVersion::Smpp5_0, InterfaceVersion::Smpp5_0),
+        ("3.4", InterfaceVersion::Smpp5_0, InterfaceVersion::Smpp3_4),
+    ];
+    for (server_version, requested, expected) in cases {
+        let mut config = test_config();
+        config.smpp.version = server_version.to_string();
+        let session_manager = SessionManager::new();
+        let message_queue = MessageQueue::new();
+        let mut current_session_id: Option<String> = None;
+        let (tx, _rx) = mpsc::channel(1);
+
+        let bind_req = BindTransceiver::new(
+            COctetString::from_str("user").unwrap(),
+            COctetString::from_str("pass").unwrap(),
+            COctetString::empty(),
+            requested,
+            Ton::Unknown,
+            Npi::Unknown,
+            COctetString::empty(),
+        );
+        let command = Command::new(CommandStatus::EsmeRok, 1, Pdu::BindTransceiver(bind_req));
+        let resp = handle_command(&command, &config, &session_manager, &message_queue, &mut current_session_id, test_addr(), tx).await.unwrap();
+
+        let Some(Pdu::BindTransceiverResp(body)) = resp.pdu() else { panic!("Expected BindTransceiverResp, got {:?}", resp.pdu()) };
+        assert_eq!(body.sc_interface_version(), Some(expected), "server {} / client {:?}", server_version, requested);
+        let session = session_manager.get_session(&current_session_id.unwrap()).unwrap();
+        assert_eq!(session.interface_version, Some(expected));
+    }
+}
+
+#[tokio::test]
+async fn test_bind_resp_omits_sc_interface_version_for_smpp33() {
+    let config = test_config();
+    let session_manager = SessionManager::new();
+    let message_queue = MessageQueue::new();
+    let mut current_session_id: Option<String> = None;
+    let (tx, _rx) = mpsc::channel(1);
+
+    let bind_req = BindTransceiver::new(
+        COctetString::from_str("user").unwrap(),
+        COctetString::from_str("pass").unwrap(),
+        COctetString::empty(),
+        InterfaceVersion::Smpp3_3OrEarlier(0x33),
+        Ton::Unknown,
+        Npi::Unknown,
+        COctetString::empty(),
+    );
+    let command = Command::new(CommandStatus::EsmeRok, 1, Pdu::BindTransceiver(bind_req));
+    let resp = handle_command(&command, &config, &session_manager, &message_queue, &mut current_session_id, test_addr(), tx).await.unwrap();
+
+    // The bind succeeds, but without a TLV the client could not parse
+    assert_eq!(resp.status, CommandStatus::EsmeRok);
+    let Some(Pdu::BindTransceiverResp(body)) = resp.pdu() else { panic!("Expected BindTransceiverResp, got {:?}", resp.pdu()) };
+    assert!(body.sc_interface_version_tlv().is_none());
+    let session = session_manager.get_session(&current_session_id.unwrap()).unwrap();
+    assert_eq!(session.interface_version, Some(InterfaceVersion::Smpp3_3OrEarlier(0x33)));
+}
+
 #[tokio::test]
 async fn test_bind_failure_bad_creds() {
     let config = test_config();
@@ -156,6 +227,124 @@ async fn test_enquire_link() {
     assert!(matches!(resp_cmd.pdu(), Some(Pdu::EnquireLinkResp)));
 }
 
+#[tokio::test]
+async fn test_enquire_link_drop_and_delay() {
+    let mut config = test_config();
+    let session_manager = SessionManager::new();
+    let message_queue = MessageQueue::new();
+    let mut current_session_id: Option<String> = None;
+    let (tx, _rx) = mpsc::channel(1);
+    let command = Command::new(CommandStatus::EsmeRok, 3, Pdu::EnquireLink);
+
+    config.smpp.enquire_link_drop_percent = 100;
+    let resp = handle_command(&command, &config, &session_manager, &message_queue, &mut current_session_id, test_addr(), tx.clone()).await;
+    assert!(resp.is_none());
+
+    config.smpp.enquire_link_drop_percent = 0;
+    config.smpp.enquire_link_delay_ms = 100;
+    let started = std::time::Instant::now();
+    let resp = handle_command(&command, &config, &session_manager, &message_queue, &mut current_session_id, test_addr(), tx).await.unwrap();
+    assert!(started.elapsed() >= std::time::Duration::from_millis(100));
+    assert!(matches!(resp.pdu(), Some(Pdu::EnquireLinkResp)));
+}
+
+#[tokio::test]
+async fn test_bind_lockout_after_repeated_failures() {
+    let mut config = test_config();
+    config.smpp.max_bind_failures = 3;
+    config.smpp.lockout_duration_ms = 200;
+    let session_manager = SessionManager::new();
+    let message_queue = MessageQueue::new();
+    let (tx, _rx) = mpsc::channel(1);
+    let bind = |seq, password: &str| Command::new(CommandStatus::EsmeRok, seq, Pdu::BindTransmitter(BindTransmitter::new(
+        COctetString::from_str("user").unwrap(),
+        COctetString::from_str(password).unwrap(),
+        COctetString::empty(),
+        InterfaceVersion::Smpp5_0,
+        Ton::Unknown,
+        Npi::Unknown,
+        COctetString::empty(),
+    )));
+    let mut current_session_id: Option<String> = None;
+
+    // A success resets the count, so two failures either side of it don't lock the account
+    for (seq, password) in [(1, "bad"), (2, "bad"), (3, "pass"), (4, "bad"), (5, "bad")] {
+        handle_command(&bind(seq, password), &config, &session_manager, &message_queue, &mut current_session_id, test_addr(), tx.clone()).await.unwrap();
+    }
+    assert!(session_manager.bind_lockout_remaining("user").is_none());
+
+    // The third consecutive failure locks it; then even the right password is refused
+    handle_command(&bind(6, "bad"), &config, &session_manager, &message_queue, &mut current_session_id, test_addr(), tx.clone()).await.unwrap();
+    current_session_id = None;
+    let resp = handle_command(&bind(7, "pass"), &config, &session_manager, &message_queue, &mut current_session_id, test_addr(), tx.clone()).await.unwrap();
+    assert_eq!(resp.status(), CommandStatus::EsmeRbindfail);
+    assert!(current_session_id.is_none());
+
+    // Other accounts are unaffected
+    assert!(session_manager.bind_lockout_remaining("other").is_none());
+
+    // Once the lockout has passed the correct password works again
+    tokio::time::sleep(std::time::Duration::from_millis(250)).await;
+    let resp = handle_command(&bind(8, "pass"), &config, &session_manager, &message_queue, &mut current_session_id, test_addr(), tx).await.unwrap();
+    assert_eq!(resp.status(), CommandStatus::EsmeRok);
+}
+
+#[tokio::test]
+async fn test_bind_delay() {
+    let mut config = test_config();
+    config.smpp.bind_delay_ms = 100;
+    let session_manager = SessionManager::new();
+    let message_queue = MessageQueue::new();
+    let (tx, _rx) = mpsc::channel(1);
+    let bind = Command::new(CommandStatus::EsmeRok, 1, Pdu::BindTransmitter(BindTransmitter::new(
+        COctetString::from_str("user").unwrap(),
+        COctetString::from_str("pass").unwrap(),
+        COctetString::empty(),
+        InterfaceVersion::Smpp5_0,
+        Ton::Unknown,
+        Npi::Unknown,
+        COctetString::empty(),
+    )));
+    let mut current_session_id: Option<String> = None;
+
+    let started = std::time::Instant::now();
+    let resp = handle_command(&bind, &config, &session_manager, &message_queue, &mut current_session_id, test_addr(), tx).await.unwrap();
+    assert!(started.elapsed() >= std::time::Duration::from_millis(100));
+    assert_eq!(resp.status(), CommandStatus::EsmeRok);
+    assert!(current_session_id.is_some());
+}
+
+#[tokio::test]
+async fn test_force_bind_failure() {
+    let config = test_config();
+    let session_manager = SessionManager::new();
+    let message_queue = MessageQueue::new();
+    let (tx, _rx) = mpsc::channel(1);
+    let bind = |seq| Command::new(CommandStatus::EsmeRok, seq, Pdu::BindTransmitter(BindTransmitter::new(
+        COctetString::from_str("user").unwrap(),
+        COctetString::from_str("pass").unwrap(),
+        COctetString::empty(),
+        InterfaceVersion::Smpp5_0,
+        Ton::Unknown,
+        Npi::Unknown,
+        COctetString::empty(),
+    )));
+
+    // Valid credentials are refused while the toggle is on...
+    session_manager.set_force_bind_failure(true);
+    let mut current_session_id: Option<String> = None;
+    let resp = handle_command(&bind(1), &config, &session_manager, &message_queue, &mut current_session_id, test_addr(), tx.clone()).await.unwrap();
+    assert_eq!(resp.status(), CommandStatus::EsmeRbindfail);
+    assert!(current_session_id.is_none());
+    assert_eq!(session_manager.count(), 0);
+
+    // ...and accepted again once it is off
+    session_manager.set_force_bind_failure(false);
+    let resp = handle_command(&bind(2), &config, &session_manager, &message_queue, &mut current_session_id, test_addr(), tx).await.unwrap();
+    assert_eq!(resp.status(), CommandStatus::EsmeRok);
+    assert!(current_session_id.is_some());
+}
+
 #[tokio::test]
 async fn test_unbind() {
     let config = test_config();
@@ -189,3 +378,1510 @@ async fn test_unbind() {
     assert!(current_session_id.is_none());
     assert!(session_manager.get_session(&sid).is_none());
 }
+
+#[tokio::test]
+async fn test_rapid_rebind_rejected() {
+    let mut config = test_config();
+    config.smpp.min_reconnect_interval_ms = 60_000;
+    let session_manager = SessionManager::new();
+    let message_queue = MessageQueue::new();
+    let mut current_session_id: Option<String> = None;
+    let (tx, _rx) = mpsc::channel(1);
+
+    let bind = || Command::new(CommandStatus::EsmeRok, 5, Pdu::BindTransmitter(BindTransmitter::new(
+        COctetString::from_str("user").unwrap(),
+        COctetString::from_str("pass").unwrap(),
+        COctetString::empty(),
+        InterfaceVersion::Smpp5_0,
+        Ton::Unknown,
+        Npi::Unknown,
+        COctetString::empty(),
+    )));
+
+    // First bind succeeds
+    let resp = handle_command(&bind(), &config, &session_manager, &message_queue, &mut current_session_id, test_addr(), tx.clone()).await.unwrap();
+    assert_eq!(resp.status, CommandStatus::EsmeRok);
+
+    // Disconnect
+    let unbind = Command::new(CommandStatus::EsmeRok, 6, Pdu::Unbind);
+    handle_command(&unbind, &config, &session_manager, &message_queue, &mut current_session_id, test_addr(), tx.clone()).await;
+    assert!(current_session_id.is_none());
+
+    // Immediate rebind is rejected
+    let resp = handle_command(&bind(), &config, &session_manager, &message_queue, &mut current_session_id, test_addr(), tx).await.unwrap();
+    assert_eq!(resp.status, CommandStatus::EsmeRbindfail);
+    assert!(current_session_id.is_none());
+    assert_eq!(session_manager.count(), 0);
+}
+
+#[tokio::test]
+async fn test_submit_sm_captures_tlvs() {
+    let config = test_config();
+    let session_manager = SessionManager::new();
+    let message_queue = MessageQueue::new();
+    let (tx, _rx) = mpsc::channel(1);
+
+    let session = Session::new("user".to_string(), BindType::Transmitter, test_addr(), tx.clone(), None);
+    let mut current_session_id: Option<String> = Some(session.id.clone());
+    session_manager.add_session(session);
+
+    let submit = SubmitSm::builder()
+        .source_addr(COctetString::from_str("src").unwrap())
+        .destination_addr(COctetString::from_str("dst").unwrap())
+        .short_message(OctetString::from_str("hi").unwrap())
+        .push_tlv(MessageSubmissionRequestTlvValue::PrivacyIndicator(PrivacyIndicator::Restricted))
+        .push_tlv(MessageSubmissionRequestTlvValue::UserMessageReference(UserMessageReference::new(0x1234)))
+        .build();
+    let command = Command::new(CommandStatus::EsmeRok, 7, Pdu::SubmitSm(submit));
+
+    let resp = handle_command(&command, &config, &session_manager, &message_queue, &mut current_session_id, test_addr(), tx).await.unwrap();
+    assert_eq!(resp.status, CommandStatus::EsmeRok);
+
+    let msgs = message_queue.get_recent_messages();
+    assert_eq!(msgs.len(), 1);
+    let tlvs = &msgs[0].tlvs;
+    assert_eq!(tlvs.len(), 2, "Both TLVs should be captured");
+    assert_eq!(tlvs[0].tag, 0x0201);
+    assert_eq!(tlvs[0].name, "PrivacyIndicator");
+    assert_eq!(tlvs[0].value, "01");
+    assert_eq!(tlvs[1].tag, 0x0204);
+    assert_eq!(tlvs[1].value, "1234");
+
+    let with_tlv = |tag| MessageFilter { tlv: Some(tag), ..Default::default() };
+    assert_eq!(message_queue.query(&with_tlv(0x0201), 10, 0).1, 1);
+    assert_eq!(message_queue.query(&with_tlv(0x0381), 10, 0).1, 0);
+}
+
+#[tokio::test]
+async fn test_async_message_id() {
+    let mut config = test_config();
+    config.smpp.async_message_id = true;
+    let session_manager = SessionManager::new();
+    let message_queue = MessageQueue::new();
+    let (tx, _rx) = mpsc::channel(1);
+
+    let session = Session::new("user".to_string(), BindType::Transceiver, test_addr(), tx.clone(), None);
+    let mut current_session_id: Option<String> = Some(session.id.clone());
+    session_manager.add_session(session);
+
+    let submit = SubmitSm::builder()
+        .source_addr(COctetString::from_str("src").unwrap())
+        .destination_addr(COctetString::from_str("dst").unwrap())
+        .short_message(OctetString::from_str("hi").unwrap())
+        .build();
+    let command = Command::new(CommandStatus::EsmeRok, 8, Pdu::SubmitSm(submit));
+    let resp = handle_command(&command, &config, &session_manager, &message_queue, &mut current_session_id, test_addr(), tx.clone()).await.unwrap();
+    let Some(Pdu::SubmitSmResp(submit_resp)) = resp.pdu() else { panic!("Expected SubmitSmResp") };
+    let provisional_id = submit_resp.message_id().to_string();
+
+    // The receipt carries the final ID in receipted_message_id
+    let msg = message_queue.get_message(&provisional_id).unwrap();
+    let dr = create_delivery_receipt(&msg, MessageState::Delivered, &config).unwrap();
+    let Some(Pdu::DeliverSm(deliver)) = dr.pdu() else { panic!("Expected DeliverSm") };
+    let receipted_id = deliver.tlvs().iter().find_map(|tlv| match tlv.value() {
+        Some(TlvValue::ReceiptedMessageId(id)) => Some(id.to_string()),
+        _ => None,
+    }).expect("receipted_message_id TLV");
+    assert_ne!(provisional_id, receipted_id, "Final ID should differ from the provisional one");
+
+    // query_sm accepts both IDs
+    for id in [&provisional_id, &receipted_id] {
+        let query = QuerySm::builder().message_id(COctetString::from_str(id).unwrap()).build();
+        let command = Command::new(CommandStatus::EsmeRok, 9, Pdu::QuerySm(query));
+        let resp = handle_command(&command, &config, &session_manager, &message_queue, &mut current_session_id, test_addr(), tx.clone()).await.unwrap();
+        assert_eq!(resp.status, CommandStatus::EsmeRok, "query_sm should accept {}", id);
+        assert!(matches!(resp.pdu(), Some(Pdu::QuerySmResp(_))));
+    }
+
+    // Unknown IDs fail
+    let query = QuerySm::builder().message_id(COctetString::from_str("nope").unwrap()).build();
+    let command = Command::new(CommandStatus::EsmeRok, 10, Pdu::QuerySm(query));
+    let resp = handle_command(&command, &config, &session_manager, &message_queue, &mut current_session_id, test_addr(), tx).await.unwrap();
+    assert_eq!(resp.status, CommandStatus::EsmeRqueryfail);
+}
+
+#[tokio::test]
+async fn test_smsc_system_id_override() {
+    let mut config = test_config();
+    config.smpp.smsc_system_id = Some("SMSC01".to_string());
+    let session_manager = SessionManager::new();
+    let message_queue = MessageQueue::new();
+    let mut current_session_id: Option<String> = None;
+    let (tx, _rx) = mpsc::channel(1);
+
+    let bind_req = BindTransmitter::new(
+        COctetString::from_str("user").unwrap(),
+        COctetString::from_str("pass").unwrap(),
+        COctetString::empty(),
+        InterfaceVersion::Smpp5_0,
+        Ton::Unknown,
+        Npi::Unknown,
+        COctetString::empty(),
+    );
+    let command = Command::new(CommandStatus::EsmeRok, 11, Pdu::BindTransmitter(bind_req));
+
+    let resp = handle_command(&command, &config, &session_manager, &message_queue, &mut current_session_id, test_addr(), tx).await.unwrap();
+    assert_eq!(resp.status, CommandStatus::EsmeRok);
+    if let Some(Pdu::BindTransmitterResp(body)) = resp.pdu() {
+        assert_eq!(body.system_id.to_string(), "SMSC01");
+    } else {
+        panic!("Expected BindTransmitterResp, got {:?}", resp.pdu());
+    }
+    
+    // The session itself is still tracked under the client's system_id
+    let session = session_manager.get_session(&current_session_id.unwrap()).unwrap();
+    assert_eq!(session.system_id, "user");
+}
+
+#[tokio::test]
+async fn test_smsc_system_id_defaults_to_system_id() {
+    let mut config = test_config();
+    config.smpp.accounts = vec![crate::config::SmppAccount::new("other", "secret")];
+    let session_manager = SessionManager::new();
+    let message_queue = MessageQueue::new();
+    let (tx, _rx) = mpsc::channel(1);
+
+    // An extra account is answered with the SMSC's own id, not its own
+    for (seq, password, status) in [(1, "secret", CommandStatus::EsmeRok), (2, "wrong", CommandStatus::EsmeRbindfail)] {
+        let mut current_session_id: Option<String> = None;
+        let bind_req = BindTransmitter::new(
+            COctetString::from_str("other").unwrap(),
+            COctetString::from_str(password).unwrap(),
+            COctetString::empty(),
+            InterfaceVersion::Smpp3_4,
+            Ton::Unknown,
+            Npi::Unknown,
+            COctetString::empty(),
+        );
+        let command = Command::new(CommandStatus::EsmeRok, seq, Pdu::BindTransmitter(bind_req));
+        let resp = handle_command(&command, &config, &session_manager, &message_queue, &mut current_session_id, test_addr(), tx.clone()).await.unwrap();
+        assert_eq!(resp.status, status);
+        let Some(Pdu::BindTransmitterResp(body)) = resp.pdu() else {
+            panic!("Expected BindTransmitterResp, got {:?}", resp.pdu());
+        };
+        assert_eq!(body.system_id.to_string(), "user");
+    }
+}
+
+#[tokio::test]
+async fn test_submit_sm_tlv_limits() {
+    let mut config = test_config();
+    config.smpp.max_tlvs = 1;
+    let session_manager = SessionManager::new();
+    let message_queue = MessageQueue::new();
+    let (tx, _rx) = mpsc::channel(1);
+
+    let session = Session::new("user".to_string(), BindType::Transmitter, test_addr(), tx.clone(), None);
+    let mut current_session_id: Option<String> = Some(session.id.clone());
+    session_manager.add_session(session);
+
+    let submit = SubmitSm::builder()
+        .source_addr(COctetString::from_str("src").unwrap())
+        .destination_addr(COctetString::from_str("dst").unwrap())
+        .push_tlv(MessageSubmissionRequestTlvValue::PrivacyIndicator(PrivacyIndicator::Restricted))
+        .push_tlv(MessageSubmissionRequestTlvValue::UserMessageReference(UserMessageReference::new(1)))
+        .build();
+    let command = Command::new(CommandStatus::EsmeRok, 12, Pdu::SubmitSm(submit.clone()));
+
+    // Too many TLVs
+    let resp = handle_command(&command, &config, &session_manager, &message_queue, &mut current_session_id, test_addr(), tx.clone()).await.unwrap();
+    assert_eq!(resp.status, CommandStatus::EsmeRinvtlvstream);
+    assert_eq!(message_queue.message_count(), 0, "Rejected submits should not be queued");
+
+    // Count is fine but total size is not: each TLV above is 5 and 6 bytes encoded
+    config.smpp.max_tlvs = 0;
+    config.smpp.max_tlv_bytes = 10;
+    let resp = handle_command(&command, &config, &session_manager, &message_queue, &mut current_session_id, test_addr(), tx.clone()).await.unwrap();
+    assert_eq!(resp.status, CommandStatus::EsmeRinvtlvstream);
+
+    config.smpp.max_tlv_bytes = 11;
+    let resp = handle_command(&command, &config, &session_manager, &message_queue, &mut current_session_id, test_addr(), tx).await.unwrap();
+    assert_eq!(resp.status, CommandStatus::EsmeRok);
+}
+
+/// Sink that records written PDUs and counts flushes
+#[derive(Default)]
+struct CountingSink {
+    items: Vec<Command>,
+    flushes: usize,
+}
+
+impl futures::Sink<Command> for CountingSink {
+    type Error = std::io::Error;
+
+    fn poll_ready(self: std::pin::Pin<&mut Self>, _cx: &mut std::task::Context<'_>) -> std::task::Poll<Result<(), Self::Error>> {
+        std::task::Poll::Ready(Ok(()))
+    }
+
+    fn start_send(mut self: std::pin::Pin<&mut Self>, item: Command) -> Result<(), Self::Error> {
+        self.items.push(item);
+        Ok(())
+    }
+
+    fn poll_flush(mut self: std::pin::Pin<&mut Self>, _cx: &mut std::task::Context<'_>) -> std::task::Poll<Result<(), Self::Error>> {
+        self.flushes += 1;
+        std::task::Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: std::pin::Pin<&mut Self>, _cx: &mut std::task::Context<'_>) -> std::task::Poll<Result<(), Self::Error>> {
+        std::task::Poll::Ready(Ok(()))
+    }
+}
+
+#[tokio::test]
+async fn test_batch_dr_sends() {
+    const COUNT: usize = 20;
+    let mut config = test_config();
+    config.lifecycle.batch_dr_sends = true;
+    config.lifecycle.max_time_enroute_ms = 0;
+    config.lifecycle.percent_delivered = 100;
+    let session_manager = SessionManager::new();
+    let message_queue = MessageQueue::new();
+    let (tx, mut rx) = mpsc::channel(100);
+
+    let session = Session::new("user".to_string(), BindType::Transceiver, test_addr(), tx.clone(), None);
+    let mut current_session_id: Option<String> = Some(session.id.clone());
+    session_manager.add_session(session);
+
+    for seq in 0..COUNT {
+        let submit = SubmitSm::builder()
+            .source_addr(COctetString::from_str("src").unwrap())
+            .destination_addr(COctetString::from_str("dst").unwrap())
+            .short_message(OctetString::from_str("hi").unwrap())
+            .build();
+        let command = Command::new(CommandStatus::EsmeRok, seq as u32 + 1, Pdu::SubmitSm(submit));
+        handle_command(&command, &config, &session_manager, &message_queue, &mut current_session_id, test_addr(), tx.clone()).await.unwrap();
+    }
+
+    process_pending_messages(&config, &session_manager, &message_queue).await;
+    assert_eq!(message_queue.pending_dr_count(), 0);
+
+    // The whole batch is written before a single flush
+    let mut sink = CountingSink::default();
+    let first = rx.recv().await.unwrap();
+    let written = send_outbound(&mut sink, &mut rx, first, true).await.unwrap();
+    assert_eq!(written, COUNT);
+    assert_eq!(sink.items.len(), COUNT);
+    assert!(sink.items.iter().all(|c| matches!(c.pdu(), Some(Pdu::DeliverSm(_)))));
+    assert_eq!(sink.flushes, 1);
+
+    // Without batching every PDU is flushed on its own
+    let mut sink = CountingSink::default();
+    for _ in 0..3 {
+        tx.send(Command::builder().status(CommandStatus::EsmeRok).sequence_number(1).pdu(Pdu::EnquireLink)).await.unwrap();
+    }
+    while let Ok(command) = rx.try_recv() {
+        send_outbound(&mut sink, &mut rx, command, false).await.unwrap();
+    }
+    assert_eq!(sink.items.len(), 3);
+    assert_eq!(sink.flushes, 3);
+}
+
+#[tokio::test]
+async fn test_session_message_counters() {
+    let mut config = test_config();
+    config.lifecycle.max_time_enroute_ms = 0;
+    config.lifecycle.percent_delivered = 100;
+    let session_manager = SessionManager::new();
+    let message_queue = MessageQueue::new();
+
+    let mut sessions = Vec::new();
+    for (system_id, submits) in [("alpha", 3), ("beta", 1)] {
+        let (tx, rx) = mpsc::channel(100);
+        let session = Session::new(system_id.to_string(), BindType::Transceiver, test_addr(), tx.clone(), None);
+        let mut current_session_id = Some(session.id.clone());
+        session_manager.add_session(session.clone());
+        for seq in 0..submits {
+            let submit = SubmitSm::builder()
+                .source_addr(COctetString::from_str("src").unwrap())
+                .destination_addr(COctetString::from_str(system_id).unwrap())
+                .short_message(OctetString::from_str("hi").unwrap())
+                .build();
+            let command = Command::new(CommandStatus::EsmeRok, seq + 1, Pdu::SubmitSm(submit));
+            handle_command(&command, &config, &session_manager, &message_queue, &mut current_session_id, test_addr(), tx.clone()).await.unwrap();
+        }
+        sessions.push((session, submits as u64, rx));
+    }
+
+    process_pending_messages(&config, &session_manager, &message_queue).await;
+
+    // Clones taken before the traffic see the shared counts
+    for (session, submits, _rx) in &sessions {
+        assert_eq!(session.submit_count.load(std::sync::atomic::Ordering::Relaxed), *submits, "{}", session.system_id);
+        assert_eq!(session.deliver_count.load(std::sync::atomic::Ordering::Relaxed), *submits, "{}", session.system_id);
+    }
+}
+
+#[tokio::test]
+async fn test_account_bind_type_and_rate_restrictions() {
+    let mut config = test_config();
+    let mut receiver_only = crate::config::SmppAccount::new("rxonly", "pass");
+    receiver_only.allowed_bind_types = vec!["receiver".to_string()];
+    let mut limited = crate::config::SmppAccount::new("limited", "pass");
+    limited.max_submit_per_second = Some(2);
+    config.smpp.accounts = vec![receiver_only, limited];
+    let session_manager = SessionManager::new();
+    let message_queue = MessageQueue::new();
+    let (tx, _rx) = mpsc::channel(10);
+
+    let bind = |system_id: &str| BindTransmitter::new(
+        COctetString::from_str(system_id).unwrap(),
+        COctetString::from_str("pass").unwrap(),
+        COctetString::empty(),
+        InterfaceVersion::Smpp5_0,
+        Ton::Unknown,
+        Npi::Unknown,
+        COctetString::empty(),
+    );
+
+    // The receiver-only account cannot bind as a transmitter
+    let mut current_session_id: Option<String> = None;
+    let command = Command::new(CommandStatus::EsmeRok, 1, Pdu::BindTransmitter(bind("rxonly")));
+    let resp = handle_command(&command, &config, &session_manager, &message_queue, &mut current_session_id, test_addr(), tx.clone()).await.unwrap();
+    assert_eq!(resp.status, CommandStatus::EsmeRbindfail);
+    assert!(current_session_id.is_none());
+
+    // The rate-limited account gets throttled once its bucket is drained
+    let command = Command::new(CommandStatus::EsmeRok, 2, Pdu::BindTransmitter(bind("limited")));
+    let resp = handle_command(&command, &config, &session_manager, &message_queue, &mut current_session_id, test_addr(), tx.clone()).await.unwrap();
+    assert_eq!(resp.status, CommandStatus::EsmeRok);
+
+    let mut statuses = vec![];
+    for seq in 3..8 {
+        let submit = SubmitSm::builder()
+            .source_addr(COctetString::from_str("src").unwrap())
+            .destination_addr(COctetString::from_str("dst").unwrap())
+            .short_message(OctetString::from_str("hi").unwrap())
+            .build();
+        let command = Command::new(CommandStatus::EsmeRok, seq, Pdu::SubmitSm(submit));
+        let resp = handle_command(&command, &config, &session_manager, &message_queue, &mut current_session_id, test_addr(), tx.clone()).await.unwrap();
+        statuses.push(resp.status);
+    }
+    assert_eq!(statuses.iter().filter(|s| **s == CommandStatus::EsmeRok).count(), 2);
+    assert_eq!(statuses.iter().filter(|s| **s == CommandStatus::EsmeRthrottled).count(), 3);
+    assert_eq!(message_queue.message_count(), 2);
+
+    // The default account is unrestricted
+    let mut default_session_id: Option<String> = None;
+    let command = Command::new(CommandStatus::EsmeRok, 9, Pdu::BindTransmitter(bind("user")));
+    let resp = handle_command(&command, &config, &session_manager, &message_queue, &mut default_session_id, test_addr(), tx).await.unwrap();
+    assert_eq!(resp.status, CommandStatus::EsmeRok);
+}
+
+#[tokio::test]
+async fn test_submit_sm_throttled() {
+    let mut config = test_config();
+    config.smpp.max_submit_per_second = 3;
+    let session_manager = SessionManager::new();
+    let message_queue = MessageQueue::new();
+    let (tx, _rx) = mpsc::channel(1);
+
+    let session = Session::new("user".to_string(), BindType::Transceiver, test_addr(), tx.clone(), None);
+    let mut current_session_id: Option<String> = Some(session.id.clone());
+    session_manager.add_session(session);
+
+    let mut throttled = 0;
+    for seq in 1..=10 {
+        let submit = SubmitSm::builder()
+            .source_addr(COctetString::from_str("src").unwrap())
+            .destination_addr(COctetString::from_str("dst").unwrap())
+            .short_message(OctetString::from_str("hi").unwrap())
+            .build();
+        let command = Command::new(CommandStatus::EsmeRok, seq, Pdu::SubmitSm(submit));
+        let resp = handle_command(&command, &config, &session_manager, &message_queue, &mut current_session_id, test_addr(), tx.clone()).await.unwrap();
+        let Some(Pdu::SubmitSmResp(submit_resp)) = resp.pdu() else { panic!("Expected SubmitSmResp") };
+        if resp.status == CommandStatus::EsmeRthrottled {
+            assert!(submit_resp.message_id().to_string().is_empty());
+            throttled += 1;
+        }
+    }
+
+    // Only the first bucketful is accepted and queued
+    assert!(throttled >= 7, "Expected at least 7 throttled submits, got {}", throttled);
+    assert_eq!(message_queue.message_count(), 10 - throttled);
+}
+
+#[tokio::test]
+async fn test_submit_sm_congestion_ramp() {
+    let mut config = test_config();
+    config.smpp.congestion_throttle_per_second = 10;
+    config.smpp.congestion_onset_percent = 50;
+    config.smpp.congestion_window_ms = 1000;
+    let session_manager = SessionManager::new();
+    let message_queue = MessageQueue::new();
+    let (tx, _rx) = mpsc::channel(1);
+
+    let session = Session::new("user".to_string(), BindType::Transceiver, test_addr(), tx.clone(), None);
+    let mut current_session_id: Option<String> = Some(session.id.clone());
+    session_manager.add_session(session);
+
+    let mut reported = Vec::new();
+    for seq in 1..=10 {
+        let submit = SubmitSm::builder()
+            .source_addr(COctetString::from_str("src").unwrap())
+            .destination_addr(COctetString::from_str("dst").unwrap())
+            .short_message(OctetString::from_str(&format!("hi {seq}")).unwrap())
+            .build();
+        let command = Command::new(CommandStatus::EsmeRok, seq, Pdu::SubmitSm(submit));
+        let resp = handle_command(&command, &config, &session_manager, &message_queue, &mut current_session_id, test_addr(), tx.clone()).await.unwrap();
+        let Some(Pdu::SubmitSmResp(submit_resp)) = resp.pdu() else { panic!("Expected SubmitSmResp") };
+        let congestion_state = submit_resp.tlvs().iter()
+            .find(|tlv| tlv.tag() == TlvTag::CongestionState)
+            .map(|tlv| match tlv.value() {
+                Some(TlvValue::CongestionState(state)) => u8::from(*state),
+                Some(TlvValue::Other { value, .. }) => value.as_ref()[0],
+                other => panic!("unexpected congestion_state value {:?}", other),
+            });
+        reported.push((resp.status, congestion_state));
+    }
+
+    // Quiet below the onset, then a rising congestion_state, then a hard throttle at 100
+    for (status, state) in &reported[..4] {
+        assert_eq!((*status, *state), (CommandStatus::EsmeRok, None));
+    }
+    for (i, (status, state)) in reported[4..9].iter().enumerate() {
+        assert_eq!((*status, *state), (CommandStatus::EsmeRok, Some(50 + 10 * i as u8)));
+    }
+    assert_eq!(reported[9], (CommandStatus::EsmeRthrottled, Some(100)));
+    assert_eq!(message_queue.message_count(), 9);
+}
+
+#[tokio::test]
+async fn test_submit_sm_window_full() {
+    let mut config = test_config();
+    config.smpp.max_window_size = 2;
+    let session_manager = SessionManager::new();
+    let message_queue = MessageQueue::new();
+    let (tx, _rx) = mpsc::channel(1);
+
+    let session = Session::new("user".to_string(), BindType::Transceiver, test_addr(), tx.clone(), None);
+    let mut current_session_id: Option<String> = Some(session.id.clone());
+    session_manager.add_session(session.clone());
+
+    let submit = |seq: u32| {
+        let submit = SubmitSm::builder()
+            .source_addr(COctetString::from_str("src").unwrap())
+            .destination_addr(COctetString::from_str("dst").unwrap())
+            .short_message(OctetString::from_str("hi").unwrap())
+            .build();
+        Command::new(CommandStatus::EsmeRok, seq, Pdu::SubmitSm(submit))
+    };
+
+    // No responses have been written yet, so the third submit finds the window full
+    for seq in 1..=2 {
+        let resp = handle_command(&submit(seq), &config, &session_manager, &message_queue, &mut current_session_id, test_addr(), tx.clone()).await.unwrap();
+        assert_eq!(resp.status, CommandStatus::EsmeRok);
+    }
+    let resp = handle_command(&submit(3), &config, &session_manager, &message_queue, &mut current_session_id, test_addr(), tx.clone()).await.unwrap();
+    assert_eq!(resp.status, CommandStatus::EsmeRmsgqful);
+    assert_eq!(session.window_in_use(), 2);
+
+    // Writing a response frees a slot
+    session.release_window();
+    let resp = handle_command(&submit(4), &config, &session_manager, &message_queue, &mut current_session_id, test_addr(), tx).await.unwrap();
+    assert_eq!(resp.status, CommandStatus::EsmeRok);
+    assert_eq!(message_queue.message_count(), 3);
+}
+
+#[tokio::test]
+async fn test_unsupported_pdu_generic_nack() {
+    let config = test_config();
+    let session_manager = SessionManager::new();
+    let message_queue = MessageQueue::new();
+    let mut current_session_id: Option<String> = None;
+    let (tx, _rx) = mpsc::channel(1);
+
+    // outbind is only ever sent by the SMSC
+    let command = Command::new(CommandStatus::EsmeRok, 42, Pdu::Outbind(Outbind::builder().build()));
+    let resp = handle_command(&command, &config, &session_manager, &message_queue, &mut current_session_id, test_addr(), tx.clone()).await.unwrap();
+    assert!(matches!(resp.pdu(), Some(Pdu::GenericNack)), "Expected GenericNack, got {:?}", resp);
+    assert_eq!(resp.status, CommandStatus::EsmeRinvcmdid);
+    assert_eq!(resp.sequence_number(), 42);
+
+    // Responses from the client are not answered
+    let command = Command::new(CommandStatus::EsmeRok, 43, Pdu::EnquireLinkResp);
+    let resp = handle_command(&command, &config, &session_manager, &message_queue, &mut current_session_id, test_addr(), tx).await;
+    assert!(resp.is_none());
+}
+
+#[tokio::test]
+async fn test_per_account_dr_template() {
+    let mut config = test_config();
+    config.lifecycle.max_time_enroute_ms = 0;
+    config.lifecycle.percent_delivered = 100;
+    config.lifecycle.dr_template = Some("GLOBAL {id} {stat}".to_string());
+    let mut short = crate::config::SmppAccount::new("short", "pass");
+    short.dr_template = Some("{id}:{stat}:{err}".to_string());
+    config.smpp.accounts = vec![short, crate::config::SmppAccount::new("plain", "pass")];
+    let session_manager = SessionManager::new();
+    let message_queue = MessageQueue::new();
+
+    let mut receivers = vec![];
+    for system_id in ["short", "plain"] {
+        let (tx, rx) = mpsc::channel(10);
+        let session = Session::new(system_id.to_string(), BindType::Transceiver, test_addr(), tx.clone(), None);
+        let mut current_session_id = Some(session.id.clone());
+        session_manager.add_session(session);
+
+        let submit = SubmitSm::builder()
+            .source_addr(COctetString::from_str("src").unwrap())
+            .destination_addr(COctetString::from_str("dst").unwrap())
+            .short_message(OctetString::from_str(system_id).unwrap())
+            .build();
+        let command = Command::new(CommandStatus::EsmeRok, 1, Pdu::SubmitSm(submit));
+        let resp = handle_command(&command, &config, &session_manager, &message_queue, &mut current_session_id, test_addr(), tx).await.unwrap();
+        let Some(Pdu::SubmitSmResp(submit_resp)) = resp.pdu() else { panic!("Expected SubmitSmResp") };
+        receivers.push((submit_resp.message_id().to_string(), rx));
+    }
+
+    process_pending_messages(&config, &session_manager, &message_queue).await;
+
+    let mut texts = vec![];
+    for (_, rx) in receivers.iter_mut() {
+        let dr = rx.try_recv().expect("DR expected");
+        let Some(Pdu::DeliverSm(deliver)) = dr.pdu() else { panic!("Expected DeliverSm") };
+        texts.push(String::from_utf8_lossy(deliver.short_message().as_ref()).to_string());
+    }
+
+    // The account with its own template gets it; the other falls back to the global one
+    assert_eq!(texts[0], format!("{}:DELIVRD:000", receivers[0].0));
+    assert_eq!(texts[1], format!("GLOBAL {} DELIVRD", receivers[1].0));
+}
+
+#[tokio::test]
+async fn test_intermediate_receipts() {
+    let mut config = test_config();
+    config.lifecycle.intermediate_receipts = true;
+    config.lifecycle.max_time_enroute_ms = 200;
+    config.lifecycle.percent_delivered = 100;
+    let session_manager = SessionManager::new();
+    let message_queue = MessageQueue::new();
+    let (tx, mut rx) = mpsc::channel(10);
+
+    let session = Session::new("user".to_string(), BindType::Transceiver, test_addr(), tx.clone(), None);
+    let mut current_session_id = Some(session.id.clone());
+    session_manager.add_session(session);
+
+    let submit = SubmitSm::builder()
+        .source_addr(COctetString::from_str("src").unwrap())
+        .destination_addr(COctetString::from_str("dst").unwrap())
+        .short_message(OctetString::from_str("hi").unwrap())
+        .build();
+    let command = Command::new(CommandStatus::EsmeRok, 1, Pdu::SubmitSm(submit));
+    handle_command(&command, &config, &session_manager, &message_queue, &mut current_session_id, test_addr(), tx).await.unwrap();
+
+    // Nothing before the halfway point
+    process_pending_messages(&config, &session_manager, &message_queue).await;
+    assert!(rx.try_recv().is_err());
+
+    // Halfway through: one ENROUTE notification, and the message is still pending
+    tokio::time::sleep(std::time::Duration::from_millis(110)).await;
+    process_pending_messages(&config, &session_manager, &message_queue).await;
+    let dr = rx.try_recv().expect("interim receipt expected");
+    let Some(Pdu::DeliverSm(deliver)) = dr.pdu() else { panic!("Expected DeliverSm") };
+    assert!(String::from_utf8_lossy(deliver.short_message().as_ref()).contains("stat:ENROUTE"));
+    assert_eq!(deliver.esm_class.message_type, rusmpp::values::MessageType::ShortMessageContainsIntermediateDeliveryNotification);
+    assert_eq!(message_queue.pending_dr_count(), 1);
+
+    process_pending_messages(&config, &session_manager, &message_queue).await;
+    assert!(rx.try_recv().is_err());
+
+    // The final receipt still follows at the end of the window
+    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    process_pending_messages(&config, &session_manager, &message_queue).await;
+    let dr = rx.try_recv().expect("final receipt expected");
+    let Some(Pdu::DeliverSm(deliver)) = dr.pdu() else { panic!("Expected DeliverSm") };
+    assert!(String::from_utf8_lossy(deliver.short_message().as_ref()).contains("stat:DELIVRD"));
+    assert_eq!(message_queue.pending_dr_count(), 0);
+}
+
+#[tokio::test]
+async fn test_cancel_sm_reports_deleted() {
+    let mut config = test_config();
+    config.lifecycle.max_time_enroute_ms = 60_000;
+    let session_manager = SessionManager::new();
+    let message_queue = MessageQueue::new();
+    let (tx, mut rx) = mpsc::channel(10);
+
+    let session = Session::new("user".to_string(), BindType::Transceiver, test_addr(), tx.clone(), None);
+    let mut current_session_id = Some(session.id.clone());
+    session_manager.add_session(session);
+
+    let mut message_ids = vec![];
+    for (seq, dest) in [(1, "111"), (2, "222"), (3, "222")] {
+        let submit = SubmitSm::builder()
+            .source_addr(COctetString::from_str("src").unwrap())
+            .destination_addr(COctetString::from_str(dest).unwrap())
+            .short_message(OctetString::from_str("hi").unwrap())
+            .build();
+        let command = Command::new(CommandStatus::EsmeRok, seq, Pdu::SubmitSm(submit));
+        let resp = handle_command(&command, &config, &session_manager, &message_queue, &mut current_session_id, test_addr(), tx.clone()).await.unwrap();
+        let Some(Pdu::SubmitSmResp(submit_resp)) = resp.pdu() else { panic!("Expected SubmitSmResp") };
+        message_ids.push(submit_resp.message_id().to_string());
+    }
+
+    let cancel = |message_id: &str, source: &str, dest: &str| Command::new(CommandStatus::EsmeRok, 10, Pdu::CancelSm(CancelSm::builder()
+        .message_id(COctetString::from_str(message_id).unwrap())
+        .source_addr(COctetString::from_str(source).unwrap())
+        .destination_addr(COctetString::from_str(dest).unwrap())
+        .build()));
+
+    // By ID: the source address has to match the original submit
+    let resp = handle_command(&cancel(&message_ids[0], "other", ""), &config, &session_manager, &message_queue, &mut current_session_id, test_addr(), tx.clone()).await.unwrap();
+    assert_eq!(resp.status(), CommandStatus::EsmeRcancelfail);
+    let resp = handle_command(&cancel(&message_ids[0], "src", ""), &config, &session_manager, &message_queue, &mut current_session_id, test_addr(), tx.clone()).await.unwrap();
+    assert_eq!(resp.status(), CommandStatus::EsmeRok);
+    assert!(matches!(resp.pdu(), Some(Pdu::CancelSmResp)));
+
+    // Without an ID every pending message to the destination goes
+    let resp = handle_command(&cancel("", "src", "222"), &config, &session_manager, &message_queue, &mut current_session_id, test_addr(), tx.clone()).await.unwrap();
+    assert_eq!(resp.status(), CommandStatus::EsmeRok);
+    let resp = handle_command(&cancel("", "src", "222"), &config, &session_manager, &message_queue, &mut current_session_id, test_addr(), tx.clone()).await.unwrap();
+    assert_eq!(resp.status(), CommandStatus::EsmeRcancelfail, "Already cancelled");
+
+    // The next lifecycle pass reports all three as DELETED
+    process_pending_messages(&config, &session_manager, &message_queue).await;
+    for _ in 0..3 {
+        let dr = rx.try_recv().expect("DELETED receipt expected");
+        let Some(Pdu::DeliverSm(deliver)) = dr.pdu() else { panic!("Expected DeliverSm") };
+        assert!(String::from_utf8_lossy(deliver.short_message().as_ref()).contains("stat:DELETED"));
+    }
+    assert_eq!(message_queue.pending_dr_count(), 0);
+    assert_eq!(message_queue.get_message(&message_ids[1]).unwrap().final_state, Some(MessageState::Deleted));
+
+    let resp = handle_command(&cancel(&message_ids[0], "src", ""), &config, &session_manager, &message_queue, &mut current_session_id, test_addr(), tx).await.unwrap();
+    assert_eq!(resp.status(), CommandStatus::EsmeRcancelfail, "No longer pending");
+}
+
+#[tokio::test]
+async fn test_concatenated_submit_reassembled() {
+    use rusmpp::values::{Ansi41Specific, EsmClass, GsmFeatures, MessageType, MessagingMode};
+
+    let config = test_config();
+    let session_manager = SessionManager::new();
+    let message_queue = MessageQueue::new();
+    let (tx, _rx) = mpsc::channel(10);
+    let session = Session::new("user".to_string(), BindType::Transceiver, test_addr(), tx.clone(), None);
+    let mut current_session_id = Some(session.id.clone());
+    session_manager.add_session(session);
+
+    let udhi = EsmClass::new(MessagingMode::default(), MessageType::default(), Ansi41Specific::default(), GsmFeatures::UdhiIndicator);
+    let segment = |seq: u8, text: &str| {
+        let mut body = vec![0x05, 0x00, 0x03, 0x7F, 0x02, seq];
+        body.extend_from_slice(text.as_bytes());
+        SubmitSm::builder()
+            .source_addr(COctetString::from_str("src").unwrap())
+            .destination_addr(COctetString::from_str("dst").unwrap())
+            .esm_class(udhi)
+            .short_message(OctetString::from_bytes(body.into()).unwrap())
+            .build()
+    };
+    let single = SubmitSm::builder()
+        .source_addr(COctetString::from_str("src").unwrap())
+        .destination_addr(COctetString::from_str("dst").unwrap())
+        .short_message(OctetString::from_str("unrelated").unwrap())
+        .build();
+
+    // Second part first: stored on its own until the message is complete
+    let mut message_ids = vec![];
+    for (seq, submit) in [(1, segment(2, " world")), (2, single), (3, segment(1, "Hello"))] {
+        let command = Command::new(CommandStatus::EsmeRok, seq, Pdu::SubmitSm(submit));
+        let resp = handle_command(&command, &config, &session_manager, &message_queue, &mut current_session_id, test_addr(), tx.clone()).await.unwrap();
+        let Some(Pdu::SubmitSmResp(submit_resp)) = resp.pdu() else { panic!("Expected SubmitSmResp") };
+        message_ids.push(submit_resp.message_id().to_string());
+        if seq == 1 {
+            assert_eq!(message_queue.get_message(&message_ids[0]).unwrap().short_message, b" world");
+        }
+    }
+
+    // Every part still gets its own receipt, but only one combined message is stored
+    assert_eq!(message_queue.pending_dr_count(), 3);
+    assert_eq!(message_queue.message_count(), 2);
+    let combined = message_queue.get_message(&message_ids[2]).expect("stored under the first part's ID");
+    assert_eq!(combined.short_message, b"Hello world");
+    assert_eq!(message_queue.get_message(&message_ids[1]).unwrap().short_message, b"unrelated");
+}
+
+#[tokio::test]
+async fn test_concatenated_part_ids_resolve() {
+    use rusmpp::values::{Ansi41Specific, EsmClass, GsmFeatures, MessageType, MessagingMode};
+
+    let mut config = test_config();
+    config.lifecycle.max_time_enroute_ms = 0;
+    config.lifecycle.percent_delivered = 100;
+    let session_manager = SessionManager::new();
+    let message_queue = MessageQueue::new();
+    let (tx, _rx) = mpsc::channel(10);
+    let session = Session::new("user".to_string(), BindType::Transceiver, test_addr(), tx.clone(), None);
+    let mut current_session_id = Some(session.id.clone());
+    session_manager.add_session(session);
+
+    let udhi = EsmClass::new(MessagingMode::default(), MessageType::default(), Ansi41Specific::default(), GsmFeatures::UdhiIndicator);
+    let segment = |reference: u8, total: u8, seq: u8, text: &str| {
+        let mut body = vec![0x05, 0x00, 0x03, reference, total, seq];
+        body.extend_from_slice(text.as_bytes());
+        Command::new(CommandStatus::EsmeRok, u32::from(seq), Pdu::SubmitSm(SubmitSm::builder()
+            .source_addr(COctetString::from_str("src").unwrap())
+            .destination_addr(COctetString::from_str("dst").unwrap())
+            .esm_class(udhi)
+            .short_message(OctetString::from_bytes(body.into()).unwrap())
+            .build()))
+    };
+    let query = |id: &str| Command::new(CommandStatus::EsmeRok, 9, Pdu::QuerySm(QuerySm::builder()
+        .message_id(COctetString::from_str(id).unwrap())
+        .build()));
+    let mut message_ids = vec![];
+    for command in [segment(0x10, 2, 1, "Hello"), segment(0x10, 2, 2, " world")] {
+        let resp = handle_command(&command, &config, &session_manager, &message_queue, &mut current_session_id, test_addr(), tx.clone()).await.unwrap();
+        let Some(Pdu::SubmitSmResp(submit_resp)) = resp.pdu() else { panic!("Expected SubmitSmResp") };
+        message_ids.push(submit_resp.message_id().to_string());
+    }
+
+    // The second part's ID reaches the combined record for queries and the API
+    let resp = handle_command(&query(&message_ids[1]), &config, &session_manager, &message_queue, &mut current_session_id, test_addr(), tx.clone()).await.unwrap();
+    assert_eq!(resp.status, CommandStatus::EsmeRok);
+    assert_eq!(message_queue.find_message(&message_ids[1]).unwrap().short_message, b"Hello world");
+
+    // ... and cancels that part
+    let cancel = Command::new(CommandStatus::EsmeRok, 10, Pdu::CancelSm(CancelSm::builder()
+        .message_id(COctetString::from_str(&message_ids[1]).unwrap())
+        .build()));
+    let resp = handle_command(&cancel, &config, &session_manager, &message_queue, &mut current_session_id, test_addr(), tx.clone()).await.unwrap();
+    assert_eq!(resp.status(), CommandStatus::EsmeRok);
+
+    // The lifecycle's state for each part lands on the combined record
+    process_pending_messages(&config, &session_manager, &message_queue).await;
+    assert_eq!(message_queue.pending_dr_count(), 0);
+    assert!(message_queue.find_message(&message_ids[1]).unwrap().final_state.is_some());
+
+    // An incomplete set is stored part by part, so its IDs can be queried
+    let resp = handle_command(&segment(0x20, 3, 1, "Part one"), &config, &session_manager, &message_queue, &mut current_session_id, test_addr(), tx.clone()).await.unwrap();
+    let Some(Pdu::SubmitSmResp(submit_resp)) = resp.pdu() else { panic!("Expected SubmitSmResp") };
+    let part_id = submit_resp.message_id().to_string();
+    let resp = handle_command(&query(&part_id), &config, &session_manager, &message_queue, &mut current_session_id, test_addr(), tx.clone()).await.unwrap();
+    assert_eq!(resp.status, CommandStatus::EsmeRok);
+    assert_eq!(message_queue.get_message(&part_id).unwrap().short_message, b"Part one");
+
+    // Its buffer is given up after a while; the part stays stored
+    assert_eq!(message_queue.expire_concat_parts(std::time::Duration::ZERO), 1);
+    assert_eq!(message_queue.expire_concat_parts(std::time::Duration::ZERO), 0);
+    assert!(message_queue.get_message(&part_id).is_some());
+}
+
+#[tokio::test]
+async fn test_submit_message_payload_body() {
+    let config = test_config();
+    let session_manager = SessionManager::new();
+    let message_queue = MessageQueue::new();
+    let (tx, _rx) = mpsc::channel(10);
+    let session = Session::new("user".to_string(), BindType::Transceiver, test_addr(), tx.clone(), None);
+    let mut current_session_id = Some(session.id.clone());
+    session_manager.add_session(session);
+
+    let body = b"long ".repeat(80);
+    let submit = SubmitSm::builder()
+        .source_addr(COctetString::from_str("src").unwrap())
+        .destination_addr(COctetString::from_str("dst").unwrap())
+        .push_tlv(MessageSubmissionRequestTlvValue::MessagePayload(
+            rusmpp::values::MessagePayload::new(rusmpp::types::AnyOctetString::from_vec(body.clone())),
+        ))
+        .build();
+    let command = Command::new(CommandStatus::EsmeRok, 1, Pdu::SubmitSm(submit));
+    let resp = handle_command(&command, &config, &session_manager, &message_queue, &mut current_session_id, test_addr(), tx).await.unwrap();
+    let Some(Pdu::SubmitSmResp(submit_resp)) = resp.pdu() else { panic!("Expected SubmitSmResp") };
+
+    let stored = message_queue.get_message(&submit_resp.message_id().to_string()).unwrap();
+    assert_eq!(stored.short_message, body);
+}
+
+#[tokio::test]
+async fn test_submit_short_message_too_long() {
+    let mut config = test_config();
+    config.smpp.max_sm_length = 140;
+    let session_manager = SessionManager::new();
+    let message_queue = MessageQueue::new();
+    let (tx, _rx) = mpsc::channel(10);
+    let session = Session::new("user".to_string(), BindType::Transceiver, test_addr(), tx.clone(), None);
+    let mut current_session_id = Some(session.id.clone());
+    session_manager.add_session(session);
+
+    let mut statuses = vec![];
+    for (seq, len) in [(1, 140), (2, 141)] {
+        let submit = SubmitSm::builder()
+            .source_addr(COctetString::from_str("src").unwrap())
+            .destination_addr(COctetString::from_str("dst").unwrap())
+            .short_message(OctetString::from_vec(vec![b'x'; len]).unwrap())
+            .build();
+        let command = Command::new(CommandStatus::EsmeRok, seq, Pdu::SubmitSm(submit));
+        let resp = handle_command(&command, &config, &session_manager, &message_queue, &mut current_session_id, test_addr(), tx.clone()).await.unwrap();
+        assert!(matches!(resp.pdu(), Some(Pdu::SubmitSmResp(_))));
+        statuses.push(resp.status);
+    }
+    assert_eq!(statuses, vec![CommandStatus::EsmeRok, CommandStatus::EsmeRinvmsglen]);
+
+    // The same content in message_payload is accepted
+    let submit = SubmitSm::builder()
+        .source_addr(COctetString::from_str("src").unwrap())
+        .destination_addr(COctetString::from_str("dst").unwrap())
+        .push_tlv(MessageSubmissionRequestTlvValue::MessagePayload(
+            rusmpp::values::MessagePayload::new(rusmpp::types::AnyOctetString::from_vec(vec![b'x'; 141])),
+        ))
+        .build();
+    let command = Command::new(CommandStatus::EsmeRok, 3, Pdu::SubmitSm(submit));
+    let resp = handle_command(&command, &config, &session_manager, &message_queue, &mut current_session_id, test_addr(), tx).await.unwrap();
+    assert_eq!(resp.status, CommandStatus::EsmeRok);
+    assert_eq!(message_queue.message_count(), 2);
+}
+
+#[test]
+fn test_parse_command_status() {
+    assert_eq!(parse_command_status("ESME_RINVDSTADR"), Some(CommandStatus::EsmeRinvdstadr));
+    assert_eq!(parse_command_status("EsmeRsubmitfail"), Some(CommandStatus::EsmeRsubmitfail));
+    assert_eq!(parse_command_status(" esme_rthrottled "), Some(CommandStatus::EsmeRthrottled));
+    assert_eq!(parse_command_status("0x0B"), Some(CommandStatus::EsmeRinvdstadr));
+    assert_eq!(parse_command_status("1025"), Some(CommandStatus::Other(0x401)));
+    assert_eq!(parse_command_status("ESME_RNOSUCHTHING"), None);
+}
+
+#[tokio::test]
+async fn test_submit_errors_by_destination() {
+    let mut config = test_config();
+    config.smpp.submit_errors = vec![
+        crate::config::SubmitErrorRule::new("^999", "ESME_RINVDSTADR"),
+        crate::config::SubmitErrorRule::new("^88", "EsmeRsubmitfail"),
+    ];
+    let session_manager = SessionManager::new();
+    let message_queue = MessageQueue::new();
+    let (tx, _rx) = mpsc::channel(10);
+    let session = Session::new("user".to_string(), BindType::Transceiver, test_addr(), tx.clone(), None);
+    let mut current_session_id = Some(session.id.clone());
+    session_manager.add_session(session);
+
+    for (seq, dest, expected) in [
+        (1, "999123", CommandStatus::EsmeRinvdstadr),
+        (2, "881234", CommandStatus::EsmeRsubmitfail),
+        (3, "123999", CommandStatus::EsmeRok),
+    ] {
+        let submit = SubmitSm::builder()
+            .source_addr(COctetString::from_str("src").unwrap())
+            .destination_addr(COctetString::from_str(dest).unwrap())
+            .short_message(OctetString::from_str("hi").unwrap())
+            .build();
+        let command = Command::new(CommandStatus::EsmeRok, seq, Pdu::SubmitSm(submit));
+        let resp = handle_command(&command, &config, &session_manager, &message_queue, &mut current_session_id, test_addr(), tx.clone()).await.unwrap();
+        assert_eq!(resp.status(), expected, "{}", dest);
+        assert!(matches!(resp.pdu(), Some(Pdu::SubmitSmResp(_))));
+    }
+
+    // Rejected submits are never queued
+    assert_eq!(message_queue.message_count(), 1);
+    assert_eq!(message_queue.pending_dr_count(), 1);
+}
+
+#[tokio::test]
+async fn test_deliver_sm_resp_acknowledges_receipt() {
+    let mut config = test_config();
+    config.lifecycle.max_time_enroute_ms = 0;
+    config.lifecycle.percent_delivered = 100;
+    let session_manager = SessionManager::new();
+    let message_queue = MessageQueue::new();
+    let (tx, mut rx) = mpsc::channel(10);
+
+    let session = Session::new("user".to_string(), BindType::Transceiver, test_addr(), tx.clone(), None);
+    let mut current_session_id = Some(session.id.clone());
+    session_manager.add_session(session.clone());
+
+    for seq in 1..=2 {
+        let submit = SubmitSm::builder()
+            .source_addr(COctetString::from_str("src").unwrap())
+            .destination_addr(COctetString::from_str("dst").unwrap())
+            .short_message(OctetString::from_str("hi").unwrap())
+            .build();
+        let command = Command::new(CommandStatus::EsmeRok, seq, Pdu::SubmitSm(submit));
+        handle_command(&command, &config, &session_manager, &message_queue, &mut current_session_id, test_addr(), tx.clone()).await.unwrap();
+    }
+    process_pending_messages(&config, &session_manager, &message_queue).await;
+
+    // Each DR gets its own non-zero sequence number and stays outstanding until answered
+    let first = rx.try_recv().unwrap();
+    let second = rx.try_recv().unwrap();
+    assert_ne!(first.sequence_number(), 0);
+    assert_ne!(first.sequence_number(), second.sequence_number());
+    assert_eq!(session.unacked_receipt_count(), 2);
+    let first_id = session.unacked_receipts.get(&first.sequence_number()).unwrap().message_id.clone();
+    let second_id = session.unacked_receipts.get(&second.sequence_number()).unwrap().message_id.clone();
+    assert_ne!(first_id, second_id);
+
+    let ack = Command::new(CommandStatus::EsmeRok, first.sequence_number(), Pdu::DeliverSmResp(Default::default()));
+    assert!(handle_command(&ack, &config, &session_manager, &message_queue, &mut current_session_id, test_addr(), tx.clone()).await.is_none());
+    assert_eq!(session.unacked_receipt_count(), 1);
+    assert!(message_queue.find_message(&first_id).unwrap().receipt_acked);
+
+    // An error response clears the DR but does not count as acknowledged
+    let nack = Command::new(CommandStatus::EsmeRsyserr, second.sequence_number(), Pdu::DeliverSmResp(Default::default()));
+    assert!(handle_command(&nack, &config, &session_manager, &message_queue, &mut current_session_id, test_addr(), tx.clone()).await.is_none());
+    assert_eq!(session.unacked_receipt_count(), 0);
+    assert!(!message_queue.find_message(&second_id).unwrap().receipt_acked);
+
+    // Unknown sequence numbers (e.g. MO deliver_sm) are ignored, never nacked
+    let stray = Command::new(CommandStatus::EsmeRok, 0, Pdu::DeliverSmResp(Default::default()));
+    assert!(handle_command(&stray, &config, &session_manager, &message_queue, &mut current_session_id, test_addr(), tx).await.is_none());
+}
+
+#[tokio::test]
+async fn test_unacked_receipts_are_retried() {
+    let mut config = test_config();
+    config.lifecycle.max_time_enroute_ms = 0;
+    config.lifecycle.percent_delivered = 100;
+    config.lifecycle.dr_retry_count = 2;
+    config.lifecycle.dr_retry_interval_ms = 50;
+    let session_manager = SessionManager::new();
+    let message_queue = MessageQueue::new();
+    let (tx, mut rx) = mpsc::channel(10);
+
+    let session = Session::new("user".to_string(), BindType::Transceiver, test_addr(), tx.clone(), None);
+    let mut current_session_id = Some(session.id.clone());
+    session_manager.add_session(session.clone());
+
+    let submit = SubmitSm::builder()
+        .source_addr(COctetString::from_str("src").unwrap())
+        .destination_addr(COctetString::from_str("dst").unwrap())
+        .short_message(OctetString::from_str("hi").unwrap())
+        .build();
+    let command = Command::new(CommandStatus::EsmeRok, 1, Pdu::SubmitSm(submit));
+    handle_command(&command, &config, &session_manager, &message_queue, &mut current_session_id, test_addr(), tx.clone()).await.unwrap();
+
+    process_pending_messages(&config, &session_manager, &message_queue).await;
+    let original = rx.try_recv().expect("DR expected");
+
+    // Not re-sent before the interval, then re-sent as the same PDU once per interval
+    process_pending_messages(&config, &session_manager, &message_queue).await;
+    assert!(rx.try_recv().is_err());
+    for _ in 0..2 {
+        tokio::time::sleep(std::time::Duration::from_millis(60)).await;
+        process_pending_messages(&config, &session_manager, &message_queue).await;
+        let retry = rx.try_recv().expect("retry expected");
+        assert_eq!(retry, original);
+    }
+
+    // Retries exhausted: dropped without another send
+    tokio::time::sleep(std::time::Duration::from_millis(60)).await;
+    process_pending_messages(&config, &session_manager, &message_queue).await;
+    assert!(rx.try_recv().is_err());
+    assert_eq!(session.unacked_receipt_count(), 0);
+
+    // An acknowledged receipt is never re-sent
+    let submit = SubmitSm::builder()
+        .source_addr(COctetString::from_str("src").unwrap())
+        .destination_addr(COctetString::from_str("dst").unwrap())
+        .short_message(OctetString::from_str("again").unwrap())
+        .build();
+    let command = Command::new(CommandStatus::EsmeRok, 2, Pdu::SubmitSm(submit));
+    handle_command(&command, &config, &session_manager, &message_queue, &mut current_session_id, test_addr(), tx.clone()).await.unwrap();
+    process_pending_messages(&config, &session_manager, &message_queue).await;
+    let dr = rx.try_recv().expect("DR expected");
+    let ack = Command::new(CommandStatus::EsmeRok, dr.sequence_number(), Pdu::DeliverSmResp(Default::default()));
+    handle_command(&ack, &config, &session_manager, &message_queue, &mut current_session_id, test_addr(), tx).await;
+    tokio::time::sleep(std::time::Duration::from_millis(60)).await;
+    process_pending_messages(&config, &session_manager, &message_queue).await;
+    assert!(rx.try_recv().is_err());
+}
+
+#[tokio::test]
+async fn test_unacked_receipts_expire_without_retries() {
+    let mut config = test_config();
+    config.lifecycle.max_time_enroute_ms = 0;
+    config.lifecycle.percent_delivered = 100;
+    config.lifecycle.dr_retry_count = 0;
+    config.lifecycle.dr_retry_interval_ms = 50;
+    let session_manager = SessionManager::new();
+    let message_queue = MessageQueue::new();
+    let (tx, mut rx) = mpsc::channel(10);
+
+    let session = Session::new("user".to_string(), BindType::Transceiver, test_addr(), tx.clone(), None);
+    let mut current_session_id = Some(session.id.clone());
+    session_manager.add_session(session.clone());
+
+    let submit = SubmitSm::builder()
+        .source_addr(COctetString::from_str("src").unwrap())
+        .destination_addr(COctetString::from_str("dst").unwrap())
+        .short_message(OctetString::from_str("hi").unwrap())
+        .build();
+    let command = Command::new(CommandStatus::EsmeRok, 1, Pdu::SubmitSm(submit));
+    handle_command(&command, &config, &session_manager, &message_queue, &mut current_session_id, test_addr(), tx).await.unwrap();
+
+    process_pending_messages(&config, &session_manager, &message_queue).await;
+    rx.try_recv().expect("DR expected");
+    assert_eq!(session.unacked_receipt_count(), 1);
+
+    // Never re-sent, but no longer tracked once the interval has passed
+    tokio::time::sleep(std::time::Duration::from_millis(60)).await;
+    process_pending_messages(&config, &session_manager, &message_queue).await;
+    assert!(rx.try_recv().is_err());
+    assert_eq!(session.unacked_receipt_count(), 0);
+}
+
+#[tokio::test]
+async fn test_receipt_echoes_submit_ton_npi() {
+    let mut config = test_config();
+    config.lifecycle.max_time_enroute_ms = 0;
+    config.lifecycle.percent_delivered = 100;
+    let session_manager = SessionManager::new();
+    let message_queue = MessageQueue::new();
+    let (tx, mut rx) = mpsc::channel(10);
+
+    let session = Session::new("user".to_string(), BindType::Transceiver, test_addr(), tx.clone(), None);
+    let mut current_session_id = Some(session.id.clone());
+    session_manager.add_session(session);
+
+    let submit = SubmitSm::builder()
+        .source_addr_ton(Ton::Alphanumeric)
+        .source_addr_npi(Npi::Unknown)
+        .source_addr(COctetString::from_str("BRAND").unwrap())
+        .dest_addr_ton(Ton::International)
+        .dest_addr_npi(Npi::Isdn)
+        .destination_addr(COctetString::from_str("61400000001").unwrap())
+        .short_message(OctetString::from_str("hi").unwrap())
+        .build();
+    let command = Command::new(CommandStatus::EsmeRok, 1, Pdu::SubmitSm(submit));
+    handle_command(&command, &config, &session_manager, &message_queue, &mut current_session_id, test_addr(), tx).await.unwrap();
+    process_pending_messages(&config, &session_manager, &message_queue).await;
+
+    // The receipt comes from the original destination and goes to the original sender
+    let dr = rx.try_recv().expect("DR expected");
+    let Some(Pdu::DeliverSm(deliver)) = dr.pdu() else { panic!("Expected DeliverSm") };
+    assert_eq!(deliver.source_addr.to_string(), "61400000001");
+    assert_eq!((deliver.source_addr_ton, deliver.source_addr_npi), (Ton::International, Npi::Isdn));
+    assert_eq!(deliver.destination_addr.to_string(), "BRAND");
+    assert_eq!((deliver.dest_addr_ton, deliver.dest_addr_npi), (Ton::Alphanumeric, Npi::Unknown));
+}
+
+#[tokio::test]
+async fn test_submit_sm_rejected_on_receiver_bind() {
+    let config = test_config();
+    let session_manager = SessionManager::new();
+    let message_queue = MessageQueue::new();
+    let (tx, _rx) = mpsc::channel(10);
+
+    let submit = || SubmitSm::builder()
+        .source_addr(COctetString::from_str("src").unwrap())
+        .destination_addr(COctetString::from_str("dst").unwrap())
+        .short_message(OctetString::from_str("hi").unwrap())
+        .build();
+
+    let mut receiver_session_id: Option<String> = None;
+    let bind_req = BindReceiver::new(
+        COctetString::from_str("user").unwrap(),
+        COctetString::from_str("pass").unwrap(),
+        COctetString::empty(),
+        InterfaceVersion::Smpp3_4,
+        Ton::Unknown,
+        Npi::Unknown,
+        COctetString::empty(),
+    );
+    let command = Command::new(CommandStatus::EsmeRok, 1, Pdu::BindReceiver(bind_req));
+    let resp = handle_command(&command, &config, &session_manager, &message_queue, &mut receiver_session_id, test_addr(), tx.clone()).await.unwrap();
+    assert_eq!(resp.status, CommandStatus::EsmeRok);
+
+    let command = Command::new(CommandStatus::EsmeRok, 2, Pdu::SubmitSm(submit()));
+    let resp = handle_command(&command, &config, &session_manager, &message_queue, &mut receiver_session_id, test_addr(), tx.clone()).await.unwrap();
+    assert_eq!(resp.status, CommandStatus::EsmeRinvbndsts);
+    assert!(matches!(resp.pdu(), Some(Pdu::SubmitSmResp(_))));
+    assert_eq!(message_queue.message_count(), 0);
+
+    // Transmitters and transceivers still submit
+    for bind_type in [BindType::Transmitter, BindType::Transceiver] {
+        let session = Session::new("user".to_string(), bind_type, test_addr(), tx.clone(), None);
+        let mut current_session_id = Some(session.id.clone());
+        session_manager.add_session(session);
+        let command = Command::new(CommandStatus::EsmeRok, 3, Pdu::SubmitSm(submit()));
+        let resp = handle_command(&command, &config, &session_manager, &message_queue, &mut current_session_id, test_addr(), tx.clone()).await.unwrap();
+        assert_eq!(resp.status, CommandStatus::EsmeRok);
+    }
+    assert_eq!(message_queue.message_count(), 2);
+}
+
+#[tokio::test]
+async fn test_submit_sm_source_outside_address_range() {
+    let config = test_config();
+    let session_manager = SessionManager::new();
+    let message_queue = MessageQueue::new();
+    let (tx, _rx) = mpsc::channel(10);
+
+    let session = Session::new("user".to_string(), BindType::Transmitter, test_addr(), tx.clone(), Some("^6612".to_string()));
+    let mut current_session_id = Some(session.id.clone());
+    session_manager.add_session(session);
+
+    let mut statuses = vec![];
+    for (seq, source) in [(1, "66123456"), (2, "66999999"), (3, "BRAND")] {
+        let submit = SubmitSm::builder()
+            .source_addr(COctetString::from_str(source).unwrap())
+            .destination_addr(COctetString::from_str("dst").unwrap())
+            .short_message(OctetString::from_str("hi").unwrap())
+            .build();
+        let command = Command::new(CommandStatus::EsmeRok, seq, Pdu::SubmitSm(submit));
+        let resp = handle_command(&command, &config, &session_manager, &message_queue, &mut current_session_id, test_addr(), tx.clone()).await.unwrap();
+        statuses.push(resp.status);
+    }
+    assert_eq!(statuses, vec![CommandStatus::EsmeRok, CommandStatus::EsmeRinvsrcadr, CommandStatus::EsmeRinvsrcadr]);
+    assert_eq!(message_queue.message_count(), 1);
+
+    // Without an address_range any source is accepted
+    let session = Session::new("user".to_string(), BindType::Transmitter, test_addr(), tx.clone(), None);
+    let mut current_session_id = Some(session.id.clone());
+    session_manager.add_session(session);
+    let submit = SubmitSm::builder()
+        .source_addr(COctetString::from_str("BRAND").unwrap())
+        .destination_addr(COctetString::from_str("dst").unwrap())
+        .short_message(OctetString::from_str("hi").unwrap())
+        .build();
+    let command = Command::new(CommandStatus::EsmeRok, 4, Pdu::SubmitSm(submit));
+    let resp = handle_command(&command, &config, &session_manager, &message_queue, &mut current_session_id, test_addr(), tx).await.unwrap();
+    assert_eq!(resp.status, CommandStatus::EsmeRok);
+}
+
+#[tokio::test]
+async fn test_submit_tlvs_echoed_in_receipt() {
+    let mut config = test_config();
+    config.smpp.capture_tlvs = false;
+    config.lifecycle.echo_tlvs = vec![0x0204, 0x1400];
+    let session_manager = SessionManager::new();
+    let message_queue = MessageQueue::new();
+    let (tx, _rx) = mpsc::channel(10);
+    let session = Session::new("user".to_string(), BindType::Transceiver, test_addr(), tx.clone(), None);
+    let mut current_session_id = Some(session.id.clone());
+    session_manager.add_session(session);
+
+    let submit = SubmitSm::builder()
+        .source_addr(COctetString::from_str("src").unwrap())
+        .destination_addr(COctetString::from_str("dst").unwrap())
+        .short_message(OctetString::from_str("hi").unwrap())
+        .push_tlv(MessageSubmissionRequestTlvValue::PrivacyIndicator(PrivacyIndicator::Restricted))
+        .push_tlv(MessageSubmissionRequestTlvValue::UserMessageReference(UserMessageReference::new(0x1234)))
+        .push_tlv(MessageSubmissionRequestTlvValue::Other {
+            tag: rusmpp::tlvs::TlvTag::Other(0x1400),
+            value: rusmpp::types::AnyOctetString::from_vec(b"BILL42".to_vec()),
+        })
+        .build();
+    let command = Command::new(CommandStatus::EsmeRok, 1, Pdu::SubmitSm(submit));
+    let resp = handle_command(&command, &config, &session_manager, &message_queue, &mut current_session_id, test_addr(), tx).await.unwrap();
+    let Some(Pdu::SubmitSmResp(submit_resp)) = resp.pdu() else { panic!("Expected SubmitSmResp") };
+    let stored = message_queue.get_message(&submit_resp.message_id().to_string()).unwrap();
+
+    let receipt = create_delivery_receipt(&stored, MessageState::Delivered, &config).unwrap();
+    let Some(Pdu::DeliverSm(deliver)) = receipt.pdu() else { panic!("Expected DeliverSm") };
+    let echoed: Vec<(u16, Vec<u8>)> = deliver.tlvs().iter()
+        .filter_map(|tlv| match tlv.value() {
+            Some(TlvValue::Other { tag, value }) => Some((u16::from(*tag), value.as_ref().to_vec())),
+            _ => None,
+        })
+        .collect();
+    // Only allowlisted tags come back; privacy_indicator (0x0201) is not echoed
+    assert_eq!(echoed, vec![(0x0204, vec![0x12, 0x34]), (0x1400, b"BILL42".to_vec())]);
+}
+
+#[test]
+fn test_response_delay_per_pdu() {
+    use std::time::Duration;
+    let mut config = test_config();
+    let submit_resp = Command::builder().status(CommandStatus::EsmeRok).sequence_number(1)
+        .pdu(Pdu::SubmitSmResp(rusmpp::pdus::SubmitSmResp::new(COctetString::from_str("1").unwrap(), vec![])));
+    let enquire_resp = Command::builder().status(CommandStatus::EsmeRok).sequence_number(2).pdu(Pdu::EnquireLinkResp);
+    assert_eq!(response_delay(&submit_resp, &config), Duration::ZERO);
+
+    config.smpp.response_delay_ms = 50;
+    config.smpp.response_delays.insert("submit_sm_resp".to_string(), 2000);
+    assert_eq!(response_delay(&submit_resp, &config), Duration::from_millis(2000));
+    assert_eq!(response_delay(&enquire_resp, &config), Duration::from_millis(50));
+
+    // Only submit_sm_resp is delayed; the global default stays off
+    config.smpp.response_delay_ms = 0;
+    config.smpp.response_delays = [("SubmitSmResp".to_string(), 300)].into();
+    assert_eq!(response_delay(&submit_resp, &config), Duration::from_millis(300));
+    assert_eq!(response_delay(&enquire_resp, &config), Duration::ZERO);
+}
+
+#[tokio::test]
+async fn test_drop_response_percent() {
+    let mut config = test_config();
+    config.smpp.drop_response_percent = 100;
+    let session_manager = SessionManager::new();
+    let message_queue = MessageQueue::new();
+    let (tx, _rx) = mpsc::channel(10);
+    let session = Session::new("user".to_string(), BindType::Transceiver, test_addr(), tx.clone(), None);
+    let mut current_session_id = Some(session.id.clone());
+    session_manager.add_session(session.clone());
+
+    let submit = |text: &str| SubmitSm::builder()
+        .source_addr(COctetString::from_str("src").unwrap())
+        .destination_addr(COctetString::from_str("dst").unwrap())
+        .short_message(OctetString::from_str(text).unwrap())
+        .build();
+
+    // By default the message is still accepted; only its response goes missing
+    let command = Command::new(CommandStatus::EsmeRok, 1, Pdu::SubmitSm(submit("kept")));
+    let resp = handle_command(&command, &config, &session_manager, &message_queue, &mut current_session_id, test_addr(), tx.clone()).await;
+    assert!(resp.is_none());
+    assert_eq!(message_queue.message_count(), 1);
+    assert_eq!(session.window_in_use(), 0, "A dropped response must not hold a window slot");
+
+    config.smpp.drop_response_queue = false;
+    let command = Command::new(CommandStatus::EsmeRok, 2, Pdu::SubmitSm(submit("lost")));
+    let resp = handle_command(&command, &config, &session_manager, &message_queue, &mut current_session_id, test_addr(), tx.clone()).await;
+    assert!(resp.is_none());
+    assert_eq!(message_queue.message_count(), 1);
+
+    config.smpp.drop_response_percent = 0;
+    let command = Command::new(CommandStatus::EsmeRok, 3, Pdu::SubmitSm(submit("answered")));
+    let resp = handle_command(&command, &config, &session_manager, &message_queue, &mut current_session_id, test_addr(), tx).await;
+    assert_eq!(resp.unwrap().status, CommandStatus::EsmeRok);
+}
+
+#[tokio::test]
+async fn test_audit_log_records_submit_and_receipt() {
+    let mut config = test_config();
+    config.lifecycle.max_time_enroute_ms = 0;
+    config.lifecycle.percent_delivered = 100;
+    let path = std::env::temp_dir().join(format!("smpp-sim-audit-{}.jsonl", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let session_manager = SessionManager::new();
+    session_manager.set_audit_log(crate::smpp::audit::AuditLog::open(path.to_str().unwrap()).await.unwrap());
+    let message_queue = MessageQueue::new();
+    let (tx, _rx) = mpsc::channel(100);
+
+    let session = Session::new("auditor".to_string(), BindType::Transceiver, test_addr(), tx.clone(), None);
+    let mut current_session_id = Some(session.id.clone());
+    session_manager.add_session(session);
+
+    let submit = SubmitSm::builder()
+        .source_addr(COctetString::from_str("src").unwrap())
+        .destination_addr(COctetString::from_str("dst").unwrap())
+        .short_message(OctetString::from_str("hi").unwrap())
+        .build();
+    let command = Command::new(CommandStatus::EsmeRok, 1, Pdu::SubmitSm(submit));
+    handle_command(&command, &config, &session_manager, &message_queue, &mut current_session_id, test_addr(), tx).await.unwrap();
+    process_pending_messages(&config, &session_manager, &message_queue).await;
+
+    // The writer task flushes in the background
+    let mut lines = Vec::new();
+    for _ in 0..50 {
+        lines = std::fs::read_to_string(&path).unwrap_or_default().lines().map(String::from).collect::<Vec<_>>();
+        if lines.len() >= 2 {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+    let _ = std::fs::remove_file(&path);
+    assert_eq!(lines.len(), 2);
+    let submit: serde_json::Value = serde_json::from_str(&lines[0]).unwrap();
+    let receipt: serde_json::Value = serde_json::from_str(&lines[1]).unwrap();
+    assert_eq!(submit["event"], "submit_sm");
+    assert_eq!(submit["system_id"], "auditor");
+    assert_eq!(submit["dest_addr"], "dst");
+    assert_eq!(receipt["event"], "delivery_receipt");
+    assert_eq!(receipt["state"], "DELIVRD");
+    assert_eq!(receipt["message_id"], submit["message_id"]);
+}
+
+#[tokio::test]
+async fn test_audit_log_skips_receipts_never_sent() {
+    let mut config = test_config();
+    config.lifecycle.max_time_enroute_ms = 0;
+    config.lifecycle.percent_delivered = 100;
+    let path = std::env::temp_dir().join(format!("smpp-sim-audit-unsent-{}.jsonl", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let session_manager = SessionManager::new();
+    session_manager.set_audit_log(crate::smpp::audit::AuditLog::open(path.to_str().unwrap()).await.unwrap());
+    let message_queue = MessageQueue::new();
+    let (tx, _rx) = mpsc::channel(100);
+
+    let session = Session::new("auditor".to_string(), BindType::Transceiver, test_addr(), tx.clone(), None);
+    let session_id = session.id.clone();
+    let mut current_session_id = Some(session_id.clone());
+    session_manager.add_session(session);
+
+    let submit = SubmitSm::builder()
+        .source_addr(COctetString::from_str("src").unwrap())
+        .destination_addr(COctetString::from_str("dst").unwrap())
+        .short_message(OctetString::from_str("hi").unwrap())
+        .build();
+    let command = Command::new(CommandStatus::EsmeRok, 1, Pdu::SubmitSm(submit));
+    handle_command(&command, &config, &session_manager, &message_queue, &mut current_session_id, test_addr(), tx).await.unwrap();
+
+    // The session is gone by the time the message resolves, so no receipt goes out
+    session_manager.remove_session(&session_id);
+    process_pending_messages(&config, &session_manager, &message_queue).await;
+    assert!(message_queue.get_pending_messages().is_empty());
+
+    tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+    let lines = std::fs::read_to_string(&path).unwrap_or_default().lines().map(String::from).collect::<Vec<_>>();
+    let _ = std::fs::remove_file(&path);
+    assert_eq!(lines.len(), 1, "Only the submit is audited: {:?}", lines);
+    assert!(lines[0].contains("\"event\":\"submit_sm\""));
+}
+
+#[tokio::test]
+async fn test_replace_if_present_overwrites_pending() {
+    let config = test_config();
+    let session_manager = SessionManager::new();
+    let message_queue = MessageQueue::new();
+    let (tx, _rx) = mpsc::channel(100);
+    let session = Session::new("user".to_string(), BindType::Transceiver, test_addr(), tx.clone(), None);
+    let mut current_session_id = Some(session.id.clone());
+    session_manager.add_session(session);
+
+    let submit = |text: &str, dest: &str, flag: ReplaceIfPresentFlag| SubmitSm::builder()
+        .source_addr(COctetString::from_str("bank").unwrap())
+        .destination_addr(COctetString::from_str(dest).unwrap())
+        .replace_if_present_flag(flag)
+        .short_message(OctetString::from_str(text).unwrap())
+        .build();
+    let mut message_ids = Vec::new();
+    for (seq, (text, dest, flag)) in [
+        ("OTP 1111", "66811111111", ReplaceIfPresentFlag::Replace),
+        ("OTP 2222", "66811111111", ReplaceIfPresentFlag::Replace),
+        ("OTP 3333", "66822222222", ReplaceIfPresentFlag::Replace),
+        ("OTP 4444", "66822222222", ReplaceIfPresentFlag::DoNotReplace),
+    ].into_iter().enumerate() {
+        let command = Command::new(CommandStatus::EsmeRok, seq as u32 + 1, Pdu::SubmitSm(submit(text, dest, flag)));
+        let resp = handle_command(&command, &config, &session_manager, &message_queue, &mut current_session_id, test_addr(), tx.clone()).await.unwrap();
+        let Some(Pdu::SubmitSmResp(resp)) = resp.pdu() else { panic!("expected SubmitSmResp") };
+        message_ids.push(resp.message_id().to_string());
+    }
+
+    // The second OTP took over the first one's ID and content
+    assert_eq!(message_ids[1], message_ids[0]);
+    assert_eq!(message_queue.get_message(&message_ids[0]).unwrap().short_message, b"OTP 2222");
+    // Nothing to replace for the first submit to a new destination, and no replace without the flag
+    assert_ne!(message_ids[2], message_ids[0]);
+    assert_ne!(message_ids[3], message_ids[2]);
+    assert_eq!(message_queue.pending_dr_count(), 3);
+
+    // Only the submitting session's messages with the same service_type are replaced
+    let mut other = submit("OTP 5555", "66811111111", ReplaceIfPresentFlag::Replace);
+    other.service_type = ServiceType::new(COctetString::from_str("OTP").unwrap());
+    let command = Command::new(CommandStatus::EsmeRok, 5, Pdu::SubmitSm(other));
+    let resp = handle_command(&command, &config, &session_manager, &message_queue, &mut current_session_id, test_addr(), tx.clone()).await.unwrap();
+    let Some(Pdu::SubmitSmResp(resp)) = resp.pdu() else { panic!("expected SubmitSmResp") };
+    assert_ne!(resp.message_id().to_string(), message_ids[0]);
+    let second = Session::new("user".to_string(), BindType::Transceiver, test_addr(), tx.clone(), None);
+    let mut second_session_id = Some(second.id.clone());
+    session_manager.add_session(second);
+    let command = Command::new(CommandStatus::EsmeRok, 1, Pdu::SubmitSm(submit("OTP 6666", "66811111111", ReplaceIfPresentFlag::Replace)));
+    let resp = handle_command(&command, &config, &session_manager, &message_queue, &mut second_session_id, test_addr(), tx).await.unwrap();
+    let Some(Pdu::SubmitSmResp(resp)) = resp.pdu() else { panic!("expected SubmitSmResp") };
+    assert_ne!(resp.message_id().to_string(), message_ids[0]);
+    assert_eq!(message_queue.get_message(&message_ids[0]).unwrap().short_message, b"OTP 2222");
+    assert_eq!(message_queue.pending_dr_count(), 5);
+}
+
+#[tokio::test]
+async fn test_strict_replace_if_present_keeps_latest() {
+    let mut config = test_config();
+    config.smpp.strict_replace_if_present = true;
+    let session_manager = SessionManager::new();
+    let message_queue = MessageQueue::new();
+    let (tx, _rx) = mpsc::channel(100);
+    let session = Session::new("user".to_string(), BindType::Transceiver, test_addr(), tx.clone(), None);
+    let mut current_session_id = Some(session.id.clone());
+    session_manager.add_session(session);
+
+    let submit = |text: &str, flag: ReplaceIfPresentFlag| SubmitSm::builder()
+        .source_addr(COctetString::from_str("bank").unwrap())
+        .destination_addr(COctetString::from_str("66811111111").unwrap())
+        .replace_if_present_flag(flag)
+        .short_message(OctetString::from_str(text).unwrap())
+        .build();
+    let mut message_ids = Vec::new();
+    for (seq, (text, flag)) in [
+        ("OTP 1111", ReplaceIfPresentFlag::DoNotReplace),
+        ("OTP 2222", ReplaceIfPresentFlag::DoNotReplace),
+        ("OTP 3333", ReplaceIfPresentFlag::Replace),
+        ("OTP 4444", ReplaceIfPresentFlag::Replace),
+    ].into_iter().enumerate() {
+        let command = Command::new(CommandStatus::EsmeRok, seq as u32 + 1, Pdu::SubmitSm(submit(text, flag)));
+        let resp = handle_command(&command, &config, &session_manager, &message_queue, &mut current_session_id, test_addr(), tx.clone()).await.unwrap();
+        let Some(Pdu::SubmitSmResp(resp)) = resp.pdu() else { panic!("expected SubmitSmResp") };
+        message_ids.push(resp.message_id().to_string());
+        if seq == 0 {
+            message_queue.cancel_pending(&message_ids[0]);
+        }
+    }
+
+    // Each submit kept its own ID, but only the latest is still waiting for a receipt;
+    // the cancelled one is left for the lifecycle to finish
+    assert_eq!(message_ids.iter().collect::<std::collections::HashSet<_>>().len(), 4);
+    assert_eq!(message_queue.pending_dr_count(), 2);
+    assert!(message_queue.is_pending_dr(&message_ids[0]));
+    assert!(message_queue.is_pending_dr(&message_ids[3]));
+    for message_id in &message_ids[1..3] {
+        assert_eq!(message_queue.get_message(message_id).unwrap().final_state, Some(MessageState::Deleted));
+    }
+    let deleted = message_queue.final_state_counts().into_iter().find(|(state, _)| *state == MessageState::Deleted);
+    assert_eq!(deleted, Some((MessageState::Deleted, 2)));
+}
+
+#[tokio::test]
+async fn test_sme_acks_follow_delivered_receipt() {
+    for sme_acks in [false, true] {
+        let mut config = test_config();
+        config.lifecycle.max_time_enroute_ms = 0;
+        config.lifecycle.percent_delivered = 100;
+        config.lifecycle.sme_acks = sme_acks;
+        let session_manager = SessionManager::new();
+        let message_queue = MessageQueue::new();
+        let (tx, mut rx) = mpsc::channel(10);
+        let session = Session::new("user".to_string(), BindType::Transceiver, test_addr(), tx.clone(), None);
+        let mut current_session_id = Some(session.id.clone());
+        session_manager.add_session(session);
+
+        // MC receipt plus both SME acknowledgements requested
+        let submit = SubmitSm::builder()
+            .source_addr(COctetString::from_str("src").unwrap())
+            .destination_addr(COctetString::from_str("dst").unwrap())
+            .registered_delivery(rusmpp::values::RegisteredDelivery::from(0x0D))
+            .short_message(OctetString::from_str("hi").unwrap())
+            .build();
+        let command = Command::new(CommandStatus::EsmeRok, 1, Pdu::SubmitSm(submit));
+        handle_command(&command, &config, &session_manager, &message_queue, &mut current_session_id, test_addr(), tx).await.unwrap();
+        process_pending_messages(&config, &session_manager, &message_queue).await;
+
+        let mut message_types = Vec::new();
+        while let Ok(command) = rx.try_recv() {
+            let Some(Pdu::DeliverSm(deliver)) = command.pdu() else { panic!("Expected DeliverSm, got {:?}", command.pdu()) };
+            assert_eq!(deliver.source_addr.to_string(), "dst");
+            assert_eq!(deliver.destination_addr.to_string(), "src");
+            message_types.push(u8::from(deliver.esm_class) & 0x3C);
+        }
+        if sme_acks {
+            assert_eq!(message_types.len(), 3);
+            assert_eq!(&message_types[1..], &[0x08, 0x10]);
+        } else {
+            assert_eq!(message_types.len(), 1);
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_configure_socket() {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let _client = tokio::net::TcpStream::connect(addr).await.unwrap();
+    let (socket, _) = listener.accept().await.unwrap();
+
+    let mut config = test_config().smpp;
+    config.tcp_keepalive_secs = 15;
+    configure_socket(&socket, &config);
+    let sock = socket2::SockRef::from(&socket);
+    assert!(sock.tcp_nodelay().unwrap());
+    assert!(sock.keepalive().unwrap());
+    #[cfg(target_os = "linux")]
+    {
+        assert_eq!(sock.tcp_keepalive_time().unwrap(), std::time::Duration::from_secs(15));
+        assert_eq!(sock.tcp_keepalive_interval().unwrap(), std::time::Duration::from_secs(15));
+    }
+
+    config.tcp_nodelay = false;
+    configure_socket(&socket, &config);
+    assert!(!sock.tcp_nodelay().unwrap());
+}
@@ -2,8 +2,21 @@ use dashmap::DashMap;
 use uuid::Uuid;
 use serde::Serialize;
 use regex;
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
 
 use rusmpp::values::InterfaceVersion;
+use tokio_util::sync::CancellationToken;
+
+use crate::config::AppConfig;
+use crate::smpp::audit::{AuditLog, AuditRecord};
+use crate::smpp::capture::PduCapture;
+use crate::smpp::metrics::Metrics;
+
+/// Highest sequence number allowed by SMPP; generated numbers wrap back to 1 after it
+pub const MAX_SEQUENCE_NUMBER: u32 = 0x7FFF_FFFF;
 
 #[derive(Debug, Clone, PartialEq, Serialize)]
 pub enum BindType {
@@ -12,6 +25,18 @@ pub enum BindType {
     Transceiver,
 }
 
+impl BindType {
+    /// Parse a bind type name as used in configuration ("transmitter", "receiver", "transceiver")
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "transmitter" | "tx" => Some(BindType::Transmitter),
+            "receiver" | "rx" => Some(BindType::Receiver),
+            "transceiver" | "trx" => Some(BindType::Transceiver),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize)]
 pub struct Session {
     pub id: String,
@@ -25,6 +50,45 @@ pub struct Session {
     pub sender: mpsc::Sender<Command>,
     #[serde(skip)]
     pub address_range: Option<String>,
+    /// `address_range` compiled once at bind time; `None` if absent or not a valid regex
+    #[serde(skip)]
+    pub compiled_range: Option<regex::Regex>,
+    /// Accepted submit_sm whose submit_sm_resp has not been written yet; shared by clones
+    #[serde(skip)]
+    pub outstanding_submits: Arc<AtomicUsize>,
+    /// submit_sm accepted on this session; shared by clones
+    #[serde(serialize_with = "serialize_counter")]
+    pub submit_count: Arc<AtomicU64>,
+    /// Arrival times of recent submit_sm, for the congestion model; shared by clones
+    #[serde(skip)]
+    pub recent_submits: Arc<parking_lot::Mutex<VecDeque<Instant>>>,
+    /// deliver_sm (DRs and MO) sent to this session; shared by clones
+    #[serde(serialize_with = "serialize_counter")]
+    pub deliver_count: Arc<AtomicU64>,
+    /// Next sequence number for a server-initiated delivery receipt; shared by clones
+    #[serde(skip)]
+    pub next_sequence: Arc<AtomicU32>,
+    /// Delivery receipts sent but not yet answered with deliver_sm_resp, by sequence number
+    #[serde(skip)]
+    pub unacked_receipts: Arc<DashMap<u32, UnackedReceipt>>,
+    /// Cancelled to make the connection task drop the socket
+    #[serde(skip)]
+    pub disconnect: CancellationToken,
+    /// When the session was bound, for its age
+    #[serde(skip)]
+    pub connected_at: Instant,
+    pub connected_wall: chrono::DateTime<chrono::Local>,
+}
+
+/// A delivery receipt written to the client that is still waiting for its deliver_sm_resp
+#[derive(Debug, Clone)]
+pub struct UnackedReceipt {
+    pub message_id: String,
+    /// The deliver_sm as sent, kept for re-sending
+    pub pdu: Command,
+    pub sent_at: Instant,
+    /// Re-sends so far
+    pub retries: u32,
 }
 
 fn serialize_addr<S>(addr: &std::net::SocketAddr, serializer: S) -> Result<S::Ok, S::Error>
@@ -34,11 +98,19 @@ where
     serializer.serialize_str(&addr.to_string())
 }
 
+fn serialize_counter<S>(counter: &Arc<AtomicU64>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    serializer.serialize_u64(counter.load(Ordering::Relaxed))
+}
+
 use tokio::sync::mpsc;
 use rusmpp::Command;
 
 impl Session {
     pub fn new(system_id: String, bind_type: BindType, addr: std::net::SocketAddr, sender: mpsc::Sender<Command>, address_range: Option<String>) -> Self {
+        let compiled_range = address_range.as_deref().and_then(|range| regex::Regex::new(range).ok());
         Self {
             id: Uuid::new_v4().to_string(),
             system_id,
@@ -47,35 +119,375 @@ impl Session {
             addr,
             sender,
             address_range,
+            compiled_range,
+            outstanding_submits: Arc::new(AtomicUsize::new(0)),
+            submit_count: Arc::new(AtomicU64::new(0)),
+            recent_submits: Arc::new(parking_lot::Mutex::new(VecDeque::new())),
+            deliver_count: Arc::new(AtomicU64::new(0)),
+            next_sequence: Arc::new(AtomicU32::new(1)),
+            unacked_receipts: Arc::new(DashMap::new()),
+            disconnect: CancellationToken::new(),
+            connected_at: Instant::now(),
+            connected_wall: chrono::Local::now(),
         }
     }
+
+    /// Count a submit_sm accepted on this session
+    pub fn record_submit(&self) {
+        self.submit_count.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Note a submit_sm arriving and return the session's submit rate per second over the
+    /// last `window`, this submit included
+    pub fn submit_rate(&self, window: Duration) -> u32 {
+        let now = Instant::now();
+        let mut recent = self.recent_submits.lock();
+        while recent.front().is_some_and(|at| now.duration_since(*at) > window) {
+            recent.pop_front();
+        }
+        recent.push_back(now);
+        (recent.len() as u128 * 1000 / window.as_millis().max(1)) as u32
+    }
+
+    /// Count a deliver_sm sent to this session
+    pub fn record_deliver(&self) {
+        self.deliver_count.fetch_add(1, Ordering::Relaxed);
+    }
     
-    pub async fn send_command(&self, command: Command) -> Result<(), mpsc::error::SendError<Command>> {
+    /// Whether `addr` falls within the bind's `address_range`; false when there is none.
+    /// A range that is not a valid regex is matched as a prefix.
+    pub fn in_address_range(&self, addr: &str) -> bool {
+        match (&self.address_range, &self.compiled_range) {
+            (Some(_), Some(re)) => re.is_match(addr),
+            (Some(range), None) => addr.starts_with(range.as_str()),
+            (None, _) => false,
+        }
+    }
+
+    /// Number of submits currently occupying the session's window
+    pub fn window_in_use(&self) -> usize {
+        self.outstanding_submits.load(Ordering::SeqCst)
+    }
+
+    /// Take a window slot for an accepted submit
+    pub fn acquire_window(&self) {
+        self.outstanding_submits.fetch_add(1, Ordering::SeqCst);
+    }
+
+    /// Free a window slot once its submit_sm_resp has been sent
+    pub fn release_window(&self) {
+        let _ = self.outstanding_submits.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1));
+    }
+    
+    /// Next sequence number for a PDU the simulator originates on this session.
+    /// Runs 1..=0x7FFFFFFF as the spec requires, then wraps back to 1.
+    pub fn next_sequence_number(&self) -> u32 {
+        self.next_sequence
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| Some(if n >= MAX_SEQUENCE_NUMBER { 1 } else { n + 1 }))
+            .map_or(1, |n| n.clamp(1, MAX_SEQUENCE_NUMBER))
+    }
+
+    /// Give a delivery receipt for `message_id` the next sequence number and remember it
+    /// until the client's deliver_sm_resp arrives
+    pub fn track_receipt(&self, message_id: &str, mut pdu: Command) -> Command {
+        pdu.sequence_number = self.next_sequence_number();
+        self.unacked_receipts.insert(pdu.sequence_number, UnackedReceipt {
+            message_id: message_id.to_string(),
+            pdu: pdu.clone(),
+            sent_at: Instant::now(),
+            retries: 0,
+        });
+        pdu
+    }
+
+    /// Match a deliver_sm_resp to the receipt it answers, returning that receipt's message ID
+    pub fn ack_receipt(&self, sequence_number: u32) -> Option<String> {
+        self.unacked_receipts.remove(&sequence_number).map(|(_, receipt)| receipt.message_id)
+    }
+
+    /// Delivery receipts still waiting for a deliver_sm_resp
+    pub fn unacked_receipt_count(&self) -> usize {
+        self.unacked_receipts.len()
+    }
+
+    pub async fn send_command(&self, mut command: Command) -> Result<(), mpsc::error::SendError<Command>> {
+        // Server-initiated requests built without a sequence number get the session's next one
+        if command.sequence_number == 0 && !command.id().is_response() {
+            command.sequence_number = self.next_sequence_number();
+        }
         self.sender.send(command).await
     }
 }
 
+/// Consecutive failed binds for one system_id
+#[derive(Debug, Clone, Copy)]
+struct BindFailures {
+    count: u32,
+    /// Start of the current counting window
+    first_at: Instant,
+    locked_until: Option<Instant>,
+}
+
+/// Simple token bucket: holds up to `rate` tokens and refills `rate` tokens per second
+#[derive(Debug, Clone)]
+pub struct TokenBucket {
+    rate: f64,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    pub fn new(rate: u32) -> Self {
+        Self {
+            rate: rate as f64,
+            tokens: rate as f64,
+            last_refill: Instant::now(),
+        }
+    }
+
+    /// Take a token if one is available
+    pub fn try_acquire(&mut self) -> bool {
+        let now = Instant::now();
+        let refill = now.duration_since(self.last_refill).as_secs_f64() * self.rate;
+        self.tokens = (self.tokens + refill).min(self.rate);
+        self.last_refill = now;
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
 pub struct SessionManager {
     // Map Session ID -> Session
     sessions: DashMap<String, Session>,
+    // Map System ID -> time its most recent session was removed
+    last_disconnect: DashMap<String, Instant>,
+    // Map System ID -> recent failed binds, for `smpp.max_bind_failures`
+    bind_failures: DashMap<String, BindFailures>,
+    // Map System ID -> submit rate bucket, shared by all of the account's sessions
+    account_buckets: DashMap<String, TokenBucket>,
+    // Map Session ID -> submit rate bucket for that session alone
+    session_buckets: DashMap<String, TokenBucket>,
+    // Map peer address -> client certificate CN for connections made over mTLS
+    client_identities: DashMap<std::net::SocketAddr, String>,
+    // Map peer address -> raw PDU capture, when `smpp.capture_pdus` is on
+    captures: DashMap<std::net::SocketAddr, Arc<PduCapture>>,
+    // Flipped to true when the server starts shutting down
+    shutdown: tokio::sync::watch::Sender<bool>,
+    // Prometheus counters shared with the web server
+    metrics: Arc<Metrics>,
+    // Reject every bind regardless of credentials; toggled at runtime
+    force_bind_failure: AtomicBool,
+    // Set once every SMPP listener has bound, cleared when they close
+    listener_ready: AtomicBool,
+    // Config swapped in at runtime; None until the first reload
+    live_config: parking_lot::RwLock<Option<Arc<AppConfig>>>,
+    // Audit file writer, set at startup when `audit.file_path` is configured
+    audit: std::sync::OnceLock<AuditLog>,
+}
+
+impl Default for SessionManager {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl SessionManager {
     pub fn new() -> Self {
         Self {
             sessions: DashMap::new(),
+            last_disconnect: DashMap::new(),
+            bind_failures: DashMap::new(),
+            account_buckets: DashMap::new(),
+            session_buckets: DashMap::new(),
+            client_identities: DashMap::new(),
+            captures: DashMap::new(),
+            shutdown: tokio::sync::watch::Sender::new(false),
+            metrics: Arc::new(Metrics::new()),
+            force_bind_failure: AtomicBool::new(false),
+            listener_ready: AtomicBool::new(false),
+            live_config: parking_lot::RwLock::new(None),
+            audit: std::sync::OnceLock::new(),
+        }
+    }
+
+    pub fn metrics(&self) -> &Arc<Metrics> {
+        &self.metrics
+    }
+
+    /// Whether binds are currently forced to fail
+    pub fn force_bind_failure(&self) -> bool {
+        self.force_bind_failure.load(Ordering::SeqCst)
+    }
+
+    /// Turn forced bind failures on or off
+    pub fn set_force_bind_failure(&self, enabled: bool) {
+        self.force_bind_failure.store(enabled, Ordering::SeqCst);
+    }
+
+    /// Whether the SMPP listeners are bound and accepting connections
+    pub fn listener_ready(&self) -> bool {
+        self.listener_ready.load(Ordering::SeqCst)
+    }
+
+    pub fn set_listener_ready(&self, ready: bool) {
+        self.listener_ready.store(ready, Ordering::SeqCst);
+    }
+
+    /// Start writing audit records to `log`; only the first call takes effect
+    pub fn set_audit_log(&self, log: AuditLog) {
+        let _ = self.audit.set(log);
+    }
+
+    /// Append a record to the audit file, if one is configured
+    pub fn audit(&self, record: AuditRecord) {
+        if let Some(log) = self.audit.get() {
+            log.record(record);
         }
     }
 
+    /// The config currently in effect: the latest reloaded one, or `startup` if there was none
+    pub fn live_config(&self, startup: &Arc<AppConfig>) -> Arc<AppConfig> {
+        self.live_config.read().clone().unwrap_or_else(|| startup.clone())
+    }
+
+    /// Swap in a new config. Running tasks pick it up on their next pass; listeners,
+    /// ports and TLS settings keep what they were started with.
+    pub fn set_live_config(&self, config: AppConfig) {
+        *self.live_config.write() = Some(Arc::new(config));
+    }
+
+    /// Edit the config in effect under a single write lock, so a concurrent update or reload
+    /// is not lost. If `update` returns an error the config is left as it was.
+    pub fn update_live_config<T>(
+        &self,
+        startup: &Arc<AppConfig>,
+        update: impl FnOnce(&mut AppConfig) -> Result<T, String>,
+    ) -> Result<T, String> {
+        let mut live = self.live_config.write();
+        let mut config = AppConfig::clone(live.as_ref().unwrap_or(startup));
+        let result = update(&mut config)?;
+        *live = Some(Arc::new(config));
+        Ok(result)
+    }
+
     pub fn add_session(&self, session: Session) {
         self.sessions.insert(session.id.clone(), session);
     }
 
     pub fn remove_session(&self, session_id: &str) {
-        self.sessions.remove(session_id);
+        self.session_buckets.remove(session_id);
+        if let Some((_, session)) = self.sessions.remove(session_id) {
+            self.last_disconnect.insert(session.system_id, Instant::now());
+        }
+    }
+
+    /// Remove a session and close its connection; false if the id is unknown
+    pub fn disconnect_session(&self, session_id: &str) -> bool {
+        let Some(session) = self.get_session(session_id) else {
+            return false;
+        };
+        session.disconnect.cancel();
+        self.remove_session(session_id);
+        true
+    }
+
+    /// Time elapsed since a session for `system_id` was last removed, if ever
+    pub fn since_last_disconnect(&self, system_id: &str) -> Option<Duration> {
+        self.last_disconnect.get(system_id).map(|t| t.elapsed())
     }
     
-        pub fn get_session(&self, session_id: &str) -> Option<Session> {
+    /// Count a failed bind for `system_id`. Returns true when this reaches `max_failures`
+    /// within `window` and locks the account out for `lockout`.
+    pub fn record_bind_failure(&self, system_id: &str, max_failures: u32, window: Duration, lockout: Duration) -> bool {
+        let now = Instant::now();
+        let mut failures = self.bind_failures.entry(system_id.to_string())
+            .or_insert(BindFailures { count: 0, first_at: now, locked_until: None });
+        if now.duration_since(failures.first_at) > window {
+            *failures = BindFailures { count: 0, first_at: now, locked_until: None };
+        }
+        failures.count += 1;
+        if failures.count >= max_failures {
+            failures.locked_until = Some(now + lockout);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Time left on `system_id`'s lockout, if it is locked out. An expired lockout
+    /// clears the failure count.
+    pub fn bind_lockout_remaining(&self, system_id: &str) -> Option<Duration> {
+        let locked_until = self.bind_failures.get(system_id).and_then(|f| f.locked_until)?;
+        let remaining = locked_until.checked_duration_since(Instant::now());
+        if remaining.is_none() {
+            self.bind_failures.remove(system_id);
+        }
+        remaining
+    }
+
+    /// Forget `system_id`'s failed binds after a successful one
+    pub fn clear_bind_failures(&self, system_id: &str) {
+        self.bind_failures.remove(system_id);
+    }
+
+    /// Consume one submit from `system_id`'s rate bucket; false when it is empty
+    pub fn try_account_submit(&self, system_id: &str, per_second: u32) -> bool {
+        self.account_buckets.entry(system_id.to_string())
+            .or_insert_with(|| TokenBucket::new(per_second))
+            .try_acquire()
+    }
+    
+    /// Consume one submit from the session's own rate bucket; false when it is empty
+    pub fn try_session_submit(&self, session_id: &str, per_second: u32) -> bool {
+        self.session_buckets.entry(session_id.to_string())
+            .or_insert_with(|| TokenBucket::new(per_second))
+            .try_acquire()
+    }
+    
+    /// Record the verified client certificate CN for a TLS connection
+    pub fn set_client_identity(&self, addr: std::net::SocketAddr, common_name: String) {
+        self.client_identities.insert(addr, common_name);
+    }
+
+    /// Client certificate CN for the connection from `addr`, if it used mTLS
+    pub fn client_identity(&self, addr: &std::net::SocketAddr) -> Option<String> {
+        self.client_identities.get(addr).map(|cn| cn.clone())
+    }
+
+    pub fn clear_client_identity(&self, addr: &std::net::SocketAddr) {
+        self.client_identities.remove(addr);
+    }
+
+    /// Attach a connection's PDU capture so it can be fetched by session
+    pub fn set_capture(&self, addr: std::net::SocketAddr, capture: Arc<PduCapture>) {
+        self.captures.insert(addr, capture);
+    }
+
+    /// The PDU capture of a bound session's connection, if capturing
+    pub fn capture_for_session(&self, session_id: &str) -> Option<Arc<PduCapture>> {
+        let addr = self.sessions.get(session_id)?.addr;
+        self.captures.get(&addr).map(|c| c.clone())
+    }
+
+    pub fn clear_capture(&self, addr: &std::net::SocketAddr) {
+        self.captures.remove(addr);
+    }
+    
+    /// Tell every connection that the server is shutting down
+    pub fn begin_shutdown(&self) {
+        self.shutdown.send_replace(true);
+    }
+
+    /// Receiver that connections watch for server shutdown
+    pub fn shutdown_signal(&self) -> tokio::sync::watch::Receiver<bool> {
+        self.shutdown.subscribe()
+    }
+    
+    pub fn get_session(&self, session_id: &str) -> Option<Session> {
         self.sessions.get(session_id).map(|s| s.clone())
     }
 
@@ -91,31 +503,34 @@ impl SessionManager {
     /// Simplified matching: check if session has a range, and if dest_addr starts with it (regex support is complex here, sticking to prefix or exact match for now, or just regex if easy).
     /// SMPP spec says address_range is regex.
     pub fn find_subscriber(&self, dest_addr: &str) -> Option<Session> {
+        self.find_subscriber_preferring(dest_addr, None)
+    }
+
+    /// Like `find_subscriber`, but when several sessions match, return one with the
+    /// `preferred` bind type if there is one.
+    pub fn find_subscriber_preferring(&self, dest_addr: &str, preferred: Option<&BindType>) -> Option<Session> {
         // Simple strategy: First Receiver/Transceiver that matches.
         // If range is null, maybe catch-all? Usually null means no routing. // SMPPSim behavior: matches address_range.
+        let mut fallback: Option<Session> = None;
         
         for entry in self.sessions.iter() {
             let session = entry.value();
             // Skip Transmitters
-            match session.bind_type {
-                BindType::Transmitter => continue,
-                _ => {}
+            if session.bind_type == BindType::Transmitter {
+                continue;
             }
             
-            if let Some(range) = &session.address_range {
-                // Try Regex match
-                if let Ok(re) = regex::Regex::new(range) {
-                    if re.is_match(dest_addr) {
-                        return Some(session.clone());
-                    }
-                } else {
-                    // Fallback to simple prefix match if regex fails to compile
-                    if dest_addr.starts_with(range) {
-                         return Some(session.clone());
+            if session.in_address_range(dest_addr) {
+                match preferred {
+                    Some(bind_type) if *bind_type != session.bind_type => {
+                        if fallback.is_none() {
+                            fallback = Some(session.clone());
+                        }
                     }
+                    _ => return Some(session.clone()),
                 }
             }
         }
-        None
+        fallback
     }
 }
@@ -134,3 +134,56 @@ fn test_session_count() {
     manager.remove_session(&s1_id);
     assert_eq!(manager.count(), 1);
 }
+
+#[test]
+fn test_find_subscriber_uses_compiled_range() {
+    let manager = SessionManager::new();
+    let (tx, _rx) = mpsc::channel(1);
+    
+    let regex_session = Session::new("re".to_string(), BindType::Receiver, test_addr(), tx.clone(), Some("^66\\d+$".to_string()));
+    assert!(regex_session.compiled_range.is_some(), "Valid range should be compiled at creation");
+    let regex_id = regex_session.id.clone();
+    manager.add_session(regex_session);
+    
+    // "[1" is not a valid regex, so it falls back to prefix matching
+    let prefix_session = Session::new("prefix".to_string(), BindType::Transceiver, test_addr(), tx.clone(), Some("[1".to_string()));
+    assert!(prefix_session.compiled_range.is_none());
+    let prefix_id = prefix_session.id.clone();
+    manager.add_session(prefix_session);
+    
+    // Transmitters never receive MO, even when the range matches
+    manager.add_session(Session::new("tx".to_string(), BindType::Transmitter, test_addr(), tx, Some("^99".to_string())));
+    
+    assert_eq!(manager.find_subscriber("66812345678").map(|s| s.id), Some(regex_id));
+    assert_eq!(manager.find_subscriber("[1234").map(|s| s.id), Some(prefix_id));
+    assert!(manager.find_subscriber("99123").is_none());
+}
+
+#[tokio::test]
+async fn test_server_initiated_sequence_numbers() {
+    use crate::smpp::session::MAX_SEQUENCE_NUMBER;
+    use rusmpp::{Command, CommandStatus, Pdu};
+    use std::sync::atomic::Ordering;
+
+    let (tx, mut rx) = mpsc::channel(10);
+    let session = Session::new("client1".to_string(), BindType::Receiver, test_addr(), tx, None);
+    let request = || Command::builder().status(CommandStatus::EsmeRok).sequence_number(0).pdu(Pdu::EnquireLink);
+
+    // Requests built with 0 are numbered from the session's own sequence space
+    session.send_command(request()).await.unwrap();
+    session.send_command(request()).await.unwrap();
+    assert_eq!(rx.recv().await.unwrap().sequence_number, 1);
+    assert_eq!(rx.recv().await.unwrap().sequence_number, 2);
+
+    // Responses and requests that already carry a number are left alone
+    session.send_command(Command::builder().status(CommandStatus::EsmeRok).sequence_number(0).pdu(Pdu::EnquireLinkResp)).await.unwrap();
+    session.send_command(Command::builder().status(CommandStatus::EsmeRok).sequence_number(77).pdu(Pdu::EnquireLink)).await.unwrap();
+    assert_eq!(rx.recv().await.unwrap().sequence_number, 0);
+    assert_eq!(rx.recv().await.unwrap().sequence_number, 77);
+
+    // The sequence wraps to 1 after the spec maximum, never reaching 0 or the high bit
+    session.next_sequence.store(MAX_SEQUENCE_NUMBER, Ordering::SeqCst);
+    assert_eq!(session.next_sequence_number(), MAX_SEQUENCE_NUMBER);
+    assert_eq!(session.next_sequence_number(), 1);
+    assert_eq!(session.next_sequence_number(), 2);
+}
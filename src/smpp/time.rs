@@ -0,0 +1,87 @@
+//! SMPP time format parsing
+//!
+//! `schedule_delivery_time` and `validity_period` use a 16 character format
+//! `YYMMDDhhmmsstnnp`:
+//! - absolute: `p` is `+` or `-`, `t` is tenths of a second and `nn` is the
+//!   offset from UTC in quarter hours
+//! - relative: `p` is `R`, the fields are an offset from now and `tnn` is `000`
+
+use chrono::{DateTime, Duration, Months, NaiveDate, Utc};
+
+/// Parse an SMPP time string relative to `now`. Returns `None` for empty or malformed values.
+pub fn parse_smpp_time(value: &str, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    let value = value.trim();
+    if value.len() != 16 || !value.is_ascii() {
+        return None;
+    }
+
+    let field = |range: std::ops::Range<usize>| value[range].parse::<u32>().ok();
+    let (yy, mm, dd) = (field(0..2)?, field(2..4)?, field(4..6)?);
+    let (hh, mi, ss) = (field(6..8)?, field(8..10)?, field(10..12)?);
+    let tenths = field(12..13)?;
+    let quarters = field(13..15)?;
+
+    match &value[15..16] {
+        "R" => {
+            let shifted = now.checked_add_months(Months::new(yy * 12 + mm))?;
+            Some(shifted
+                + Duration::days(dd as i64)
+                + Duration::hours(hh as i64)
+                + Duration::minutes(mi as i64)
+                + Duration::seconds(ss as i64))
+        }
+        sign @ ("+" | "-") => {
+            let local = NaiveDate::from_ymd_opt(2000 + yy as i32, mm, dd)?
+                .and_hms_milli_opt(hh, mi, ss, tenths * 100)?;
+            let offset = Duration::minutes(quarters as i64 * 15);
+            // The time is expressed in a zone `offset` ahead of (+) or behind (-) UTC
+            let utc = if sign == "+" { local - offset } else { local + offset };
+            Some(utc.and_utc())
+        }
+        _ => None,
+    }
+}
+
+/// Convert an SMPP time string into a duration from now (zero if already in the past).
+pub fn smpp_time_from_now(value: &str) -> Option<std::time::Duration> {
+    let now = Utc::now();
+    let target = parse_smpp_time(value, now)?;
+    Some((target - now).to_std().unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn now() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 1, 15, 12, 0, 0).unwrap()
+    }
+
+    #[test]
+    fn test_relative_time() {
+        let parsed = parse_smpp_time("000000000130000R", now()).unwrap();
+        assert_eq!(parsed - now(), Duration::seconds(90));
+
+        let parsed = parse_smpp_time("000102000000000R", now()).unwrap();
+        assert_eq!(parsed, Utc.with_ymd_and_hms(2024, 2, 17, 12, 0, 0).unwrap());
+    }
+
+    #[test]
+    fn test_absolute_time() {
+        // 2024-02-01 09:30:00 at UTC+7 (28 quarter hours)
+        let parsed = parse_smpp_time("240201093000028+", now()).unwrap();
+        assert_eq!(parsed, Utc.with_ymd_and_hms(2024, 2, 1, 2, 30, 0).unwrap());
+
+        let parsed = parse_smpp_time("240201093000004-", now()).unwrap();
+        assert_eq!(parsed, Utc.with_ymd_and_hms(2024, 2, 1, 10, 30, 0).unwrap());
+    }
+
+    #[test]
+    fn test_invalid_time() {
+        assert!(parse_smpp_time("", now()).is_none());
+        assert!(parse_smpp_time("24020109300002+", now()).is_none());
+        assert!(parse_smpp_time("240201093000028X", now()).is_none());
+        assert!(parse_smpp_time("241301093000028+", now()).is_none());
+    }
+}
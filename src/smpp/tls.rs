@@ -0,0 +1,62 @@
+//! TLS (SMPPS) support
+//!
+//! Builds the `TlsAcceptor` for the secure listener from the PEM files named in
+//! `[smpp.tls]`, optionally requiring client certificates signed by a configured CA.
+
+use std::io;
+use std::sync::Arc;
+
+use tokio_rustls::TlsAcceptor;
+use tokio_rustls::rustls::{self, RootCertStore};
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use tokio_rustls::rustls::pki_types::pem::PemObject;
+use tokio_rustls::rustls::server::WebPkiClientVerifier;
+
+use crate::config::TlsConfig;
+
+fn load_certs(path: &str) -> io::Result<Vec<CertificateDer<'static>>> {
+    CertificateDer::pem_file_iter(path)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| io::Error::other(format!("failed to read certificates from {}: {}", path, e)))
+}
+
+fn load_key(path: &str) -> io::Result<PrivateKeyDer<'static>> {
+    PrivateKeyDer::from_pem_file(path)
+        .map_err(|e| io::Error::other(format!("failed to read private key from {}: {}", path, e)))
+}
+
+/// Build the acceptor for the TLS listener
+pub fn build_acceptor(tls: &TlsConfig) -> io::Result<TlsAcceptor> {
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let builder = rustls::ServerConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()
+        .map_err(io::Error::other)?;
+
+    let builder = if tls.require_client_cert {
+        let ca_path = tls.ca_path.as_deref()
+            .ok_or_else(|| io::Error::other("smpp.tls.require_client_cert needs smpp.tls.ca_path"))?;
+        let mut roots = RootCertStore::empty();
+        for cert in load_certs(ca_path)? {
+            roots.add(cert).map_err(io::Error::other)?;
+        }
+        let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider)
+            .build()
+            .map_err(io::Error::other)?;
+        builder.with_client_cert_verifier(verifier)
+    } else {
+        builder.with_no_client_auth()
+    };
+
+    let server_config = builder
+        .with_single_cert(load_certs(&tls.cert_path)?, load_key(&tls.key_path)?)
+        .map_err(io::Error::other)?;
+    Ok(TlsAcceptor::from(Arc::new(server_config)))
+}
+
+/// Common name of the verified client certificate, if the peer presented one
+pub fn peer_common_name(conn: &rustls::ServerConnection) -> Option<String> {
+    let cert = conn.peer_certificates()?.first()?;
+    let (_, parsed) = x509_parser::parse_x509_certificate(cert.as_ref()).ok()?;
+    let cn = parsed.subject().iter_common_name().next()?.as_str().ok()?.to_string();
+    Some(cn)
+}
@@ -0,0 +1,67 @@
+//! User Data Header parsing for concatenated SMS
+//!
+//! Long texts arrive as several submit_sm whose short_message starts with a UDH
+//! (esm_class UDHI bit set) carrying a concatenation information element: a
+//! reference shared by all parts, the part count and this part's number.
+
+/// Concatenation IE, 8-bit reference (UDH `05 00 03 rr tt ss`)
+const IEI_CONCAT_8BIT: u8 = 0x00;
+/// Concatenation IE, 16-bit reference (UDH `06 08 04 rr rr tt ss`)
+const IEI_CONCAT_16BIT: u8 = 0x08;
+
+/// Position of one part within a concatenated message
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Concat {
+    pub reference: u16,
+    pub total: u8,
+    pub seq: u8,
+}
+
+/// Split a UDH-prefixed short_message into its concatenation IE and the payload after
+/// the header. Returns None when the header is malformed or has no concatenation IE.
+pub fn split_concat(short_message: &[u8]) -> Option<(Concat, &[u8])> {
+    let (&udhl, rest) = short_message.split_first()?;
+    let udhl = usize::from(udhl);
+    if rest.len() < udhl {
+        return None;
+    }
+    let (mut header, payload) = rest.split_at(udhl);
+
+    let mut concat = None;
+    while let [iei, len, tail @ ..] = header {
+        let len = usize::from(*len);
+        if tail.len() < len {
+            return None;
+        }
+        let (data, next) = tail.split_at(len);
+        concat = match (*iei, data) {
+            (IEI_CONCAT_8BIT, &[reference, total, seq]) => Some(Concat { reference: u16::from(reference), total, seq }),
+            (IEI_CONCAT_16BIT, &[hi, lo, total, seq]) => Some(Concat { reference: u16::from_be_bytes([hi, lo]), total, seq }),
+            _ => concat,
+        };
+        header = next;
+    }
+
+    concat
+        .filter(|c| c.total > 0 && (1..=c.total).contains(&c.seq))
+        .map(|c| (c, payload))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_concat() {
+        let part = [0x05, 0x00, 0x03, 0x2A, 0x02, 0x01, b'h', b'i'];
+        assert_eq!(split_concat(&part), Some((Concat { reference: 0x2A, total: 2, seq: 1 }, &b"hi"[..])));
+
+        let part = [0x06, 0x08, 0x04, 0x12, 0x34, 0x03, 0x03, b'!'];
+        assert_eq!(split_concat(&part), Some((Concat { reference: 0x1234, total: 3, seq: 3 }, &b"!"[..])));
+
+        // Truncated header, no concatenation IE, part number out of range
+        assert_eq!(split_concat(&[0x05, 0x00, 0x03, 0x2A]), None);
+        assert_eq!(split_concat(&[0x04, 0x0A, 0x02, 0x00, 0x00, b'x']), None);
+        assert_eq!(split_concat(&[0x05, 0x00, 0x03, 0x2A, 0x02, 0x03, b'x']), None);
+    }
+}
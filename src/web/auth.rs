@@ -0,0 +1,82 @@
+//! Optional authentication for the web UI and REST API
+//!
+//! Enabled by a `[server.auth]` block. Every route except `/health` and `/ready` then needs either
+//! HTTP Basic credentials matching `username`/`password` or, for `/api/*` and
+//! `/metrics`, an `Authorization: Bearer <api_token>` header. A block with only `api_token`
+//! protects the API and leaves the browser UI open, since there is nothing to log in with.
+
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{AUTHORIZATION, WWW_AUTHENTICATE};
+use actix_web::middleware::Next;
+use actix_web::{web, Error, HttpResponse};
+use base64::Engine;
+
+use crate::config::AuthConfig;
+use super::AppState;
+
+/// Realm shown in the browser's login prompt
+const REALM: &str = "SMPP Simulator";
+
+/// Middleware entry point; register with `middleware::from_fn(require_auth)`
+pub async fn require_auth(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, Error> {
+    let auth = req.app_data::<web::Data<AppState>>()
+        .and_then(|data| data.config.server.auth.clone());
+    let Some(auth) = auth else {
+        return next.call(req).await.map(ServiceResponse::map_into_left_body);
+    };
+
+    let path = req.path();
+    if path == "/health" || path == "/ready" {
+        return next.call(req).await.map(ServiceResponse::map_into_left_body);
+    }
+    let is_api = path.starts_with("/api/") || path == "/metrics";
+    if !is_api && !auth.has_basic_credentials() {
+        return next.call(req).await.map(ServiceResponse::map_into_left_body);
+    }
+
+    let header = req.headers().get(AUTHORIZATION).and_then(|v| v.to_str().ok());
+    if header.is_some_and(|h| authorized(&auth, h, is_api)) {
+        return next.call(req).await.map(ServiceResponse::map_into_left_body);
+    }
+
+    tracing::warn!("Unauthorized web request for {}", path);
+    let mut resp = HttpResponse::Unauthorized();
+    if is_api && auth.api_token.is_some() {
+        resp.insert_header((WWW_AUTHENTICATE, "Bearer"));
+    } else {
+        resp.insert_header((WWW_AUTHENTICATE, format!("Basic realm=\"{}\"", REALM)));
+    }
+    Ok(req.into_response(resp.body("Unauthorized")).map_into_right_body())
+}
+
+/// Check an `Authorization` header value against the configured credentials
+fn authorized(auth: &AuthConfig, header: &str, is_api: bool) -> bool {
+    if let Some(token) = header.strip_prefix("Bearer ") {
+        return is_api && auth.api_token.as_deref().is_some_and(|expected| constant_time_eq(token.trim(), expected));
+    }
+    let Some(encoded) = header.strip_prefix("Basic ") else {
+        return false;
+    };
+    let (Some(username), Some(password)) = (&auth.username, &auth.password) else {
+        return false;
+    };
+    let Ok(decoded) = base64::engine::general_purpose::STANDARD.decode(encoded.trim()) else {
+        return false;
+    };
+    let Ok(credentials) = String::from_utf8(decoded) else {
+        return false;
+    };
+    match credentials.split_once(':') {
+        Some((user, pass)) => constant_time_eq(user, username) & constant_time_eq(pass, password),
+        None => false,
+    }
+}
+
+/// Compare secrets without returning early on the first differing byte
+fn constant_time_eq(a: &str, b: &str) -> bool {
+    a.len() == b.len() && a.bytes().zip(b.bytes()).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
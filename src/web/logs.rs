@@ -5,10 +5,27 @@ use tokio::sync::broadcast;
 
 const MAX_LOG_LINES: usize = 200;
 
+/// A formatted log line tagged with its level, so readers can filter by severity
+#[derive(Debug, Clone)]
+pub struct LogLine {
+    pub level: tracing::Level,
+    pub text: String,
+}
+
+impl LogLine {
+    /// Whether the line is at least as severe as `min` (`None` keeps everything)
+    pub fn at_least(&self, min: Option<tracing::Level>) -> bool {
+        // tracing orders levels by verbosity: ERROR < WARN < ... < TRACE
+        min.is_none_or(|min| self.level <= min)
+    }
+}
+
 /// Shared log buffer for real-time log streaming
 pub struct LogBuffer {
-    logs: RwLock<VecDeque<String>>,
-    tx: broadcast::Sender<String>,
+    logs: RwLock<VecDeque<LogLine>>,
+    tx: broadcast::Sender<LogLine>,
+    /// How lines are written, so lines the buffer makes up itself match the rest
+    format: RwLock<LogFormat>,
 }
 
 impl LogBuffer {
@@ -17,11 +34,18 @@ impl LogBuffer {
         Arc::new(Self {
             logs: RwLock::new(VecDeque::with_capacity(MAX_LOG_LINES)),
             tx,
+            format: RwLock::new(LogFormat::Text),
         })
     }
 
+    /// Set the format lines are written in; `LogBufferLayer::with_format` keeps it in step
+    pub fn set_format(&self, format: LogFormat) {
+        *self.format.write() = format;
+    }
+
     /// Add a log line to the buffer and broadcast it
-    pub fn push(&self, line: String) {
+    pub fn push(&self, level: tracing::Level, text: String) {
+        let line = LogLine { level, text };
         {
             let mut logs = self.logs.write();
             if logs.len() >= MAX_LOG_LINES {
@@ -34,57 +58,265 @@ impl LogBuffer {
 
     /// Get all current logs
     pub fn get_all(&self) -> Vec<String> {
-        self.logs.read().iter().cloned().collect()
+        self.get_at_least(None)
+    }
+
+    /// Get current logs at or above a severity
+    pub fn get_at_least(&self, min: Option<tracing::Level>) -> Vec<String> {
+        self.logs.read().iter().filter(|l| l.at_least(min)).map(|l| l.text.clone()).collect()
     }
 
     /// Subscribe to new log lines
-    pub fn subscribe(&self) -> broadcast::Receiver<String> {
-        self.tx.subscribe()
+    pub fn subscribe(&self) -> LogSubscription {
+        LogSubscription { rx: self.tx.subscribe(), format: *self.format.read() }
+    }
+}
+
+/// A live feed of new log lines that survives falling behind the broadcast channel
+pub struct LogSubscription {
+    rx: broadcast::Receiver<LogLine>,
+    format: LogFormat,
+}
+
+impl LogSubscription {
+    /// Next log line; None once the buffer is gone. A reader that fell more than the channel
+    /// capacity behind gets a "... N log lines dropped ..." line in place of what it missed,
+    /// at ERROR so that every level filter shows it, and carries on from the oldest line kept.
+    /// In JSON mode the marker is a JSON line like any other, with that text as its message.
+    pub async fn recv(&mut self) -> Option<LogLine> {
+        match self.rx.recv().await {
+            Ok(line) => Some(line),
+            Err(broadcast::error::RecvError::Lagged(dropped)) => {
+                let message = format!("... {} log lines dropped ...", dropped);
+                let text = match self.format {
+                    LogFormat::Text => message,
+                    LogFormat::Json => json_line(&tracing::Level::ERROR, module_path!(), message, Default::default(), vec![]),
+                };
+                Some(LogLine { level: tracing::Level::ERROR, text })
+            }
+            Err(broadcast::error::RecvError::Closed) => None,
+        }
     }
 }
 
 /// Custom tracing layer that writes to LogBuffer
 use tracing_subscriber::Layer;
+use tracing_subscriber::registry::LookupSpan;
+
+use crate::config::LogFormat;
 
 pub struct LogBufferLayer {
     buffer: Arc<LogBuffer>,
+    format: LogFormat,
 }
 
 impl LogBufferLayer {
     pub fn new(buffer: Arc<LogBuffer>) -> Self {
-        Self { buffer }
+        Self { buffer, format: LogFormat::Text }
     }
+
+    /// Write lines as `format` (`log.format`) instead of plain text
+    pub fn with_format(mut self, format: LogFormat) -> Self {
+        self.format = format;
+        self.buffer.set_format(format);
+        self
+    }
+}
+
+/// Fields recorded on a span, kept in its extensions so events inside it can show them
+#[derive(Default)]
+struct SpanFields(Vec<SpanField>);
+
+struct SpanField {
+    name: &'static str,
+    /// `{:?}` rendering, as in text lines
+    text: String,
+    json: serde_json::Value,
+}
+
+impl SpanFields {
+    /// Set a field, replacing an earlier value (several layers may record the same one)
+    fn set(&mut self, name: &'static str, text: String, json: serde_json::Value) {
+        match self.0.iter_mut().find(|f| f.name == name) {
+            Some(field) => (field.text, field.json) = (text, json),
+            None => self.0.push(SpanField { name, text, json }),
+        }
+    }
+}
+
+impl tracing::field::Visit for SpanFields {
+    fn record_str(&mut self, field: &tracing::field::Field, value: &str) {
+        self.set(field.name(), format!("{:?}", value), value.into());
+    }
+    fn record_i64(&mut self, field: &tracing::field::Field, value: i64) {
+        self.set(field.name(), value.to_string(), value.into());
+    }
+    fn record_u64(&mut self, field: &tracing::field::Field, value: u64) {
+        self.set(field.name(), value.to_string(), value.into());
+    }
+    fn record_bool(&mut self, field: &tracing::field::Field, value: bool) {
+        self.set(field.name(), value.to_string(), value.into());
+    }
+    fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+        let text = format!("{:?}", value);
+        self.set(field.name(), text.clone(), text.into());
+    }
+}
+
+/// A span enclosing an event: its name and recorded fields, outermost first
+struct SpanContext {
+    name: &'static str,
+    fields: Vec<(&'static str, String, serde_json::Value)>,
 }
 
 impl<S> Layer<S> for LogBufferLayer
 where
-    S: tracing::Subscriber,
+    S: tracing::Subscriber + for<'a> LookupSpan<'a>,
 {
+    fn on_new_span(
+        &self,
+        attrs: &tracing::span::Attributes<'_>,
+        id: &tracing::span::Id,
+        ctx: tracing_subscriber::layer::Context<'_, S>,
+    ) {
+        let Some(span) = ctx.span(id) else { return };
+        let mut extensions = span.extensions_mut();
+        if extensions.get_mut::<SpanFields>().is_none() {
+            extensions.insert(SpanFields::default());
+        }
+        if let Some(fields) = extensions.get_mut::<SpanFields>() {
+            attrs.record(fields);
+        }
+    }
+
+    fn on_record(
+        &self,
+        id: &tracing::span::Id,
+        values: &tracing::span::Record<'_>,
+        ctx: tracing_subscriber::layer::Context<'_, S>,
+    ) {
+        let Some(span) = ctx.span(id) else { return };
+        if let Some(fields) = span.extensions_mut().get_mut::<SpanFields>() {
+            values.record(fields);
+        }
+    }
+
     fn on_event(
         &self,
         event: &tracing::Event<'_>,
-        _ctx: tracing_subscriber::layer::Context<'_, S>,
+        ctx: tracing_subscriber::layer::Context<'_, S>,
     ) {
-        use std::fmt::Write;
-        
-        let mut message = String::new();
-        let meta = event.metadata();
-        
-        // Format: [LEVEL] target: message
-        let _ = write!(message, "[{}] {}: ", meta.level(), meta.target());
-        
-        struct Visitor<'a>(&'a mut String);
-        impl tracing::field::Visit for Visitor<'_> {
-            fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
-                if field.name() == "message" {
-                    let _ = write!(self.0, "{:?}", value);
-                } else {
-                    let _ = write!(self.0, " {}={:?}", field.name(), value);
-                }
+        let spans: Vec<SpanContext> = ctx.event_scope(event)
+            .map(|scope| scope.from_root().map(|span| SpanContext {
+                name: span.name(),
+                fields: span.extensions().get::<SpanFields>()
+                    .map(|fields| fields.0.iter().map(|f| (f.name, f.text.clone(), f.json.clone())).collect())
+                    .unwrap_or_default(),
+            }).collect())
+            .unwrap_or_default();
+        let line = match self.format {
+            LogFormat::Text => format_text(event, &spans),
+            LogFormat::Json => format_json(event, &spans),
+        };
+        self.buffer.push(*event.metadata().level(), line);
+    }
+}
+
+/// Format: [LEVEL] target: span{key=value}: message key=value...
+fn format_text(event: &tracing::Event<'_>, spans: &[SpanContext]) -> String {
+    use std::fmt::Write;
+
+    let mut message = String::new();
+    let meta = event.metadata();
+    let _ = write!(message, "[{}] {}: ", meta.level(), meta.target());
+    for span in spans {
+        let fields: Vec<String> = span.fields.iter().map(|(name, text, _)| format!("{}={}", name, text)).collect();
+        let _ = write!(message, "{}{{{}}}: ", span.name, fields.join(" "));
+    }
+
+    struct Visitor<'a>(&'a mut String);
+    impl tracing::field::Visit for Visitor<'_> {
+        fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+            if field.name() == "message" {
+                let _ = write!(self.0, "{:?}", value);
+            } else {
+                let _ = write!(self.0, " {}={:?}", field.name(), value);
             }
         }
-        
-        event.record(&mut Visitor(&mut message));
-        self.buffer.push(message);
     }
+
+    event.record(&mut Visitor(&mut message));
+    message
+}
+
+/// One JSON object: {"timestamp", "level", "target", "message", "fields": {...}, "spans": [...]}
+fn format_json(event: &tracing::Event<'_>, spans: &[SpanContext]) -> String {
+    use serde_json::{Map, Value};
+
+    #[derive(Default)]
+    struct Visitor {
+        message: String,
+        fields: Map<String, Value>,
+    }
+    impl Visitor {
+        fn record(&mut self, field: &tracing::field::Field, value: Value) {
+            if field.name() == "message" {
+                self.message = match value {
+                    Value::String(s) => s,
+                    other => other.to_string(),
+                };
+            } else {
+                self.fields.insert(field.name().to_string(), value);
+            }
+        }
+    }
+    impl tracing::field::Visit for Visitor {
+        fn record_str(&mut self, field: &tracing::field::Field, value: &str) {
+            self.record(field, value.into());
+        }
+        fn record_i64(&mut self, field: &tracing::field::Field, value: i64) {
+            self.record(field, value.into());
+        }
+        fn record_u64(&mut self, field: &tracing::field::Field, value: u64) {
+            self.record(field, value.into());
+        }
+        fn record_bool(&mut self, field: &tracing::field::Field, value: bool) {
+            self.record(field, value.into());
+        }
+        fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+            self.record(field, format!("{:?}", value).into());
+        }
+    }
+
+    let mut visitor = Visitor::default();
+    event.record(&mut visitor);
+    // Like tracing-subscriber's JSON output: each enclosing span as {"name", ...fields}
+    let spans: Vec<Value> = spans.iter().map(|span| {
+        let mut object = Map::new();
+        object.insert("name".to_string(), span.name.into());
+        for (name, _, json) in &span.fields {
+            object.insert(name.to_string(), json.clone());
+        }
+        Value::Object(object)
+    }).collect();
+    let meta = event.metadata();
+    json_line(meta.level(), meta.target(), visitor.message, visitor.fields, spans)
+}
+
+/// The JSON line for one entry, stamped with the current time
+fn json_line(
+    level: &tracing::Level,
+    target: &str,
+    message: String,
+    fields: serde_json::Map<String, serde_json::Value>,
+    spans: Vec<serde_json::Value>,
+) -> String {
+    serde_json::json!({
+        "timestamp": chrono::Local::now().to_rfc3339(),
+        "level": level.to_string(),
+        "target": target,
+        "message": message,
+        "fields": fields,
+        "spans": spans,
+    }).to_string()
 }
@@ -1,23 +1,73 @@
 use actix_web::{get, post, web, App, HttpResponse, HttpServer, Responder};
 use askama::Template;
 use crate::config::AppConfig;
-use crate::smpp::session::SessionManager;
-use crate::smpp::queue::{MessageQueue, MoMessageQueue, MoMessage};
+use crate::smpp::audit::{AuditEvent, AuditRecord};
+use crate::smpp::encoding::{decode_short_message, encode_text, MAX_SHORT_MESSAGE_LEN};
+use crate::smpp::metrics::Metrics;
+use crate::smpp::mo_service::create_deliver_sm;
+use crate::smpp::server::{queue_submit, SubmitOutcome, SubmitReply};
+use crate::smpp::session::{BindType, Session, SessionManager};
+use crate::smpp::queue::{MessageFilter, MessageQueue, MoCsvStatus, MoMessageQueue, MoMessage, QueuedMessage};
+use rusmpp::{Command, CommandStatus, Pdu};
+use rusmpp::pdus::{AlertNotification, SubmitSm};
+use rusmpp::tlvs::MessageSubmissionRequestTlvValue;
+use rusmpp::types::{AnyOctetString, COctetString, OctetString};
+use rusmpp::values::{DataCoding, EsmClass, MessagePayload, MsAvailabilityStatus, Npi, RegisteredDelivery, ServiceType, Ton};
+use std::str::FromStr;
 use std::sync::Arc;
 use serde::{Deserialize, Serialize};
 
 pub mod utils;
 pub mod logs;
+pub mod auth;
 
 pub use logs::{LogBuffer, LogBufferLayer};
 
+#[cfg(test)]
+mod web_tests;
+
 /// Shared application state
 pub struct AppState {
     pub config: Arc<AppConfig>,
+    /// `--config` file the config was loaded from, re-read on reload
+    pub config_path: Option<std::path::PathBuf>,
     pub session_manager: Arc<SessionManager>,
     pub message_queue: Arc<MessageQueue>,
     pub mo_queue: Arc<MoMessageQueue>,
     pub log_buffer: Arc<LogBuffer>,
+    pub metrics: Arc<Metrics>,
+}
+
+impl AppState {
+    /// The config in effect, including any runtime reload
+    pub fn live_config(&self) -> Arc<AppConfig> {
+        self.session_manager.live_config(&self.config)
+    }
+
+    /// Validate `config` and make it the live one, re-seeding the runtime settings main()
+    /// derives from it. Returns the settings that differ but only apply after a restart.
+    pub fn apply_config(&self, config: AppConfig) -> Result<Vec<&'static str>, String> {
+        config.smpp.validate()?;
+        config.lifecycle.validate()?;
+        let current = self.live_config();
+        let restart_required: Vec<&'static str> = [
+            ("server.host", current.server.host != config.server.host),
+            ("server.port", current.server.port != config.server.port),
+            ("smpp.host", current.smpp.host != config.smpp.host),
+            ("smpp.port", current.smpp.listen_ports() != config.smpp.listen_ports()),
+            ("smpp.version", current.smpp.version != config.smpp.version),
+            ("smpp.message_id_format", current.smpp.message_id_format != config.smpp.message_id_format),
+            ("smpp.tls", current.smpp.tls.enabled != config.smpp.tls.enabled || current.smpp.tls.port != config.smpp.tls.port),
+            ("log", current.log.level != config.log.level || current.log.format != config.log.format),
+            ("audit.file_path", current.audit.file_path != config.audit.file_path),
+        ].into_iter().filter_map(|(name, changed)| changed.then_some(name)).collect();
+
+        self.session_manager.set_force_bind_failure(config.smpp.force_bind_failure);
+        self.message_queue.set_dedup_window_ms(config.smpp.dedup_window_ms);
+        self.mo_queue.set_park_capacity(config.mo_service.max_parked);
+        self.session_manager.set_live_config(config);
+        Ok(restart_required)
+    }
 }
 
 #[derive(Serialize)]
@@ -26,6 +76,64 @@ struct MessageDisplay {
     source_addr: String,
     dest_addr: String,
     content: String,
+    service_type: String,
+    esm_class: EsmClassDisplay,
+}
+
+/// The esm_class bits of a submit_sm, decoded for display
+#[derive(Serialize)]
+struct EsmClassDisplay {
+    raw: String,
+    messaging_mode: &'static str,
+    message_type: &'static str,
+    udhi: bool,
+    reply_path: bool,
+}
+
+impl EsmClassDisplay {
+    fn new(esm_class: u8) -> Self {
+        let messaging_mode = match esm_class & 0x03 {
+            0x01 => "datagram",
+            0x02 => "forward",
+            0x03 => "store and forward",
+            _ => "default",
+        };
+        let message_type = match esm_class & 0x3C {
+            0x00 => "default",
+            0x08 => "delivery ack",
+            0x10 => "manual/user ack",
+            _ => "reserved",
+        };
+        Self {
+            raw: format!("0x{:02X}", esm_class),
+            messaging_mode,
+            message_type,
+            udhi: esm_class & 0x40 != 0,
+            reply_path: esm_class & 0x80 != 0,
+        }
+    }
+
+    /// e.g. "0x43 store and forward, UDHI"; default mode and type are left out
+    fn summary(&self) -> String {
+        let mut flags = vec![];
+        if self.messaging_mode != "default" {
+            flags.push(self.messaging_mode);
+        }
+        if self.message_type != "default" {
+            flags.push(self.message_type);
+        }
+        if self.udhi {
+            flags.push("UDHI");
+        }
+        if self.reply_path {
+            flags.push("reply path");
+        }
+        if flags.is_empty() {
+            self.raw.clone()
+        } else {
+            format!("{} {}", self.raw, flags.join(", "))
+        }
+    }
 }
 
 #[derive(Serialize)]
@@ -34,12 +142,18 @@ struct SessionDisplay {
     system_id: String,
     bind_type: String,
     addr: String,
+    submit_count: u64,
+    deliver_count: u64,
+    connected_at: String,
+    /// Time since bind, e.g. "1h 02m 03s"
+    age: String,
+    age_secs: u64,
 }
 
 #[derive(Template)]
 #[template(path = "dashboard.html")]
 struct DashboardTemplate {
-    smpp_port: u16,
+    smpp_ports: String,
     smpp_version: String,
     system_id: String,
     session_count: usize,
@@ -54,6 +168,32 @@ struct InjectMoRequest {
     source: String,
     dest: String,
     message: String,
+    /// data_coding for the deliver_sm
+    #[serde(default)]
+    data_coding: Option<u8>,
+    /// `message` is hex-encoded binary content
+    #[serde(default)]
+    hex: bool,
+}
+
+impl InjectMoRequest {
+    fn to_mo_message(&self) -> Result<MoMessage, String> {
+        let short_message = if self.hex {
+            let digits = self.message.trim_start_matches("0x");
+            hex::decode(digits).map_err(|e| format!("invalid hex message: {}", e))?;
+            format!("0x{}", digits)
+        } else {
+            self.message.clone()
+        };
+        Ok(MoMessage {
+            source_addr: self.source.clone(),
+            dest_addr: self.dest.clone(),
+            short_message,
+            data_coding: self.data_coding,
+            source_ton: None,
+            source_npi: None,
+        })
+    }
 }
 
 #[get("/health")]
@@ -61,6 +201,34 @@ async fn health_check() -> impl Responder {
     HttpResponse::Ok().body("OK")
 }
 
+/// Readiness for probes: 200 once the SMPP listeners are accepting connections, 503 before
+#[get("/ready")]
+async fn readiness(data: web::Data<AppState>) -> impl Responder {
+    let listener_ready = data.session_manager.listener_ready();
+    let body = serde_json::json!({
+        "ready": listener_ready,
+        "smpp_listener": listener_ready,
+        "active_sessions": data.session_manager.count(),
+        "pending_dr": data.message_queue.pending_dr_count(),
+        "mo_service_running": data.mo_queue.is_running(),
+    });
+    if listener_ready {
+        HttpResponse::Ok().json(body)
+    } else {
+        HttpResponse::ServiceUnavailable().json(body)
+    }
+}
+
+/// Compact session age: "12s", "3m 05s", "1h 02m 03s"
+fn format_age(age: std::time::Duration) -> String {
+    let secs = age.as_secs();
+    match (secs / 3600, secs % 3600 / 60, secs % 60) {
+        (0, 0, s) => format!("{}s", s),
+        (0, m, s) => format!("{}m {:02}s", m, s),
+        (h, m, s) => format!("{}h {:02}m {:02}s", h, m, s),
+    }
+}
+
 fn get_display_data(data: &web::Data<AppState>) -> (Vec<SessionDisplay>, Vec<MessageDisplay>) {
     let sessions: Vec<SessionDisplay> = data.session_manager.get_all_sessions()
         .into_iter()
@@ -69,15 +237,23 @@ fn get_display_data(data: &web::Data<AppState>) -> (Vec<SessionDisplay>, Vec<Mes
             system_id: s.system_id,
             bind_type: format!("{:?}", s.bind_type),
             addr: s.addr.to_string(),
+            submit_count: s.submit_count.load(std::sync::atomic::Ordering::Relaxed),
+            deliver_count: s.deliver_count.load(std::sync::atomic::Ordering::Relaxed),
+            connected_at: s.connected_wall.to_rfc3339(),
+            age: format_age(s.connected_at.elapsed()),
+            age_secs: s.connected_at.elapsed().as_secs(),
         })
         .collect();
+    let gsm7_packing = data.live_config().smpp.gsm7_packing;
     let messages: Vec<MessageDisplay> = data.message_queue.get_recent_messages()
         .into_iter()
         .map(|m| MessageDisplay {
             message_id: m.message_id,
             source_addr: m.source_addr,
             dest_addr: m.dest_addr,
-            content: String::from_utf8_lossy(&m.short_message).to_string(),
+            content: decode_short_message(&m.short_message, m.data_coding, gsm7_packing),
+            service_type: m.service_type,
+            esm_class: EsmClassDisplay::new(m.esm_class),
         })
         .collect();
     (sessions, messages)
@@ -88,7 +264,7 @@ async fn dashboard(data: web::Data<AppState>) -> impl Responder {
     let (sessions, messages) = get_display_data(&data);
 
     let template = DashboardTemplate {
-        smpp_port: data.config.smpp.port,
+        smpp_ports: data.config.smpp.listen_ports().iter().map(u16::to_string).collect::<Vec<_>>().join(", :"),
         smpp_version: data.config.smpp.version.clone(),
         system_id: data.config.smpp.system_id.clone(),
         session_count: sessions.len(),
@@ -131,6 +307,78 @@ async fn partials_stats(data: web::Data<AppState>) -> impl Responder {
     }
 }
 
+/// Final-state counts with each state's share of all finished messages
+#[derive(Serialize)]
+struct FinalStateDisplay {
+    state: String,
+    count: u64,
+    percent: String,
+}
+
+fn final_state_display(data: &web::Data<AppState>) -> Vec<FinalStateDisplay> {
+    let counts = data.message_queue.final_state_counts();
+    let total: u64 = counts.iter().map(|(_, count)| count).sum();
+    counts.into_iter()
+        .map(|(state, count)| FinalStateDisplay {
+            state: format!("{:?}", state),
+            count,
+            percent: if total == 0 { "-".to_string() } else { format!("{:.1}%", count as f64 * 100.0 / total as f64) },
+        })
+        .collect()
+}
+
+#[get("/partials/final-states")]
+async fn partials_final_states(data: web::Data<AppState>) -> impl Responder {
+    #[derive(Template)]
+    #[template(path = "partials/final_states.html")]
+    struct T { states: Vec<FinalStateDisplay> }
+
+    match (T { states: final_state_display(&data) }).render() {
+        Ok(html) => HttpResponse::Ok().content_type("text/html").body(html),
+        Err(e) => {
+            tracing::error!("Template error: {}", e);
+            HttpResponse::InternalServerError().body("Template error")
+        }
+    }
+}
+
+/// Submit and delivery receipt rates, from the per-second rings on `Metrics`
+#[derive(Serialize)]
+struct ThroughputDisplay {
+    /// Accepted in the last complete second
+    submit_per_sec: u64,
+    /// Mean per second over the last minute
+    submit_avg_per_sec: f64,
+    receipt_per_sec: u64,
+    receipt_avg_per_sec: f64,
+}
+
+impl ThroughputDisplay {
+    fn new(m: &Metrics) -> Self {
+        Self {
+            submit_per_sec: m.submit_rate.last_second(),
+            submit_avg_per_sec: (m.submit_rate.average() * 10.0).round() / 10.0,
+            receipt_per_sec: m.receipt_rate.last_second(),
+            receipt_avg_per_sec: (m.receipt_rate.average() * 10.0).round() / 10.0,
+        }
+    }
+}
+
+#[get("/partials/throughput")]
+async fn partials_throughput(data: web::Data<AppState>) -> impl Responder {
+    #[derive(Template)]
+    #[template(path = "partials/throughput.html")]
+    struct T { throughput: ThroughputDisplay }
+
+    match (T { throughput: ThroughputDisplay::new(&data.metrics) }).render() {
+        Ok(html) => HttpResponse::Ok().content_type("text/html").body(html),
+        Err(e) => {
+            tracing::error!("Template error: {}", e);
+            HttpResponse::InternalServerError().body("Template error")
+        }
+    }
+}
+
 #[get("/partials/sessions")]
 async fn partials_sessions(data: web::Data<AppState>) -> impl Responder {
     let (sessions, _) = get_display_data(&data);
@@ -165,37 +413,477 @@ async fn partials_messages(data: web::Data<AppState>) -> impl Responder {
     }
 }
 
+/// MO CSV injector status and recent parse errors
+#[derive(Template)]
+#[template(path = "partials/mo_status.html")]
+struct MoStatusTemplate {
+    enabled: bool,
+    file_path: String,
+    parked_count: usize,
+    csv: MoCsvStatus,
+}
+
+impl MoStatusTemplate {
+    fn new(data: &AppState) -> Self {
+        Self {
+            enabled: data.config.mo_service.enabled,
+            file_path: data.live_config().mo_service.file_path.clone(),
+            parked_count: data.mo_queue.parked_count(),
+            csv: data.mo_queue.csv_status(),
+        }
+    }
+}
+
+#[get("/partials/mo-status")]
+async fn partials_mo_status(data: web::Data<AppState>) -> impl Responder {
+    match MoStatusTemplate::new(&data).render() {
+        Ok(html) => HttpResponse::Ok().content_type("text/html").body(html),
+        Err(e) => {
+            tracing::error!("Template error: {}", e);
+            HttpResponse::InternalServerError().body("Template error")
+        }
+    }
+}
+
+/// Whether the MO CSV file was found, rows dispatched in the last pass and recent parse errors
+#[get("/api/mo/status")]
+async fn mo_status(data: web::Data<AppState>) -> impl Responder {
+    let status = MoStatusTemplate::new(&data);
+    HttpResponse::Ok().json(serde_json::json!({
+        "enabled": status.enabled,
+        "file_path": status.file_path,
+        "loop": data.config.mo_service.loop_file,
+        "parked_count": status.parked_count,
+        "csv": status.csv,
+    }))
+}
+
+/// Prometheus text exposition
+#[get("/metrics")]
+async fn metrics(data: web::Data<AppState>) -> impl Responder {
+    data.metrics.sessions_active.set(data.session_manager.count() as i64);
+    data.metrics.pending_dr.set(data.message_queue.pending_dr_count() as i64);
+    HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(data.metrics.render())
+}
+
 #[get("/api/stats")]
 async fn get_stats(data: web::Data<AppState>) -> impl Responder {
-    let sessions = data.session_manager.get_all_sessions();
-    let messages: Vec<MessageDisplay> = data.message_queue.get_recent_messages()
-        .into_iter()
-        .map(|m| MessageDisplay {
-            message_id: m.message_id,
-            source_addr: m.source_addr,
-            dest_addr: m.dest_addr,
-            content: String::from_utf8_lossy(&m.short_message).to_string(),
-        })
-        .collect();
+    let (sessions, messages) = get_display_data(&data);
 
     let stats = serde_json::json!({
         "session_count": sessions.len(),
         "message_count": messages.len(),
         "pending_dr_count": data.message_queue.pending_dr_count(),
+        "parked_mo_count": data.mo_queue.parked_count(),
+        "final_states": data.message_queue.final_state_counts().into_iter()
+            .map(|(state, count)| (format!("{:?}", state), serde_json::Value::from(count)))
+            .collect::<serde_json::Map<_, _>>(),
+        "throughput": ThroughputDisplay::new(&data.metrics),
         "sessions": sessions,
         "messages": messages,
     });
     HttpResponse::Ok().json(stats)
 }
 
+#[derive(Deserialize)]
+struct MessagesQuery {
+    /// Only return messages carrying this TLV tag (decimal or 0x-prefixed hex)
+    tlv: Option<String>,
+    /// Substring filters on the source and destination addresses
+    source: Option<String>,
+    dest: Option<String>,
+    #[serde(default = "default_page_limit")]
+    limit: usize,
+    #[serde(default)]
+    offset: usize,
+}
+
+fn default_page_limit() -> usize {
+    50
+}
+
+fn parse_tlv_tag(s: &str) -> Option<u16> {
+    let s = s.trim();
+    match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u16::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok(),
+    }
+}
+
+fn message_detail(m: &QueuedMessage, config: &AppConfig) -> serde_json::Value {
+    serde_json::json!({
+        "message_id": m.message_id,
+        "source_addr": m.source_addr,
+        "dest_addr": m.dest_addr,
+        "content": decode_short_message(&m.short_message, m.data_coding, config.smpp.gsm7_packing),
+        "session_id": m.session_id,
+        "service_type": m.service_type,
+        "esm_class": EsmClassDisplay::new(m.esm_class),
+        "tlvs": m.tlvs.iter().map(|t| serde_json::json!({
+            "tag": format!("0x{:04X}", t.tag),
+            "name": t.name,
+            "value": t.value,
+        })).collect::<Vec<_>>(),
+    })
+}
+
+/// A page of stored messages, newest first
+#[get("/api/messages")]
+async fn list_messages(data: web::Data<AppState>, query: web::Query<MessagesQuery>) -> impl Responder {
+    let tlv = match &query.tlv {
+        Some(tag) => match parse_tlv_tag(tag) {
+            Some(tag) => Some(tag),
+            None => return HttpResponse::BadRequest().body("Invalid TLV tag"),
+        },
+        None => None,
+    };
+    let filter = MessageFilter {
+        source: query.source.clone(),
+        dest: query.dest.clone(),
+        tlv,
+    };
+    let (messages, total) = data.message_queue.query(&filter, query.limit, query.offset);
+    let config = data.live_config();
+    HttpResponse::Ok().json(serde_json::json!({
+        "total": total,
+        "offset": query.offset,
+        "limit": query.limit,
+        "messages": messages.iter().map(|m| message_detail(m, &config)).collect::<Vec<_>>(),
+    }))
+}
+
+/// A single message (by provisional or final ID) with its decoded text and DR state
+#[get("/api/messages/{id}")]
+async fn get_message(data: web::Data<AppState>, path: web::Path<String>) -> impl Responder {
+    let Some(m) = data.message_queue.find_message(&path.into_inner()) else {
+        return HttpResponse::NotFound().body("Message not found");
+    };
+    let mut detail = message_detail(&m, &data.live_config());
+    detail["data_coding"] = m.data_coding.into();
+    detail["final_message_id"] = serde_json::json!(m.final_message_id);
+    detail["pending_dr"] = data.message_queue.is_pending_dr(&m.message_id).into();
+    detail["final_state"] = serde_json::json!(m.final_state);
+    detail["receipt_acked"] = m.receipt_acked.into();
+    detail["submitted_at"] = m.submitted_wall.to_rfc3339().into();
+    detail["done_at"] = serde_json::json!(m.done_wall.map(|t| t.to_rfc3339()));
+    HttpResponse::Ok().json(detail)
+}
+
+#[derive(Deserialize)]
+struct DedupUpdate {
+    window_ms: u64,
+}
+
+/// Current dedup window and recent dedup hits
+#[get("/api/dedup")]
+async fn get_dedup(data: web::Data<AppState>) -> impl Responder {
+    HttpResponse::Ok().json(serde_json::json!({
+        "window_ms": data.message_queue.dedup_window_ms(),
+        "hits": data.message_queue.get_dedup_hits(),
+    }))
+}
+
+/// Change the dedup window at runtime (0 turns dedup off)
+#[post("/api/dedup")]
+async fn set_dedup(data: web::Data<AppState>, body: web::Json<DedupUpdate>) -> impl Responder {
+    tracing::info!("Dedup window set to {}ms", body.window_ms);
+    data.message_queue.set_dedup_window_ms(body.window_ms);
+    HttpResponse::Ok().json(serde_json::json!({ "window_ms": body.window_ms }))
+}
+
+#[derive(Deserialize)]
+struct ForceBindFailureUpdate {
+    enabled: bool,
+}
+
+/// Whether binds are currently forced to fail
+#[get("/api/config/force-bind-failure")]
+async fn get_force_bind_failure(data: web::Data<AppState>) -> impl Responder {
+    HttpResponse::Ok().json(serde_json::json!({ "enabled": data.session_manager.force_bind_failure() }))
+}
+
+/// Make every bind fail with ESME_RBINDFAIL (or stop doing so) without a restart
+#[post("/api/config/force-bind-failure")]
+async fn set_force_bind_failure(data: web::Data<AppState>, body: web::Json<ForceBindFailureUpdate>) -> impl Responder {
+    tracing::info!("Forced bind failure {}", if body.enabled { "enabled" } else { "disabled" });
+    data.session_manager.set_force_bind_failure(body.enabled);
+    HttpResponse::Ok().json(serde_json::json!({ "enabled": body.enabled }))
+}
+
+/// Re-read the config files and environment and swap the result in without dropping sessions
+#[post("/api/config/reload")]
+async fn reload_config(data: web::Data<AppState>) -> impl Responder {
+    let config = match AppConfig::new(data.config_path.as_deref()) {
+        Ok(config) => config,
+        Err(e) => return HttpResponse::BadRequest().json(serde_json::json!({ "reloaded": false, "error": e.to_string() })),
+    };
+    match data.apply_config(config) {
+        Ok(restart_required) => {
+            tracing::info!("Configuration reloaded (restart required for: {:?})", restart_required);
+            HttpResponse::Ok().json(serde_json::json!({ "reloaded": true, "restart_required": restart_required }))
+        }
+        Err(e) => {
+            tracing::warn!("Configuration reload rejected: {}", e);
+            HttpResponse::BadRequest().json(serde_json::json!({ "reloaded": false, "error": e }))
+        }
+    }
+}
+
+/// Lifecycle parameters adjustable at runtime; fields left out keep their current value
+#[derive(Deserialize)]
+struct LifecycleUpdate {
+    percent_delivered: Option<u8>,
+    percent_undeliverable: Option<u8>,
+    percent_accepted: Option<u8>,
+    percent_rejected: Option<u8>,
+    percent_deleted: Option<u8>,
+    percent_unknown: Option<u8>,
+    max_time_enroute_ms: Option<u64>,
+}
+
+fn lifecycle_json(lifecycle: &crate::config::LifecycleConfig) -> serde_json::Value {
+    serde_json::json!({
+        "percent_delivered": lifecycle.percent_delivered,
+        "percent_undeliverable": lifecycle.percent_undeliverable,
+        "percent_accepted": lifecycle.percent_accepted,
+        "percent_rejected": lifecycle.percent_rejected,
+        "percent_deleted": lifecycle.percent_deleted,
+        "percent_unknown": lifecycle.percent_unknown,
+        "max_time_enroute_ms": lifecycle.max_time_enroute_ms,
+    })
+}
+
+/// Current lifecycle odds and time en route
+#[get("/api/lifecycle")]
+async fn get_lifecycle(data: web::Data<AppState>) -> impl Responder {
+    HttpResponse::Ok().json(lifecycle_json(&data.live_config().lifecycle))
+}
+
+/// Change the lifecycle odds or time en route mid-scenario; the next lifecycle tick uses them
+#[post("/api/lifecycle")]
+async fn set_lifecycle(data: web::Data<AppState>, body: web::Json<LifecycleUpdate>) -> impl Responder {
+    // Only the lifecycle section changes; runtime toggles seeded from the rest are left alone
+    let updated = data.session_manager.update_live_config(&data.config, |config| {
+        let mut lifecycle = config.lifecycle.clone();
+        for (field, value) in [
+            (&mut lifecycle.percent_delivered, body.percent_delivered),
+            (&mut lifecycle.percent_undeliverable, body.percent_undeliverable),
+            (&mut lifecycle.percent_accepted, body.percent_accepted),
+            (&mut lifecycle.percent_rejected, body.percent_rejected),
+            (&mut lifecycle.percent_deleted, body.percent_deleted),
+            (&mut lifecycle.percent_unknown, body.percent_unknown),
+        ] {
+            if let Some(value) = value {
+                *field = value;
+            }
+        }
+        if let Some(ms) = body.max_time_enroute_ms {
+            lifecycle.max_time_enroute_ms = ms;
+        }
+        lifecycle.validate()?;
+        let updated = lifecycle_json(&lifecycle);
+        config.lifecycle = lifecycle;
+        Ok(updated)
+    });
+    match updated {
+        Ok(updated) => {
+            tracing::info!("Lifecycle updated: {}", updated);
+            HttpResponse::Ok().json(updated)
+        }
+        Err(e) => HttpResponse::BadRequest().json(serde_json::json!({ "error": e })),
+    }
+}
+
+#[derive(Deserialize)]
+struct ResetQuery {
+    /// Also restart message IDs from 1
+    #[serde(default)]
+    reset_counter: bool,
+}
+
+/// Clear stored messages between test runs
+#[post("/api/reset")]
+async fn reset(data: web::Data<AppState>, query: web::Query<ResetQuery>) -> impl Responder {
+    data.message_queue.clear();
+    if query.reset_counter {
+        data.message_queue.reset_counter();
+    }
+    tracing::info!("Message store cleared (reset_counter: {})", query.reset_counter);
+    HttpResponse::Ok().json(serde_json::json!({ "cleared": true, "reset_counter": query.reset_counter }))
+}
+
+/// A bound session as returned by `/api/sessions`; unlike `Session` it carries no channel handles
+#[derive(Serialize)]
+struct SessionInfo {
+    id: String,
+    system_id: String,
+    bind_type: BindType,
+    addr: String,
+    address_range: Option<String>,
+    /// Negotiated interface_version, e.g. "0x34"
+    interface_version: Option<String>,
+    /// Verified client certificate CN, for mTLS connections
+    client_identity: Option<String>,
+    connected_at: chrono::DateTime<chrono::Local>,
+    age_secs: u64,
+    submit_count: u64,
+    deliver_count: u64,
+    /// Accepted submits whose response has not been written yet
+    window_in_use: usize,
+    /// Delivery receipts waiting for their deliver_sm_resp
+    unacked_receipts: usize,
+}
+
+impl SessionInfo {
+    fn new(session: Session, session_manager: &SessionManager) -> Self {
+        Self {
+            client_identity: session_manager.client_identity(&session.addr),
+            interface_version: session.interface_version.map(|v| format!("{:#04x}", u8::from(v))),
+            age_secs: session.connected_at.elapsed().as_secs(),
+            submit_count: session.submit_count.load(std::sync::atomic::Ordering::Relaxed),
+            deliver_count: session.deliver_count.load(std::sync::atomic::Ordering::Relaxed),
+            window_in_use: session.window_in_use(),
+            unacked_receipts: session.unacked_receipts.len(),
+            addr: session.addr.to_string(),
+            connected_at: session.connected_wall,
+            address_range: session.address_range,
+            bind_type: session.bind_type,
+            system_id: session.system_id,
+            id: session.id,
+        }
+    }
+}
+
+/// Every bound session, oldest first
+#[get("/api/sessions")]
+async fn list_sessions(data: web::Data<AppState>) -> impl Responder {
+    let mut sessions = data.session_manager.get_all_sessions();
+    sessions.sort_by_key(|s| s.connected_at);
+    let sessions: Vec<SessionInfo> = sessions.into_iter()
+        .map(|s| SessionInfo::new(s, &data.session_manager))
+        .collect();
+    HttpResponse::Ok().json(serde_json::json!({ "count": sessions.len(), "sessions": sessions }))
+}
+
+/// Drop a bound session's connection, e.g. to exercise client reconnect logic
+#[post("/api/sessions/{id}/disconnect")]
+async fn disconnect_session(data: web::Data<AppState>, path: web::Path<String>) -> impl Responder {
+    let session_id = path.into_inner();
+    if data.session_manager.disconnect_session(&session_id) {
+        tracing::info!("Session {} disconnected via API", session_id);
+        HttpResponse::Ok().json(serde_json::json!({ "disconnected": session_id }))
+    } else {
+        HttpResponse::NotFound().body("Session not found")
+    }
+}
+
+#[derive(Deserialize)]
+struct AlertNotificationRequest {
+    /// Subscriber that became available
+    source_addr: String,
+    /// ESME address that asked to be alerted; picks the session like an MO dest_addr
+    esme_addr: String,
+    /// ms_availability_status TLV (0 available, 1 denied, 2 unavailable)
+    #[serde(default)]
+    ms_availability_status: Option<u8>,
+}
+
+/// Build an alert_notification PDU; the session assigns its sequence number when sending
+fn create_alert_notification(req: &AlertNotificationRequest) -> Result<Command, String> {
+    let source_addr = COctetString::from_str(&req.source_addr).map_err(|e| format!("invalid source_addr: {}", e))?;
+    let esme_addr = COctetString::from_str(&req.esme_addr).map_err(|e| format!("invalid esme_addr: {}", e))?;
+    Ok(Command::builder()
+        .status(CommandStatus::EsmeRok)
+        .sequence_number(0)
+        .pdu(Pdu::AlertNotification(AlertNotification::new(
+            Ton::Unknown,
+            Npi::Unknown,
+            source_addr,
+            Ton::Unknown,
+            Npi::Unknown,
+            esme_addr,
+            req.ms_availability_status.map(MsAvailabilityStatus::from),
+        ))))
+}
+
+/// Send an alert_notification through the receiver/transceiver session matching `esme_addr`
+#[post("/api/alert-notification")]
+async fn alert_notification(data: web::Data<AppState>, body: web::Json<AlertNotificationRequest>) -> impl Responder {
+    let command = match create_alert_notification(&body) {
+        Ok(command) => command,
+        Err(e) => return HttpResponse::BadRequest().json(serde_json::json!({ "sent": false, "error": e })),
+    };
+    let Some(session) = data.session_manager.find_subscriber(&body.esme_addr) else {
+        tracing::warn!("No suitable session found for alert_notification to {}", body.esme_addr);
+        return HttpResponse::NotFound().json(serde_json::json!({ "sent": false, "error": "No eligible session bound" }));
+    };
+    tracing::info!("Sending alert_notification for {} to {} via session {}", body.source_addr, body.esme_addr, session.id);
+    if let Err(e) = session.send_command(command).await {
+        tracing::error!("Failed to send alert_notification to session {}: {}", session.id, e);
+        return HttpResponse::InternalServerError().json(serde_json::json!({ "sent": false, "error": "Failed to send" }));
+    }
+    HttpResponse::Ok().json(serde_json::json!({ "sent": true, "session_id": session.id }))
+}
+
+/// Send a deliver_sm to one bound session, bypassing address_range routing
+#[post("/api/sessions/{id}/deliver")]
+async fn deliver_to_session(data: web::Data<AppState>, path: web::Path<String>, body: web::Json<InjectMoRequest>) -> impl Responder {
+    let session_id = path.into_inner();
+    let mo_msg = match body.to_mo_message() {
+        Ok(mo_msg) => mo_msg,
+        Err(e) => return HttpResponse::BadRequest().json(serde_json::json!({ "sent": false, "error": e })),
+    };
+    let Some(session) = data.session_manager.get_session(&session_id)
+        .filter(|session| session.bind_type != BindType::Transmitter) else {
+        return HttpResponse::NotFound().json(serde_json::json!({ "sent": false, "error": "No receiving session with that id" }));
+    };
+    let Some(command) = create_deliver_sm(&mo_msg, &data.live_config()) else {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "sent": false, "error": "Could not build deliver_sm" }));
+    };
+    tracing::info!("Delivering MO from {} to {} via session {} (API)", mo_msg.source_addr, mo_msg.dest_addr, session.id);
+    if let Err(e) = session.send_command(command).await {
+        tracing::error!("Failed to send MO to session {}: {}", session.id, e);
+        return HttpResponse::InternalServerError().json(serde_json::json!({ "sent": false, "error": "Failed to send" }));
+    }
+    session.record_deliver();
+    data.metrics.deliver_total.inc();
+    data.session_manager.audit(AuditRecord::new(AuditEvent::MoDeliverSm, &session.system_id, "", &mo_msg.source_addr, &mo_msg.dest_addr));
+    HttpResponse::Ok().json(serde_json::json!({ "sent": true, "session_id": session.id }))
+}
+
+#[derive(Deserialize)]
+struct CaptureQuery {
+    /// "hex" (default) or "pcap"
+    #[serde(default)]
+    format: Option<String>,
+}
+
+/// Raw PDUs exchanged on a session's connection, as a hex dump or a pcap file
+#[get("/api/sessions/{id}/capture")]
+async fn session_capture(data: web::Data<AppState>, path: web::Path<String>, query: web::Query<CaptureQuery>) -> impl Responder {
+    let session_id = path.into_inner();
+    let Some(capture) = data.session_manager.capture_for_session(&session_id) else {
+        return HttpResponse::NotFound().body("No capture for session (unknown session, or smpp.capture_pdus is off)");
+    };
+    match query.format.as_deref().unwrap_or("hex") {
+        "hex" => HttpResponse::Ok().content_type("text/plain; charset=utf-8").body(capture.hex_dump()),
+        "pcap" => HttpResponse::Ok()
+            .content_type("application/vnd.tcpdump.pcap")
+            .insert_header(("Content-Disposition", format!("attachment; filename=\"session-{}.pcap\"", session_id)))
+            .body(capture.to_pcap()),
+        other => HttpResponse::BadRequest().body(format!("unknown capture format: {}", other)),
+    }
+}
+
 #[post("/api/inject-mo")]
 async fn inject_mo(data: web::Data<AppState>, body: web::Form<InjectMoRequest>) -> impl Responder {
     tracing::info!("MO Injection: {} -> {}: {}", body.source, body.dest, body.message);
     
-    let mo_msg = MoMessage {
-         source_addr: body.source.clone(),
-         dest_addr: body.dest.clone(),
-         short_message: body.message.clone(),
+    let mo_msg = match body.to_mo_message() {
+        Ok(mo_msg) => mo_msg,
+        Err(e) => return HttpResponse::BadRequest().body(e),
     };
     
     if let Err(e) = data.mo_queue.inject(mo_msg).await {
@@ -208,10 +896,156 @@ async fn inject_mo(data: web::Data<AppState>, body: web::Form<InjectMoRequest>)
         .body("<div class=\"success\">✓ Message queued for delivery</div>")
 }
 
+#[derive(Deserialize)]
+struct SubmitRequest {
+    source: String,
+    dest: String,
+    message: String,
+    #[serde(default)]
+    data_coding: u8,
+    #[serde(default)]
+    registered_delivery: u8,
+    #[serde(default)]
+    esm_class: u8,
+    #[serde(default)]
+    service_type: String,
+    /// `message` is hex-encoded binary content (e.g. with a UDH when esm_class has UDHI set)
+    #[serde(default)]
+    hex: bool,
+    /// Bound session that receives the delivery receipt; without one the message still runs its lifecycle
+    #[serde(default)]
+    session_id: Option<String>,
+}
+
+impl SubmitRequest {
+    /// The submit_sm this request stands for, so it is queued exactly as one from a bound client
+    fn to_submit_sm(&self, config: &AppConfig) -> Result<SubmitSm, String> {
+        if self.source.is_empty() || self.dest.is_empty() {
+            return Err("source and dest are required".to_string());
+        }
+        let body = if self.hex {
+            hex::decode(self.message.trim_start_matches("0x")).map_err(|e| format!("invalid hex message: {}", e))?
+        } else {
+            encode_text(&self.message, self.data_coding, config.smpp.gsm7_packing)
+        };
+        let source_addr = COctetString::from_str(&self.source).map_err(|e| format!("invalid source: {}", e))?;
+        let destination_addr = COctetString::from_str(&self.dest).map_err(|e| format!("invalid dest: {}", e))?;
+        let service_type = COctetString::from_str(&self.service_type).map_err(|e| format!("invalid service_type: {}", e))?;
+        let submit = SubmitSm::builder()
+            .service_type(ServiceType::new(service_type))
+            .source_addr(source_addr)
+            .destination_addr(destination_addr)
+            .esm_class(EsmClass::from(self.esm_class))
+            .registered_delivery(RegisteredDelivery::from(self.registered_delivery))
+            .data_coding(DataCoding::from(self.data_coding));
+        // Bodies too long for short_message go in message_payload, as a client would send them
+        Ok(if body.len() <= MAX_SHORT_MESSAGE_LEN {
+            submit.short_message(OctetString::from_bytes(body.into()).unwrap_or_default())
+        } else {
+            submit.push_tlv(MessageSubmissionRequestTlvValue::MessagePayload(MessagePayload::new(AnyOctetString::from_vec(body))))
+        }.build())
+    }
+}
+
+/// Queue a submit as if a bound client had sent it, for replaying captured traffic.
+/// The message goes through the lifecycle like any other and its ID is returned for polling.
+#[post("/api/submit")]
+async fn submit_message(data: web::Data<AppState>, body: web::Json<SubmitRequest>) -> impl Responder {
+    let config = data.live_config();
+    let session = match body.session_id.as_deref() {
+        Some(session_id) => match data.session_manager.get_session(session_id) {
+            Some(session) => Some(session),
+            None => return HttpResponse::NotFound().json(serde_json::json!({ "queued": false, "error": "Session not found" })),
+        },
+        None => None,
+    };
+    let submit = match body.to_submit_sm(&config) {
+        Ok(submit) => submit,
+        Err(e) => return HttpResponse::BadRequest().json(serde_json::json!({ "queued": false, "error": e })),
+    };
+    tracing::info!("API submit: {} -> {}", body.source, body.dest);
+
+    // No submit_sm_resp is written for an API submit, so it holds no window slot
+    match queue_submit(&submit, session.as_ref(), &config, &data.session_manager, &data.message_queue, SubmitReply::NoResponse) {
+        SubmitOutcome::Queued(message_id) => HttpResponse::Ok().json(serde_json::json!({ "queued": true, "message_id": message_id })),
+        SubmitOutcome::Duplicate(original_id) => HttpResponse::Ok().json(serde_json::json!({ "queued": false, "duplicate": true, "message_id": original_id })),
+        SubmitOutcome::Dropped => HttpResponse::Ok().json(serde_json::json!({ "queued": false })),
+    }
+}
+
+/// JSON variant of `/api/inject-mo` for scripted test harnesses
+#[post("/api/inject-mo-json")]
+async fn inject_mo_json(data: web::Data<AppState>, body: web::Json<InjectMoRequest>) -> impl Responder {
+    tracing::info!("MO Injection (JSON): {} -> {}: {}", body.source, body.dest, body.message);
+
+    let mo_msg = match body.to_mo_message() {
+        Ok(mo_msg) => mo_msg,
+        Err(e) => return HttpResponse::BadRequest().json(serde_json::json!({ "queued": false, "error": e })),
+    };
+
+    if let Err(e) = data.mo_queue.inject(mo_msg).await {
+        tracing::error!("Failed to inject MO message: {}", e);
+        return HttpResponse::InternalServerError().json(serde_json::json!({ "queued": false, "error": "Failed to inject" }));
+    }
+
+    HttpResponse::Ok().json(serde_json::json!({ "queued": true }))
+}
+
+/// Queue a list of MO messages, paced by `mo_service.delivery_messages_per_minute` when set
+#[post("/api/inject-mo/batch")]
+async fn inject_mo_batch(data: web::Data<AppState>, body: web::Json<Vec<InjectMoRequest>>) -> impl Responder {
+    let rate = data.live_config().mo_service.delivery_messages_per_minute;
+    let period = (rate > 0).then(|| std::time::Duration::from_millis(60_000 / rate as u64));
+    tracing::info!("MO batch injection of {} messages", body.len());
+
+    let (mut queued, mut failed) = (0usize, 0usize);
+    for request in body.iter() {
+        let mo_msg = match request.to_mo_message() {
+            Ok(mo_msg) => mo_msg,
+            Err(e) => {
+                tracing::warn!("Skipping MO {} -> {}: {}", request.source, request.dest, e);
+                failed += 1;
+                continue;
+            }
+        };
+        if let Some(period) = period
+            && queued > 0 {
+            tokio::time::sleep(period).await;
+        }
+        match data.mo_queue.inject(mo_msg).await {
+            Ok(()) => queued += 1,
+            Err(e) => {
+                tracing::error!("Failed to inject MO message: {}", e);
+                failed += 1;
+            }
+        }
+    }
+
+    HttpResponse::Ok().json(serde_json::json!({ "queued": queued, "failed": failed }))
+}
+
+#[derive(Deserialize)]
+struct LogsQuery {
+    /// Only lines at or above this severity ("error", "warn", "info", "debug", "trace")
+    level: Option<String>,
+}
+
+impl LogsQuery {
+    fn min_level(&self) -> Result<Option<tracing::Level>, String> {
+        self.level.as_deref()
+            .map(|level| level.parse::<tracing::Level>().map_err(|_| format!("invalid log level: {}", level)))
+            .transpose()
+    }
+}
+
 /// Get recent logs as HTML partial (for initial load)
 #[get("/partials/logs")]
-async fn partials_logs(data: web::Data<AppState>) -> impl Responder {
-    let logs = data.log_buffer.get_all();
+async fn partials_logs(data: web::Data<AppState>, query: web::Query<LogsQuery>) -> impl Responder {
+    let min = match query.min_level() {
+        Ok(min) => min,
+        Err(e) => return HttpResponse::BadRequest().body(e),
+    };
+    let logs = data.log_buffer.get_at_least(min);
     let html: String = logs.iter()
         .rev()  // newest first
         .take(50)
@@ -228,15 +1062,22 @@ async fn partials_logs(data: web::Data<AppState>) -> impl Responder {
 
 /// SSE endpoint for real-time log streaming
 #[get("/api/logs/stream")]
-async fn logs_stream(data: web::Data<AppState>) -> impl Responder {
+async fn logs_stream(data: web::Data<AppState>, query: web::Query<LogsQuery>) -> impl Responder {
     use actix_web::http::header;
     use futures::StreamExt;
 
+    let min = match query.min_level() {
+        Ok(min) => min,
+        Err(e) => return HttpResponse::BadRequest().body(e),
+    };
     let mut rx = data.log_buffer.subscribe();
     
     let stream = async_stream::stream! {
-        while let Ok(line) = rx.recv().await {
-            let escaped = line.replace('\n', " ").replace('\r', "");
+        while let Some(line) = rx.recv().await {
+            if !line.at_least(min) {
+                continue;
+            }
+            let escaped = line.text.replace('\n', " ").replace('\r', "");
             yield Ok::<_, std::io::Error>(
                 actix_web::web::Bytes::from(format!("data: {}\n\n", escaped))
             );
@@ -249,8 +1090,46 @@ async fn logs_stream(data: web::Data<AppState>) -> impl Responder {
         .streaming(stream.boxed())
 }
 
+/// Register the dashboard and API routes
+fn routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(health_check)
+        .service(readiness)
+        .service(dashboard)
+        .service(partials_stats)
+        .service(partials_final_states)
+        .service(partials_throughput)
+        .service(partials_sessions)
+        .service(partials_messages)
+        .service(partials_mo_status)
+        .service(partials_logs)
+        .service(logs_stream)
+        .service(get_stats)
+        .service(metrics)
+        .service(list_messages)
+        .service(get_message)
+        .service(mo_status)
+        .service(get_dedup)
+        .service(set_dedup)
+        .service(get_force_bind_failure)
+        .service(set_force_bind_failure)
+        .service(reload_config)
+        .service(get_lifecycle)
+        .service(set_lifecycle)
+        .service(reset)
+        .service(list_sessions)
+        .service(disconnect_session)
+        .service(session_capture)
+        .service(deliver_to_session)
+        .service(inject_mo)
+        .service(inject_mo_json)
+        .service(submit_message)
+        .service(inject_mo_batch)
+        .service(alert_notification);
+}
+
 pub async fn start_web_server(
     config: Arc<AppConfig>,
+    config_path: Option<std::path::PathBuf>,
     session_manager: Arc<SessionManager>,
     message_queue: Arc<MessageQueue>,
     mo_queue: Arc<MoMessageQueue>,
@@ -260,26 +1139,22 @@ pub async fn start_web_server(
     
     let app_state = web::Data::new(AppState {
         config: config.clone(),
+        config_path,
+        metrics: session_manager.metrics().clone(),
         session_manager,
         message_queue,
         mo_queue,
         log_buffer,
     });
     
-    tracing::info!("Starting Web UI on {}:{}", server_config.host, server_config.port);
+    tracing::info!("Starting Web UI on {}:{}{}", server_config.host, server_config.port,
+        if server_config.auth.is_some() { " (authentication required)" } else { "" });
 
     HttpServer::new(move || {
         App::new()
             .app_data(app_state.clone())
-            .service(health_check)
-            .service(dashboard)
-            .service(partials_stats)
-            .service(partials_sessions)
-            .service(partials_messages)
-            .service(partials_logs)
-            .service(logs_stream)
-            .service(get_stats)
-            .service(inject_mo)
+            .wrap(actix_web::middleware::from_fn(auth::require_auth))
+            .configure(routes)
             .service(actix_files::Files::new("/static", "static").show_files_listing())
     })
     .bind((server_config.host.as_str(), server_config.port))?
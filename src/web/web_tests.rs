@@ -0,0 +1,1051 @@
+//! Unit tests for the web API
+//!
+//! Each test builds an `AppState` around fresh SMPP state and drives the routes
+//! through actix's test service.
+
+use super::*;
+use crate::config::{AuditConfig, LifecycleConfig, LogConfig, MoServiceConfig, ServerConfig, ShutdownConfig, SmppConfig};
+use crate::smpp::server::handle_command;
+use crate::smpp::session::{BindType, Session};
+use actix_web::test;
+use rusmpp::{Command, CommandStatus, Pdu, pdus::SubmitSm, types::{COctetString, OctetString}};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::str::FromStr;
+use tokio::sync::mpsc;
+
+fn test_addr() -> SocketAddr {
+    SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 12345)
+}
+
+fn test_config() -> AppConfig {
+    AppConfig {
+        server: ServerConfig { host: "127.0.0.1".to_string(), port: 8080, auth: None },
+        smpp: SmppConfig::default(),
+        log: LogConfig { level: "info".to_string(), ..Default::default() },
+        lifecycle: LifecycleConfig::default(),
+        mo_service: MoServiceConfig::default(),
+        outbind: vec![],
+        shutdown: ShutdownConfig::default(),
+        audit: AuditConfig::default(),
+    }
+}
+
+fn test_state() -> web::Data<AppState> {
+    test_state_with_config(test_config())
+}
+
+fn test_state_with_config(config: AppConfig) -> web::Data<AppState> {
+    let session_manager = Arc::new(SessionManager::new());
+    web::Data::new(AppState {
+        config: Arc::new(config),
+        config_path: None,
+        metrics: session_manager.metrics().clone(),
+        session_manager,
+        message_queue: Arc::new(MessageQueue::new()),
+        mo_queue: Arc::new(MoMessageQueue::new()),
+        log_buffer: LogBuffer::new(),
+    })
+}
+
+/// Submit "hi" to dst over a bound session and return the message_id from the resp
+async fn submit(state: &AppState, current_session_id: &mut Option<String>, tx: mpsc::Sender<Command>) -> String {
+    let submit = SubmitSm::builder()
+        .source_addr(COctetString::from_str("src").unwrap())
+        .destination_addr(COctetString::from_str("dst").unwrap())
+        .short_message(OctetString::from_str("hi").unwrap())
+        .build();
+    let command = Command::new(CommandStatus::EsmeRok, 1, Pdu::SubmitSm(submit));
+    let resp = handle_command(&command, &state.config, &state.session_manager, &state.message_queue, current_session_id, test_addr(), tx).await.unwrap();
+    let Some(Pdu::SubmitSmResp(submit_resp)) = resp.pdu() else { panic!("Expected SubmitSmResp") };
+    submit_resp.message_id().to_string()
+}
+
+#[actix_web::test]
+async fn test_dedup_api() {
+    let state = test_state();
+    state.message_queue.set_dedup_window_ms(60_000);
+    let app = test::init_service(App::new().app_data(state.clone()).configure(routes)).await;
+
+    let (tx, _rx) = mpsc::channel(10);
+    let session = Session::new("user".to_string(), BindType::Transceiver, test_addr(), tx.clone(), None);
+    let mut current_session_id = Some(session.id.clone());
+    state.session_manager.add_session(session);
+
+    // A repeat within the window is a dedup hit and gets the original ID
+    let first = submit(&state, &mut current_session_id, tx.clone()).await;
+    let second = submit(&state, &mut current_session_id, tx.clone()).await;
+    assert_eq!(first, second);
+    assert_eq!(state.message_queue.message_count(), 1);
+
+    let req = test::TestRequest::get().uri("/api/dedup").to_request();
+    let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(body["window_ms"], 60_000);
+    assert_eq!(body["hits"].as_array().unwrap().len(), 1);
+    assert_eq!(body["hits"][0]["original_message_id"], first);
+
+    // Turning the window off lets the same content through again
+    let req = test::TestRequest::post().uri("/api/dedup").set_json(serde_json::json!({ "window_ms": 0 })).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success());
+
+    let third = submit(&state, &mut current_session_id, tx).await;
+    assert_ne!(first, third);
+    assert_eq!(state.message_queue.message_count(), 2);
+
+    let req = test::TestRequest::get().uri("/api/dedup").to_request();
+    let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(body["window_ms"], 0);
+    assert_eq!(body["hits"].as_array().unwrap().len(), 1);
+}
+
+#[actix_web::test]
+async fn test_stats_include_session_counters() {
+    let state = test_state();
+    let app = test::init_service(App::new().app_data(state.clone()).configure(routes)).await;
+
+    let (tx, _rx) = mpsc::channel(10);
+    let session = Session::new("user".to_string(), BindType::Transceiver, test_addr(), tx.clone(), None);
+    let mut current_session_id = Some(session.id.clone());
+    state.session_manager.add_session(session);
+
+    submit(&state, &mut current_session_id, tx.clone()).await;
+    submit(&state, &mut current_session_id, tx).await;
+
+    let req = test::TestRequest::get().uri("/api/stats").to_request();
+    let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(body["sessions"][0]["submit_count"], 2);
+    assert_eq!(body["sessions"][0]["deliver_count"], 0);
+    assert!(body["sessions"][0]["connected_at"].is_string());
+    assert_eq!(body["sessions"][0]["age_secs"], 0);
+
+    let req = test::TestRequest::get().uri("/partials/sessions").to_request();
+    let html = String::from_utf8(test::call_and_read_body(&app, req).await.to_vec()).unwrap();
+    assert!(html.contains("<th>Age</th>"));
+}
+
+#[actix_web::test]
+async fn test_stats_decode_esm_class() {
+    let state = test_state();
+    let app = test::init_service(App::new().app_data(state.clone()).configure(routes)).await;
+
+    let (tx, _rx) = mpsc::channel(10);
+    let session = Session::new("user".to_string(), BindType::Transceiver, test_addr(), tx.clone(), None);
+    let mut current_session_id = Some(session.id.clone());
+    state.session_manager.add_session(session);
+    let submit = SubmitSm::builder()
+        .source_addr(COctetString::from_str("src").unwrap())
+        .destination_addr(COctetString::from_str("dst").unwrap())
+        .esm_class(rusmpp::values::EsmClass::from(0x83))
+        .short_message(OctetString::from_str("hi").unwrap())
+        .build();
+    let command = Command::new(CommandStatus::EsmeRok, 1, Pdu::SubmitSm(submit));
+    handle_command(&command, &state.config, &state.session_manager, &state.message_queue, &mut current_session_id, test_addr(), tx).await.unwrap();
+
+    let req = test::TestRequest::get().uri("/api/stats").to_request();
+    let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    let esm_class = &body["messages"][0]["esm_class"];
+    assert_eq!(esm_class["raw"], "0x83");
+    assert_eq!(esm_class["messaging_mode"], "store and forward");
+    assert_eq!(esm_class["message_type"], "default");
+    assert_eq!(esm_class["udhi"], false);
+    assert_eq!(esm_class["reply_path"], true);
+
+    let req = test::TestRequest::get().uri("/partials/messages").to_request();
+    let html = String::from_utf8(test::call_and_read_body(&app, req).await.to_vec()).unwrap();
+    assert!(html.contains("0x83 store and forward, reply path"), "{}", html);
+    assert_eq!(EsmClassDisplay::new(0x00).summary(), "0x00");
+    assert_eq!(EsmClassDisplay::new(0x48).summary(), "0x48 delivery ack, UDHI");
+}
+
+#[actix_web::test]
+async fn test_throughput_stats() {
+    let state = test_state();
+    let app = test::init_service(App::new().app_data(state.clone()).configure(routes)).await;
+    let (tx, _rx) = mpsc::channel(10);
+    let session = Session::new("user".to_string(), BindType::Transceiver, test_addr(), tx.clone(), None);
+    let mut current_session_id = Some(session.id.clone());
+    state.session_manager.add_session(session);
+    for _ in 0..3 {
+        submit(&state, &mut current_session_id, tx.clone()).await;
+    }
+
+    // Rates only count complete seconds, so the submits just made are not in them yet
+    let req = test::TestRequest::get().uri("/api/stats").to_request();
+    let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(body["throughput"]["submit_per_sec"], 0);
+    assert!(body["throughput"]["submit_avg_per_sec"].is_number());
+    assert!(body["throughput"]["receipt_per_sec"].is_number());
+
+    let req = test::TestRequest::get().uri("/partials/throughput").to_request();
+    let html = String::from_utf8(test::call_and_read_body(&app, req).await.to_vec()).unwrap();
+    assert!(html.contains("Submits/s") && html.contains("DRs/s"), "{}", html);
+}
+
+#[actix_web::test]
+async fn test_final_state_counts() {
+    let mut config = test_config();
+    config.lifecycle.max_time_enroute_ms = 0;
+    config.lifecycle.percent_delivered = 100;
+    config.lifecycle.percent_undeliverable = 0;
+    config.lifecycle.percent_accepted = 0;
+    config.lifecycle.percent_rejected = 0;
+    let state = test_state_with_config(config);
+    let app = test::init_service(App::new().app_data(state.clone()).configure(routes)).await;
+
+    let (tx, _rx) = mpsc::channel(10);
+    let session = Session::new("user".to_string(), BindType::Transceiver, test_addr(), tx.clone(), None);
+    let mut current_session_id = Some(session.id.clone());
+    state.session_manager.add_session(session);
+    submit(&state, &mut current_session_id, tx.clone()).await;
+    submit(&state, &mut current_session_id, tx).await;
+    crate::smpp::lifecycle::process_pending_messages(&state.config, &state.session_manager, &state.message_queue).await;
+    state.message_queue.record_final_state(crate::smpp::queue::MessageState::Rejected);
+    state.message_queue.record_final_state(crate::smpp::queue::MessageState::Rejected);
+
+    let req = test::TestRequest::get().uri("/api/stats").to_request();
+    let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(body["final_states"]["Delivered"], 2);
+    assert_eq!(body["final_states"]["Rejected"], 2);
+    assert_eq!(body["final_states"]["Undeliverable"], 0);
+
+    let req = test::TestRequest::get().uri("/partials/final-states").to_request();
+    let html = String::from_utf8(test::call_and_read_body(&app, req).await.to_vec()).unwrap();
+    assert!(html.contains("Delivered") && html.contains("50.0%"), "{}", html);
+
+    // The reset endpoint starts the tally over
+    let req = test::TestRequest::post().uri("/api/reset").to_request();
+    test::call_service(&app, req).await;
+    let req = test::TestRequest::get().uri("/api/stats").to_request();
+    let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(body["final_states"]["Delivered"], 0);
+}
+
+#[actix_web::test]
+async fn test_format_age() {
+    assert_eq!(format_age(std::time::Duration::from_secs(12)), "12s");
+    assert_eq!(format_age(std::time::Duration::from_secs(185)), "3m 05s");
+    assert_eq!(format_age(std::time::Duration::from_secs(3723)), "1h 02m 03s");
+}
+
+#[actix_web::test]
+async fn test_disconnect_session() {
+    let state = test_state();
+    let app = test::init_service(App::new().app_data(state.clone()).configure(routes)).await;
+
+    let req = test::TestRequest::post().uri("/api/sessions/no-such-session/disconnect").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), actix_web::http::StatusCode::NOT_FOUND);
+
+    let (tx, _rx) = mpsc::channel(10);
+    let session = Session::new("user".to_string(), BindType::Transceiver, test_addr(), tx, None);
+    let disconnect = session.disconnect.clone();
+    let uri = format!("/api/sessions/{}/disconnect", session.id);
+    state.session_manager.add_session(session);
+
+    let req = test::TestRequest::post().uri(&uri).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success());
+    assert!(disconnect.is_cancelled(), "Connection task should be told to close");
+    assert_eq!(state.session_manager.count(), 0);
+}
+
+#[actix_web::test]
+async fn test_prometheus_metrics() {
+    let state = test_state();
+    let app = test::init_service(App::new().app_data(state.clone()).configure(routes)).await;
+
+    let (tx, _rx) = mpsc::channel(10);
+    let session = Session::new("user".to_string(), BindType::Transceiver, test_addr(), tx.clone(), None);
+    let mut current_session_id = Some(session.id.clone());
+    state.session_manager.add_session(session);
+    submit(&state, &mut current_session_id, tx.clone()).await;
+    submit(&state, &mut current_session_id, tx).await;
+    state.metrics.delivery_receipts_total.with_label_values(&["DELIVRD"]).inc();
+
+    let req = test::TestRequest::get().uri("/metrics").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success());
+    let body = String::from_utf8(test::read_body(resp).await.to_vec()).unwrap();
+    for line in [
+        "smpp_sessions_active 1",
+        "smpp_submit_total 2",
+        "smpp_deliver_total 0",
+        "smpp_pending_dr 2",
+        "smpp_delivery_receipts_total{state=\"DELIVRD\"} 1",
+    ] {
+        assert!(body.lines().any(|l| l == line), "Missing `{}` in:\n{}", line, body);
+    }
+    assert!(body.contains("# TYPE smpp_submit_total counter"));
+}
+
+#[actix_web::test]
+async fn test_inject_mo_json() {
+    let state = test_state();
+    let mut mo_rx = state.mo_queue.take_receiver().unwrap();
+    let app = test::init_service(App::new().app_data(state.clone()).configure(routes)).await;
+
+    let req = test::TestRequest::post().uri("/api/inject-mo-json")
+        .set_json(serde_json::json!({ "source": "111", "dest": "222", "message": "hello" }))
+        .to_request();
+    let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(body["queued"], true);
+    let mo = mo_rx.try_recv().unwrap();
+    assert_eq!((mo.source_addr.as_str(), mo.dest_addr.as_str(), mo.short_message.as_str()), ("111", "222", "hello"));
+    assert_eq!(mo.data_coding, None);
+
+    // Binary content with an explicit data_coding
+    let req = test::TestRequest::post().uri("/api/inject-mo-json")
+        .set_json(serde_json::json!({ "source": "111", "dest": "222", "message": "0500030a0101", "hex": true, "data_coding": 4 }))
+        .to_request();
+    let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(body["queued"], true);
+    let mo = mo_rx.try_recv().unwrap();
+    assert_eq!(mo.short_message, "0x0500030a0101");
+    assert_eq!(mo.data_coding, Some(4));
+
+    let req = test::TestRequest::post().uri("/api/inject-mo-json")
+        .set_json(serde_json::json!({ "source": "111", "dest": "222", "message": "zz", "hex": true }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), actix_web::http::StatusCode::BAD_REQUEST);
+    assert!(mo_rx.try_recv().is_err());
+}
+
+#[actix_web::test]
+async fn test_inject_mo_batch() {
+    let state = test_state();
+    let mut mo_rx = state.mo_queue.take_receiver().unwrap();
+    let app = test::init_service(App::new().app_data(state.clone()).configure(routes)).await;
+
+    let batch: Vec<_> = (0..500)
+        .map(|i| serde_json::json!({ "source": "111", "dest": "222", "message": format!("msg {}", i) }))
+        .chain(std::iter::once(serde_json::json!({ "source": "111", "dest": "222", "message": "zz", "hex": true })))
+        .collect();
+    let req = test::TestRequest::post().uri("/api/inject-mo/batch").set_json(&batch).to_request();
+    let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(body["queued"], 500);
+    assert_eq!(body["failed"], 1);
+
+    let mut received = 0;
+    while let Ok(mo) = mo_rx.try_recv() {
+        assert_eq!(mo.short_message, format!("msg {}", received));
+        received += 1;
+    }
+    assert_eq!(received, 500);
+}
+
+#[actix_web::test]
+async fn test_inject_mo_batch_respects_rate() {
+    let mut config = test_config();
+    config.mo_service.delivery_messages_per_minute = 600; // one per 100ms
+    let state = test_state_with_config(config);
+    let app = test::init_service(App::new().app_data(state.clone()).configure(routes)).await;
+
+    let batch: Vec<_> = (0..3)
+        .map(|i| serde_json::json!({ "source": "111", "dest": "222", "message": format!("msg {}", i) }))
+        .collect();
+    let started = std::time::Instant::now();
+    let req = test::TestRequest::post().uri("/api/inject-mo/batch").set_json(&batch).to_request();
+    let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(body["queued"], 3);
+    assert!(started.elapsed() >= std::time::Duration::from_millis(200), "Batch should be paced");
+}
+
+#[actix_web::test]
+async fn test_reset() {
+    let state = test_state();
+    let app = test::init_service(App::new().app_data(state.clone()).configure(routes)).await;
+
+    let (tx, _rx) = mpsc::channel(10);
+    let session = Session::new("user".to_string(), BindType::Transceiver, test_addr(), tx.clone(), None);
+    let mut current_session_id = Some(session.id.clone());
+    state.session_manager.add_session(session);
+
+    let first = submit(&state, &mut current_session_id, tx.clone()).await;
+    submit(&state, &mut current_session_id, tx.clone()).await;
+
+    // Without the query param IDs keep counting up
+    let req = test::TestRequest::post().uri("/api/reset").to_request();
+    let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(body["reset_counter"], false);
+    assert_eq!(state.message_queue.message_count(), 0);
+    assert_eq!(state.message_queue.pending_dr_count(), 0);
+    let third = submit(&state, &mut current_session_id, tx.clone()).await;
+    assert_ne!(third, first);
+
+    let req = test::TestRequest::post().uri("/api/reset?reset_counter=true").to_request();
+    let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(body["reset_counter"], true);
+    assert_eq!(state.message_queue.message_count(), 0);
+    assert_eq!(submit(&state, &mut current_session_id, tx).await, first);
+}
+
+#[actix_web::test]
+async fn test_get_message_status() {
+    let state = test_state();
+    let app = test::init_service(App::new().app_data(state.clone()).configure(routes)).await;
+
+    let req = test::TestRequest::get().uri("/api/messages/unknown").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), actix_web::http::StatusCode::NOT_FOUND);
+
+    let (tx, _rx) = mpsc::channel(10);
+    let session = Session::new("user".to_string(), BindType::Transceiver, test_addr(), tx.clone(), None);
+    let mut current_session_id = Some(session.id.clone());
+    state.session_manager.add_session(session);
+    let message_id = submit(&state, &mut current_session_id, tx).await;
+
+    let uri = format!("/api/messages/{}", message_id);
+    let req = test::TestRequest::get().uri(&uri).to_request();
+    let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(body["message_id"], message_id);
+    assert_eq!(body["content"], "hi");
+    assert_eq!(body["pending_dr"], true);
+    assert!(body["final_state"].is_null());
+
+    // Once the lifecycle resolves it, the final state is reported
+    state.message_queue.remove_pending_dr(&message_id);
+    state.message_queue.set_final_state(&message_id, crate::smpp::queue::MessageState::Delivered);
+    let req = test::TestRequest::get().uri(&uri).to_request();
+    let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(body["pending_dr"], false);
+    assert_eq!(body["final_state"], "Delivered");
+    assert!(body["done_at"].is_string());
+}
+
+#[actix_web::test]
+async fn test_ucs2_content_decoded() {
+    let state = test_state();
+    let app = test::init_service(App::new().app_data(state.clone()).configure(routes)).await;
+
+    let (tx, _rx) = mpsc::channel(10);
+    let session = Session::new("user".to_string(), BindType::Transceiver, test_addr(), tx.clone(), None);
+    let mut current_session_id = Some(session.id.clone());
+    state.session_manager.add_session(session);
+    let submit = SubmitSm::builder()
+        .source_addr(COctetString::from_str("src").unwrap())
+        .destination_addr(COctetString::from_str("dst").unwrap())
+        .data_coding(rusmpp::values::DataCoding::Ucs2)
+        .short_message(OctetString::from_bytes(vec![0x04, 0x1F, 0x04, 0x40, 0x04, 0x38].into()).unwrap())
+        .build();
+    let command = Command::new(CommandStatus::EsmeRok, 1, Pdu::SubmitSm(submit));
+    handle_command(&command, &state.config, &state.session_manager, &state.message_queue, &mut current_session_id, test_addr(), tx).await.unwrap();
+
+    let req = test::TestRequest::get().uri("/api/stats").to_request();
+    let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(body["messages"][0]["content"], "\u{41F}\u{440}\u{438}");
+
+    let req = test::TestRequest::get().uri("/api/messages").to_request();
+    let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(body["messages"][0]["content"], "\u{41F}\u{440}\u{438}");
+}
+
+#[actix_web::test]
+async fn test_list_messages_paged() {
+    let state = test_state();
+    let app = test::init_service(App::new().app_data(state.clone()).configure(routes)).await;
+
+    let (tx, _rx) = mpsc::channel(10);
+    let session = Session::new("user".to_string(), BindType::Transceiver, test_addr(), tx.clone(), None);
+    let mut current_session_id = Some(session.id.clone());
+    state.session_manager.add_session(session);
+    let mut ids = Vec::new();
+    for _ in 0..5 {
+        ids.push(submit(&state, &mut current_session_id, tx.clone()).await);
+    }
+
+    let req = test::TestRequest::get().uri("/api/messages?limit=2&offset=1").to_request();
+    let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(body["total"], 5);
+    assert_eq!(body["limit"], 2);
+    let page: Vec<_> = body["messages"].as_array().unwrap().iter().map(|m| m["message_id"].as_str().unwrap().to_string()).collect();
+    assert_eq!(page, [ids[3].clone(), ids[2].clone()]);
+
+    let req = test::TestRequest::get().uri("/api/messages?dest=ds&source=nomatch").to_request();
+    let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(body["total"], 0);
+
+    let req = test::TestRequest::get().uri("/api/messages?dest=ds").to_request();
+    let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(body["total"], 5);
+    assert_eq!(body["messages"].as_array().unwrap().len(), 5);
+}
+
+#[actix_web::test]
+async fn test_auth_middleware() {
+    use actix_web::http::{StatusCode, header};
+    use base64::Engine;
+
+    // No [server.auth]: open access
+    let state = test_state();
+    let app = test::init_service(App::new().app_data(state).wrap(actix_web::middleware::from_fn(auth::require_auth)).configure(routes)).await;
+    let resp = test::call_service(&app, test::TestRequest::get().uri("/api/stats").to_request()).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let mut config = test_config();
+    config.server.auth = Some(crate::config::AuthConfig {
+        username: Some("admin".to_string()),
+        password: Some("secret".to_string()),
+        api_token: Some("tok123".to_string()),
+    });
+    let state = test_state_with_config(config);
+    let app = test::init_service(App::new().app_data(state).wrap(actix_web::middleware::from_fn(auth::require_auth)).configure(routes)).await;
+    let basic = format!("Basic {}", base64::engine::general_purpose::STANDARD.encode("admin:secret"));
+    let wrong_basic = format!("Basic {}", base64::engine::general_purpose::STANDARD.encode("admin:nope"));
+
+    let call = |uri: &str, authorization: Option<&str>| {
+        let mut req = test::TestRequest::get().uri(uri);
+        if let Some(value) = authorization {
+            req = req.insert_header((header::AUTHORIZATION, value.to_string()));
+        }
+        req.to_request()
+    };
+
+    // Liveness probes stay open
+    let resp = test::call_service(&app, call("/health", None)).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let resp = test::call_service(&app, call("/ready", None)).await;
+    assert_ne!(resp.status(), StatusCode::UNAUTHORIZED);
+
+    // Browser routes challenge for Basic auth
+    let resp = test::call_service(&app, call("/partials/stats", None)).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    assert!(resp.headers().get(header::WWW_AUTHENTICATE).unwrap().to_str().unwrap().starts_with("Basic"));
+    let resp = test::call_service(&app, call("/partials/stats", Some(&basic))).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let resp = test::call_service(&app, call("/partials/stats", Some(&wrong_basic))).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    let resp = test::call_service(&app, call("/partials/stats", Some("Bearer tok123"))).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED, "Tokens are only for the API");
+
+    // The API takes the bearer token or Basic credentials
+    let resp = test::call_service(&app, call("/api/stats", None)).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(resp.headers().get(header::WWW_AUTHENTICATE).unwrap(), "Bearer");
+    let resp = test::call_service(&app, call("/api/stats", Some("Bearer tok123"))).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let resp = test::call_service(&app, call("/api/stats", Some("Bearer tok124"))).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    let resp = test::call_service(&app, call("/api/stats", Some(&basic))).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let resp = test::call_service(&app, call("/metrics", Some("Bearer tok123"))).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    // Token only: the API is protected, the browser UI has nothing to log in with and stays open
+    let mut config = test_config();
+    config.server.auth = Some(crate::config::AuthConfig { api_token: Some("tok123".to_string()), ..Default::default() });
+    let state = test_state_with_config(config);
+    let app = test::init_service(App::new().app_data(state).wrap(actix_web::middleware::from_fn(auth::require_auth)).configure(routes)).await;
+    let resp = test::call_service(&app, call("/partials/stats", None)).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let resp = test::call_service(&app, call("/api/stats", None)).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    let resp = test::call_service(&app, call("/api/stats", Some("Bearer tok123"))).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn test_force_bind_failure_toggle() {
+    let state = test_state();
+    let app = test::init_service(App::new().app_data(state.clone()).configure(routes)).await;
+
+    let req = test::TestRequest::get().uri("/api/config/force-bind-failure").to_request();
+    let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(body["enabled"], false);
+
+    let req = test::TestRequest::post().uri("/api/config/force-bind-failure")
+        .set_json(serde_json::json!({ "enabled": true }))
+        .to_request();
+    let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(body["enabled"], true);
+    assert!(state.session_manager.force_bind_failure());
+}
+
+#[actix_web::test]
+async fn test_alert_notification() {
+    let state = test_state();
+    let app = test::init_service(App::new().app_data(state.clone()).configure(routes)).await;
+    let request = serde_json::json!({ "source_addr": "61400000001", "esme_addr": "1234", "ms_availability_status": 0 });
+
+    let req = test::TestRequest::post().uri("/api/alert-notification").set_json(&request).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), actix_web::http::StatusCode::NOT_FOUND);
+
+    // Transmitters never receive alerts even when their range matches
+    let (tx, mut tx_rx) = mpsc::channel(10);
+    state.session_manager.add_session(Session::new("tx".to_string(), BindType::Transmitter, test_addr(), tx, Some("^12".to_string())));
+    let (rx, mut rx_rx) = mpsc::channel(10);
+    let receiver = Session::new("rx".to_string(), BindType::Receiver, test_addr(), rx, Some("^12".to_string()));
+    let receiver_id = receiver.id.clone();
+    state.session_manager.add_session(receiver);
+
+    let req = test::TestRequest::post().uri("/api/alert-notification").set_json(&request).to_request();
+    let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(body["sent"], true);
+    assert_eq!(body["session_id"], receiver_id);
+    assert!(tx_rx.try_recv().is_err());
+    let command = rx_rx.try_recv().unwrap();
+    let Some(Pdu::AlertNotification(alert)) = command.pdu() else {
+        panic!("Expected AlertNotification, got {:?}", command.pdu());
+    };
+    assert_eq!(alert.source_addr.to_string(), "61400000001");
+    assert_eq!(alert.esme_addr.to_string(), "1234");
+    assert_eq!(alert.ms_availability_status(), Some(MsAvailabilityStatus::Available));
+}
+
+#[actix_web::test]
+async fn test_list_sessions() {
+    let state = test_state();
+    let app = test::init_service(App::new().app_data(state.clone()).configure(routes)).await;
+
+    let req = test::TestRequest::get().uri("/api/sessions").to_request();
+    let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(body["count"], 0);
+
+    let (tx, _rx) = mpsc::channel(10);
+    let mut session = Session::new("esme".to_string(), BindType::Receiver, test_addr(), tx, Some("^12".to_string()));
+    session.interface_version = Some(rusmpp::values::InterfaceVersion::Smpp3_4);
+    session.record_deliver();
+    let session_id = session.id.clone();
+    state.session_manager.add_session(session);
+
+    let req = test::TestRequest::get().uri("/api/sessions").to_request();
+    let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(body["count"], 1);
+    let info = &body["sessions"][0];
+    assert_eq!(info["id"], session_id);
+    assert_eq!(info["system_id"], "esme");
+    assert_eq!(info["bind_type"], "Receiver");
+    assert_eq!(info["addr"], "127.0.0.1:12345");
+    assert_eq!(info["address_range"], "^12");
+    assert_eq!(info["interface_version"], "0x34");
+    assert_eq!(info["submit_count"], 0);
+    assert_eq!(info["deliver_count"], 1);
+    assert!(info["connected_at"].is_string());
+    // Nothing internal leaks into the DTO
+    assert!(info.get("sender").is_none());
+}
+
+#[actix_web::test]
+async fn test_api_submit_runs_lifecycle() {
+    let mut config = test_config();
+    config.lifecycle.max_time_enroute_ms = 0;
+    config.lifecycle.percent_delivered = 100;
+    config.lifecycle.percent_undeliverable = 0;
+    config.lifecycle.percent_accepted = 0;
+    config.lifecycle.percent_rejected = 0;
+    let state = test_state_with_config(config);
+    let app = test::init_service(App::new().app_data(state.clone()).configure(routes)).await;
+
+    let req = test::TestRequest::post().uri("/api/submit")
+        .set_json(serde_json::json!({ "source": "", "dest": "61400000001", "message": "hi" })).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), actix_web::http::StatusCode::BAD_REQUEST);
+    let req = test::TestRequest::post().uri("/api/submit")
+        .set_json(serde_json::json!({ "source": "1234", "dest": "61400000001", "message": "hi", "session_id": "missing" })).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), actix_web::http::StatusCode::NOT_FOUND);
+
+    let (tx, mut rx) = mpsc::channel(10);
+    let session = Session::new("user".to_string(), BindType::Transceiver, test_addr(), tx, None);
+    let session_id = session.id.clone();
+    state.session_manager.add_session(session);
+
+    let request = serde_json::json!({
+        "source": "1234", "dest": "61400000001", "message": "\u{41F}\u{440}",
+        "data_coding": 8, "registered_delivery": 1, "esm_class": 3, "session_id": session_id,
+    });
+    let req = test::TestRequest::post().uri("/api/submit").set_json(&request).to_request();
+    let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(body["queued"], true);
+    let message_id = body["message_id"].as_str().unwrap().to_string();
+
+    let uri = format!("/api/messages/{}", message_id);
+    let req = test::TestRequest::get().uri(&uri).to_request();
+    let detail: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(detail["content"], "\u{41F}\u{440}");
+    assert_eq!(detail["pending_dr"], true);
+
+    // The lifecycle treats it like a real submit and sends the receipt to the named session
+    crate::smpp::lifecycle::process_pending_messages(&state.config, &state.session_manager, &state.message_queue).await;
+    let req = test::TestRequest::get().uri(&uri).to_request();
+    let detail: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(detail["final_state"], "Delivered");
+    let receipt = rx.try_recv().unwrap();
+    let Some(Pdu::DeliverSm(deliver)) = receipt.pdu() else { panic!("Expected DeliverSm, got {:?}", receipt.pdu()) };
+    assert_eq!(deliver.destination_addr.to_string(), "1234");
+    assert!(String::from_utf8_lossy(deliver.short_message().as_ref()).contains(&format!("id:{}", message_id)));
+}
+
+#[actix_web::test]
+async fn test_api_submit_matches_smpp_submit() {
+    let mut config = test_config();
+    config.smpp.async_message_id = true;
+    let state = test_state_with_config(config);
+    state.message_queue.set_dedup_window_ms(60_000);
+    let app = test::init_service(App::new().app_data(state.clone()).configure(routes)).await;
+
+    // A rejected request takes no message ID
+    let before = state.message_queue.next_message_id();
+    let req = test::TestRequest::post().uri("/api/submit")
+        .set_json(serde_json::json!({ "source": "1234", "dest": "61400000001", "message": "zz", "hex": true })).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), actix_web::http::StatusCode::BAD_REQUEST);
+
+    let request = serde_json::json!({ "source": "1234", "dest": "61400000001", "message": "hi" });
+    let req = test::TestRequest::post().uri("/api/submit").set_json(&request).to_request();
+    let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(body["queued"], true);
+    let message_id = body["message_id"].as_str().unwrap().to_string();
+    assert_eq!(u64::from_str_radix(&message_id, 16).unwrap(), u64::from_str_radix(&before, 16).unwrap() + 1);
+    // Async mode gives the receipt its own final ID
+    let stored = state.message_queue.find_message(&message_id).unwrap();
+    assert!(stored.final_message_id.is_some_and(|final_id| final_id != message_id));
+
+    // A repeat inside the dedup window returns the original ID without queueing
+    let req = test::TestRequest::post().uri("/api/submit").set_json(&request).to_request();
+    let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(body["queued"], false);
+    assert_eq!(body["message_id"], message_id.as_str());
+    assert_eq!(state.message_queue.message_count(), 1);
+}
+
+#[actix_web::test]
+async fn test_deliver_to_session() {
+    let state = test_state();
+    let app = test::init_service(App::new().app_data(state.clone()).configure(routes)).await;
+    let request = serde_json::json!({ "source": "61400000001", "dest": "1234", "message": "hello" });
+
+    let req = test::TestRequest::post().uri("/api/sessions/missing/deliver").set_json(&request).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), actix_web::http::StatusCode::NOT_FOUND);
+
+    // Transmitters can't receive, even when addressed directly
+    let (tx, mut tx_rx) = mpsc::channel(10);
+    let transmitter = Session::new("tx".to_string(), BindType::Transmitter, test_addr(), tx, None);
+    let uri = format!("/api/sessions/{}/deliver", transmitter.id);
+    state.session_manager.add_session(transmitter);
+    let req = test::TestRequest::post().uri(&uri).set_json(&request).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), actix_web::http::StatusCode::NOT_FOUND);
+    assert!(tx_rx.try_recv().is_err());
+
+    // Two receivers match the address; only the addressed one gets the deliver_sm
+    let (first, mut first_rx) = mpsc::channel(10);
+    state.session_manager.add_session(Session::new("rx1".to_string(), BindType::Receiver, test_addr(), first, Some("^12".to_string())));
+    let (second, mut second_rx) = mpsc::channel(10);
+    let target = Session::new("rx2".to_string(), BindType::Receiver, test_addr(), second, Some("^12".to_string()));
+    let target_id = target.id.clone();
+    state.session_manager.add_session(target);
+
+    let uri = format!("/api/sessions/{}/deliver", target_id);
+    let req = test::TestRequest::post().uri(&uri).set_json(&request).to_request();
+    let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(body["sent"], true);
+    assert_eq!(body["session_id"], target_id);
+    assert!(first_rx.try_recv().is_err());
+    let command = second_rx.try_recv().unwrap();
+    assert_ne!(command.sequence_number, 0);
+    let Some(Pdu::DeliverSm(deliver)) = command.pdu() else {
+        panic!("Expected DeliverSm, got {:?}", command.pdu());
+    };
+    assert_eq!(deliver.source_addr.to_string(), "61400000001");
+    assert_eq!(deliver.destination_addr.to_string(), "1234");
+    assert_eq!(deliver.short_message().as_ref(), b"hello");
+    let session = state.session_manager.get_session(&target_id).unwrap();
+    assert_eq!(session.deliver_count.load(std::sync::atomic::Ordering::Relaxed), 1);
+}
+
+#[actix_web::test]
+async fn test_mo_status() {
+    let mut config = test_config();
+    config.mo_service.enabled = true;
+    config.mo_service.file_path = "missing.csv".to_string();
+    let state = test_state_with_config(config);
+    let app = test::init_service(App::new().app_data(state.clone()).configure(routes)).await;
+
+    state.mo_queue.record_csv_open_error("No such file or directory".to_string());
+    let req = test::TestRequest::get().uri("/api/mo/status").to_request();
+    let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(body["enabled"], true);
+    assert_eq!(body["file_path"], "missing.csv");
+    assert_eq!(body["csv"]["file_found"], false);
+    assert_eq!(body["csv"]["open_error"], "No such file or directory");
+
+    state.mo_queue.record_csv_error(3, "only,two", "expected at least 3 columns (source,dest,message), found 2".to_string());
+    state.mo_queue.record_csv_pass(4);
+    let req = test::TestRequest::get().uri("/api/mo/status").to_request();
+    let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(body["csv"]["file_found"], true);
+    assert_eq!(body["csv"]["last_pass_dispatched"], 4);
+    assert_eq!(body["csv"]["errors"][0]["line"], 3);
+
+    let req = test::TestRequest::get().uri("/partials/mo-status").to_request();
+    let html = String::from_utf8(test::call_and_read_body(&app, req).await.to_vec()).unwrap();
+    assert!(html.contains("4 dispatched last pass"), "{}", html);
+    assert!(html.contains("only,two"));
+}
+
+#[actix_web::test]
+async fn test_log_buffer_formats() {
+    use tracing_subscriber::layer::SubscriberExt;
+
+    let text = LogBuffer::new();
+    let json = LogBuffer::new();
+    let subscriber = tracing_subscriber::registry()
+        .with(LogBufferLayer::new(text.clone()))
+        .with(LogBufferLayer::new(json.clone()).with_format(crate::config::LogFormat::Json));
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::warn!(session = "abc", window = 3u64, "Window full for {}", "user");
+    });
+
+    let line = &text.get_all()[0];
+    assert!(line.starts_with("[WARN] "), "{}", line);
+    assert!(line.contains("Window full for user session=\"abc\" window=3"), "{}", line);
+
+    let line: serde_json::Value = serde_json::from_str(&json.get_all()[0]).unwrap();
+    assert_eq!(line["level"], "WARN");
+    assert_eq!(line["message"], "Window full for user");
+    assert_eq!(line["fields"]["session"], "abc");
+    assert_eq!(line["fields"]["window"], 3);
+    assert!(line["target"].as_str().unwrap().contains("web_tests"));
+    assert!(chrono::DateTime::parse_from_rfc3339(line["timestamp"].as_str().unwrap()).is_ok());
+}
+
+#[actix_web::test]
+async fn test_log_subscription_survives_lag() {
+    let buffer = LogBuffer::new();
+    let mut rx = buffer.subscribe();
+    for i in 0..300 {
+        buffer.push(tracing::Level::DEBUG, format!("line {}", i));
+    }
+
+    // The overflow is reported once, then the feed resumes with the oldest line still held
+    let marker = rx.recv().await.unwrap();
+    assert!(marker.at_least(Some(tracing::Level::ERROR)));
+    let dropped: usize = marker.text
+        .strip_prefix("... ").and_then(|rest| rest.strip_suffix(" log lines dropped ..."))
+        .and_then(|n| n.parse().ok())
+        .unwrap_or_else(|| panic!("unexpected marker {:?}", marker.text));
+    assert!(dropped >= 100, "{}", dropped);
+    assert_eq!(rx.recv().await.unwrap().text, format!("line {}", dropped));
+
+    buffer.push(tracing::Level::INFO, "after the burst".to_string());
+    let mut last = None;
+    for _ in dropped + 1..=300 {
+        last = rx.recv().await;
+    }
+    assert_eq!(last.unwrap().text, "after the burst");
+
+    // With JSON logs the marker is a JSON line too
+    let buffer = LogBuffer::new();
+    let _layer = LogBufferLayer::new(buffer.clone()).with_format(crate::config::LogFormat::Json);
+    let mut rx = buffer.subscribe();
+    for i in 0..300 {
+        buffer.push(tracing::Level::DEBUG, format!("line {}", i));
+    }
+    let marker = rx.recv().await.unwrap();
+    let line: serde_json::Value = serde_json::from_str(&marker.text).expect("marker is JSON");
+    assert_eq!(line["level"], "ERROR");
+    assert!(line["message"].as_str().unwrap().ends_with(" log lines dropped ..."), "{}", line);
+    assert!(chrono::DateTime::parse_from_rfc3339(line["timestamp"].as_str().unwrap()).is_ok());
+}
+
+#[actix_web::test]
+async fn test_log_lines_carry_session_span() {
+    use tracing_subscriber::layer::SubscriberExt;
+
+    let text = LogBuffer::new();
+    let json = LogBuffer::new();
+    let subscriber = tracing_subscriber::registry()
+        .with(LogBufferLayer::new(text.clone()))
+        .with(LogBufferLayer::new(json.clone()).with_format(crate::config::LogFormat::Json));
+    tracing::subscriber::with_default(subscriber, || {
+        let span = tracing::info_span!("session", addr = %test_addr(), system_id = tracing::field::Empty);
+        let _entered = span.enter();
+        tracing::info!("New connection");
+        span.record("system_id", "user");
+        tracing::info!(seq = 2u64, "Bound");
+    });
+
+    let lines = text.get_all();
+    assert!(lines[0].ends_with(": session{addr=127.0.0.1:12345}: New connection"), "{}", lines[0]);
+    assert!(lines[1].ends_with(": session{addr=127.0.0.1:12345 system_id=\"user\"}: Bound seq=2"), "{}", lines[1]);
+
+    let line: serde_json::Value = serde_json::from_str(&json.get_all()[1]).unwrap();
+    assert_eq!(line["message"], "Bound");
+    assert_eq!(line["spans"], serde_json::json!([{ "name": "session", "addr": "127.0.0.1:12345", "system_id": "user" }]));
+}
+
+#[actix_web::test]
+async fn test_logs_level_filter() {
+    let state = test_state();
+    let app = test::init_service(App::new().app_data(state.clone()).configure(routes)).await;
+    state.log_buffer.push(tracing::Level::DEBUG, "[DEBUG] sim: noisy".to_string());
+    state.log_buffer.push(tracing::Level::INFO, "[INFO] sim: bound".to_string());
+    state.log_buffer.push(tracing::Level::WARN, "[WARN] sim: throttled".to_string());
+    state.log_buffer.push(tracing::Level::ERROR, "[ERROR] sim: failed".to_string());
+
+    let req = test::TestRequest::get().uri("/partials/logs?level=warn").to_request();
+    let html = String::from_utf8(test::call_and_read_body(&app, req).await.to_vec()).unwrap();
+    assert!(html.contains("throttled") && html.contains("failed"), "{}", html);
+    assert!(!html.contains("bound") && !html.contains("noisy"), "{}", html);
+
+    let req = test::TestRequest::get().uri("/partials/logs").to_request();
+    let html = String::from_utf8(test::call_and_read_body(&app, req).await.to_vec()).unwrap();
+    assert_eq!(html.matches("log-line").count(), 4);
+
+    for uri in ["/partials/logs?level=loud", "/api/logs/stream?level=loud"] {
+        let resp = test::call_service(&app, test::TestRequest::get().uri(uri).to_request()).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::BAD_REQUEST, "{}", uri);
+    }
+}
+
+#[actix_web::test]
+async fn test_session_capture() {
+    use crate::smpp::capture::{Direction, PduCapture};
+    let state = test_state();
+    let app = test::init_service(App::new().app_data(state.clone()).configure(routes)).await;
+
+    let (tx, _rx) = mpsc::channel(10);
+    let session = Session::new("user".to_string(), BindType::Transceiver, test_addr(), tx, None);
+    let uri = format!("/api/sessions/{}/capture", session.id);
+    state.session_manager.add_session(session);
+
+    // Capture off: nothing registered for the connection
+    let resp = test::call_service(&app, test::TestRequest::get().uri(&uri).to_request()).await;
+    assert_eq!(resp.status(), actix_web::http::StatusCode::NOT_FOUND);
+
+    let capture = Arc::new(PduCapture::new(test_addr(), "127.0.0.1:2775".parse().unwrap()));
+    capture.record(Direction::Inbound, &[0, 0, 0, 16, 0, 0, 0, 0x15, 0, 0, 0, 0, 0, 0, 0, 3]);
+    state.session_manager.set_capture(test_addr(), capture);
+
+    let req = test::TestRequest::get().uri(&uri).to_request();
+    let dump = String::from_utf8(test::call_and_read_body(&app, req).await.to_vec()).unwrap();
+    assert!(dump.contains("<< command_id=0x00000015 seq=3"), "{}", dump);
+
+    let resp = test::call_service(&app, test::TestRequest::get().uri(&format!("{}?format=pcap", uri)).to_request()).await;
+    assert!(resp.status().is_success());
+    assert_eq!(resp.headers().get("content-type").unwrap(), "application/vnd.tcpdump.pcap");
+    let body = test::read_body(resp).await;
+    assert_eq!(body.len(), 24 + 16 + 56);
+
+    let resp = test::call_service(&app, test::TestRequest::get().uri(&format!("{}?format=xml", uri)).to_request()).await;
+    assert_eq!(resp.status(), actix_web::http::StatusCode::BAD_REQUEST);
+}
+
+#[actix_web::test]
+async fn test_readiness() {
+    let state = test_state();
+    let app = test::init_service(App::new().app_data(state.clone()).configure(routes)).await;
+
+    // Alive but not yet listening
+    let resp = test::call_service(&app, test::TestRequest::get().uri("/ready").to_request()).await;
+    assert_eq!(resp.status(), actix_web::http::StatusCode::SERVICE_UNAVAILABLE);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["smpp_listener"], false);
+
+    state.session_manager.set_listener_ready(true);
+    state.mo_queue.set_running(true);
+    let (tx, _rx) = mpsc::channel(10);
+    state.session_manager.add_session(Session::new("user".to_string(), BindType::Transceiver, test_addr(), tx, None));
+
+    let resp = test::call_service(&app, test::TestRequest::get().uri("/ready").to_request()).await;
+    assert!(resp.status().is_success());
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["ready"], true);
+    assert_eq!(body["active_sessions"], 1);
+    assert_eq!(body["pending_dr"], 0);
+    assert_eq!(body["mo_service_running"], true);
+}
+
+#[actix_web::test]
+async fn test_config_reload() {
+    let path = std::env::temp_dir().join(format!("smpp-sim-reload-{}.toml", std::process::id()));
+    std::fs::write(&path, "[lifecycle]\npercent_delivered = 0\npercent_undeliverable = 0\npercent_accepted = 0\n\
+        percent_rejected = 0\npercent_deleted = 100\npercent_unknown = 0\n").unwrap();
+    let session_manager = Arc::new(SessionManager::new());
+    let state = web::Data::new(AppState {
+        config: Arc::new(test_config()),
+        config_path: Some(path.clone()),
+        metrics: session_manager.metrics().clone(),
+        session_manager,
+        message_queue: Arc::new(MessageQueue::new()),
+        mo_queue: Arc::new(MoMessageQueue::new()),
+        log_buffer: LogBuffer::new(),
+    });
+    let app = test::init_service(App::new().app_data(state.clone()).configure(routes)).await;
+
+    // Odds that do not add up are refused and the running config is kept
+    let mut bad = (*state.config).clone();
+    bad.lifecycle.percent_delivered = 50;
+    assert!(state.apply_config(bad).unwrap_err().contains("add up to"));
+    assert_eq!(state.live_config().lifecycle.percent_delivered, state.config.lifecycle.percent_delivered);
+
+    let mut tuned = (*state.config).clone();
+    tuned.lifecycle.percent_delivered = 0;
+    tuned.lifecycle.percent_undeliverable = 0;
+    tuned.lifecycle.percent_accepted = 0;
+    tuned.lifecycle.percent_rejected = 100;
+    tuned.lifecycle.percent_deleted = 0;
+    tuned.lifecycle.percent_unknown = 0;
+    tuned.smpp.force_bind_failure = true;
+    tuned.smpp.port += 1;
+    assert_eq!(state.apply_config(tuned).unwrap(), vec!["smpp.port"]);
+    assert_eq!(state.live_config().lifecycle.percent_rejected, 100);
+    assert!(state.session_manager.force_bind_failure(), "Runtime toggles follow the new config");
+
+    // The endpoint re-reads the --config file written above
+    let resp = test::call_service(&app, test::TestRequest::post().uri("/api/config/reload").to_request()).await;
+    std::fs::remove_file(&path).unwrap();
+    assert!(resp.status().is_success());
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["reloaded"], true);
+    assert_eq!(state.live_config().lifecycle.percent_deleted, 100);
+}
+
+#[actix_web::test]
+async fn test_lifecycle_update() {
+    let state = test_state();
+    let app = test::init_service(App::new().app_data(state.clone()).configure(routes)).await;
+
+    // From the default 90% delivered to everything rejected
+    let req = test::TestRequest::post().uri("/api/lifecycle")
+        .set_json(serde_json::json!({
+            "percent_delivered": 0, "percent_undeliverable": 0, "percent_accepted": 0,
+            "percent_rejected": 100, "max_time_enroute_ms": 250,
+        }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success());
+    let lifecycle = &state.live_config().lifecycle;
+    assert_eq!((lifecycle.percent_delivered, lifecycle.percent_rejected, lifecycle.max_time_enroute_ms), (0, 100, 250));
+
+    // Partial updates must still add up to 100
+    let req = test::TestRequest::post().uri("/api/lifecycle")
+        .set_json(serde_json::json!({ "percent_delivered": 10 }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), actix_web::http::StatusCode::BAD_REQUEST);
+    assert_eq!(state.live_config().lifecycle.percent_delivered, 0);
+
+    let req = test::TestRequest::get().uri("/api/lifecycle").to_request();
+    let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(body["percent_rejected"], 100);
+    assert_eq!(body["max_time_enroute_ms"], 250);
+}
+
+#[actix_web::test]
+async fn test_lifecycle_update_keeps_runtime_toggles() {
+    let state = test_state();
+    let app = test::init_service(App::new().app_data(state.clone()).configure(routes)).await;
+
+    let req = test::TestRequest::post().uri("/api/config/force-bind-failure")
+        .set_json(serde_json::json!({ "enabled": true }))
+        .to_request();
+    assert!(test::call_service(&app, req).await.status().is_success());
+
+    let req = test::TestRequest::post().uri("/api/lifecycle")
+        .set_json(serde_json::json!({ "max_time_enroute_ms": 250 }))
+        .to_request();
+    assert!(test::call_service(&app, req).await.status().is_success());
+    assert_eq!(state.live_config().lifecycle.max_time_enroute_ms, 250);
+    assert!(state.session_manager.force_bind_failure(), "Changing the odds must not reset the bind failure toggle");
+}
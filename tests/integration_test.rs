@@ -1,4 +1,4 @@
-use rust_smpp_sim::config::{AppConfig, SmppConfig, ServerConfig, LogConfig, LifecycleConfig, MoServiceConfig};
+use rust_smpp_sim::config::{AppConfig, AuditConfig, SmppConfig, ServerConfig, LogConfig, LifecycleConfig, MoServiceConfig, ShutdownConfig};
 use rust_smpp_sim::smpp::server::start_smpp_server;
 use rust_smpp_sim::smpp::session::SessionManager;
 use rust_smpp_sim::smpp::queue::MessageQueue;
@@ -15,47 +15,56 @@ use tokio_util::codec::Framed;
 use futures::{SinkExt, StreamExt};
 use std::str::FromStr;
 
-#[tokio::test]
-async fn test_smpp_flow() {
-    // Setup Configuration
-    let port = 2777; // Use a different port to be safe
-    let system_id = "testsys";
-    let password = "pass";
-
-    let config = Arc::new(AppConfig {
+/// Config for a simulator that accepts "testsys"/"pass" on `smpp_port`, with the web UI on `http_port`
+fn test_config(smpp_port: u16, http_port: u16) -> AppConfig {
+    AppConfig {
         server: ServerConfig {
             host: "127.0.0.1".to_string(),
-            port: 8082,
+            port: http_port,
+            auth: None,
         },
         smpp: SmppConfig {
-            system_id: system_id.to_string(),
-            password: password.to_string(),
-            port: port,
+            system_id: "testsys".to_string(),
+            password: "pass".to_string(),
+            // Loopback only, as in a locked-down CI host
+            host: "127.0.0.1".to_string(),
+            port: smpp_port,
             max_sessions: 10,
-            accounts: vec![],
-            version: "5.0".to_string(),
+            ..Default::default()
         },
         log: LogConfig {
             level: "info".to_string(),
+            ..Default::default()
         },
         lifecycle: LifecycleConfig::default(),
         mo_service: MoServiceConfig::default(),
-    });
+        outbind: vec![],
+        shutdown: ShutdownConfig::default(),
+        audit: AuditConfig::default(),
+    }
+}
 
+/// Run the SMPP server for `config` in the background and give it time to start listening
+async fn start_server(config: AppConfig) -> (Arc<SessionManager>, Arc<MessageQueue>) {
     let session_manager = Arc::new(SessionManager::new());
     let message_queue = Arc::new(MessageQueue::new());
-
-    // Start Server
-    let server_config = config.clone();
     let server_session_manager = session_manager.clone();
     let server_message_queue = message_queue.clone();
-
     tokio::spawn(async move {
-        start_smpp_server(server_config, server_session_manager, server_message_queue).await.unwrap();
+        start_smpp_server(Arc::new(config), server_session_manager, server_message_queue).await.unwrap();
     });
-
-    // Wait for server to start
     tokio::time::sleep(Duration::from_millis(500)).await;
+    (session_manager, message_queue)
+}
+
+#[tokio::test]
+async fn test_smpp_flow() {
+    // Setup Configuration
+    let port = 2777; // Use a different port to be safe
+    let system_id = "testsys";
+    let password = "pass";
+
+    let (session_manager, _message_queue) = start_server(test_config(port, 8082)).await;
 
     // Connect Client
     let stream = TcpStream::connect(format!("127.0.0.1:{}", port)).await.expect("Failed to connect");
@@ -143,3 +152,460 @@ async fn test_smpp_flow() {
     tokio::time::sleep(Duration::from_millis(100)).await;
     assert_eq!(session_manager.get_all_sessions().len(), 0);
 }
+
+/// CA, server and client certificates written to a scratch directory for the TLS tests
+struct TestPki {
+    dir: std::path::PathBuf,
+    ca_pem: String,
+    client_cert_pem: String,
+    client_key_pem: String,
+}
+
+fn generate_pki(client_cn: &str) -> TestPki {
+    use rcgen::{BasicConstraints, CertificateParams, DnType, ExtendedKeyUsagePurpose, IsCa, KeyPair};
+
+    let ca_key = KeyPair::generate().unwrap();
+    let mut ca_params = CertificateParams::new(vec![]).unwrap();
+    ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+    ca_params.distinguished_name.push(DnType::CommonName, "Test CA");
+    let ca_cert = ca_params.self_signed(&ca_key).unwrap();
+
+    let server_key = KeyPair::generate().unwrap();
+    let mut server_params = CertificateParams::new(vec!["localhost".to_string()]).unwrap();
+    server_params.extended_key_usages = vec![ExtendedKeyUsagePurpose::ServerAuth];
+    let server_cert = server_params.signed_by(&server_key, &ca_cert, &ca_key).unwrap();
+
+    let client_key = KeyPair::generate().unwrap();
+    let mut client_params = CertificateParams::new(vec![]).unwrap();
+    client_params.distinguished_name.push(DnType::CommonName, client_cn);
+    client_params.extended_key_usages = vec![ExtendedKeyUsagePurpose::ClientAuth];
+    let client_cert = client_params.signed_by(&client_key, &ca_cert, &ca_key).unwrap();
+
+    let dir = std::env::temp_dir().join(format!("smpp-sim-tls-{}-{}", std::process::id(), client_cn));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("ca.pem"), ca_cert.pem()).unwrap();
+    std::fs::write(dir.join("server.pem"), server_cert.pem()).unwrap();
+    std::fs::write(dir.join("server.key"), server_key.serialize_pem()).unwrap();
+
+    TestPki {
+        dir,
+        ca_pem: ca_cert.pem(),
+        client_cert_pem: client_cert.pem(),
+        client_key_pem: client_key.serialize_pem(),
+    }
+}
+
+fn tls_connector(pki: &TestPki, with_client_cert: bool) -> tokio_rustls::TlsConnector {
+    use tokio_rustls::rustls::{self, pki_types::{CertificateDer, PrivateKeyDer, pem::PemObject}};
+
+    let mut roots = rustls::RootCertStore::empty();
+    roots.add(CertificateDer::from_pem_slice(pki.ca_pem.as_bytes()).unwrap()).unwrap();
+    let builder = rustls::ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .with_root_certificates(roots);
+    let client_config = if with_client_cert {
+        let certs = vec![CertificateDer::from_pem_slice(pki.client_cert_pem.as_bytes()).unwrap()];
+        let key = PrivateKeyDer::from_pem_slice(pki.client_key_pem.as_bytes()).unwrap();
+        builder.with_client_auth_cert(certs, key).unwrap()
+    } else {
+        builder.with_no_client_auth()
+    };
+    tokio_rustls::TlsConnector::from(Arc::new(client_config))
+}
+
+fn bind_transmitter(sequence_number: u32, system_id: &str, password: &str) -> Command {
+    Command::builder()
+        .status(CommandStatus::EsmeRok)
+        .sequence_number(sequence_number)
+        .pdu(Pdu::BindTransmitter(BindTransmitter::new(
+            COctetString::from_str(system_id).unwrap(),
+            COctetString::from_str(password).unwrap(),
+            COctetString::from_str("").unwrap(),
+            InterfaceVersion::Smpp5_0,
+            Ton::Unknown,
+            Npi::Unknown,
+            COctetString::from_str("").unwrap(),
+        )))
+}
+
+#[tokio::test]
+async fn test_tls_client_cert_required() {
+    use rust_smpp_sim::config::{SmppAccount, TlsConfig};
+    use tokio_rustls::rustls::pki_types::ServerName;
+
+    let pki = generate_pki("certsys");
+    let tls_port = 3551;
+    let mut config = test_config(2778, 8083);
+    config.smpp.accounts = vec![SmppAccount::new("certsys", "pass")];
+    config.smpp.tls = TlsConfig {
+        enabled: true,
+        cert_path: pki.dir.join("server.pem").to_string_lossy().to_string(),
+        key_path: pki.dir.join("server.key").to_string_lossy().to_string(),
+        port: tls_port,
+        require_client_cert: true,
+        ca_path: Some(pki.dir.join("ca.pem").to_string_lossy().to_string()),
+    };
+    let (session_manager, _message_queue) = start_server(config).await;
+
+    let server_name = ServerName::try_from("localhost").unwrap();
+
+    // A valid client certificate lets the bind for its CN proceed
+    let stream = TcpStream::connect(format!("127.0.0.1:{}", tls_port)).await.unwrap();
+    let tls_stream = tls_connector(&pki, true).connect(server_name.clone(), stream).await.expect("TLS handshake failed");
+    let mut framed = Framed::new(tls_stream, CommandCodec::new());
+
+    // ...but only as the system_id named in the certificate
+    framed.send(bind_transmitter(1, "testsys", "pass")).await.unwrap();
+    let resp = framed.next().await.expect("Stream closed").expect("Decoding error");
+    assert_eq!(resp.status(), CommandStatus::EsmeRbindfail);
+
+    framed.send(bind_transmitter(2, "certsys", "pass")).await.unwrap();
+    let resp = framed.next().await.expect("Stream closed").expect("Decoding error");
+    assert!(matches!(resp.pdu(), Some(Pdu::BindTransmitterResp(_))), "Expected BindTransmitterResp, got {:?}", resp);
+    assert_eq!(resp.status(), CommandStatus::EsmeRok);
+    assert_eq!(session_manager.count(), 1);
+
+    // Without a client certificate the connection is refused
+    let stream = TcpStream::connect(format!("127.0.0.1:{}", tls_port)).await.unwrap();
+    let rejected = match tls_connector(&pki, false).connect(server_name, stream).await {
+        Err(_) => true,
+        Ok(tls_stream) => {
+            // TLS 1.3 reports client auth failure after the client finishes its handshake
+            let mut framed = Framed::new(tls_stream, CommandCodec::new());
+            let _ = framed.send(bind_transmitter(1, "certsys", "pass")).await;
+            !matches!(framed.next().await, Some(Ok(_)))
+        }
+    };
+    assert!(rejected, "Connection without a client certificate should be rejected");
+    assert_eq!(session_manager.count(), 1);
+
+    let _ = std::fs::remove_dir_all(&pki.dir);
+}
+
+#[tokio::test]
+async fn test_outbind_prompts_bind_receiver() {
+    use rust_smpp_sim::config::OutbindTarget;
+    use rust_smpp_sim::smpp::outbind::start_outbind_connector;
+    use rusmpp::pdus::BindReceiver;
+
+    // Play the ESME: listen and wait for the simulator to dial in
+    let esme = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let esme_port = esme.local_addr().unwrap().port();
+
+    let mut config = test_config(2779, 8084);
+    config.smpp.outbind_enabled = true;
+    let config = Arc::new(AppConfig {
+        outbind: vec![OutbindTarget {
+            host: "127.0.0.1".to_string(),
+            port: esme_port,
+            system_id: "SMSC".to_string(),
+            password: "secret".to_string(),
+            retry_interval_ms: 1000,
+        }],
+        ..config
+    });
+
+    let session_manager = Arc::new(SessionManager::new());
+    let message_queue = Arc::new(MessageQueue::new());
+    tokio::spawn(start_outbind_connector(config, session_manager.clone(), message_queue));
+
+    let (stream, _) = tokio::time::timeout(Duration::from_secs(5), esme.accept()).await.expect("No outbind connection").unwrap();
+    let mut framed = Framed::new(stream, CommandCodec::new());
+
+    let outbind = framed.next().await.expect("Stream closed").expect("Decoding error");
+    match outbind.pdu() {
+        Some(Pdu::Outbind(o)) => {
+            assert_eq!(o.system_id.to_string(), "SMSC");
+            assert_eq!(o.password.to_string(), "secret");
+        }
+        _ => panic!("Expected Outbind, got {:?}", outbind),
+    }
+
+    // The ESME binds as a receiver over the same socket
+    let bind_req = Command::builder()
+        .status(CommandStatus::EsmeRok)
+        .sequence_number(1)
+        .pdu(Pdu::BindReceiver(BindReceiver::new(
+            COctetString::from_str("testsys").unwrap(),
+            COctetString::from_str("pass").unwrap(),
+            COctetString::from_str("").unwrap(),
+            InterfaceVersion::Smpp5_0,
+            Ton::Unknown,
+            Npi::Unknown,
+            COctetString::from_str("").unwrap(),
+        )));
+    framed.send(bind_req).await.unwrap();
+
+    let resp = framed.next().await.expect("Stream closed").expect("Decoding error");
+    assert!(matches!(resp.pdu(), Some(Pdu::BindReceiverResp(_))), "Expected BindReceiverResp, got {:?}", resp);
+    assert_eq!(resp.status(), CommandStatus::EsmeRok);
+    assert_eq!(session_manager.count(), 1);
+}
+
+fn submit_sm(sequence_number: u32) -> Command {
+    Command::builder()
+        .status(CommandStatus::EsmeRok)
+        .sequence_number(sequence_number)
+        .pdu(Pdu::SubmitSm(SubmitSm::builder()
+            .source_addr(COctetString::from_str("source").unwrap())
+            .destination_addr(COctetString::from_str("dest").unwrap())
+            .short_message(OctetString::from_str("fire and forget").unwrap())
+            .build()))
+}
+
+#[tokio::test]
+async fn test_fire_and_forget_client_does_not_block_others() {
+    let port = 2780;
+    let mut config = test_config(port, 8085);
+    config.smpp.fire_and_forget = true;
+    let (_session_manager, message_queue) = start_server(config).await;
+
+    // Client A binds, then submits without ever reading
+    let stream = TcpStream::connect(format!("127.0.0.1:{}", port)).await.unwrap();
+    let mut one_way = Framed::new(stream, CommandCodec::new());
+    one_way.send(bind_transmitter(1, "testsys", "pass")).await.unwrap();
+    let resp = one_way.next().await.expect("Stream closed").expect("Decoding error");
+    assert_eq!(resp.status(), CommandStatus::EsmeRok);
+
+    const SUBMITS: u32 = 2000;
+    for seq in 0..SUBMITS {
+        one_way.feed(submit_sm(seq + 2)).await.unwrap();
+    }
+    SinkExt::<Command>::flush(&mut one_way).await.unwrap();
+
+    // Client B is served meanwhile
+    let stream = TcpStream::connect(format!("127.0.0.1:{}", port)).await.unwrap();
+    let mut other = Framed::new(stream, CommandCodec::new());
+    other.send(bind_transmitter(1, "testsys", "pass")).await.unwrap();
+    let resp = tokio::time::timeout(Duration::from_secs(2), other.next()).await
+        .expect("Second client blocked").expect("Stream closed").expect("Decoding error");
+    assert_eq!(resp.status(), CommandStatus::EsmeRok);
+
+    // Every submit is accepted but none is answered
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while message_queue.message_count() < SUBMITS as usize {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    }).await.expect("Submits were not all processed");
+    assert!(tokio::time::timeout(Duration::from_millis(200), one_way.next()).await.is_err(), "No SubmitSmResp expected");
+}
+
+#[tokio::test]
+async fn test_tls_listener_alongside_plaintext() {
+    use rust_smpp_sim::config::TlsConfig;
+    use tokio_rustls::rustls::pki_types::ServerName;
+
+    let pki = generate_pki("unused");
+    let port = 2781;
+    let tls_port = 3552;
+    let mut config = test_config(port, 8086);
+    config.smpp.tls = TlsConfig {
+        enabled: true,
+        cert_path: pki.dir.join("server.pem").to_string_lossy().to_string(),
+        key_path: pki.dir.join("server.key").to_string_lossy().to_string(),
+        port: tls_port,
+        ..Default::default()
+    };
+    let (session_manager, _message_queue) = start_server(config).await;
+
+    // SMPPS without a client certificate
+    let stream = TcpStream::connect(format!("127.0.0.1:{}", tls_port)).await.unwrap();
+    let tls_stream = tls_connector(&pki, false).connect(ServerName::try_from("localhost").unwrap(), stream).await.expect("TLS handshake failed");
+    let mut secure = Framed::new(tls_stream, CommandCodec::new());
+    secure.send(bind_transmitter(1, "testsys", "pass")).await.unwrap();
+    let resp = secure.next().await.expect("Stream closed").expect("Decoding error");
+    assert_eq!(resp.status(), CommandStatus::EsmeRok);
+
+    // Plain SMPP keeps working on its own port
+    let stream = TcpStream::connect(format!("127.0.0.1:{}", port)).await.unwrap();
+    let mut plain = Framed::new(stream, CommandCodec::new());
+    plain.send(bind_transmitter(1, "testsys", "pass")).await.unwrap();
+    let resp = plain.next().await.expect("Stream closed").expect("Decoding error");
+    assert_eq!(resp.status(), CommandStatus::EsmeRok);
+
+    assert_eq!(session_manager.count(), 2);
+    let _ = std::fs::remove_dir_all(&pki.dir);
+}
+
+#[tokio::test]
+async fn test_smpp34_bind_without_null_terminator() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let port = 2782;
+    let mut config = test_config(port, 8087);
+    config.smpp.version = "3.4".to_string();
+    let (session_manager, _message_queue) = start_server(config).await;
+
+    // bind_transmitter as sent by legacy 3.4 clients: address_range has no null terminator
+    let mut body = Vec::new();
+    body.extend_from_slice(b"testsys\0");
+    body.extend_from_slice(b"pass\0");
+    body.extend_from_slice(b"\0"); // system_type
+    body.extend_from_slice(&[0x34, 0x00, 0x00]); // interface_version, addr_ton, addr_npi
+    let mut pdu = Vec::new();
+    pdu.extend_from_slice(&(16 + body.len() as u32).to_be_bytes());
+    pdu.extend_from_slice(&0x0000_0002u32.to_be_bytes()); // bind_transmitter
+    pdu.extend_from_slice(&0u32.to_be_bytes());
+    pdu.extend_from_slice(&1u32.to_be_bytes());
+    pdu.extend_from_slice(&body);
+
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{}", port)).await.unwrap();
+    stream.write_all(&pdu).await.unwrap();
+
+    let mut header = [0u8; 16];
+    tokio::time::timeout(Duration::from_secs(2), stream.read_exact(&mut header)).await
+        .expect("No bind response").expect("Connection closed");
+    let command_id = u32::from_be_bytes([header[4], header[5], header[6], header[7]]);
+    let status = u32::from_be_bytes([header[8], header[9], header[10], header[11]]);
+    assert_eq!(command_id, 0x8000_0002, "Expected bind_transmitter_resp");
+    assert_eq!(status, 0, "Bind should be accepted in 3.4 mode");
+    assert_eq!(session_manager.count(), 1);
+}
+
+#[tokio::test]
+async fn test_shutdown_drain_timeout() {
+    use rust_smpp_sim::smpp::server::run_smpp_server;
+
+    let port = 2783;
+    let config = Arc::new(AppConfig {
+        shutdown: ShutdownConfig { drain_timeout_ms: 300 },
+        ..test_config(port, 8088)
+    });
+
+    let session_manager = Arc::new(SessionManager::new());
+    let message_queue = Arc::new(MessageQueue::new());
+    let (stop_tx, stop_rx) = tokio::sync::oneshot::channel::<()>();
+    let server = tokio::spawn(run_smpp_server(config, session_manager.clone(), message_queue, async {
+        let _ = stop_rx.await;
+    }));
+    tokio::time::sleep(Duration::from_millis(500)).await;
+
+    // A bound client that will never acknowledge the unbind
+    let stream = TcpStream::connect(format!("127.0.0.1:{}", port)).await.unwrap();
+    let mut framed = Framed::new(stream, CommandCodec::new());
+    framed.send(bind_transmitter(1, "testsys", "pass")).await.unwrap();
+    let resp = framed.next().await.expect("Stream closed").expect("Decoding error");
+    assert_eq!(resp.status(), CommandStatus::EsmeRok);
+
+    let started = std::time::Instant::now();
+    stop_tx.send(()).unwrap();
+    tokio::time::timeout(Duration::from_secs(2), server).await
+        .expect("Shutdown did not finish within the drain bound").unwrap().unwrap();
+    assert!(started.elapsed() >= Duration::from_millis(300), "Shutdown should wait for the drain timeout");
+
+    // The client was asked to unbind, then force-closed
+    let unbind = framed.next().await.expect("Stream closed").expect("Decoding error");
+    assert!(matches!(unbind.pdu(), Some(Pdu::Unbind)), "Expected Unbind, got {:?}", unbind);
+    assert!(framed.next().await.is_none(), "Connection should be closed");
+    assert_eq!(session_manager.count(), 0);
+}
+
+#[tokio::test]
+async fn test_disconnect_session_closes_connection() {
+    let port = 2784;
+    let (session_manager, _message_queue) = start_server(test_config(port, 8089)).await;
+
+    let stream = TcpStream::connect(format!("127.0.0.1:{}", port)).await.unwrap();
+    let mut framed = Framed::new(stream, CommandCodec::new());
+    framed.send(bind_transmitter(1, "testsys", "pass")).await.unwrap();
+    let resp = framed.next().await.expect("Stream closed").expect("Decoding error");
+    assert_eq!(resp.status(), CommandStatus::EsmeRok);
+
+    let session_id = session_manager.get_all_sessions()[0].id.clone();
+    assert!(session_manager.disconnect_session(&session_id));
+
+    let closed = tokio::time::timeout(Duration::from_secs(2), framed.next()).await
+        .expect("Connection was not closed");
+    assert!(closed.is_none(), "Expected EOF, got {:?}", closed);
+    assert_eq!(session_manager.count(), 0);
+}
+
+#[tokio::test]
+async fn test_listen_on_multiple_ports() {
+    let ports = vec![2785, 2786];
+    let mut config = test_config(2775, 8090);
+    config.smpp.ports = ports.clone();
+    config.smpp.capture_pdus = true;
+    let (session_manager, _message_queue) = start_server(config).await;
+
+    // Both ports accept binds into the same session manager
+    let mut clients = vec![];
+    for (seq, port) in ports.iter().enumerate() {
+        let stream = TcpStream::connect(format!("127.0.0.1:{}", port)).await.expect("Failed to connect");
+        let mut framed = Framed::new(stream, CommandCodec::new());
+        framed.send(bind_transmitter(seq as u32 + 1, "testsys", "pass")).await.unwrap();
+        let resp = framed.next().await.expect("Stream closed").expect("Decoding error");
+        assert_eq!(resp.status(), CommandStatus::EsmeRok, "bind on port {}", port);
+        clients.push(framed);
+    }
+    assert_eq!(session_manager.count(), 2);
+
+    // Each pcap shows the port the client actually connected to; the first packet is its bind,
+    // a raw IPv4 + TCP packet after the 24-byte global and 16-byte record headers
+    let mut captured_ports: Vec<u16> = session_manager.get_all_sessions().iter()
+        .map(|session| {
+            let pcap = session_manager.capture_for_session(&session.id).expect("capture enabled").to_pcap();
+            u16::from_be_bytes([pcap[24 + 16 + 22], pcap[24 + 16 + 23]])
+        })
+        .collect();
+    captured_ports.sort();
+    assert_eq!(captured_ports, ports);
+}
+
+#[tokio::test]
+async fn test_malformed_pdu_keeps_connection() {
+    use tokio::io::AsyncWriteExt;
+
+    let port = 2787;
+    let (session_manager, _message_queue) = start_server(test_config(port, 8091)).await;
+
+    let stream = TcpStream::connect(format!("127.0.0.1:{}", port)).await.unwrap();
+    let mut framed = Framed::new(stream, CommandCodec::new());
+    framed.send(bind_transmitter(1, "testsys", "pass")).await.unwrap();
+    let resp = framed.next().await.expect("Stream closed").expect("Decoding error");
+    assert_eq!(resp.status(), CommandStatus::EsmeRok);
+
+    // submit_sm cut off inside service_type
+    let mut bad = Vec::new();
+    bad.extend_from_slice(&19u32.to_be_bytes());
+    bad.extend_from_slice(&0x0000_0004u32.to_be_bytes());
+    bad.extend_from_slice(&0u32.to_be_bytes());
+    bad.extend_from_slice(&2u32.to_be_bytes());
+    bad.extend_from_slice(b"abc");
+    framed.get_mut().write_all(&bad).await.unwrap();
+    framed.send(Command::builder()
+        .status(CommandStatus::EsmeRok)
+        .sequence_number(3)
+        .pdu(Pdu::EnquireLink)).await.unwrap();
+
+    let nack = tokio::time::timeout(Duration::from_secs(2), framed.next()).await
+        .expect("No generic_nack").expect("Stream closed").expect("Decoding error");
+    assert!(matches!(nack.pdu(), Some(Pdu::GenericNack)), "Expected GenericNack, got {:?}", nack);
+    assert_eq!(nack.status(), CommandStatus::EsmeRinvcmdlen);
+    assert_eq!(nack.sequence_number(), 2);
+
+    let resp = tokio::time::timeout(Duration::from_secs(2), framed.next()).await
+        .expect("No enquire_link_resp").expect("Stream closed").expect("Decoding error");
+    assert!(matches!(resp.pdu(), Some(Pdu::EnquireLinkResp)), "Expected EnquireLinkResp, got {:?}", resp);
+    assert_eq!(resp.sequence_number(), 3);
+    assert_eq!(session_manager.count(), 1);
+}
+
+#[tokio::test]
+async fn test_listen_on_ipv6_host() {
+    // Hosts without IPv6 loopback have nothing to test
+    if std::net::TcpListener::bind("[::1]:0").is_err() {
+        return;
+    }
+    let port = 2788;
+    let mut config = test_config(port, 8092);
+    config.smpp.host = "::1".to_string();
+    let (_session_manager, _message_queue) = start_server(config).await;
+
+    let stream = TcpStream::connect(("::1", port)).await.expect("Failed to connect over IPv6");
+    let mut framed = Framed::new(stream, CommandCodec::new());
+    framed.send(bind_transmitter(1, "testsys", "pass")).await.unwrap();
+    let resp = framed.next().await.expect("Stream closed").expect("Decoding error");
+    assert_eq!(resp.status(), CommandStatus::EsmeRok);
+}